@@ -29,6 +29,7 @@ fn main() -> anyhow::Result<()> {
             test_directory_module(out, "tests/misc_testsuite/simd", strategy)?;
             test_directory_module(out, "tests/misc_testsuite/threads", strategy)?;
             test_directory_module(out, "tests/misc_testsuite/memory64", strategy)?;
+            test_directory_module(out, "tests/misc_testsuite/memref", strategy)?;
             test_directory_module(out, "tests/misc_testsuite/component-model", strategy)?;
             Ok(())
         })?;
@@ -49,6 +49,22 @@ pub enum TrapCode {
     /// This trap is resumable.
     Interrupt,
 
+    /// A memref access or narrowing fell outside of the bounds of the object the memref
+    /// points into.
+    MemRefOutOfBounds,
+
+    /// A memref was used to access an allocation after it was deallocated.
+    MemRefUseAfterFree,
+
+    /// A memref was deallocated while its allocation was already deallocated.
+    MemRefDoubleFree,
+
+    /// A memref allocation couldn't be given a key as all lock table slots were live.
+    MemRefKeysExhausted,
+
+    /// A memref allocation extended past the end of its linear memory.
+    MemRefAllocOutOfBounds,
+
     /// A user-defined trap code.
     User(u16),
 }
@@ -68,6 +84,11 @@ impl TrapCode {
             TrapCode::BadConversionToInteger,
             TrapCode::UnreachableCodeReached,
             TrapCode::Interrupt,
+            TrapCode::MemRefOutOfBounds,
+            TrapCode::MemRefUseAfterFree,
+            TrapCode::MemRefDoubleFree,
+            TrapCode::MemRefKeysExhausted,
+            TrapCode::MemRefAllocOutOfBounds,
         ]
     }
 }
@@ -87,6 +108,11 @@ impl Display for TrapCode {
             BadConversionToInteger => "bad_toint",
             UnreachableCodeReached => "unreachable",
             Interrupt => "interrupt",
+            MemRefOutOfBounds => "memref_oob",
+            MemRefUseAfterFree => "memref_uaf",
+            MemRefDoubleFree => "memref_double_free",
+            MemRefKeysExhausted => "memref_keys_exhausted",
+            MemRefAllocOutOfBounds => "memref_alloc_oob",
             User(x) => return write!(f, "user{}", x),
         };
         f.write_str(identifier)
@@ -110,6 +136,11 @@ impl FromStr for TrapCode {
             "bad_toint" => Ok(BadConversionToInteger),
             "unreachable" => Ok(UnreachableCodeReached),
             "interrupt" => Ok(Interrupt),
+            "memref_oob" => Ok(MemRefOutOfBounds),
+            "memref_uaf" => Ok(MemRefUseAfterFree),
+            "memref_double_free" => Ok(MemRefDoubleFree),
+            "memref_keys_exhausted" => Ok(MemRefKeysExhausted),
+            "memref_alloc_oob" => Ok(MemRefAllocOutOfBounds),
             _ if s.starts_with("user") => s[4..].parse().map(User).map_err(|_| ()),
             _ => Err(()),
         }
@@ -0,0 +1,80 @@
+;;! target = "x86_64"
+
+;; The second `i32.msload` through the memref parameter is covered by the
+;; bounds check of the first one, so only its heap access is emitted.
+;;
+;; (module
+;;   (memory 1)
+;;   (func (param memref) (result i32)
+;;     local.get 0
+;;     i32.msload
+;;     local.get 0
+;;     i32.msload
+;;     i32.add))
+
+(module binary
+  "\00asm\01\00\00\00"
+  "\01\06\01\60\01\6e\01\7f"
+  "\03\02\01\00"
+  "\05\03\01\00\01"
+  "\0a\0f\01\0d\00\20\00\e0\02\00\20\00\e0\02\00\6a\0b"
+)
+
+;; function u0:0(i8x16, i64 vmctx) -> i32 fast {
+;;     gv0 = vmctx
+;;     gv1 = load.i64 notrap aligned readonly gv0
+;;
+;;                                 block0(v0: i8x16, v1: i64):
+;;                                     v26 -> v0
+;; @0020                               v3 = bitcast.i32x4 little v0
+;; @0020                               v4 = extractlane v3, 0
+;; @0020                               v5 = extractlane v3, 1
+;; @0020                               v6 = extractlane v3, 2
+;; @0020                               v7 = extractlane v3, 3
+;; @0020                               v8 = band_imm v7, 32
+;; @0020                               v9 = icmp_imm eq v8, 0
+;; @0020                               brnz v9, block2
+;; @0020                               jump block3
+;;
+;;                                 block3:
+;; @0020                               v10 = uextend.i64 v4
+;; @0020                               v11 = iadd_imm v10, 4
+;; @0020                               v12 = uextend.i64 v5
+;; @0020                               v13 = uextend.i64 v6
+;; @0020                               v14 = icmp ugt v11, v13
+;; @0020                               v15 = icmp ugt v12, v10
+;; @0020                               v16 = bor v14, v15
+;; @0020                               v17 = ushr_imm.i32 v7, 8
+;; @0020                               v18 = band_imm v17, 15
+;; @0020                               v19 = icmp_imm ne v18, 0
+;; @0020                               v20 = bor v16, v19
+;; @0020                               v21 = ireduce.i32 v10
+;; @0020                               brnz v20, block4
+;; @0020                               jump block5
+;;
+;;                                 block4 cold:
+;; @0020                               trap memref_oob
+;;
+;;                                 block5:
+;; @0020                               jump block2
+;;
+;;                                 block2:
+;; @0020                               v22 = uextend.i64 v4
+;; @0020                               v23 = global_value.i64 gv1
+;; @0020                               v24 = iadd v23, v22
+;; @0020                               v25 = load.i32 little heap v24
+;; @0025                               v27 = bitcast.i32x4 little v0
+;; @0025                               v28 = extractlane v27, 0
+;; @0025                               v29 = extractlane v27, 1
+;; @0025                               v30 = extractlane v27, 2
+;; @0025                               v31 = extractlane v27, 3
+;; @0025                               v32 = uextend.i64 v28
+;; @0025                               v33 = global_value.i64 gv1
+;; @0025                               v34 = iadd v33, v32
+;; @0025                               v35 = load.i32 little heap v34
+;; @0028                               v36 = iadd v25, v35
+;; @0029                               jump block1(v36)
+;;
+;;                                 block1(v2: i32):
+;; @0029                               return v2
+;; }
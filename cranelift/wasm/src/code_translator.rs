@@ -72,17 +72,19 @@
 //!     ("Relax verification to allow I8X16 to act as a default vector type")
 
 mod bounds_checks;
+mod memref_loops;
 
 use super::{hash_map, HashMap};
 use crate::environ::{FuncEnvironment, GlobalVariable};
-use crate::state::{ControlStackFrame, ElseData, FuncTranslationState};
+use crate::state::{ControlStackFrame, ElseData, FuncTranslationState, MemRefCheck};
 use crate::translation_utils::{
     block_with_params, blocktype_params_results, f32_translation, f64_translation,
 };
 use crate::wasm_unsupported;
-use crate::{FuncIndex, GlobalIndex, MemoryIndex, TableIndex, TypeIndex, WasmResult};
+use crate::{memref, FuncIndex, GlobalIndex, MemoryIndex, TableIndex, TypeIndex, WasmResult};
 use core::convert::TryInto;
 use core::{i32, u32};
+use cranelift_codegen::cursor::{Cursor, FuncCursor};
 use cranelift_codegen::ir::condcodes::{FloatCC, IntCC};
 use cranelift_codegen::ir::immediates::Offset32;
 use cranelift_codegen::ir::types::*;
@@ -96,7 +98,6 @@ use smallvec::SmallVec;
 use std::convert::TryFrom;
 use std::vec::Vec;
 use wasmparser::{FuncValidator, MemArg, Operator, ValType, WasmModuleResources};
-use bounds_checks::bounds_check_only;
 
 /// Given an `Option<T>`, unwrap the inner `T` or, if the option is `None`, set
 /// the state to unreachable and return.
@@ -116,14 +117,6 @@ macro_rules! unwrap_or_return_unreachable_state {
     };
 }
 
-
-    // attr flag
-const HasMetadataFlag:u8 = 0x20; // 0010 0000
-const ValidPointerFlag:u8 = 0x10; // 0001 0000
-const HeapVariableFlag:u8 = 0x02; // 0000 0010
-const GlobalVariableFlag:u8 = 0x01; // 0000 0001
-const SubObjFlag:u8 = 0x04;
-
 // Clippy warns about "align: _" but its important to document that the flags field is ignored
 #[cfg_attr(
     feature = "cargo-clippy",
@@ -159,214 +152,187 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
             state.push1(builder.ins().select(cond, arg1, arg2));
         }
         Operator::MemrefNull {} => {
-            let zero_value :Vec<u8> = vec![0;16];
-            let data = ConstantData::from(zero_value);
-            let handle = builder.func.dfg.constants.insert(data);
-            let value = builder.ins().vconst(I8X16, handle);
-            // the v128.const is typed in CLIF as a I8x16 but bitcast to a different type
-            // before use
-            let value = optionally_bitcast_vector(value, I32X4, builder);
-            let attr_val = builder.ins().iconst(I32, HasMetadataFlag as i64);
-            let value = builder.ins().insertlane(value, attr_val, 3);
+            let zero = builder.ins().iconst(I32, 0);
+            let attr_val = builder
+                .ins()
+                .iconst(I32, i64::from(memref::HAS_METADATA_FLAG));
+            let value = make_memref([zero, zero, zero, attr_val], builder, state);
             state.push1(value);
         }
         Operator::MemrefNe {} => {
-            let (mem0  , mem1) = state.pop2();
-            let mem0 = optionally_bitcast_vector(mem0, I32X4, builder);
-            let mem1 = optionally_bitcast_vector(mem1, I32X4, builder);
-            let addr0 = builder.ins().extractlane(mem0, 0);
-            let addr1 = builder.ins().extractlane(mem1, 0);
+            let (mem0, mem1) = state.pop2();
+            let [addr0, ..] = memref_lanes(mem0, builder, state);
+            let [addr1, ..] = memref_lanes(mem1, builder, state);
             let val = builder.ins().icmp(IntCC::NotEqual, addr0, addr1);
             state.push1(builder.ins().uextend(I32, val));
         }
         Operator::MemrefEq {} => {
-            let (mem0  , mem1) = state.pop2();
-            let mem0 = optionally_bitcast_vector(mem0, I32X4, builder);
-            let mem1 = optionally_bitcast_vector(mem1, I32X4, builder);
-            let addr0 = builder.ins().extractlane(mem0, 0);
-            let addr1 = builder.ins().extractlane(mem1, 0);
+            let (mem0, mem1) = state.pop2();
+            let [addr0, ..] = memref_lanes(mem0, builder, state);
+            let [addr1, ..] = memref_lanes(mem1, builder, state);
             let val = builder.ins().icmp(IntCC::Equal, addr0, addr1);
             state.push1(builder.ins().uextend(I32, val));
         }
-        Operator::MemrefConst {addr, size, attr} => {
+        Operator::MemrefConst { addr, size, attr } => {
             // memref const can not in code section
             builder.ins().trap(ir::TrapCode::UnreachableCodeReached);
         }
-        Operator::MemrefDealloc {info} => {
+        Operator::MemrefDealloc { info } => {
             let mref = state.pop1();
+            // earlier checks no longer prove that memrefs to this allocation may be used
+            state.clear_memref_checks();
+            let [_, _, _, attr] = memref_lanes(mref, builder, state);
+            // release the allocation in the lock table of the memory it was allocated in, so
+            // that outstanding memrefs to it stop being usable
+            let mut memory = 0;
+            while memory <= memref::MEMORY_INDEX_MASK
+                && validator.resources().memory_at(memory).is_some()
+            {
+                if let Some(locks) = state.get_memref_locks(builder.func, memory, environ)? {
+                    let attr_memory = memref_memory_index(attr, builder);
+                    let in_memory =
+                        builder
+                            .ins()
+                            .icmp_imm(IntCC::Equal, attr_memory, i64::from(memory));
+                    let (entry, key, keyed) = translate_memref_key_check(
+                        locks,
+                        attr,
+                        Some(in_memory),
+                        ir::TrapCode::MemRefDoubleFree,
+                        builder,
+                        environ,
+                    );
+                    let release = builder.ins().band(keyed, in_memory);
+                    translate_memref_release_key(locks, entry, key, release, builder, environ);
+                }
+                memory += 1;
+            }
         }
-        Operator::MemrefAlloc {attr} => {
+        Operator::MemrefAlloc { attr } => {
             let (addr, size) = state.pop2();
-            let mem_ref = builder.ins().splat(I32X4, addr); // insert addr and base
-            let attr_val = builder.ins().iconst(I32, *attr as i64);
-            let mem_ref = builder.ins().insertlane(mem_ref, attr_val, 3);// insert attr
-            if (*attr & (HasMetadataFlag as u32)) != 0 { // metadata is valid, so check the base+size
-                let end = builder.ins().uadd_overflow_trap(addr, size, ir::TrapCode::IntegerOverflow);
-                let heap = state.get_heap(builder.func, 0, environ)?; // index 0
-                let heap = environ.heaps()[heap].clone();
-                bounds_check_only(builder, environ, &heap, end)?;
-                // TODO:check size
-                // let size_check = builder.ins().band_imm(size, 0xff000000i64);
-                // builder.ins().trapnz(size_check, ir::TrapCode::HeapOutOfBounds);
-                let mem_ref = builder.ins().insertlane(mem_ref, end, 2); // insert size/end
+            let mut attr_val = builder.ins().iconst(I32, *attr as i64);
+            if (*attr & memref::HAS_METADATA_FLAG) != 0 {
+                // metadata is valid, so check the base+size
+                // the memory the object is allocated in is implied by the attributes
+                let memory = memref::memory_index(*attr);
+                if validator.resources().memory_at(memory).is_none() {
+                    return Err(wasm_unsupported!(
+                        "memref.alloc in unknown memory {}",
+                        memory
+                    ));
+                }
+                if let Some(locks) = state.get_memref_locks(builder.func, memory, environ)? {
+                    let key = translate_memref_new_key(locks, builder, environ);
+                    let key = builder
+                        .ins()
+                        .ishl_imm(key, i64::from(memref::ALLOC_KEY_SHIFT));
+                    attr_val = builder.ins().bor(attr_val, key);
+                }
+                // objects are addressed with 32 bits, even within 64-bit memories
+                let end = builder.ins().uadd_overflow_trap(
+                    addr,
+                    size,
+                    ir::TrapCode::MemRefAllocOutOfBounds,
+                );
+                translate_memref_alloc_size_check(memory, end, builder, state, environ)?;
+                let mem_ref = make_memref([addr, addr, end, attr_val], builder, state);
                 state.push1(mem_ref);
 
-                if let Some(funcIdx) = environ.host_set_value_func_index() {
-                    // let metadata = builder.ins().uextend(I64, size);
-                    // let new_base = builder.ins().uextend(I64, addr);
-                    // let new_base = builder.ins().ishl_imm(new_base, 32i64);
-                    // let metadata = builder.ins().bor(metadata, new_base);
-                    // let metadata = builder.ins().bor_imm(metadata, (*attr as i64)<<24);
-                    let zero_val = builder.ins().iconst(I32, 0);
-                    let (fref, num_args) = state.get_direct_func(builder.func, funcIdx, environ)?;
-                    let args :&mut[Value] = &mut[addr, addr, end, attr_val, zero_val];
-                    bitcast_wasm_params(
-                        environ,
-                        builder.func.dfg.ext_funcs[fref].signature,
-                        args,
-                        builder,
-                    );
-                    let call = environ.translate_call(
-                        builder.cursor(),
-                        FuncIndex::from_u32(funcIdx),
-                        fref,
-                        args,
-                    )?;
-                    let inst_results = builder.inst_results(call);
-                    debug_assert_eq!(
-                        inst_results.len(),
-                        builder.func.dfg.signatures[builder.func.dfg.ext_funcs[fref].signature]
-                            .returns
-                            .len(),
-                        "translate_call results should match the call signature"
-                    );
-                }
+                translate_memref_set_metadata(
+                    memory, addr, addr, end, attr_val, 0, builder, state, environ,
+                )?;
             } else {
-                let mem_ref = builder.ins().insertlane(mem_ref, attr_val, 2); // it is needed, because addr may >= (1<<24), attr < (1<<24)
+                // it is needed, because addr may >= (1<<24), attr < (1<<24)
+                let mem_ref = make_memref([addr, addr, attr_val, attr_val], builder, state);
                 state.push1(mem_ref);
             }
-
-            // state.push1(mem_ref);
         }
         Operator::MemrefAdd => {
             let (mem_ref, val) = state.pop2();
-            let mem_ref = optionally_bitcast_vector(mem_ref, I32X4, builder);
-            let addr = builder.ins().extractlane(mem_ref, 0);
+            let [addr, base, end, attr] = memref_lanes(mem_ref, builder, state);
             let res = builder.ins().iadd(val, addr);
-            state.push1(builder.ins().insertlane(mem_ref, res, 0));
+            let mem_ref = make_memref([res, base, end, attr], builder, state);
+            state.push1(mem_ref);
         }
         Operator::MemrefAnd => {
             let (mem_ref, val) = state.pop2();
-            let mem_ref = optionally_bitcast_vector(mem_ref, I32X4, builder);
-            let addr = builder.ins().extractlane(mem_ref, 0);
+            let [addr, base, end, attr] = memref_lanes(mem_ref, builder, state);
             let res = builder.ins().band(val, addr);
-            let mem_ref = builder.ins().insertlane(mem_ref, res, 0);
+            let mem_ref = make_memref([res, base, end, attr], builder, state);
             state.push1(mem_ref);
         }
-        Operator::MemrefField{field} => {
-            let mem_ref = optionally_bitcast_vector(state.pop1(), I32X4, builder);
+        Operator::MemrefField { field } => {
+            let mem_ref = state.pop1();
             // field has been checked in wasmparser
-            let val = builder.ins().extractlane(mem_ref, *field as u8);
+            let val = memref_lanes(mem_ref, builder, state)[*field as usize];
             state.push1(val);
         }
-        Operator::MemrefNarrow {narrow_size} => {
+        Operator::MemrefNarrow { narrow_size } => {
             let (narrow_base, mem_ref) = state.pop2();
-// create block
-            // let next = block_with_params(builder, std::iter::empty::<ValType>(), environ)?;
-            // state.push_block(next, 0, 0);
-            if (narrow_size & 0x08000000) == 0{ // if need check
+            let mem_ref = if (narrow_size & 0x08000000) == 0 {
+                // if need check
                 let narrow_size = &(*narrow_size & 0xf7ffffff);
-                let mem_ref = optionally_bitcast_vector(mem_ref, I32X4, builder);
-                let addr = builder.ins().extractlane(mem_ref, 0);
-                let base = builder.ins().extractlane(mem_ref, 1);
-                let end = builder.ins().extractlane(mem_ref, 2);
-                let attr = builder.ins().extractlane(mem_ref, 3);
+                environ.translate_memref_count(builder.cursor(), memref::MemRefCounter::Narrows)?;
+                let [addr, base, end, attr] = memref_lanes(mem_ref, builder, state);
 
-                let has_metadata = builder.ins().band_imm(attr, HasMetadataFlag as i64);
+                let has_metadata = builder
+                    .ins()
+                    .band_imm(attr, i64::from(memref::HAS_METADATA_FLAG));
                 let has_metadata = builder.ins().icmp_imm(IntCC::NotEqual, has_metadata, 0);
-                // do nothing if there is no metadata
-                // translate_br_if(0, builder, state);
-
-                // else check
                 let narrow_size = builder.ins().iconst(I32, *narrow_size as i64);
-                let narrow_upper = builder.ins().uadd_overflow_trap(narrow_base, narrow_size, ir::TrapCode::IntegerOverflow);
+                let narrow_upper = builder.ins().uadd_overflow_trap(
+                    narrow_base,
+                    narrow_size,
+                    ir::TrapCode::IntegerOverflow,
+                );
 
-                // check
-                // let upper = builder.ins().uadd_overflow_trap(base, size, ir::TrapCode::IntegerOverflow);
                 // base is no need to check, it comes from mref.field 0
-                // if narrow_upper > upper, trap
-                let is_trap = builder.ins().icmp(IntCC::UnsignedGreaterThan, narrow_upper, end);
+                // if narrow_upper > upper, trap, but only check memrefs with metadata
+                let is_trap = builder
+                    .ins()
+                    .icmp(IntCC::UnsignedGreaterThan, narrow_upper, end);
                 let is_trap = builder.ins().band(has_metadata, is_trap);
-
-                // TODO:need a new TrapCode here
-                builder.ins().trapnz(is_trap, ir::TrapCode::HeapOutOfBounds);
+                translate_memref_bounds_check(is_trap, narrow_base, base, end, builder, environ)?;
 
                 // if size is zero
                 let zero_size = builder.ins().iconst(I32, 0);
                 let narrow_size = builder.ins().select(has_metadata, narrow_size, zero_size);
 
-                let mem_ref = builder.ins().insertlane(mem_ref, narrow_base, 1);
-                let mem_ref = builder.ins().insertlane(mem_ref, narrow_upper, 2);
-                let attr = builder.ins().bor_imm(attr, SubObjFlag as i64); // sub-obj
-                let mem_ref = builder.ins().insertlane(mem_ref, attr, 3);
-
-                // end block
-                // let frame = state.control_stack.pop().unwrap();
-                // let next_block = frame.following_code();
-                // canonicalise_then_jump(builder, next_block, &[]);
-                // builder.switch_to_block(next_block);
-                // builder.seal_block(next_block);
-            }
+                let attr = builder.ins().bor_imm(attr, i64::from(memref::SUB_OBJ_FLAG)); // sub-obj
+                make_memref([addr, narrow_base, narrow_upper, attr], builder, state)
+            } else {
+                mem_ref
+            };
             state.push1(mem_ref);
         }
         Operator::MemrefMSStore { memarg } => {
-            // let mut mem_arg = memarg.clone();
-            let val = optionally_bitcast_vector(state.pop1(), I32X4, builder);
-            let mem_ref = optionally_bitcast_vector(state.pop1(), I32X4, builder);
-            let addr = builder.ins().extractlane(val, 0);
-            translate_msstore(mem_ref, memarg, ir::Opcode::Store, addr, builder, state, environ)?;
-            // val's metadata
-            let base = builder.ins().extractlane(val, 1);
-            let end = builder.ins().extractlane(val, 2);
-            let attr = builder.ins().extractlane(val, 3);
-            // size = size | (attr << 24)
-            // let size = builder.ins().isub(end, base);
-            // let attr = builder.ins().ishl_imm(attr, 24i64);
-            // let size = builder.ins().band_imm(size, 0xffffff);
-            // let size = builder.ins().bor(size, attr);
-
-            // let cmpxxx = builder.ins().icmp_imm(IntCC::Equal, size, 0x20000008i64);
-            // builder.ins().trapnz(cmpxxx, TrapCode::UnreachableCodeReached);
-            // store metadata
-            if let Some(funcIdx) = environ.host_set_value_func_index() {
-                // let metadata = builder.ins().uextend(I64, size);
-                // let new_base = builder.ins().uextend(I64, base);
-                // let new_base = builder.ins().ishl_imm(new_base, 32i64);
-                // let metadata = builder.ins().bor(metadata, new_base);
-                let imm_val = builder.ins().iconst(I32, memarg.metadata as i64);
-                let (fref, num_args) = state.get_direct_func(builder.func, funcIdx, environ)?;
-                let args :&mut[Value] = &mut[addr, base, end, attr, imm_val];
-                bitcast_wasm_params(
-                    environ,
-                    builder.func.dfg.ext_funcs[fref].signature,
-                    args,
-                    builder,
-                );
-                let call = environ.translate_call(
-                    builder.cursor(),
-                    FuncIndex::from_u32(funcIdx),
-                    fref,
-                    args,
-                )?;
-                let inst_results = builder.inst_results(call);
-                debug_assert_eq!(
-                    inst_results.len(),
-                    builder.func.dfg.signatures[builder.func.dfg.ext_funcs[fref].signature]
-                        .returns
-                        .len(),
-                    "translate_call results should match the call signature"
-                );
+            let val = state.pop1();
+            let mem_ref = state.pop1();
+            let [addr, base, end, attr] = memref_lanes(val, builder, state);
+            translate_msstore(
+                mem_ref,
+                memarg,
+                ir::Opcode::Store,
+                addr,
+                builder,
+                state,
+                environ,
+            )?;
+            if !state.reachable {
+                return Ok(());
             }
+            // store val's metadata
+            translate_memref_set_metadata(
+                memarg.memory,
+                addr,
+                base,
+                end,
+                attr,
+                memarg.metadata as u32,
+                builder,
+                state,
+                environ,
+            )?;
         }
         Operator::I32MSStore { memarg }
         | Operator::I64MSStore { memarg }
@@ -374,22 +340,54 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
         | Operator::F64MSStore { memarg } => {
             let val = state.pop1();
             let mem_ref = state.pop1();
-            translate_msstore(mem_ref, memarg, ir::Opcode::Store, val, builder, state, environ)?;
+            translate_msstore(
+                mem_ref,
+                memarg,
+                ir::Opcode::Store,
+                val,
+                builder,
+                state,
+                environ,
+            )?;
         }
         Operator::I32MSStore8 { memarg } | Operator::I64MSStore8 { memarg } => {
             let val = state.pop1();
             let mem_ref = state.pop1();
-            translate_msstore(mem_ref, memarg, ir::Opcode::Istore8, val, builder, state, environ)?;
+            translate_msstore(
+                mem_ref,
+                memarg,
+                ir::Opcode::Istore8,
+                val,
+                builder,
+                state,
+                environ,
+            )?;
         }
         Operator::I32MSStore16 { memarg } | Operator::I64MSStore16 { memarg } => {
             let val = state.pop1();
             let mem_ref = state.pop1();
-            translate_msstore(mem_ref, memarg, ir::Opcode::Istore16, val, builder, state, environ)?;
+            translate_msstore(
+                mem_ref,
+                memarg,
+                ir::Opcode::Istore16,
+                val,
+                builder,
+                state,
+                environ,
+            )?;
         }
         Operator::I64MSStore32 { memarg } => {
             let val = state.pop1();
             let mem_ref = state.pop1();
-            translate_msstore(mem_ref, memarg, ir::Opcode::Istore32, val, builder, state, environ)?;
+            translate_msstore(
+                mem_ref,
+                memarg,
+                ir::Opcode::Istore32,
+                val,
+                builder,
+                state,
+                environ,
+            )?;
         }
         Operator::MemrefMSLoad { memarg } => {
             // opcode is not used
@@ -453,7 +451,12 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
             // Ensure SIMD values are cast to their default Cranelift type, I8x16.
             let ty = builder.func.dfg.value_type(val);
             if ty.is_vector() {
+                let lanes = state.memref_lanes(val);
                 val = optionally_bitcast_vector(val, I8X16, builder);
+                // keep memrefs scalar when read back from the local within the same block
+                if let Some(lanes) = lanes {
+                    state.set_memref_lanes(val, lanes);
+                }
             }
 
             builder.def_var(Variable::from_u32(*local_index), val);
@@ -466,7 +469,12 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
             // Ensure SIMD values are cast to their default Cranelift type, I8x16.
             let ty = builder.func.dfg.value_type(val);
             if ty.is_vector() {
+                let lanes = state.memref_lanes(val);
                 val = optionally_bitcast_vector(val, I8X16, builder);
+                // keep memrefs scalar when read back from the local within the same block
+                if let Some(lanes) = lanes {
+                    state.set_memref_lanes(val, lanes);
+                }
             }
 
             builder.def_var(Variable::from_u32(*local_index), val);
@@ -489,15 +497,9 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
                     // memref: addr-0, base-1, size-2, attr-3
                     if ty.is_vector() {
                         optionally_bitcast_vector(val, I32X4, builder)
-                        // let (hi, lo) = builder.ins().vsplit(val);
-                        // let (base, size) = builder.ins().isplit(hi);
-                        // let (attr, addr) = builder.ins().isplit(lo);
-                        // let mem_ref = builder.ins().splat(I32X4, attr);
-                        // let mem_ref = builder.ins().insertlane(mem_ref, base, 1);
-                        // let mem_ref = builder.ins().insertlane(mem_ref, size, 2);
-                        // let mem_ref = builder.ins().insertlane(mem_ref, addr, 0);
-                        // mem_ref
-                    } else { val }
+                    } else {
+                        val
+                    }
                 }
                 GlobalVariable::Custom => environ.translate_custom_global_get(
                     builder.cursor(),
@@ -583,13 +585,20 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
             let (params, results) = blocktype_params_results(validator, *blockty)?;
             let next = block_with_params(builder, results.clone(), environ)?;
             state.push_block(next, params.len(), results.len());
+            state.enter_memref_scope(false);
         }
         Operator::Loop { blockty } => {
             let (params, results) = blocktype_params_results(validator, *blockty)?;
             let loop_body = block_with_params(builder, params.clone(), environ)?;
             let next = block_with_params(builder, results.clone(), environ)?;
-            canonicalise_then_jump(builder, loop_body, state.peekn(params.len()));
+            let preheader_jump =
+                canonicalise_then_jump(builder, loop_body, state.peekn(params.len()));
             state.push_loop(loop_body, next, params.len(), results.len());
+            // later iterations may follow a call or deallocation within the loop, which frees the
+            // allocations earlier checks covered
+            let barrier = memref_temporal_safety(validator, builder, state, environ)?;
+            state.enter_memref_scope(barrier);
+            state.enter_memref_loop(preheader_jump, loop_body);
 
             // Pop the initial `Block` actuals and replace them with the `Block`'s
             // params since control flow joins at the top of the loop.
@@ -644,8 +653,10 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
                 results.len(),
                 *blockty,
             );
+            state.enter_memref_scope(false);
         }
         Operator::Else => {
+            state.reset_memref_scope();
             let i = state.control_stack.len() - 1;
             match state.control_stack[i] {
                 ControlStackFrame::If {
@@ -732,10 +743,13 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
 
             builder.switch_to_block(next_block);
             builder.seal_block(next_block);
+            state.exit_memref_scope();
 
             // If it is a loop we also have to seal the body loop block
             if let ControlStackFrame::Loop { header, .. } = frame {
-                builder.seal_block(header)
+                builder.seal_block(header);
+                let (lp, checks) = state.exit_memref_loop(header);
+                memref_loops::hoist_memref_loop_checks(lp, checks, builder, state, environ)?;
             }
 
             frame.truncate_value_stack_to_original_size(&mut state.stack);
@@ -925,6 +939,7 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
             );
             state.popn(num_args);
             state.pushn(inst_results);
+            forget_memref_checks_after_call(validator, builder, state, environ)?;
         }
         Operator::CallIndirect {
             type_index,
@@ -959,6 +974,7 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
             );
             state.popn(num_args);
             state.pushn(inst_results);
+            forget_memref_checks_after_call(validator, builder, state, environ)?;
         }
         /******************************* Memory management ***********************************
          * Memory management is handled by environment. It is usually translated into calls to
@@ -2428,11 +2444,14 @@ fn translate_unreachable_operator<FE: FuncEnvironment + ?Sized>(
                 0,
                 blockty,
             );
+            state.enter_memref_scope(false);
         }
         Operator::Loop { blockty: _ } | Operator::Block { blockty: _ } => {
             state.push_block(ir::Block::reserved_value(), 0, 0);
+            state.enter_memref_scope(false);
         }
         Operator::Else => {
+            state.reset_memref_scope();
             let i = state.control_stack.len() - 1;
             match state.control_stack[i] {
                 ControlStackFrame::If {
@@ -2481,17 +2500,18 @@ fn translate_unreachable_operator<FE: FuncEnvironment + ?Sized>(
             }
         }
         Operator::End => {
-            let stack = &mut state.stack;
-            let control_stack = &mut state.control_stack;
-            let frame = control_stack.pop().unwrap();
+            let frame = state.control_stack.pop().unwrap();
+            state.exit_memref_scope();
 
             // Pop unused parameters from stack.
-            frame.truncate_value_stack_to_original_size(stack);
+            frame.truncate_value_stack_to_original_size(&mut state.stack);
 
             let reachable_anyway = match frame {
                 // If it is a loop we also have to seal the body loop block
                 ControlStackFrame::Loop { header, .. } => {
                     builder.seal_block(header);
+                    let (lp, checks) = state.exit_memref_loop(header);
+                    memref_loops::hoist_memref_loop_checks(lp, checks, builder, state, environ)?;
                     // And loops can't have branches to the end.
                     false
                 }
@@ -2523,7 +2543,9 @@ fn translate_unreachable_operator<FE: FuncEnvironment + ?Sized>(
 
                 // And add the return values of the block but only if the next block is reachable
                 // (which corresponds to testing if the stack depth is 1)
-                stack.extend_from_slice(builder.block_params(frame.following_code()));
+                state
+                    .stack
+                    .extend_from_slice(builder.block_params(frame.following_code()));
                 state.reachable = true;
             }
         }
@@ -2714,63 +2736,135 @@ fn prepare_ms_addr<FE>(
     environ: &mut FE,
     no_check: bool,
 ) -> WasmResult<Option<(MemFlags, Value)>>
-    where
-        FE: FuncEnvironment + ?Sized,
+where
+    FE: FuncEnvironment + ?Sized,
 {
+    // the attributes of a memref only have room for the index of one of the first memories
+    if memarg.memory > memref::MEMORY_INDEX_MASK {
+        return Err(wasm_unsupported!(
+            "memref access to memory {}, memrefs may only point into the first {} memories",
+            memarg.memory,
+            memref::MEMORY_INDEX_MASK + 1
+        ));
+    }
     if !builder.func.dfg.value_type(mem_ref).is_vector() {
         return Ok(None);
     }
-    let mem_ref = optionally_bitcast_vector(mem_ref, I32X4, builder);
     // memref: addr-0, base-1, size-2, attr-3
-    let addr = builder.ins().extractlane(mem_ref, 0);
-    let base = builder.ins().extractlane(mem_ref, 1);
-    let end = builder.ins().extractlane(mem_ref, 2);
-    let attr = builder.ins().extractlane(mem_ref, 3);
-
-    let addr_base = if memarg.offset != 0 {
-        builder.ins().iadd_imm(addr, memarg.offset as i64)
-    }else { addr };
+    let [addr, base, end, attr] = memref_lanes(mem_ref, builder, state);
+
+    // memref addresses are 32 bits wide, even within 64-bit memories
+    if memarg.offset > u64::from(u32::MAX) {
+        builder.ins().trap(ir::TrapCode::HeapOutOfBounds);
+        return Ok(None);
+    }
     let heap = state.get_heap(builder.func, memarg.memory, environ)?;
     let heap = environ.heaps()[heap].clone();
 
-    // msload/msstore flag
-    let no_check_flag:u8    = 0x01; // 0000 0001
-    let lower_check_flag:u8 = 0x02; // 0000 0010
-    let upper_check_flag:u8 = 0x04; // 0000 0100
-
-    let addr_upper = builder.ins().iadd_imm(addr_base, i64::from(access_size as i32));
-    if !no_check && (memarg.metadata & no_check_flag) == 0 { // not store only check and doesn't has no check flag
+    // msload/msstore flags
+    let metadata = memarg.metadata as u32;
+    let upper_only = (metadata & memref::UPPER_CHECK_FLAG) != 0
+        || (!environ.memref_check_policy().checks_lower_bound()
+            && (metadata & memref::LOWER_CHECK_FLAG) == 0);
+    let lower_only = !upper_only && (metadata & memref::LOWER_CHECK_FLAG) != 0;
+    let check = MemRefCheck {
+        mem_ref,
+        lanes: [addr, base, end, attr],
+        memory: memarg.memory,
+        lower: if upper_only {
+            None
+        } else {
+            Some(memarg.offset)
+        },
+        upper: if lower_only {
+            None
+        } else {
+            Some(memarg.offset + u64::from(access_size))
+        },
+    };
+    let checked = !no_check && (metadata & memref::NO_CHECK_FLAG) == 0;
+    if checked && state.memref_check_covers(&check) {
+        // a dominating check of the same memref already covered this access
+        state.memref_check_elided();
+    } else if checked {
+        // not store only check and doesn't has no check flag
+        state.memref_check_emitted();
         // new block
         let next = block_with_params(builder, std::iter::empty::<ValType>(), environ)?;
         state.push_block(next, 0, 0);
 
         // check
-        let has_metadata = builder.ins().band_imm(attr, 0x20i64); // true if has_metadata
-        // let has_metadata = builder.ins().icmp_imm(IntCC::Equal, has_metadata, 0x20i64);
+        let has_metadata = builder
+            .ins()
+            .band_imm(attr, i64::from(memref::HAS_METADATA_FLAG));
         let no_metadata = builder.ins().icmp_imm(IntCC::Equal, has_metadata, 0);
 
-        // translate_br_if(0, builder, state); break if no metadata
+        // break if no metadata
         let (br_destination, inputs) = translate_br_if_args(0, state);
-        canonicalise_then_brnz(builder, no_metadata, br_destination, inputs);
+        let branch = canonicalise_then_brnz(builder, no_metadata, br_destination, inputs);
+        state.record_memref_loop_check(check, branch);
         let next_block = builder.create_block();
         canonicalise_then_jump(builder, next_block, &[]);
         builder.seal_block(next_block); // The only predecessor is the current block.
         builder.switch_to_block(next_block);
+        environ.translate_memref_count(builder.cursor(), memref::MemRefCounter::BoundsChecks)?;
+
+        // the accessed range is computed in 64 bits, so that neither the offset nor the access
+        // size wrap around the 32-bit address space
+        let addr_base = builder.ins().uextend(I64, addr);
+        let addr_base = if memarg.offset != 0 {
+            builder.ins().iadd_imm(addr_base, memarg.offset as i64)
+        } else {
+            addr_base
+        };
+        let addr_upper = builder.ins().iadd_imm(addr_base, i64::from(access_size));
+        let base_wide = builder.ins().uextend(I64, base);
+        let end_wide = builder.ins().uextend(I64, end);
 
         // try to touch memory [addr_base...addr_upper]
-        // can touch memory [base...upper]
-        // let upper = builder.ins().iadd(base, size);
-        let may_trap = if (memarg.metadata & upper_check_flag) != 0 || (environ.upper_check_only() && (memarg.metadata & lower_check_flag) == 0){ // only do upper check
-            builder.ins().icmp(IntCC::UnsignedGreaterThan, addr_upper, end)
-        } else if (memarg.metadata & lower_check_flag) != 0 { // only do lower check
-            builder.ins().icmp(IntCC::UnsignedGreaterThan, base, addr_base)
-        }  else {
-            let cmp_upper_trap = builder.ins().icmp(IntCC::UnsignedGreaterThan, addr_upper, end);
-            let cmp_base_trap = builder.ins().icmp(IntCC::UnsignedGreaterThan, base, addr_base);
+        // can touch memory [base...end]
+        let may_trap = if upper_only {
+            // only do upper check
+            builder
+                .ins()
+                .icmp(IntCC::UnsignedGreaterThan, addr_upper, end_wide)
+        } else if lower_only {
+            // only do lower check
+            builder
+                .ins()
+                .icmp(IntCC::UnsignedGreaterThan, base_wide, addr_base)
+        } else {
+            let cmp_upper_trap =
+                builder
+                    .ins()
+                    .icmp(IntCC::UnsignedGreaterThan, addr_upper, end_wide);
+            let cmp_base_trap =
+                builder
+                    .ins()
+                    .icmp(IntCC::UnsignedGreaterThan, base_wide, addr_base);
             builder.ins().bor(cmp_upper_trap, cmp_base_trap)
         };
-        // let may_trap = builder.ins().band(has_metadata, may_trap);
-        builder.ins().trapnz(may_trap, ir::TrapCode::HeapOutOfBounds);
+        // the memref must also point into the memory being accessed
+        let memory = memref_memory_index(attr, builder);
+        let wrong_memory =
+            builder
+                .ins()
+                .icmp_imm(IntCC::NotEqual, memory, i64::from(memarg.memory));
+        let may_trap = builder.ins().bor(may_trap, wrong_memory);
+        let report_addr = builder.ins().ireduce(I32, addr_base);
+        translate_memref_bounds_check(may_trap, report_addr, base, end, builder, environ)?;
+
+        // the allocation must still be live
+        if let Some(locks) = state.get_memref_locks(builder.func, memarg.memory, environ)? {
+            translate_memref_key_check(
+                locks,
+                attr,
+                None,
+                ir::TrapCode::MemRefUseAfterFree,
+                builder,
+                environ,
+            );
+        }
 
         // end block
         let frame = state.control_stack.pop().unwrap();
@@ -2778,18 +2872,28 @@ fn prepare_ms_addr<FE>(
         canonicalise_then_jump(builder, next_block, &[]);
         builder.switch_to_block(next_block);
         builder.seal_block(next_block);
-    } else {
-        bounds_check_only(builder, environ, &heap, addr_upper)?;
+        // an audited check lets failing accesses through, so it proves nothing
+        if !environ.memref_audit() {
+            state.record_memref_check(check);
+        }
     }
 
-
-
-    let addr = bounds_checks::compute_addr_with_no_bounds_check(
+    // the memref check only proves that the access stays within its object, which may itself
+    // extend past the end of the memory, so the access is still checked against the heap
+    // unless its guard pages already catch it
+    let index = if heap.index_type == I64 {
+        // 64-bit memories are indexed by the zero-extended memref address
+        builder.ins().uextend(I64, addr)
+    } else {
+        addr
+    };
+    let addr = bounds_checks::bounds_check_and_compute_addr(
         builder,
         environ,
         &heap,
-        addr_base,
-        // access_size,
+        index,
+        memarg.offset as u32,
+        access_size,
     )?;
 
     let addr = match addr {
@@ -2885,11 +2989,11 @@ fn translate_msload<FE: FuncEnvironment + ?Sized>(
     builder: &mut FunctionBuilder,
     state: &mut FuncTranslationState,
     environ: &mut FE,
-) -> WasmResult<()>
-{
+) -> WasmResult<()> {
     let mem_ref = state.pop1();
     if result_ty != I32X4 {
-        let val = translate_msload_helper(mem_ref, memarg, opcode, result_ty, builder, state, environ)?;
+        let val =
+            translate_msload_helper(mem_ref, memarg, opcode, result_ty, builder, state, environ)?;
         match val {
             None => state.reachable = false,
             Some(v) => {
@@ -2900,8 +3004,15 @@ fn translate_msload<FE: FuncEnvironment + ?Sized>(
             return Ok(());
         }
     } else {
-        // let mut memArg = memarg.clone();
-        let addr = match translate_msload_helper(mem_ref, memarg, ir::Opcode::Load, I32, builder, state, environ)? {
+        let addr = match translate_msload_helper(
+            mem_ref,
+            memarg,
+            ir::Opcode::Load,
+            I32,
+            builder,
+            state,
+            environ,
+        )? {
             Some(v) => v,
             None => {
                 state.reachable = false;
@@ -2909,59 +3020,20 @@ fn translate_msload<FE: FuncEnvironment + ?Sized>(
             }
         };
         // load metadata
-        match environ.host_get_value_func_index() {
+        match translate_memref_get_metadata(memarg.memory, addr, builder, state, environ)? {
             // has metadata
-            Some(funcIdx) => {
-                // let metadata = builder.ins().iconcat(base, size);
-                let (fref, num_args) = state.get_direct_func(builder.func, funcIdx, environ)?;
-                let args: &mut [Value] = &mut [addr];
-                bitcast_wasm_params(
-                    environ,
-                    builder.func.dfg.ext_funcs[fref].signature,
-                    args,
-                    builder,
-                );
-                let call = environ.translate_call(
-                    builder.cursor(),
-                    FuncIndex::from_u32(funcIdx),
-                    fref,
-                    args,
-                )?;
-                let inst_results = builder.inst_results(call);
-                debug_assert_eq!(
-                    inst_results.len(),
-                    builder.func.dfg.signatures[builder.func.dfg.ext_funcs[fref].signature]
-                        .returns
-                        .len(),
-                    "translate_call results should match the call signature"
-                );
-                let metadata = if let Some(res) = inst_results.get(0) {
-                    *res
-                } else {
-                    state.reachable = false;
-                    return Ok(());
-                };
-
-                let size_attr = builder.ins().ireduce(I32, metadata);
-                let base = builder.ins().ushr_imm(metadata, 32i64);
-                let base = builder.ins().ireduce(I32, base);
-                // attr = (size&0xff000000) >>24, size = size & 0xffffff
-                let attr = builder.ins().band_imm(size_attr, 0xff000000i64);//TODO:
-                let attr = builder.ins().ushr_imm(attr, 24i64);
-                let size = builder.ins().band_imm(size_attr, 0x00ffffffi64);
-                let end = builder.ins().iadd(base, size);
+            Some((base, end, attr)) => {
                 // restore the value
-                let mem_ref = builder.ins().splat(I32X4, addr);
-                let mem_ref = builder.ins().insertlane(mem_ref, base, 1);
-                let mem_ref = builder.ins().insertlane(mem_ref, end, 2);
-                let mem_ref = builder.ins().insertlane(mem_ref, attr, 3);
+                let mem_ref = make_memref([addr, base, end, attr], builder, state);
                 state.push1(mem_ref);
             }
             None => {
+                if !state.reachable {
+                    return Ok(());
+                }
                 // no metadata
                 let attr = builder.ins().iconst(I32, 0x00i64);
-                let mem_ref = builder.ins().splat(I32X4, attr);
-                let mem_ref = builder.ins().insertlane(mem_ref, addr, 0);
+                let mem_ref = make_memref([addr, attr, attr, attr], builder, state);
                 state.push1(mem_ref);
             }
         };
@@ -2969,6 +3041,610 @@ fn translate_msload<FE: FuncEnvironment + ?Sized>(
     return Ok(());
 }
 
+/// Returns the `[addr, base, end, attr]` lanes of the memref `mem_ref`.
+///
+/// Memrefs built by `make_memref` are taken apart for free, so memrefs are only extracted from
+/// their vector after crossing a call, global, memory or block boundary.
+fn memref_lanes(
+    mem_ref: Value,
+    builder: &mut FunctionBuilder,
+    state: &FuncTranslationState,
+) -> [Value; 4] {
+    if let Some(lanes) = state.memref_lanes(mem_ref) {
+        return lanes;
+    }
+    // The extracted lanes aren't recorded: they only dominate the uses of `mem_ref` that follow
+    // this one in the current block.
+    let mem_ref = optionally_bitcast_vector(mem_ref, I32X4, builder);
+    [
+        memref::ADDR_LANE,
+        memref::BASE_LANE,
+        memref::END_LANE,
+        memref::ATTR_LANE,
+    ]
+    .map(|lane| builder.ins().extractlane(mem_ref, lane))
+}
+
+/// Builds a memref vector out of its `[addr, base, end, attr]` lanes.
+///
+/// The lanes are recorded in `state` so that memref operations on the result use them directly.
+/// The vector itself is removed as dead code if the memref is only ever used through its lanes.
+fn make_memref(
+    lanes: [Value; 4],
+    builder: &mut FunctionBuilder,
+    state: &mut FuncTranslationState,
+) -> Value {
+    let [addr, base, end, attr] = lanes;
+    let mem_ref = builder.ins().splat(I32X4, addr);
+    let mem_ref = builder.ins().insertlane(mem_ref, base, memref::BASE_LANE);
+    let mem_ref = builder.ins().insertlane(mem_ref, end, memref::END_LANE);
+    let mem_ref = builder.ins().insertlane(mem_ref, attr, memref::ATTR_LANE);
+    state.set_memref_lanes(mem_ref, lanes);
+    mem_ref
+}
+
+/// Branch to a cold block reporting a memref bounds violation if `is_trap` is set.
+///
+/// `addr` is the offending address and `base` and `end` are the bounds of the memref's object.
+fn translate_memref_bounds_check<FE: FuncEnvironment + ?Sized>(
+    is_trap: Value,
+    addr: Value,
+    base: Value,
+    end: Value,
+    builder: &mut FunctionBuilder,
+    environ: &mut FE,
+) -> WasmResult<()> {
+    let trap_block = builder.create_block();
+    let continuation = builder.create_block();
+    builder.set_cold_block(trap_block);
+    builder.ins().brnz(is_trap, trap_block, &[]);
+    builder.ins().jump(continuation, &[]);
+    builder.seal_block(trap_block);
+
+    builder.switch_to_block(trap_block);
+    environ.translate_memref_out_of_bounds(builder.cursor(), addr, base, end)?;
+    if environ.memref_audit() {
+        // the violation was only recorded, carry on as if the check passed; accesses are still
+        // checked against the heap, so audited code can't reach outside of linear memory
+        builder.ins().jump(continuation, &[]);
+    }
+    builder.seal_block(continuation);
+
+    builder.switch_to_block(continuation);
+    Ok(())
+}
+
+/// Trap if an object allocated by `memref.alloc` in linear memory `memory` would extend past
+/// `end`, beyond the current size of the memory.
+///
+/// Memref bounds are kept exactly for any object which fits, so this is the only limit on the
+/// size of an allocation.
+fn translate_memref_alloc_size_check<FE: FuncEnvironment + ?Sized>(
+    memory: u32,
+    end: Value,
+    builder: &mut FunctionBuilder,
+    state: &mut FuncTranslationState,
+    environ: &mut FE,
+) -> WasmResult<()> {
+    let memory_size = translate_memory_byte_size(memory, builder, state, environ)?;
+    let end = builder.ins().uextend(I64, end);
+    let oob = builder
+        .ins()
+        .icmp(IntCC::UnsignedGreaterThan, end, memory_size);
+    builder
+        .ins()
+        .trapnz(oob, ir::TrapCode::MemRefAllocOutOfBounds);
+    Ok(())
+}
+
+/// Returns the current size, in bytes, of linear memory `memory` as an `I64`.
+fn translate_memory_byte_size<FE: FuncEnvironment + ?Sized>(
+    memory: u32,
+    builder: &mut FunctionBuilder,
+    state: &mut FuncTranslationState,
+    environ: &mut FE,
+) -> WasmResult<Value> {
+    let heap = state.get_heap(builder.func, memory, environ)?;
+    let pages =
+        environ.translate_memory_size(builder.cursor(), MemoryIndex::from_u32(memory), heap)?;
+    let pages = if builder.func.dfg.value_type(pages) == I64 {
+        pages
+    } else {
+        builder.ins().uextend(I64, pages)
+    };
+    // wasm pages are 64KiB
+    Ok(builder.ins().ishl_imm(pages, 16))
+}
+
+/// Whether temporal safety is enabled for any memory, so that calls and deallocations may free
+/// the allocations covered by earlier memref checks.
+fn memref_temporal_safety<FE: FuncEnvironment + ?Sized>(
+    validator: &FuncValidator<impl WasmModuleResources>,
+    builder: &mut FunctionBuilder,
+    state: &mut FuncTranslationState,
+    environ: &mut FE,
+) -> WasmResult<bool> {
+    let mut memory = 0;
+    while memory <= memref::MEMORY_INDEX_MASK && validator.resources().memory_at(memory).is_some() {
+        if state
+            .get_memref_locks(builder.func, memory, environ)?
+            .is_some()
+        {
+            return Ok(true);
+        }
+        memory += 1;
+    }
+    Ok(false)
+}
+
+/// Forget the memref checks made before a call if temporal safety is enabled for any memory,
+/// since the callee may deallocate the memrefs they covered.
+fn forget_memref_checks_after_call<FE: FuncEnvironment + ?Sized>(
+    validator: &FuncValidator<impl WasmModuleResources>,
+    builder: &mut FunctionBuilder,
+    state: &mut FuncTranslationState,
+    environ: &mut FE,
+) -> WasmResult<()> {
+    if memref_temporal_safety(validator, builder, state, environ)? {
+        state.clear_memref_checks();
+    }
+    Ok(())
+}
+
+/// Returns the address of the entry for lock table slot `slot` within the memref lock table
+/// `locks`.
+fn memref_lock_entry(
+    locks: ir::GlobalValue,
+    slot: Value,
+    pos: &mut FuncCursor,
+    pointer_type: ir::Type,
+) -> Value {
+    let locks_base = pos.ins().global_value(pointer_type, locks);
+    let index = pos.ins().uextend(pointer_type, slot);
+    let offset = pos
+        .ins()
+        .ishl_imm(index, i64::from(memref::LOCK_ENTRY_SIZE.trailing_zeros()));
+    let entries = pos
+        .ins()
+        .iadd_imm(locks_base, i64::from(memref::LOCK_HEADER_SIZE));
+    pos.ins().iadd(entries, offset)
+}
+
+/// Hand out the key of a new allocation from the memref lock table `locks` and mark it live.
+///
+/// The key is the one the first slot of the queue of released slots was left with, or the
+/// first generation of a slot which was never handed out if the queue is empty. This traps
+/// once all `memref::ALLOC_SLOT_MASK` slots hold live allocations.
+fn translate_memref_new_key<FE: FuncEnvironment + ?Sized>(
+    locks: ir::GlobalValue,
+    builder: &mut FunctionBuilder,
+    environ: &mut FE,
+) -> Value {
+    let pointer_type = environ.pointer_type();
+    let flags = MemFlags::trusted();
+    let header = builder.ins().global_value(pointer_type, locks);
+    let fresh = builder
+        .ins()
+        .load(I32, flags, header, memref::LOCK_FRESH_OFFSET);
+    let head = builder
+        .ins()
+        .load(I32, flags, header, memref::LOCK_HEAD_OFFSET);
+    let tail = builder
+        .ins()
+        .load(I32, flags, header, memref::LOCK_TAIL_OFFSET);
+
+    let no_released = builder.ins().icmp_imm(IntCC::Equal, head, 0);
+    let no_fresh = builder
+        .ins()
+        .icmp_imm(IntCC::Equal, fresh, i64::from(memref::ALLOC_SLOT_MASK));
+    let exhausted = builder.ins().band(no_released, no_fresh);
+    builder
+        .ins()
+        .trapnz(exhausted, ir::TrapCode::MemRefKeysExhausted);
+
+    // take the first released slot, or the next fresh one
+    let fresh_slot = builder.ins().iadd_imm(fresh, 1);
+    let slot = builder.ins().select(head, head, fresh_slot);
+    let fresh = builder.ins().select(head, fresh, fresh_slot);
+    let entry = memref_lock_entry(locks, slot, &mut builder.cursor(), pointer_type);
+    let state = builder
+        .ins()
+        .load(I32, flags, entry, memref::LOCK_STATE_OFFSET);
+    let next = builder
+        .ins()
+        .load(I32, flags, entry, memref::LOCK_NEXT_OFFSET);
+    let head = builder.ins().select(head, next, head);
+    let zero = builder.ins().iconst(I32, 0);
+    let tail = builder.ins().select(head, tail, zero);
+    builder
+        .ins()
+        .store(flags, fresh, header, memref::LOCK_FRESH_OFFSET);
+    builder
+        .ins()
+        .store(flags, head, header, memref::LOCK_HEAD_OFFSET);
+    builder
+        .ins()
+        .store(flags, tail, header, memref::LOCK_TAIL_OFFSET);
+
+    // released slots hold the key to hand out next and fresh ones hold zero
+    let key = builder.ins().bor(state, slot);
+    let live = builder
+        .ins()
+        .bor_imm(key, i64::from(memref::LOCK_LIVE_FLAG));
+    builder
+        .ins()
+        .store(flags, live, entry, memref::LOCK_STATE_OFFSET);
+    key
+}
+
+/// Returns the address of the entry of the key's slot in the memref lock table `locks` for a
+/// memref with attributes `attr`, the key, whether the memref carries a key at all and whether
+/// the allocation with that key is no longer live.
+fn memref_key_freed(
+    locks: ir::GlobalValue,
+    attr: Value,
+    pos: &mut FuncCursor,
+    pointer_type: ir::Type,
+) -> (Value, Value, Value, Value) {
+    let key = pos.ins().ushr_imm(attr, i64::from(memref::ALLOC_KEY_SHIFT));
+    let slot = pos.ins().band_imm(key, i64::from(memref::ALLOC_SLOT_MASK));
+    let entry = memref_lock_entry(locks, slot, pos, pointer_type);
+    let state = pos
+        .ins()
+        .load(I32, MemFlags::trusted(), entry, memref::LOCK_STATE_OFFSET);
+    // the slot is live with this very key, and not a later generation of it
+    let live = pos.ins().bor_imm(key, i64::from(memref::LOCK_LIVE_FLAG));
+    let keyed = pos.ins().icmp_imm(IntCC::NotEqual, key, 0);
+    let freed = pos.ins().icmp(IntCC::NotEqual, state, live);
+    (entry, key, keyed, freed)
+}
+
+/// Trap with `code` if a memref with attributes `attr` carries the key of an allocation which
+/// is no longer live according to the memref lock table `locks`. If `guard` is given, only
+/// trap if it's also set.
+///
+/// Returns the address of the entry of the key's slot in the lock table, the key and whether
+/// the memref carries a key at all.
+fn translate_memref_key_check<FE: FuncEnvironment + ?Sized>(
+    locks: ir::GlobalValue,
+    attr: Value,
+    guard: Option<Value>,
+    code: ir::TrapCode,
+    builder: &mut FunctionBuilder,
+    environ: &mut FE,
+) -> (Value, Value, Value) {
+    let (entry, key, keyed, freed) =
+        memref_key_freed(locks, attr, &mut builder.cursor(), environ.pointer_type());
+    let mut is_trap = builder.ins().band(keyed, freed);
+    if let Some(guard) = guard {
+        is_trap = builder.ins().band(is_trap, guard);
+    }
+    builder.ins().trapnz(is_trap, code);
+    (entry, key, keyed)
+}
+
+/// If `release` is set, release the live allocation with key `key`, whose slot has the entry
+/// `entry` in the memref lock table `locks`: the slot is left with the key of its next
+/// generation and queued for reuse.
+///
+/// The lock table is updated without branching, by storing back the values it already held
+/// when `release` isn't set.
+fn translate_memref_release_key<FE: FuncEnvironment + ?Sized>(
+    locks: ir::GlobalValue,
+    entry: Value,
+    key: Value,
+    release: Value,
+    builder: &mut FunctionBuilder,
+    environ: &mut FE,
+) {
+    let pointer_type = environ.pointer_type();
+    let flags = MemFlags::trusted();
+    let header = builder.ins().global_value(pointer_type, locks);
+    let head = builder
+        .ins()
+        .load(I32, flags, header, memref::LOCK_HEAD_OFFSET);
+    let tail = builder
+        .ins()
+        .load(I32, flags, header, memref::LOCK_TAIL_OFFSET);
+    let state = builder
+        .ins()
+        .load(I32, flags, entry, memref::LOCK_STATE_OFFSET);
+    let next = builder
+        .ins()
+        .load(I32, flags, entry, memref::LOCK_NEXT_OFFSET);
+    // when the queue is empty this is the entry of slot 0, which is never handed out
+    let tail_entry = memref_lock_entry(locks, tail, &mut builder.cursor(), pointer_type);
+    let tail_next = builder
+        .ins()
+        .load(I32, flags, tail_entry, memref::LOCK_NEXT_OFFSET);
+
+    let slot = builder
+        .ins()
+        .band_imm(key, i64::from(memref::ALLOC_SLOT_MASK));
+    let next_key = builder
+        .ins()
+        .iadd_imm(key, i64::from(memref::ALLOC_GENERATION_STEP));
+    let next_key = builder
+        .ins()
+        .band_imm(next_key, i64::from(memref::ALLOC_KEY_MASK));
+    let zero = builder.ins().iconst(I32, 0);
+    let state = builder.ins().select(release, next_key, state);
+    let next = builder.ins().select(release, zero, next);
+    let tail_next = builder.ins().select(release, slot, tail_next);
+    let new_head = builder.ins().select(head, head, slot);
+    let head = builder.ins().select(release, new_head, head);
+    let tail = builder.ins().select(release, slot, tail);
+    builder
+        .ins()
+        .store(flags, state, entry, memref::LOCK_STATE_OFFSET);
+    builder
+        .ins()
+        .store(flags, next, entry, memref::LOCK_NEXT_OFFSET);
+    builder
+        .ins()
+        .store(flags, tail_next, tail_entry, memref::LOCK_NEXT_OFFSET);
+    builder
+        .ins()
+        .store(flags, head, header, memref::LOCK_HEAD_OFFSET);
+    builder
+        .ins()
+        .store(flags, tail, header, memref::LOCK_TAIL_OFFSET);
+}
+
+/// Returns the index of the linear memory a memref with attributes `attr` points into.
+fn memref_memory_index(attr: Value, builder: &mut FunctionBuilder) -> Value {
+    let memory = builder
+        .ins()
+        .ushr_imm(attr, i64::from(memref::MEMORY_INDEX_SHIFT));
+    builder
+        .ins()
+        .band_imm(memory, i64::from(memref::MEMORY_INDEX_MASK))
+}
+
+/// Returns the address of the record for `addr` within the memref shadow region `shadow`.
+fn memref_shadow_record<FE: FuncEnvironment + ?Sized>(
+    shadow: ir::GlobalValue,
+    addr: Value,
+    builder: &mut FunctionBuilder,
+    environ: &mut FE,
+) -> Value {
+    let pointer_type = environ.pointer_type();
+    let shadow_base = builder.ins().global_value(pointer_type, shadow);
+    let index = builder.ins().uextend(pointer_type, addr);
+    let offset = builder.ins().ishl_imm(
+        index,
+        i64::from(memref::SHADOW_RECORD_SIZE.trailing_zeros()),
+    );
+    builder.ins().iadd(shadow_base, offset)
+}
+
+/// Returns whether the shadow region of linear memory `memory` has a record for `addr`.
+///
+/// The region grows along with the memory, so records are kept for the addresses up to and
+/// including the current end of the memory.
+fn translate_memref_shadow_in_range<FE: FuncEnvironment + ?Sized>(
+    memory: u32,
+    addr: Value,
+    builder: &mut FunctionBuilder,
+    state: &mut FuncTranslationState,
+    environ: &mut FE,
+) -> WasmResult<Value> {
+    let memory_size = translate_memory_byte_size(memory, builder, state, environ)?;
+    let addr = builder.ins().uextend(I64, addr);
+    Ok(builder
+        .ins()
+        .icmp(IntCC::UnsignedLessThanOrEqual, addr, memory_size))
+}
+
+/// Record the metadata of a memref pointing at `addr` which is stored to linear memory `memory`.
+///
+/// The metadata is written inline to the memory's shadow region if it has one and is otherwise
+/// handed to the `__host::__set_value` import, if the module has one.
+fn translate_memref_set_metadata<FE: FuncEnvironment + ?Sized>(
+    memory: u32,
+    addr: Value,
+    base: Value,
+    end: Value,
+    attr: Value,
+    imm: u32,
+    builder: &mut FunctionBuilder,
+    state: &mut FuncTranslationState,
+    environ: &mut FE,
+) -> WasmResult<()> {
+    if let Some(shadow) = state.get_memref_shadow(builder.func, memory, environ)? {
+        environ.translate_memref_count(builder.cursor(), memref::MemRefCounter::MetadataStores)?;
+        let has_metadata = builder
+            .ins()
+            .band_imm(attr, i64::from(memref::HAS_METADATA_FLAG));
+        let has_metadata = builder.ins().icmp_imm(IntCC::NotEqual, has_metadata, 0);
+        if imm & memref::NO_CHECK_FLAG == 0 {
+            environ
+                .translate_memref_count(builder.cursor(), memref::MemRefCounter::BoundsChecks)?;
+            // the stored pointer must be within the object it was derived from
+            let below = builder.ins().icmp(IntCC::UnsignedLessThan, addr, base);
+            let above = builder.ins().icmp(IntCC::UnsignedGreaterThan, addr, end);
+            let out_of_bounds = builder.ins().bor(below, above);
+            let is_trap = builder.ins().band(has_metadata, out_of_bounds);
+            translate_memref_bounds_check(is_trap, addr, base, end, builder, environ)?;
+        }
+
+        // memrefs pointing past the end of the memory can't be recorded, and are loaded back
+        // without metadata
+        let in_range = translate_memref_shadow_in_range(memory, addr, builder, state, environ)?;
+        let record_block = builder.create_block();
+        let continuation = builder.create_block();
+        builder.ins().brz(in_range, continuation, &[]);
+        builder.ins().jump(record_block, &[]);
+        builder.seal_block(record_block);
+        builder.switch_to_block(record_block);
+
+        let record = memref_shadow_record(shadow, addr, builder, environ);
+        let flags = MemFlags::trusted();
+        let present = builder
+            .ins()
+            .load(I32, flags, record, memref::SHADOW_PRESENT_OFFSET);
+        let old_base = builder
+            .ins()
+            .load(I32, flags, record, memref::SHADOW_BASE_OFFSET);
+        let old_end = builder
+            .ins()
+            .load(I32, flags, record, memref::SHADOW_END_OFFSET);
+        let old_attr = builder
+            .ins()
+            .load(I32, flags, record, memref::SHADOW_ATTR_OFFSET);
+
+        // a sub-object never overwrites the bounds already recorded for the same address
+        let is_sub_obj = builder
+            .ins()
+            .band_imm(attr, i64::from(memref::SUB_OBJ_FLAG));
+        let keep_old = builder.ins().select(present, is_sub_obj, present);
+        let base = builder.ins().select(keep_old, old_base, base);
+        let end = builder.ins().select(keep_old, old_end, end);
+        let attr = builder.ins().select(keep_old, old_attr, attr);
+
+        let one = builder.ins().iconst(I32, 1);
+        builder
+            .ins()
+            .store(flags, base, record, memref::SHADOW_BASE_OFFSET);
+        builder
+            .ins()
+            .store(flags, end, record, memref::SHADOW_END_OFFSET);
+        builder
+            .ins()
+            .store(flags, attr, record, memref::SHADOW_ATTR_OFFSET);
+        builder
+            .ins()
+            .store(flags, one, record, memref::SHADOW_PRESENT_OFFSET);
+        builder.ins().jump(continuation, &[]);
+        builder.seal_block(continuation);
+        builder.switch_to_block(continuation);
+        return Ok(());
+    }
+
+    if let Some(func_index) = environ.host_set_value_func_index() {
+        environ.translate_memref_count(builder.cursor(), memref::MemRefCounter::MetadataStores)?;
+        environ.translate_memref_hook_memory(builder.cursor(), MemoryIndex::from_u32(memory))?;
+        let imm_val = builder.ins().iconst(I32, i64::from(imm as i32));
+        let (fref, _num_args) = state.get_direct_func(builder.func, func_index, environ)?;
+        let args: &mut [Value] = &mut [addr, base, end, attr, imm_val];
+        bitcast_wasm_params(
+            environ,
+            builder.func.dfg.ext_funcs[fref].signature,
+            args,
+            builder,
+        );
+        let call = environ.translate_call(
+            builder.cursor(),
+            FuncIndex::from_u32(func_index),
+            fref,
+            args,
+        )?;
+        let inst_results = builder.inst_results(call);
+        debug_assert_eq!(
+            inst_results.len(),
+            builder.func.dfg.signatures[builder.func.dfg.ext_funcs[fref].signature]
+                .returns
+                .len(),
+            "translate_call results should match the call signature"
+        );
+    }
+    Ok(())
+}
+
+/// Look up the `(base, end, attr)` metadata of a memref pointing at `addr` which was loaded
+/// from linear memory `memory`.
+///
+/// The metadata is read inline from the memory's shadow region if it has one and is otherwise
+/// obtained from the `__host::__get_record` import, or the legacy `__host::__get_value` one.
+/// Returns `None` if none of them is available.
+fn translate_memref_get_metadata<FE: FuncEnvironment + ?Sized>(
+    memory: u32,
+    addr: Value,
+    builder: &mut FunctionBuilder,
+    state: &mut FuncTranslationState,
+    environ: &mut FE,
+) -> WasmResult<Option<(Value, Value, Value)>> {
+    if let Some(shadow) = state.get_memref_shadow(builder.func, memory, environ)? {
+        environ.translate_memref_count(builder.cursor(), memref::MemRefCounter::MetadataLoads)?;
+        // records which were never written read as zero, i.e. without metadata, and so do the
+        // addresses past the end of the memory, which have no record
+        let in_range = translate_memref_shadow_in_range(memory, addr, builder, state, environ)?;
+        let record = memref_shadow_record(shadow, addr, builder, environ);
+        let first_record = builder.ins().global_value(environ.pointer_type(), shadow);
+        let record = builder.ins().select(in_range, record, first_record);
+        let flags = MemFlags::trusted();
+        let zero = builder.ins().iconst(I32, 0);
+        let [base, end, attr] = [
+            memref::SHADOW_BASE_OFFSET,
+            memref::SHADOW_END_OFFSET,
+            memref::SHADOW_ATTR_OFFSET,
+        ]
+        .map(|offset| {
+            let val = builder.ins().load(I32, flags, record, offset);
+            builder.ins().select(in_range, val, zero)
+        });
+        return Ok(Some((base, end, attr)));
+    }
+
+    let (func_index, record) = match (
+        environ.host_get_record_func_index(),
+        environ.host_get_value_func_index(),
+    ) {
+        (Some(idx), _) => (idx, true),
+        (None, Some(idx)) => (idx, false),
+        (None, None) => return Ok(None),
+    };
+    environ.translate_memref_count(builder.cursor(), memref::MemRefCounter::MetadataLoads)?;
+    environ.translate_memref_hook_memory(builder.cursor(), MemoryIndex::from_u32(memory))?;
+    let (fref, _num_args) = state.get_direct_func(builder.func, func_index, environ)?;
+    let args: &mut [Value] = &mut [addr];
+    bitcast_wasm_params(
+        environ,
+        builder.func.dfg.ext_funcs[fref].signature,
+        args,
+        builder,
+    );
+    let call = environ.translate_call(
+        builder.cursor(),
+        FuncIndex::from_u32(func_index),
+        fref,
+        args,
+    )?;
+    let inst_results = builder.inst_results(call);
+    debug_assert_eq!(
+        inst_results.len(),
+        builder.func.dfg.signatures[builder.func.dfg.ext_funcs[fref].signature]
+            .returns
+            .len(),
+        "translate_call results should match the call signature"
+    );
+    let metadata = if let Some(res) = inst_results.get(0) {
+        *res
+    } else {
+        state.reachable = false;
+        return Ok(None);
+    };
+
+    if record {
+        // the import returns the whole record, laid out like a memref
+        let metadata = optionally_bitcast_vector(metadata, I32X4, builder);
+        let base = builder.ins().extractlane(metadata, memref::BASE_LANE);
+        let end = builder.ins().extractlane(metadata, memref::END_LANE);
+        let attr = builder.ins().extractlane(metadata, memref::ATTR_LANE);
+        return Ok(Some((base, end, attr)));
+    }
+
+    // the legacy import packs the record as `base << 32 | attr << 24 | (end - base)`, keeping
+    // only the low 8 bits of the attributes and 24 bits of the object size
+    let size_attr = builder.ins().ireduce(I32, metadata);
+    let base = builder.ins().ushr_imm(metadata, 32i64);
+    let base = builder.ins().ireduce(I32, base);
+    let attr = builder.ins().ushr_imm(size_attr, 24i64);
+    let size = builder.ins().band_imm(size_attr, 0x00ffffffi64);
+    let end = builder.ins().iadd(base, size);
+    Ok(Some((base, end, attr)))
+}
+
 fn translate_msload_helper<FE: FuncEnvironment + ?Sized>(
     mem_ref: Value,
     memarg: &MemArg,
@@ -2978,16 +3654,21 @@ fn translate_msload_helper<FE: FuncEnvironment + ?Sized>(
     state: &mut FuncTranslationState,
     environ: &mut FE,
 ) -> WasmResult<Option<Value>> {
-    let (flags, base) =
-        match prepare_ms_addr(mem_ref, memarg,  mem_op_size(opcode, result_ty), builder, state, environ, environ.store_check_only())?{
-            None => {
-                state.reachable = false;
-                return Ok(None);
-            }
-            Some(v) => {
-                v
-            }
-        };
+    let (flags, base) = match prepare_ms_addr(
+        mem_ref,
+        memarg,
+        mem_op_size(opcode, result_ty),
+        builder,
+        state,
+        environ,
+        !environ.memref_check_policy().checks_loads(),
+    )? {
+        None => {
+            state.reachable = false;
+            return Ok(None);
+        }
+        Some(v) => v,
+    };
     let (load, dfg) = builder
         .ins()
         .Load(opcode, result_ty, flags, Offset32::new(0), base);
@@ -3019,7 +3700,7 @@ fn translate_msstore<FE: FuncEnvironment + ?Sized>(
     mem_ref: Value,
     memarg: &MemArg,
     opcode: ir::Opcode,
-    val : Value,
+    val: Value,
     builder: &mut FunctionBuilder,
     state: &mut FuncTranslationState,
     environ: &mut FE,
@@ -3027,9 +3708,18 @@ fn translate_msstore<FE: FuncEnvironment + ?Sized>(
     let val_ty = builder.func.dfg.value_type(val);
     let (flags, base) = unwrap_or_return_unreachable_state!(
         state,
-        prepare_ms_addr(mem_ref, memarg, mem_op_size(opcode, val_ty), builder, state, environ, false)?
+        prepare_ms_addr(
+            mem_ref,
+            memarg,
+            mem_op_size(opcode, val_ty),
+            builder,
+            state,
+            environ,
+            !environ.memref_check_policy().checks_stores()
+        )?
     );
-    builder.ins()
+    builder
+        .ins()
         .Store(opcode, val_ty, flags, Offset32::new(0), val, base);
     Ok(())
 }
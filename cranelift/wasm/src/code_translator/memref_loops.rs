@@ -0,0 +1,443 @@
+//! Proving the memref checks within a loop ahead of the loop.
+//!
+//! A memref check within a loop is made again on every iteration. When the base, end and
+//! attributes of the checked memref are the same on every iteration, and its address either is
+//! too or is offset by an induction variable of the loop, the check is proven for all iterations
+//! at once before the loop is entered, over the whole range the induction variable takes.
+//!
+//! The check before the loop doesn't trap: it sets a flag which lets the check within the loop
+//! be skipped, as it is for memrefs without metadata. Loops which do access out of bounds still
+//! trap at the offending access, and loops which don't reach the access don't trap at all.
+//!
+//! An induction variable is a parameter of the loop header which is entered with `init`, where
+//! the loop's only back edge is a `brnz` on `icmp ult next, bound` or `icmp ule next, bound`,
+//! `next` is the parameter plus a constant `step` and `bound` is defined before the loop.
+
+use super::memref_key_freed;
+use crate::state::{FuncTranslationState, MemRefCheck, MemRefLoop, MemRefLoopCheck};
+use crate::{memref, FuncEnvironment, WasmResult};
+use cranelift_codegen::cursor::{Cursor, FuncCursor};
+use cranelift_codegen::ir::condcodes::{CondCode, IntCC};
+use cranelift_codegen::ir::types::I64;
+use cranelift_codegen::ir::{
+    self, BranchInfo, InstBuilder, InstructionData, Opcode, Value, ValueDef,
+};
+use cranelift_frontend::FunctionBuilder;
+use std::vec::Vec;
+
+/// An induction variable of a loop.
+struct Induction {
+    /// The value the loop is entered with.
+    init: Value,
+    /// What the variable is incremented by on every iteration, wrapping around.
+    step: u32,
+    /// What the incremented variable is compared to.
+    bound: Value,
+    /// Whether the loop carries on while the incremented variable equals `bound`.
+    inclusive: bool,
+}
+
+/// Prove the memref checks emitted within the loop `lp` before it, where possible.
+///
+/// The loop's header must be sealed, so that its parameters are known.
+pub(super) fn hoist_memref_loop_checks<FE: FuncEnvironment + ?Sized>(
+    lp: MemRefLoop,
+    checks: Vec<MemRefLoopCheck>,
+    builder: &mut FunctionBuilder,
+    state: &mut FuncTranslationState,
+    environ: &mut FE,
+) -> WasmResult<()> {
+    if checks.is_empty() {
+        return Ok(());
+    }
+    let back_edge = back_edge(builder.func, &lp);
+    let pointer_type = environ.pointer_type();
+    for MemRefLoopCheck { check, branch } in checks {
+        let locks = state.get_memref_locks(builder.func, check.memory, environ)?;
+        // a deallocation within the loop may free the allocation between two iterations
+        if locks.is_some() && lp.killed {
+            continue;
+        }
+        let [addr, base, end, attr] = check.lanes;
+        let func = &*builder.func;
+        if ![base, end, attr]
+            .iter()
+            .all(|&value| is_invariant(func, lp.header, value))
+        {
+            continue;
+        }
+        let (param, terms) = match addr_terms(func, lp.header, addr) {
+            Some(addr_terms) => addr_terms,
+            None => continue,
+        };
+        let induction = match param {
+            Some(param) => match back_edge.and_then(|edge| induction(func, &lp, edge, param)) {
+                Some(induction) => Some(induction),
+                None => continue,
+            },
+            None => None,
+        };
+
+        let mut pos = FuncCursor::new(builder.func).at_inst(lp.preheader_jump);
+        let proven = translate_loop_check(
+            &check,
+            induction,
+            &terms,
+            locks,
+            lp.header,
+            &mut pos,
+            pointer_type,
+        );
+
+        // skip the check within the loop if it was proven before it
+        let mut pos = pos.at_inst(branch);
+        let no_metadata = pos.func.dfg.inst_args(branch)[0];
+        let skip = pos.ins().bor(no_metadata, proven);
+        pos.func.dfg.inst_args_mut(branch)[0] = skip;
+        state.memref_check_hoisted();
+    }
+    Ok(())
+}
+
+/// Emit whether `check` holds on every iteration of the loop with header `header` at `pos`,
+/// before the loop, as an `I8` which is nonzero if it does.
+///
+/// The checked address is the sum of `terms` and, if given, the induction variable `induction`.
+fn translate_loop_check(
+    check: &MemRefCheck,
+    induction: Option<Induction>,
+    terms: &[Value],
+    locks: Option<ir::GlobalValue>,
+    header: ir::Block,
+    pos: &mut FuncCursor,
+    pointer_type: ir::Type,
+) -> Value {
+    let [_, base, end, attr] = check.lanes;
+    let mut fails = Vec::new();
+
+    // everything is computed in 64 bits, where none of the sums wrap around
+    let mut start = pos.ins().iconst(I64, 0);
+    for &term in terms {
+        let term = materialize(pos, header, term);
+        let term = pos.ins().uextend(I64, term);
+        start = pos.ins().iadd(start, term);
+    }
+    let (lowest, highest) = match induction {
+        None => (start, start),
+        Some(Induction {
+            init,
+            step,
+            bound,
+            inclusive,
+        }) => {
+            let init = pos.ins().uextend(I64, init);
+            let bound = materialize(pos, header, bound);
+            let bound = pos.ins().uextend(I64, bound);
+            // the distance from `init` to the last value the variable may be incremented to
+            let distance = pos.ins().isub(bound, init);
+            let (incremented, distance) = if inclusive {
+                let incremented = pos
+                    .ins()
+                    .icmp(IntCC::UnsignedGreaterThanOrEqual, bound, init);
+                (incremented, distance)
+            } else {
+                let incremented = pos.ins().icmp(IntCC::UnsignedGreaterThan, bound, init);
+                (incremented, pos.ins().iadd_imm(distance, -1))
+            };
+            let zero = pos.ins().iconst(I64, 0);
+            let distance = pos.ins().select(incremented, distance, zero);
+            let step_value = pos.ins().iconst(I64, i64::from(step));
+            let steps = pos.ins().udiv(distance, step_value);
+            let distance = pos.ins().imul_imm(steps, i64::from(step));
+            let last = pos.ins().iadd(init, distance);
+            // the variable must not wrap around past its last value, onto values which pass the
+            // loop's condition again
+            let past_last = pos.ins().iadd_imm(last, i64::from(step));
+            fails.push(pos.ins().icmp_imm(
+                IntCC::UnsignedGreaterThan,
+                past_last,
+                i64::from(u32::MAX),
+            ));
+            (pos.ins().iadd(start, init), pos.ins().iadd(start, last))
+        }
+    };
+    // nor may the address
+    fails.push(
+        pos.ins()
+            .icmp_imm(IntCC::UnsignedGreaterThan, highest, i64::from(u32::MAX)),
+    );
+
+    if let Some(lower) = check.lower {
+        let lowest = pos.ins().iadd_imm(lowest, lower as i64);
+        let base = materialize(pos, header, base);
+        let base = pos.ins().uextend(I64, base);
+        fails.push(pos.ins().icmp(IntCC::UnsignedGreaterThan, base, lowest));
+    }
+    if let Some(upper) = check.upper {
+        let highest = pos.ins().iadd_imm(highest, upper as i64);
+        let end = materialize(pos, header, end);
+        let end = pos.ins().uextend(I64, end);
+        fails.push(pos.ins().icmp(IntCC::UnsignedGreaterThan, highest, end));
+    }
+    let attr = materialize(pos, header, attr);
+    let memory = pos
+        .ins()
+        .ushr_imm(attr, i64::from(memref::MEMORY_INDEX_SHIFT));
+    let memory = pos
+        .ins()
+        .band_imm(memory, i64::from(memref::MEMORY_INDEX_MASK));
+    fails.push(
+        pos.ins()
+            .icmp_imm(IntCC::NotEqual, memory, i64::from(check.memory)),
+    );
+    if let Some(locks) = locks {
+        // nothing within the loop frees allocations, so a live one stays live throughout
+        let (_, _, keyed, freed) = memref_key_freed(locks, attr, pos, pointer_type);
+        fails.push(pos.ins().band(keyed, freed));
+    }
+
+    let fail = fails
+        .into_iter()
+        .reduce(|a, b| pos.ins().bor(a, b))
+        .unwrap();
+    pos.ins().icmp_imm(IntCC::Equal, fail, 0)
+}
+
+/// Returns the only branch back to the header of the loop `lp`, if it has a single one.
+///
+/// The loop's blocks are all laid out from its header on, since they were all created within
+/// it and nothing follows the loop yet.
+fn back_edge(func: &ir::Function, lp: &MemRefLoop) -> Option<ir::Inst> {
+    let mut edges = Vec::new();
+    for block in func.layout.blocks().skip_while(|&block| block != lp.header) {
+        for inst in func.layout.block_insts(block) {
+            match func.dfg.analyze_branch(inst) {
+                BranchInfo::NotABranch => {}
+                BranchInfo::SingleDest(destination, _) => {
+                    if destination == lp.header {
+                        edges.push(inst);
+                    }
+                }
+                BranchInfo::Table(table, default) => {
+                    if default == Some(lp.header)
+                        || func.jump_tables[table]
+                            .iter()
+                            .any(|&block| block == lp.header)
+                    {
+                        return None;
+                    }
+                }
+            }
+        }
+    }
+    match edges[..] {
+        [edge] => Some(edge),
+        _ => None,
+    }
+}
+
+/// Returns the induction variable of the loop `lp` held by its header's parameter `param`,
+/// given its only back edge `edge`.
+fn induction(
+    func: &ir::Function,
+    lp: &MemRefLoop,
+    edge: ir::Inst,
+    param: usize,
+) -> Option<Induction> {
+    let init = match func.dfg.analyze_branch(lp.preheader_jump) {
+        BranchInfo::SingleDest(_, args) => *args.get(param)?,
+        _ => return None,
+    };
+    let variable = func.dfg.block_params(lp.header)[param];
+
+    if func.dfg.insts[edge].opcode() != Opcode::Brnz {
+        return None;
+    }
+    let cond = func.dfg.resolve_aliases(func.dfg.inst_args(edge)[0]);
+    let next = match func.dfg.analyze_branch(edge) {
+        BranchInfo::SingleDest(_, args) => func.dfg.resolve_aliases(*args.get(param)?),
+        _ => return None,
+    };
+
+    // `next` is `variable + step`
+    let step = match func.dfg.insts[func.dfg.value_def(next).inst()?] {
+        InstructionData::BinaryImm64 {
+            opcode: Opcode::IaddImm,
+            arg,
+            imm,
+        } if func.dfg.resolve_aliases(arg) == variable => i64::from(imm),
+        InstructionData::Binary {
+            opcode: Opcode::Iadd,
+            args,
+        } => {
+            let [a, b] = args.map(|arg| func.dfg.resolve_aliases(arg));
+            let other = if a == variable {
+                b
+            } else if b == variable {
+                a
+            } else {
+                return None;
+            };
+            match func.dfg.insts[func.dfg.value_def(other).inst()?] {
+                InstructionData::UnaryImm {
+                    opcode: Opcode::Iconst,
+                    imm,
+                } => i64::from(imm),
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+    // 32-bit constants may be sign-extended, decrements are steps which always wrap around
+    let step = step as u32;
+    if step == 0 {
+        return None;
+    }
+
+    // the loop carries on while `next` is below `bound`, or at most `bound`
+    let mut cmp = func.dfg.value_def(cond).inst()?;
+    if func.dfg.insts[cmp].opcode() == Opcode::Uextend {
+        let arg = func.dfg.resolve_aliases(func.dfg.inst_args(cmp)[0]);
+        cmp = func.dfg.value_def(arg).inst()?;
+    }
+    let (cc, a, b) = match func.dfg.insts[cmp] {
+        InstructionData::IntCompare {
+            opcode: Opcode::Icmp,
+            cond,
+            args,
+        } => {
+            let [a, b] = args.map(|arg| func.dfg.resolve_aliases(arg));
+            (cond, a, b)
+        }
+        _ => return None,
+    };
+    let (cc, bound) = if a == next {
+        (cc, b)
+    } else if b == next {
+        (cc.reverse(), a)
+    } else {
+        return None;
+    };
+    let inclusive = match cc {
+        IntCC::UnsignedLessThan => false,
+        IntCC::UnsignedLessThanOrEqual => true,
+        _ => return None,
+    };
+    if !is_invariant(func, lp.header, bound) {
+        return None;
+    }
+
+    Some(Induction {
+        init,
+        step,
+        bound,
+        inclusive,
+    })
+}
+
+/// Splits a memref address computed within the loop with header `header` into the terms it's
+/// the sum of: values which are the same on every iteration, and at most one parameter of the
+/// header, whose index is returned.
+fn addr_terms(
+    func: &ir::Function,
+    header: ir::Block,
+    addr: Value,
+) -> Option<(Option<usize>, Vec<Value>)> {
+    let mut param = None;
+    let mut terms = Vec::new();
+    let mut adds = 0;
+    let mut pending = vec![addr];
+    while let Some(value) = pending.pop() {
+        let value = func.dfg.resolve_aliases(value);
+        if is_invariant(func, header, value) {
+            terms.push(value);
+            continue;
+        }
+        match func.dfg.value_def(value) {
+            ValueDef::Param(block, index) if block == header && param.is_none() => {
+                param = Some(index)
+            }
+            // memref addresses are built up by `memref.add`, a handful is plenty
+            ValueDef::Result(inst, _)
+                if func.dfg.insts[inst].opcode() == Opcode::Iadd && adds < 8 =>
+            {
+                adds += 1;
+                pending.extend_from_slice(func.dfg.inst_args(inst));
+            }
+            _ => return None,
+        }
+    }
+    Some((param, terms))
+}
+
+/// Whether `inst` is laid out before the loop with header `header`.
+///
+/// Blocks are numbered in the order they're created in, so the loop's blocks are the header and
+/// the ones following it.
+fn before_loop(func: &ir::Function, header: ir::Block, inst: ir::Inst) -> bool {
+    matches!(func.layout.inst_block(inst), Some(block) if block.as_u32() < header.as_u32())
+}
+
+/// Whether `value` is the same on every iteration of the loop with header `header`: it's
+/// defined before the loop, or computed from such values by instructions which may be repeated
+/// before it.
+fn is_invariant(func: &ir::Function, header: ir::Block, value: Value) -> bool {
+    let value = func.dfg.resolve_aliases(value);
+    let inst = match func.dfg.value_def(value) {
+        ValueDef::Param(block, _) => return block.as_u32() < header.as_u32(),
+        ValueDef::Result(inst, _) if before_loop(func, header, inst) => return true,
+        ValueDef::Result(inst, _) => inst,
+        ValueDef::Union(..) => return false,
+    };
+    match func.dfg.insts[inst] {
+        InstructionData::UnaryImm {
+            opcode: Opcode::Iconst,
+            ..
+        } => true,
+        InstructionData::BinaryImm8 {
+            opcode: Opcode::Extractlane,
+            arg,
+            ..
+        }
+        | InstructionData::LoadNoOffset {
+            opcode: Opcode::Bitcast,
+            arg,
+            ..
+        } => is_invariant(func, header, arg),
+        _ => false,
+    }
+}
+
+/// Returns the invariant `value` of the loop with header `header` at `pos`, before the loop,
+/// repeating the instructions it was computed by within the loop.
+fn materialize(pos: &mut FuncCursor, header: ir::Block, value: Value) -> Value {
+    let value = pos.func.dfg.resolve_aliases(value);
+    let inst = match pos.func.dfg.value_def(value) {
+        ValueDef::Result(inst, _) if !before_loop(pos.func, header, inst) => inst,
+        _ => return value,
+    };
+    let ty = pos.func.dfg.value_type(value);
+    match pos.func.dfg.insts[inst] {
+        InstructionData::UnaryImm {
+            opcode: Opcode::Iconst,
+            imm,
+        } => pos.ins().iconst(ty, imm),
+        InstructionData::BinaryImm8 {
+            opcode: Opcode::Extractlane,
+            arg,
+            imm,
+        } => {
+            let arg = materialize(pos, header, arg);
+            pos.ins().extractlane(arg, imm)
+        }
+        InstructionData::LoadNoOffset {
+            opcode: Opcode::Bitcast,
+            arg,
+            flags,
+        } => {
+            let arg = materialize(pos, header, arg);
+            pos.ins().bitcast(ty, flags, arg)
+        }
+        _ => unreachable!("{} isn't invariant", value),
+    }
+}
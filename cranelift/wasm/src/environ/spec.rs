@@ -6,6 +6,7 @@
 //!
 //! [Wasmtime]: https://github.com/bytecodealliance/wasmtime
 
+use crate::memref::{MemRefCheckPolicy, MemRefCounter};
 use crate::state::FuncTranslationState;
 use crate::{
     DataIndex, ElemIndex, FuncIndex, Global, GlobalIndex, Heap, HeapData, Memory, MemoryIndex,
@@ -96,13 +97,19 @@ pub trait FuncEnvironment: TargetEnvironment {
         None
     }
 
-    /// If we do store check only for memref
-    fn store_check_only(&self) -> bool {
-        false
+    /// we should know the __host::__get_record function index when we translate some operation
+    fn host_get_record_func_index(&self) -> Option<u32> {
+        None
+    }
+
+    /// Which bounds checks are performed on accesses through memrefs.
+    fn memref_check_policy(&self) -> MemRefCheckPolicy {
+        MemRefCheckPolicy::Full
     }
 
-    /// if we do upper bound check only for memref
-    fn upper_check_only(&self) -> bool {
+    /// Whether execution continues after a failed memref bounds check, see
+    /// `translate_memref_out_of_bounds`.
+    fn memref_audit(&self) -> bool {
         false
     }
 
@@ -152,6 +159,32 @@ pub trait FuncEnvironment: TargetEnvironment {
     /// The index space covers both imported and locally declared memories.
     fn make_heap(&mut self, func: &mut ir::Function, index: MemoryIndex) -> WasmResult<Heap>;
 
+    /// Set up the necessary preamble definitions in `func` to access the shadow region holding
+    /// memref metadata for the linear memory identified by `index`.
+    ///
+    /// Returns `None` if the memory has no shadow region, in which case memref metadata is
+    /// recorded and looked up through the `__host` imports instead.
+    fn make_memref_shadow(
+        &mut self,
+        _func: &mut ir::Function,
+        _index: MemoryIndex,
+    ) -> WasmResult<Option<ir::GlobalValue>> {
+        Ok(None)
+    }
+
+    /// Set up the necessary preamble definitions in `func` to access the lock table tracking live
+    /// memref allocations for the linear memory identified by `index`.
+    ///
+    /// Returns `None` if the memory has no lock table, in which case memrefs aren't checked for
+    /// use-after-free or double-free.
+    fn make_memref_locks(
+        &mut self,
+        _func: &mut ir::Function,
+        _index: MemoryIndex,
+    ) -> WasmResult<Option<ir::GlobalValue>> {
+        Ok(None)
+    }
+
     /// Set up the necessary preamble definitions in `func` to access the table identified
     /// by `index`.
     ///
@@ -228,6 +261,47 @@ pub trait FuncEnvironment: TargetEnvironment {
         Ok(pos.ins().call(callee, call_args))
     }
 
+    /// Translate a failed memref bounds check at `pos`.
+    ///
+    /// `addr` is the offending address and `base` and `end` are the bounds of the object the
+    /// memref may access. The emitted code must not fall through unless `memref_audit` returns
+    /// true, in which case execution continues as if the check had passed; the default
+    /// implementation simply traps.
+    fn translate_memref_out_of_bounds(
+        &mut self,
+        mut pos: FuncCursor,
+        _addr: ir::Value,
+        _base: ir::Value,
+        _end: ir::Value,
+    ) -> WasmResult<()> {
+        pos.ins().trap(ir::TrapCode::MemRefOutOfBounds);
+        Ok(())
+    }
+
+    /// Translate an increment of the memref statistics `counter` at `pos`.
+    ///
+    /// The default implementation doesn't keep statistics and emits nothing.
+    fn translate_memref_count(
+        &mut self,
+        _pos: FuncCursor,
+        _counter: MemRefCounter,
+    ) -> WasmResult<()> {
+        Ok(())
+    }
+
+    /// Translate the selection of the linear memory identified by `index` as the one the next
+    /// call to a memref metadata hook records or looks up metadata for, at `pos`.
+    ///
+    /// The default implementation emits nothing, leaving the hooks unaware of which memory the
+    /// metadata belongs to.
+    fn translate_memref_hook_memory(
+        &mut self,
+        _pos: FuncCursor,
+        _index: MemoryIndex,
+    ) -> WasmResult<()> {
+        Ok(())
+    }
+
     /// Translate a `memory.grow` WebAssembly instruction.
     ///
     /// The `index` provided identifies the linear memory to grow, and `heap` is the heap reference
@@ -6,6 +6,7 @@
 
 use crate::code_translator::{bitcast_wasm_returns, translate_operator};
 use crate::environ::FuncEnvironment;
+use crate::memref::MemRefCheckStats;
 use crate::state::FuncTranslationState;
 use crate::translation_utils::get_vmctx_value_label;
 use crate::WasmResult;
@@ -116,9 +117,25 @@ impl FuncTranslator {
         parse_local_decls(&mut reader, &mut builder, num_params, environ, validator)?;
         parse_function_body(validator, reader, &mut builder, &mut self.state, environ)?;
 
+        let stats = self.state.memref_check_stats();
+        log::debug!(
+            "memref checks in {}: {} emitted, {} elided, {} hoisted",
+            builder.func.name,
+            stats.emitted,
+            stats.elided,
+            stats.hoisted
+        );
+
         builder.finalize();
         Ok(())
     }
+
+    /// Returns how many memref bounds checks the most recently translated function emitted, how
+    /// many were elided because a dominating check already covered them and how many were
+    /// proven before the loops they're in.
+    pub fn memref_check_stats(&self) -> MemRefCheckStats {
+        self.state.memref_check_stats()
+    }
 }
 
 /// Declare local variables for the signature parameters that correspond to WebAssembly locals.
@@ -275,7 +292,8 @@ fn cur_srcloc(reader: &BinaryReader) -> ir::SourceLoc {
 mod tests {
     use super::FuncTranslator;
     use crate::environ::DummyEnvironment;
-    use cranelift_codegen::ir::types::I32;
+    use crate::memref::MemRefCheckStats;
+    use cranelift_codegen::ir::types::{I32, I8X16};
     use cranelift_codegen::{ir, isa, settings, Context};
     use log::debug;
     use target_lexicon::PointerWidth;
@@ -401,6 +419,240 @@ mod tests {
         ctx.verify(&flags).unwrap();
     }
 
+    #[test]
+    fn memref_vectors_built_on_use() {
+        // A memref parameter advanced twice with `memref.add` and returned: the second add uses
+        // the lanes of the first, and only the returned memref is built into a vector.
+        let (func, _) = translate_memref_func(
+            r#"
+                (module binary
+                    "\00asm\01\00\00\00"
+                    "\01\06\01\60\01\6e\01\6e"
+                    "\03\02\01\00"
+                    "\0a\0c\01\0a\00\20\00\41\04\db\41\08\db\0b"
+                )
+            "#,
+            Some(I8X16),
+        );
+        assert_eq!(count_insts(&func, ir::Opcode::Extractlane), 4);
+        assert_eq!(count_insts(&func, ir::Opcode::Splat), 1);
+        assert_eq!(count_insts(&func, ir::Opcode::Insertlane), 3);
+
+        // The same memref dropped instead of returned is never built.
+        let (func, _) = translate_memref_func(
+            r#"
+                (module binary
+                    "\00asm\01\00\00\00"
+                    "\01\05\01\60\01\6e\00"
+                    "\03\02\01\00"
+                    "\0a\0d\01\0b\00\20\00\41\04\db\41\08\db\1a\0b"
+                )
+            "#,
+            None,
+        );
+        assert_eq!(count_insts(&func, ir::Opcode::Extractlane), 4);
+        assert_eq!(count_insts(&func, ir::Opcode::Splat), 0);
+        assert_eq!(count_insts(&func, ir::Opcode::Insertlane), 0);
+    }
+
+    #[test]
+    fn covered_memref_checks_are_elided() {
+        // The same `i32.msload` of a memref parameter twice: the first check covers the second.
+        let (func, stats) = translate_memref_func(
+            r#"
+                (module binary
+                    "\00asm\01\00\00\00"
+                    "\01\06\01\60\01\6e\01\7f"
+                    "\03\02\01\00"
+                    "\05\03\01\00\01"
+                    "\0a\0f\01\0d\00\20\00\e0\02\00\20\00\e0\02\00\6a\0b"
+                )
+            "#,
+            Some(I32),
+        );
+        assert_eq!(
+            stats,
+            MemRefCheckStats {
+                emitted: 1,
+                elided: 1,
+                hoisted: 0
+            }
+        );
+        // One branch on the memref having no metadata and one on the check failing.
+        assert_eq!(count_insts(&func, ir::Opcode::Brnz), 2);
+
+        // A load at offset 4 doesn't cover the one at offset 0 below it.
+        let (_, stats) = translate_memref_func(
+            r#"
+                (module binary
+                    "\00asm\01\00\00\00"
+                    "\01\06\01\60\01\6e\01\7f"
+                    "\03\02\01\00"
+                    "\05\03\01\00\01"
+                    "\0a\0f\01\0d\00\20\00\e0\02\04\20\00\e0\02\00\6a\0b"
+                )
+            "#,
+            Some(I32),
+        );
+        assert_eq!(
+            stats,
+            MemRefCheckStats {
+                emitted: 2,
+                elided: 0,
+                hoisted: 0
+            }
+        );
+    }
+
+    #[test]
+    fn memref_checks_are_elided_where_dominated() {
+        // (func (param memref) (result i32) (local i32)
+        //   local.get 0  i32.msload  local.tee 1
+        //   if
+        //     local.get 0  i32.msload  local.get 0  i32.msload offset=8  i32.add  local.set 1
+        //   end
+        //   local.get 0  i32.msload offset=8  local.get 1  i32.add)
+        //
+        // The check before the `if` covers the first load within it, but the check made within
+        // the `if` doesn't cover the load after it.
+        let (_, stats) = translate_memref_func(
+            r#"
+                (module binary
+                    "\00asm\01\00\00\00"
+                    "\01\06\01\60\01\6e\01\7f"
+                    "\03\02\01\00"
+                    "\05\03\01\00\01"
+                    "\0a\25\01\23\01\01\7f\20\00\e0\02\00\22\01\04\40\20\00\e0\02\00\20\00"
+                    "\e0\02\08\6a\21\01\0b\20\00\e0\02\08\20\01\6a\0b"
+                )
+            "#,
+            Some(I32),
+        );
+        assert_eq!(
+            stats,
+            MemRefCheckStats {
+                emitted: 3,
+                elided: 1,
+                hoisted: 0
+            }
+        );
+    }
+
+    #[test]
+    fn memref_loop_checks_are_hoisted() {
+        // (func (param memref) (result i32) (local i32 i32)
+        //   loop
+        //     local.get 0  local.get 1  memref.add  i32.msload
+        //     local.get 2  i32.add  local.set 2
+        //     local.get 1  i32.const 4  i32.add  local.tee 1
+        //     i32.const 64  i32.lt_u  br_if 0
+        //   end
+        //   local.get 2)
+        //
+        // The memref is offset by an induction variable running up to 64.
+        let (_, stats) = translate_memref_func(
+            r#"
+                (module binary
+                    "\00asm\01\00\00\00"
+                    "\01\06\01\60\01\6e\01\7f"
+                    "\03\02\01\00"
+                    "\05\03\01\00\01"
+                    "\0a\25\01\23\01\02\7f\03\40\20\00\20\01\db\e0\02\00\20\02\6a\21\02"
+                    "\20\01\41\04\6a\22\01\41\c0\00\49\0d\00\0b\20\02\0b"
+                )
+            "#,
+            Some(I32),
+        );
+        assert_eq!(
+            stats,
+            MemRefCheckStats {
+                emitted: 1,
+                elided: 0,
+                hoisted: 1
+            }
+        );
+
+        // The same loop carrying on while the variable isn't 64 isn't bounded.
+        let (_, stats) = translate_memref_func(
+            r#"
+                (module binary
+                    "\00asm\01\00\00\00"
+                    "\01\06\01\60\01\6e\01\7f"
+                    "\03\02\01\00"
+                    "\05\03\01\00\01"
+                    "\0a\25\01\23\01\02\7f\03\40\20\00\20\01\db\e0\02\00\20\02\6a\21\02"
+                    "\20\01\41\04\6a\22\01\41\c0\00\47\0d\00\0b\20\02\0b"
+                )
+            "#,
+            Some(I32),
+        );
+        assert_eq!(stats.hoisted, 0);
+
+        // (func (param memref) (result i32) (local i32)
+        //   loop
+        //     local.get 0  i32.msload  local.tee 1  br_if 0
+        //   end
+        //   local.get 1)
+        //
+        // The memref is the same on every iteration.
+        let (_, stats) = translate_memref_func(
+            r#"
+                (module binary
+                    "\00asm\01\00\00\00"
+                    "\01\06\01\60\01\6e\01\7f"
+                    "\03\02\01\00"
+                    "\05\03\01\00\01"
+                    "\0a\14\01\12\01\01\7f\03\40\20\00\e0\02\00\22\01\0d\00\0b\20\01\0b"
+                )
+            "#,
+            Some(I32),
+        );
+        assert_eq!(stats.hoisted, 1);
+    }
+
+    /// Translates the function of `wat`, which takes a memref and returns a value of type
+    /// `returns`, if any.
+    fn translate_memref_func(
+        wat: &str,
+        returns: Option<ir::Type>,
+    ) -> (ir::Function, MemRefCheckStats) {
+        let wasm = wat::parse_str(wat).unwrap();
+
+        let mut trans = FuncTranslator::new();
+        let flags = settings::Flags::new(settings::builder());
+        let runtime = DummyEnvironment::new(
+            isa::TargetFrontendConfig {
+                default_call_conv: isa::CallConv::Fast,
+                pointer_width: PointerWidth::U64,
+            },
+            false,
+        );
+
+        let mut ctx = Context::new();
+
+        ctx.func.name = ir::UserFuncName::testcase("memref");
+        ctx.func.signature.params.push(ir::AbiParam::new(I8X16));
+        if let Some(ty) = returns {
+            ctx.func.signature.returns.push(ir::AbiParam::new(ty));
+        }
+
+        let (body, mut validator) = extract_func(&wasm);
+        trans
+            .translate_body(&mut validator, body, &mut ctx.func, &mut runtime.func_env())
+            .unwrap();
+        debug!("{}", ctx.func.display());
+        ctx.verify(&flags).unwrap();
+        (ctx.func, trans.memref_check_stats())
+    }
+
+    fn count_insts(func: &ir::Function, opcode: ir::Opcode) -> usize {
+        func.layout
+            .blocks()
+            .flat_map(|block| func.layout.block_insts(block))
+            .filter(|&inst| func.dfg.insts[inst].opcode() == opcode)
+            .count()
+    }
+
     fn extract_func(wat: &[u8]) -> (FunctionBody<'_>, FuncValidator<ValidatorResources>) {
         let mut validator = Validator::new();
         for payload in Parser::new(0).parse_all(wat) {
@@ -4,11 +4,89 @@
 //! value and control stacks during the translation of a single function.
 
 use crate::environ::{FuncEnvironment, GlobalVariable};
+use crate::memref::MemRefCheckStats;
 use crate::{FuncIndex, GlobalIndex, Heap, MemoryIndex, TableIndex, TypeIndex, WasmResult};
 use crate::{HashMap, Occupied, Vacant};
 use cranelift_codegen::ir::{self, Block, Inst, Value};
 use std::vec::Vec;
 
+/// Offsets from a memref's address proven to be within its bounds by a memref check.
+///
+/// Memrefs without metadata are never checked, so a check also holds where its memref was found
+/// to have none.
+///
+/// A check holds in all the code it dominates. With WebAssembly's structured control flow that
+/// is the code following it up to the end of its control frame, or up to its `else`, including
+/// nested frames; the code following a frame is only reached through the frame's entry, but not
+/// necessarily through the code within it.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct MemRefCheck {
+    /// The checked memref vector.
+    pub(crate) mem_ref: Value,
+    /// The `[addr, base, end, attr]` lanes of the checked memref.
+    pub(crate) lanes: [Value; 4],
+    /// The memory the memref was checked to point into.
+    pub(crate) memory: u32,
+    /// The lowest offset from `addr` checked against the memref's base, if any.
+    pub(crate) lower: Option<u64>,
+    /// The highest offset from `addr` checked against the memref's end, if any.
+    pub(crate) upper: Option<u64>,
+}
+
+impl MemRefCheck {
+    /// Whether this check proves everything `other` would.
+    fn covers(&self, other: &MemRefCheck) -> bool {
+        self.same_memref(other)
+            && other
+                .lower
+                .map_or(true, |lower| self.lower.map_or(false, |l| l <= lower))
+            && other
+                .upper
+                .map_or(true, |upper| self.upper.map_or(false, |u| u >= upper))
+    }
+
+    /// Whether this check and `other` are of the same memref into the same memory.
+    ///
+    /// The lanes of a memref vector are extracted again wherever it's used, so the same vector
+    /// is the same memref even if its lanes differ.
+    fn same_memref(&self, other: &MemRefCheck) -> bool {
+        (self.mem_ref == other.mem_ref || self.lanes == other.lanes) && self.memory == other.memory
+    }
+}
+
+/// Where the memref checks made within a control frame start.
+#[derive(Clone, Copy, Debug)]
+struct MemRefCheckScope {
+    /// The number of checks recorded when the frame was entered.
+    start: usize,
+    /// The first check which held when the frame was entered.
+    visible: usize,
+}
+
+/// A loop being translated.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct MemRefLoop {
+    /// The jump into the loop from the block before it, where checks proving the loop's
+    /// accesses ahead of time are inserted.
+    pub(crate) preheader_jump: Inst,
+    /// The loop's header block.
+    pub(crate) header: Block,
+    /// Where the checks emitted within the loop start.
+    checks: usize,
+    /// Whether memref checks were forgotten within the loop because an allocation may have
+    /// been freed.
+    pub(crate) killed: bool,
+}
+
+/// A memref check emitted within a loop, which may be proven before the loop instead.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct MemRefLoopCheck {
+    /// The check.
+    pub(crate) check: MemRefCheck,
+    /// The branch over the check taken if the memref has no metadata.
+    pub(crate) branch: Inst,
+}
+
 /// Information about the presence of an associated `else` for an `if`, or the
 /// lack thereof.
 #[derive(Debug)]
@@ -227,6 +305,31 @@ pub struct FuncTranslationState {
     // Map of heaps that have been created by `FuncEnvironment::make_heap`.
     memory_to_heap: HashMap<MemoryIndex, Heap>,
 
+    // Map of memref shadow regions that have been created by
+    // `FuncEnvironment::make_memref_shadow`.
+    memory_to_memref_shadow: HashMap<MemoryIndex, Option<ir::GlobalValue>>,
+
+    // Map of memref lock tables that have been created by
+    // `FuncEnvironment::make_memref_locks`.
+    memory_to_memref_locks: HashMap<MemoryIndex, Option<ir::GlobalValue>>,
+
+    // Map of memref vectors built by the translator to the scalar `[addr, base, end, attr]`
+    // values they were built from. Every lane dominates the vector it belongs to, so they may
+    // be used wherever the vector is.
+    memref_lanes: HashMap<Value, [Value; 4]>,
+
+    // Memref checks which dominate the code being translated, and where the checks of each
+    // control frame being translated start. Only the checks from `memref_checks_visible` on hold
+    // within the innermost loop.
+    memref_checks: Vec<MemRefCheck>,
+    memref_check_scopes: Vec<MemRefCheckScope>,
+    memref_checks_visible: usize,
+    memref_check_stats: MemRefCheckStats,
+
+    // Loops being translated, innermost last, and the memref checks emitted within them.
+    memref_loops: Vec<MemRefLoop>,
+    memref_loop_checks: Vec<MemRefLoopCheck>,
+
     // Map of tables that have been created by `FuncEnvironment::make_table`.
     pub(crate) tables: HashMap<TableIndex, ir::Table>,
 
@@ -248,6 +351,12 @@ impl FuncTranslationState {
     pub fn reachable(&self) -> bool {
         self.reachable
     }
+
+    /// Counts of the memref bounds checks emitted, elided and hoisted so far in the current
+    /// function.
+    pub fn memref_check_stats(&self) -> MemRefCheckStats {
+        self.memref_check_stats
+    }
 }
 
 impl FuncTranslationState {
@@ -259,6 +368,15 @@ impl FuncTranslationState {
             reachable: true,
             globals: HashMap::new(),
             memory_to_heap: HashMap::new(),
+            memory_to_memref_shadow: HashMap::new(),
+            memory_to_memref_locks: HashMap::new(),
+            memref_lanes: HashMap::new(),
+            memref_checks: Vec::new(),
+            memref_check_scopes: Vec::new(),
+            memref_checks_visible: 0,
+            memref_check_stats: MemRefCheckStats::default(),
+            memref_loops: Vec::new(),
+            memref_loop_checks: Vec::new(),
             tables: HashMap::new(),
             signatures: HashMap::new(),
             functions: HashMap::new(),
@@ -271,6 +389,15 @@ impl FuncTranslationState {
         self.reachable = true;
         self.globals.clear();
         self.memory_to_heap.clear();
+        self.memory_to_memref_shadow.clear();
+        self.memory_to_memref_locks.clear();
+        self.memref_lanes.clear();
+        self.memref_checks.clear();
+        self.memref_check_scopes.clear();
+        self.memref_checks_visible = 0;
+        self.memref_check_stats = MemRefCheckStats::default();
+        self.memref_loops.clear();
+        self.memref_loop_checks.clear();
         self.tables.clear();
         self.signatures.clear();
         self.functions.clear();
@@ -470,6 +597,179 @@ impl FuncTranslationState {
         }
     }
 
+    /// Get the base address of the memref shadow region of linear memory `index`, if it has
+    /// one. Create the reference if necessary.
+    pub(crate) fn get_memref_shadow<FE: FuncEnvironment + ?Sized>(
+        &mut self,
+        func: &mut ir::Function,
+        index: u32,
+        environ: &mut FE,
+    ) -> WasmResult<Option<ir::GlobalValue>> {
+        let index = MemoryIndex::from_u32(index);
+        match self.memory_to_memref_shadow.entry(index) {
+            Occupied(entry) => Ok(*entry.get()),
+            Vacant(entry) => Ok(*entry.insert(environ.make_memref_shadow(func, index)?)),
+        }
+    }
+
+    /// Get the base address of the memref lock table of linear memory `index`, if it has one.
+    /// Create the reference if necessary.
+    pub(crate) fn get_memref_locks<FE: FuncEnvironment + ?Sized>(
+        &mut self,
+        func: &mut ir::Function,
+        index: u32,
+        environ: &mut FE,
+    ) -> WasmResult<Option<ir::GlobalValue>> {
+        let index = MemoryIndex::from_u32(index);
+        match self.memory_to_memref_locks.entry(index) {
+            Occupied(entry) => Ok(*entry.get()),
+            Vacant(entry) => Ok(*entry.insert(environ.make_memref_locks(func, index)?)),
+        }
+    }
+
+    /// Get the scalar `[addr, base, end, attr]` lanes the memref vector `mem_ref` was built
+    /// from, if it was built by the translator.
+    pub(crate) fn memref_lanes(&self, mem_ref: Value) -> Option<[Value; 4]> {
+        self.memref_lanes.get(&mem_ref).copied()
+    }
+
+    /// Record that the memref vector `mem_ref` holds the scalar `[addr, base, end, attr]`
+    /// values `lanes`, which must all dominate it.
+    pub(crate) fn set_memref_lanes(&mut self, mem_ref: Value, lanes: [Value; 4]) {
+        self.memref_lanes.insert(mem_ref, lanes);
+    }
+
+    /// Whether the memref checks dominating the code being translated already prove everything
+    /// `check` would.
+    pub(crate) fn memref_check_covers(&self, check: &MemRefCheck) -> bool {
+        self.memref_checks[self.memref_checks_visible..]
+            .iter()
+            .any(|c| c.covers(check))
+    }
+
+    /// Count a memref check emitted in the function's code.
+    pub(crate) fn memref_check_emitted(&mut self) {
+        self.memref_check_stats.emitted += 1;
+    }
+
+    /// Record that `check` holds in the code which follows, alongside the checks already
+    /// recorded for it.
+    pub(crate) fn record_memref_check(&mut self, check: MemRefCheck) {
+        // checks made in enclosing frames also hold after the current one, so they may only be
+        // widened by checks of the same frame
+        let start = self
+            .memref_check_scopes
+            .last()
+            .map_or(0, |scope| scope.start)
+            .max(self.memref_checks_visible);
+        let existing = self.memref_checks[start..]
+            .iter_mut()
+            .find(|c| c.same_memref(&check));
+        match existing {
+            Some(c) => {
+                // both ranges were proven, so everything from the lowest to the highest offset is
+                // within the memref's bounds
+                c.lower = match (c.lower, check.lower) {
+                    (Some(a), Some(b)) => Some(a.min(b)),
+                    (a, b) => a.or(b),
+                };
+                c.upper = c.upper.max(check.upper);
+            }
+            None => self.memref_checks.push(check),
+        }
+    }
+
+    /// Count a memref check which was left out because a dominating check covered it.
+    pub(crate) fn memref_check_elided(&mut self) {
+        self.memref_check_stats.elided += 1;
+    }
+
+    /// Count a memref check within a loop which was proven before the loop.
+    pub(crate) fn memref_check_hoisted(&mut self) {
+        self.memref_check_stats.hoisted += 1;
+    }
+
+    /// Note that translation enters a `block`, `loop` or `if`, which is dominated by the memref
+    /// checks made so far.
+    ///
+    /// If `barrier` is set they aren't used within the frame, as for a loop whose later
+    /// iterations may follow the deallocation of a memref they covered.
+    pub(crate) fn enter_memref_scope(&mut self, barrier: bool) {
+        self.memref_check_scopes.push(MemRefCheckScope {
+            start: self.memref_checks.len(),
+            visible: self.memref_checks_visible,
+        });
+        if barrier {
+            self.memref_checks_visible = self.memref_checks.len();
+        }
+    }
+
+    /// Note that translation continues with the `else` of the innermost `if`, which is only
+    /// dominated by the memref checks made before the `if`.
+    pub(crate) fn reset_memref_scope(&mut self) {
+        if let Some(scope) = self.memref_check_scopes.last() {
+            self.memref_checks.truncate(scope.start);
+        }
+    }
+
+    /// Note that translation continues after the end of the innermost control frame, which is
+    /// only dominated by the memref checks made before the frame.
+    pub(crate) fn exit_memref_scope(&mut self) {
+        match self.memref_check_scopes.pop() {
+            Some(scope) => {
+                self.memref_checks.truncate(scope.start);
+                self.memref_checks_visible = scope.visible;
+            }
+            // the end of the function
+            None => {
+                self.memref_checks.clear();
+                self.memref_checks_visible = 0;
+            }
+        }
+    }
+
+    /// Forget all memref checks, e.g. because an allocation may have been freed since.
+    pub(crate) fn clear_memref_checks(&mut self) {
+        self.memref_checks.clear();
+        self.memref_checks_visible = 0;
+        for scope in self.memref_check_scopes.iter_mut() {
+            scope.start = 0;
+            scope.visible = 0;
+        }
+        for lp in self.memref_loops.iter_mut() {
+            lp.killed = true;
+        }
+    }
+
+    /// Note that translation enters the loop with header `header`, jumped to from the block
+    /// before it by `preheader_jump`.
+    pub(crate) fn enter_memref_loop(&mut self, preheader_jump: Inst, header: Block) {
+        self.memref_loops.push(MemRefLoop {
+            preheader_jump,
+            header,
+            checks: self.memref_loop_checks.len(),
+            killed: false,
+        });
+    }
+
+    /// Record that `check` was emitted, with `branch` skipping it for memrefs without metadata,
+    /// if translation is within a loop.
+    pub(crate) fn record_memref_loop_check(&mut self, check: MemRefCheck, branch: Inst) {
+        if !self.memref_loops.is_empty() {
+            self.memref_loop_checks
+                .push(MemRefLoopCheck { check, branch });
+        }
+    }
+
+    /// Note that translation leaves the loop with header `header`, returning it along with the
+    /// memref checks emitted within it.
+    pub(crate) fn exit_memref_loop(&mut self, header: Block) -> (MemRefLoop, Vec<MemRefLoopCheck>) {
+        let lp = self.memref_loops.pop().unwrap();
+        debug_assert_eq!(lp.header, header);
+        let checks = self.memref_loop_checks.split_off(lp.checks);
+        (lp, checks)
+    }
+
     /// Get the `Table` reference that should be used to access table `index`.
     /// Create the reference if necessary.
     pub(crate) fn get_or_create_table<FE: FuncEnvironment + ?Sized>(
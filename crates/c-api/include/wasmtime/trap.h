@@ -48,6 +48,16 @@ enum wasmtime_trap_code_enum {
   WASMTIME_TRAP_CODE_INTERRUPT,
   /// Execution has run out of the configured fuel amount.
   WASMTIME_TRAP_CODE_OUT_OF_FUEL,
+  /// A memref access fell outside of the bounds of the object it points into.
+  WASMTIME_TRAP_CODE_MEMREF_OUT_OF_BOUNDS,
+  /// A memref was used to access an allocation after it was deallocated.
+  WASMTIME_TRAP_CODE_MEMREF_USE_AFTER_FREE,
+  /// A memref was deallocated twice.
+  WASMTIME_TRAP_CODE_MEMREF_DOUBLE_FREE,
+  /// All memref allocation slots of a memory held live allocations.
+  WASMTIME_TRAP_CODE_MEMREF_KEYS_EXHAUSTED,
+  /// A memref allocation extended past the end of its linear memory.
+  WASMTIME_TRAP_CODE_MEMREF_ALLOC_OUT_OF_BOUNDS,
 };
 
 /**
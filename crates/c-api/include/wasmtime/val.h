@@ -102,6 +102,9 @@ typedef uint8_t wasmtime_valkind_t;
 #define WASMTIME_FUNCREF 5
 /// \brief Value of #wasmtime_valkind_t meaning that #wasmtime_val_t is an externref
 #define WASMTIME_EXTERNREF 6
+/// \brief Value of #wasmtime_valkind_t meaning that #wasmtime_val_t is a
+/// memref
+#define WASMTIME_MEMREF 7
 
 /// \brief A 128-bit value representing the WebAssembly `v128` type. Bytes are
 /// stored in little-endian order.
@@ -136,7 +139,11 @@ typedef union wasmtime_valunion {
   /// If this value represents a `ref.null extern` value then this pointer will
   /// be `NULL`.
   wasmtime_externref_t *externref;
-  /// Field used if #wasmtime_val_t::kind is #WASMTIME_V128
+  /// Field used if #wasmtime_val_t::kind is #WASMTIME_V128 or
+  /// #WASMTIME_MEMREF
+  ///
+  /// A memref's lanes are, in order, its address, the base and end of the
+  /// object it points into and its attribute flags.
   wasmtime_v128 v128;
 } wasmtime_valunion_t;
 
@@ -134,6 +134,11 @@ pub extern "C" fn wasmtime_trap_code(raw: &wasm_trap_t, code: &mut u8) -> bool {
         Trap::UnreachableCodeReached => 9,
         Trap::Interrupt => 10,
         Trap::OutOfFuel => 11,
+        Trap::MemRefOutOfBounds => 12,
+        Trap::MemRefUseAfterFree => 13,
+        Trap::MemRefDoubleFree => 14,
+        Trap::MemRefKeysExhausted => 15,
+        Trap::MemRefAllocOutOfBounds => 16,
         Trap::AlwaysTrapAdapter => unreachable!("component model not supported"),
         _ => unreachable!(),
     };
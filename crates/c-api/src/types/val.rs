@@ -37,6 +37,7 @@ pub(crate) fn into_valtype(kind: wasm_valkind_t) -> ValType {
         WASM_EXTERNREF => ValType::ExternRef,
         WASM_FUNCREF => ValType::FuncRef,
         WASMTIME_V128 => ValType::V128,
+        WASMTIME_MEMREF => ValType::MemRef,
         _ => panic!("unexpected kind: {}", kind),
     }
 }
@@ -50,6 +51,7 @@ pub(crate) fn from_valtype(ty: &ValType) -> wasm_valkind_t {
         ValType::ExternRef => WASM_EXTERNREF,
         ValType::FuncRef => WASM_FUNCREF,
         ValType::V128 => WASMTIME_V128,
+        ValType::MemRef => WASMTIME_MEMREF,
     }
 }
 
@@ -61,3 +63,4 @@ pub const WASMTIME_F64: wasmtime_valkind_t = 3;
 pub const WASMTIME_V128: wasmtime_valkind_t = 4;
 pub const WASMTIME_FUNCREF: wasmtime_valkind_t = 5;
 pub const WASMTIME_EXTERNREF: wasmtime_valkind_t = 6;
+pub const WASMTIME_MEMREF: wasmtime_valkind_t = 7;
@@ -6,7 +6,7 @@ use crate::{
 use std::ffi::c_void;
 use std::mem::{self, ManuallyDrop, MaybeUninit};
 use std::ptr;
-use wasmtime::{ExternRef, Func, Val, ValType};
+use wasmtime::{ExternRef, Func, MemRef, Val, ValType};
 
 #[repr(C)]
 pub struct wasm_val_t {
@@ -219,6 +219,12 @@ impl wasmtime_val_t {
                     v128: val.to_le_bytes(),
                 },
             },
+            Val::MemRef(val) => wasmtime_val_t {
+                kind: crate::WASMTIME_MEMREF,
+                of: wasmtime_val_union {
+                    v128: val.as_u128().to_le_bytes(),
+                },
+            },
         }
     }
 
@@ -229,6 +235,9 @@ impl wasmtime_val_t {
             crate::WASMTIME_F32 => Val::F32(self.of.f32),
             crate::WASMTIME_F64 => Val::F64(self.of.f64),
             crate::WASMTIME_V128 => Val::V128(u128::from_le_bytes(self.of.v128)),
+            crate::WASMTIME_MEMREF => {
+                Val::MemRef(MemRef::from_u128(u128::from_le_bytes(self.of.v128)))
+            }
             crate::WASMTIME_FUNCREF => {
                 let store = self.of.funcref.store_id;
                 let index = self.of.funcref.index;
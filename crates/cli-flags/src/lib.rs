@@ -60,15 +60,16 @@ pub const SUPPORTED_WASI_MODULES: &[(&str, &str)] = &[
     ),
 ];
 
-fn pick_profiling_strategy(jitdump: bool, vtune: bool) -> Result<ProfilingStrategy> {
-    Ok(match (jitdump, vtune) {
-        (true, false) => ProfilingStrategy::JitDump,
-        (false, true) => ProfilingStrategy::VTune,
-        (true, true) => {
-            println!("Can't enable --jitdump and --vtune at the same time. Profiling not enabled.");
+fn pick_profiling_strategy(jitdump: bool, vtune: bool, perfmap: bool) -> Result<ProfilingStrategy> {
+    Ok(match (jitdump, vtune, perfmap) {
+        (true, false, false) => ProfilingStrategy::JitDump,
+        (false, true, false) => ProfilingStrategy::VTune,
+        (false, false, true) => ProfilingStrategy::PerfMap,
+        (false, false, false) => ProfilingStrategy::None,
+        _ => {
+            println!("Can't enable more than one of --jitdump, --vtune and --perfmap at the same time. Profiling not enabled.");
             ProfilingStrategy::None
         }
-        _ => ProfilingStrategy::None,
     })
 }
 
@@ -135,13 +136,17 @@ pub struct CommonOptions {
     pub wasi_modules: Option<WasiModules>,
 
     /// Generate jitdump file (supported on --features=profiling build)
-    #[clap(long, conflicts_with = "vtune")]
+    #[clap(long, conflicts_with_all = &["vtune", "perfmap"])]
     pub jitdump: bool,
 
     /// Generate vtune (supported on --features=vtune build)
-    #[clap(long, conflicts_with = "jitdump")]
+    #[clap(long, conflicts_with_all = &["jitdump", "perfmap"])]
     pub vtune: bool,
 
+    /// Generate a perf map file at /tmp/perf-<pid>.map (supported on Unix)
+    #[clap(long, conflicts_with_all = &["jitdump", "vtune"])]
+    pub perfmap: bool,
+
     /// Run optimization passes on translated functions, on by default
     #[clap(short = 'O', long)]
     pub optimize: bool,
@@ -231,6 +236,11 @@ pub struct CommonOptions {
     /// stack overflow is reported.
     #[clap(long)]
     pub max_wasm_stack: Option<usize>,
+
+    /// Load per-function memref check policies from a file, with one
+    /// `<function name or index> <none|store-only|upper-only|full>` per line
+    #[clap(long, value_name = "PATH")]
+    pub memref_policy_file: Option<PathBuf>,
 }
 
 impl CommonOptions {
@@ -270,7 +280,11 @@ impl CommonOptions {
             .cranelift_debug_verifier(self.enable_cranelift_debug_verifier)
             .debug_info(self.debug_info)
             .cranelift_opt_level(self.opt_level())
-            .profiler(pick_profiling_strategy(self.jitdump, self.vtune)?)
+            .profiler(pick_profiling_strategy(
+                self.jitdump,
+                self.vtune,
+                self.perfmap,
+            )?)
             .cranelift_nan_canonicalization(self.enable_cranelift_nan_canonicalization);
 
         self.enable_wasm_features(&mut config);
@@ -336,6 +350,10 @@ impl CommonOptions {
             config.max_wasm_stack(max);
         }
 
+        if let Some(path) = &self.memref_policy_file {
+            config.memref_check_policy_file(path)?;
+        }
+
         Ok(config)
     }
 
@@ -210,6 +210,7 @@ impl wasmtime_environ::Compiler for Compiler {
         }
 
         let mut func_env = FuncEnvironment::new(isa, translation, types, tunables);
+        func_env.set_memref_check_policy_for(func_index);
 
         // The `stack_limit` global value below is the implementation of stack
         // overflow checks in Wasmtime.
@@ -319,6 +320,7 @@ impl wasmtime_environ::Compiler for Compiler {
         log::trace!("{:?} timing info\n{}", func_index, timing);
 
         let sized_stack_slots = std::mem::take(&mut context.func.sized_stack_slots);
+        let memref_checks = func_translator.memref_check_stats();
 
         self.save_context(CompilerContext {
             func_translator,
@@ -331,6 +333,7 @@ impl wasmtime_environ::Compiler for Compiler {
             WasmFunctionInfo {
                 start_srcloc: address_transform.start_srcloc,
                 stack_maps: stack_maps.into(),
+                memref_checks,
             },
             Box::new(CompiledFunction {
                 body: code_buf,
@@ -1019,6 +1022,11 @@ fn mach_trap_to_trap(trap: &MachTrap) -> TrapInformation {
             ir::TrapCode::BadConversionToInteger => Trap::BadConversionToInteger,
             ir::TrapCode::UnreachableCodeReached => Trap::UnreachableCodeReached,
             ir::TrapCode::Interrupt => Trap::Interrupt,
+            ir::TrapCode::MemRefOutOfBounds => Trap::MemRefOutOfBounds,
+            ir::TrapCode::MemRefUseAfterFree => Trap::MemRefUseAfterFree,
+            ir::TrapCode::MemRefDoubleFree => Trap::MemRefDoubleFree,
+            ir::TrapCode::MemRefKeysExhausted => Trap::MemRefKeysExhausted,
+            ir::TrapCode::MemRefAllocOutOfBounds => Trap::MemRefAllocOutOfBounds,
             ir::TrapCode::User(ALWAYS_TRAP_CODE) => Trap::AlwaysTrapAdapter,
 
             // these should never be emitted by wasmtime-cranelift
@@ -13,7 +13,7 @@ use std::path::PathBuf;
 use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
 use wasmparser::ValType as WasmType;
 use wasmtime_environ::{
-    DebugInfoData, DefinedFuncIndex, EntityRef, FuncIndex, FunctionMetadata, WasmFileInfo,
+    memref, DebugInfoData, DefinedFuncIndex, EntityRef, FuncIndex, FunctionMetadata, WasmFileInfo,
 };
 
 const PRODUCER_NAME: &str = "wasmtime";
@@ -115,6 +115,7 @@ struct WasmTypesDieRefs {
     i64: write::UnitEntryId,
     f32: write::UnitEntryId,
     f64: write::UnitEntryId,
+    memref: write::UnitEntryId,
 }
 
 fn add_wasm_types(
@@ -143,6 +144,38 @@ fn add_wasm_types(
     let i64_die_id = def_type!("i64", 8, gimli::DW_ATE_signed);
     let f32_die_id = def_type!("f32", 4, gimli::DW_ATE_float);
     let f64_die_id = def_type!("f64", 8, gimli::DW_ATE_float);
+    let u32_die_id = def_type!("u32", 4, gimli::DW_ATE_unsigned);
+
+    // Describe memrefs as a structure of their four lanes rather than as an
+    // opaque vector, so that debuggers show the pointer next to its bounds.
+    let memref_die_id = unit.add(root_id, gimli::DW_TAG_structure_type);
+    let die = unit.get_mut(memref_die_id);
+    die.set(
+        gimli::DW_AT_name,
+        write::AttributeValue::StringRef(out_strings.add("memref")),
+    );
+    die.set(gimli::DW_AT_byte_size, write::AttributeValue::Data1(16));
+    for (name, lane) in [
+        ("addr", memref::ADDR_LANE),
+        ("base", memref::BASE_LANE),
+        ("end", memref::END_LANE),
+        ("attr", memref::ATTR_LANE),
+    ] {
+        let member_id = unit.add(memref_die_id, gimli::DW_TAG_member);
+        let member = unit.get_mut(member_id);
+        member.set(
+            gimli::DW_AT_name,
+            write::AttributeValue::StringRef(out_strings.add(name)),
+        );
+        member.set(
+            gimli::DW_AT_type,
+            write::AttributeValue::UnitRef(u32_die_id),
+        );
+        member.set(
+            gimli::DW_AT_data_member_location,
+            write::AttributeValue::Udata(u64::from(lane) * 4),
+        );
+    }
 
     WasmTypesDieRefs {
         vmctx: vmctx_die_id,
@@ -150,6 +183,7 @@ fn add_wasm_types(
         i64: i64_die_id,
         f32: f32_die_id,
         f64: f64_die_id,
+        memref: memref_die_id,
     }
 }
 
@@ -178,6 +212,7 @@ fn resolve_var_type(
         WasmType::I64 => wasm_types.i64,
         WasmType::F32 => wasm_types.f32,
         WasmType::F64 => wasm_types.f64,
+        WasmType::MemRef => wasm_types.memref,
         _ => {
             // Ignore unsupported types.
             return None;
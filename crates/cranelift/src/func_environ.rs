@@ -16,6 +16,7 @@ use std::convert::TryFrom;
 use std::mem;
 use wasmparser::Operator;
 use cranelift_wasm::WasmType::MemRef;
+use wasmtime_environ::memref::{MemRefCheckPolicy, MemRefCounter};
 use wasmtime_environ::{
     BuiltinFunctionIndex, MemoryPlan, MemoryStyle, Module, ModuleTranslation, ModuleTypes, PtrSize,
     TableStyle, Tunables, VMOffsets, WASM_PAGE_SIZE,
@@ -125,6 +126,10 @@ pub struct FuncEnvironment<'module_environment> {
 
     tunables: &'module_environment Tunables,
 
+    /// Which bounds checks are performed on accesses through memrefs in the
+    /// function being translated.
+    memref_check_policy: MemRefCheckPolicy,
+
     /// A function-local variable which stores the cached value of the amount of
     /// fuel remaining to execute. If used this is modified frequently so it's
     /// stored locally as a variable instead of always referenced from the field
@@ -179,6 +184,7 @@ impl<'module_environment> FuncEnvironment<'module_environment> {
             builtin_function_signatures,
             offsets: VMOffsets::new(isa.pointer_bytes(), &translation.module),
             tunables,
+            memref_check_policy: tunables.memref_check_policy,
             fuel_var: Variable::new(0),
             epoch_deadline_var: Variable::new(0),
             epoch_ptr_var: Variable::new(0),
@@ -194,6 +200,58 @@ impl<'module_environment> FuncEnvironment<'module_environment> {
         self.tunables.mem_ref
     }
 
+    /// Uses the memref check policy configured for the function at `index`,
+    /// if it overrides the module-wide policy.
+    pub fn set_memref_check_policy_for(&mut self, index: FuncIndex) {
+        let name = self
+            .translation
+            .debuginfo
+            .name_section
+            .func_names
+            .get(&index)
+            .copied();
+        if let Some(policy) = self.tunables.memref_function_policies.get(index, name) {
+            self.memref_check_policy = policy;
+        }
+    }
+
+    /// Loads the pointer stored at offset `field` of the `VMMemoryDefinition`
+    /// of memory `index`, for one of the regions reserved alongside a memory
+    /// for memref checks.
+    fn make_memref_region(
+        &mut self,
+        func: &mut ir::Function,
+        index: MemoryIndex,
+        field: u8,
+        readonly: bool,
+    ) -> ir::GlobalValue {
+        let pointer_type = self.pointer_type();
+        let vmctx = self.vmctx(func);
+        let (ptr, offset) = match self.module.defined_memory_index(index) {
+            Some(def_index) => {
+                let owned_index = self.module.owned_memory_index(def_index);
+                let offset = self.offsets.vmctx_vmmemory_definition(owned_index) + u32::from(field);
+                (vmctx, i32::try_from(offset).unwrap())
+            }
+            None => {
+                let from_offset = self.offsets.vmctx_vmmemory_import_from(index);
+                let memory = func.create_global_value(ir::GlobalValueData::Load {
+                    base: vmctx,
+                    offset: Offset32::new(i32::try_from(from_offset).unwrap()),
+                    global_type: pointer_type,
+                    readonly: true,
+                });
+                (memory, i32::from(field))
+            }
+        };
+        func.create_global_value(ir::GlobalValueData::Load {
+            base: ptr,
+            offset: Offset32::new(offset),
+            global_type: pointer_type,
+            readonly,
+        })
+    }
+
     fn pointer_type(&self) -> ir::Type {
         self.isa.pointer_type()
     }
@@ -325,6 +383,22 @@ impl<'module_environment> FuncEnvironment<'module_environment> {
         )
     }
 
+    /// Splits a memref into its low and high 64 bits, which is how memrefs
+    /// are passed to builtin functions.
+    fn memref_halves(&self, pos: &mut FuncCursor<'_>, memref: ir::Value) -> [ir::Value; 2] {
+        let halves = if pos.func.dfg.value_type(memref) == I64X2 {
+            memref
+        } else {
+            let mut flags = ir::MemFlags::new();
+            flags.set_endianness(ir::Endianness::Little);
+            pos.ins().bitcast(I64X2, flags, memref)
+        };
+        [
+            pos.ins().extractlane(halves, 0),
+            pos.ins().extractlane(halves, 1),
+        ]
+    }
+
     fn get_global_location(
         &mut self,
         func: &mut ir::Function,
@@ -837,12 +911,16 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
         self.translation.metadata_get_value_func
     }
 
-    fn store_check_only(&self) -> bool {
-        self.tunables.store_check_only
+    fn host_get_record_func_index(&self) -> Option<u32> {
+        self.translation.metadata_get_record_func
     }
 
-    fn upper_check_only(&self) -> bool {
-        self.tunables.upper_check_only
+    fn memref_check_policy(&self) -> MemRefCheckPolicy {
+        self.memref_check_policy
+    }
+
+    fn memref_audit(&self) -> bool {
+        self.tunables.memref_audit
     }
 
     fn heaps(&self) -> &PrimaryMap<Heap, HeapData> {
@@ -907,10 +985,11 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
             readonly: false,
         });
 
-        let element_size = u64::from(
-            self.reference_type(self.module.table_plans[index].table.wasm_ty)
-                .bytes(),
-        );
+        let element_size = match self.module.table_plans[index].table.wasm_ty {
+            // Memref tables hold memrefs in their 128-bit representation.
+            WasmType::MemRef => 16,
+            ty => u64::from(self.reference_type(ty).bytes()),
+        };
 
         Ok(func.create_table(ir::TableData {
             base_gv,
@@ -941,8 +1020,13 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
                     self.builtin_function_signatures
                         .table_grow_externref(&mut pos.func),
                 ),
+                WasmType::MemRef => (
+                    BuiltinFunctionIndex::table_grow_memref(),
+                    self.builtin_function_signatures
+                        .table_grow_memref(&mut pos.func),
+                ),
                 _ => return Err(WasmError::Unsupported(
-                    "`table.grow` with a table element type that is not `funcref` or `externref`"
+                    "`table.grow` with a table element type that is not `funcref`, `externref` or `memref`"
                         .into(),
                 )),
             };
@@ -950,11 +1034,20 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
         let (vmctx, func_addr) = self.translate_load_builtin_function_address(&mut pos, func_idx);
 
         let table_index_arg = pos.ins().iconst(I32, table_index.as_u32() as i64);
-        let call_inst = pos.ins().call_indirect(
-            func_sig,
-            func_addr,
-            &[vmctx, table_index_arg, delta, init_value],
-        );
+        let call_inst = if self.module.table_plans[table_index].table.wasm_ty == WasmType::MemRef {
+            let [init_lo, init_hi] = self.memref_halves(&mut pos, init_value);
+            pos.ins().call_indirect(
+                func_sig,
+                func_addr,
+                &[vmctx, table_index_arg, delta, init_lo, init_hi],
+            )
+        } else {
+            pos.ins().call_indirect(
+                func_sig,
+                func_addr,
+                &[vmctx, table_index_arg, delta, init_value],
+            )
+        };
 
         Ok(pos.func.dfg.first_result(call_inst))
     }
@@ -1089,6 +1182,13 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
 
                 Ok(elem)
             }
+            WasmType::MemRef => {
+                // Memrefs are plain values, so they are loaded out of their
+                // table without any lazy initialization or barrier.
+                let elem_addr = builder.ins().table_addr(pointer_type, table, index, 0);
+                let flags = ir::MemFlags::trusted().with_table();
+                Ok(builder.ins().load(I8X16, flags, elem_addr, 0))
+            }
             ty => Err(WasmError::Unsupported(format!(
                 "unsupported table type for `table.get` instruction: {:?}",
                 ty
@@ -1247,6 +1347,12 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
 
                 Ok(())
             }
+            WasmType::MemRef => {
+                let table_entry_addr = builder.ins().table_addr(pointer_type, table, index, 0);
+                let flags = ir::MemFlags::trusted().with_table();
+                builder.ins().store(flags, value, table_entry_addr, 0);
+                Ok(())
+            }
             ty => Err(WasmError::Unsupported(format!(
                 "unsupported table type for `table.set` instruction: {:?}",
                 ty
@@ -1274,8 +1380,13 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
                     self.builtin_function_signatures
                         .table_fill_externref(&mut pos.func),
                 ),
+                WasmType::MemRef => (
+                    BuiltinFunctionIndex::table_fill_memref(),
+                    self.builtin_function_signatures
+                        .table_fill_memref(&mut pos.func),
+                ),
                 _ => return Err(WasmError::Unsupported(
-                    "`table.fill` with a table element type that is not `funcref` or `externref`"
+                    "`table.fill` with a table element type that is not `funcref`, `externref` or `memref`"
                         .into(),
                 )),
             };
@@ -1284,11 +1395,20 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
             self.translate_load_builtin_function_address(&mut pos, builtin_idx);
 
         let table_index_arg = pos.ins().iconst(I32, table_index.as_u32() as i64);
-        pos.ins().call_indirect(
-            builtin_sig,
-            builtin_addr,
-            &[vmctx, table_index_arg, dst, val, len],
-        );
+        if self.module.table_plans[table_index].table.wasm_ty == WasmType::MemRef {
+            let [val_lo, val_hi] = self.memref_halves(&mut pos, val);
+            pos.ins().call_indirect(
+                builtin_sig,
+                builtin_addr,
+                &[vmctx, table_index_arg, dst, val_lo, val_hi, len],
+            );
+        } else {
+            pos.ins().call_indirect(
+                builtin_sig,
+                builtin_addr,
+                &[vmctx, table_index_arg, dst, val, len],
+            );
+        }
 
         Ok(())
     }
@@ -1455,6 +1575,8 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
                 offset_guard_size,
                 pre_guard_size: _,
                 memory: _,
+                memref_shadow: _,
+                memref_locks: _,
             } => {
                 let heap_bound = func.create_global_value(ir::GlobalValueData::Load {
                     base: ptr,
@@ -1475,6 +1597,8 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
                 offset_guard_size,
                 pre_guard_size: _,
                 memory: _,
+                memref_shadow: _,
+                memref_locks: _,
             } => (
                 offset_guard_size,
                 HeapStyle::Static {
@@ -1499,6 +1623,38 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
         }))
     }
 
+    fn make_memref_shadow(
+        &mut self,
+        func: &mut ir::Function,
+        index: MemoryIndex,
+    ) -> WasmResult<Option<ir::GlobalValue>> {
+        if !self.module.memory_plans[index].memref_shadow {
+            return Ok(None);
+        }
+        // The shadow region is moved to a larger one when the memory grows,
+        // so its pointer must be reloaded after any call.
+        let field = self.offsets.ptr.vmmemory_definition_memref_shadow();
+        Ok(Some(self.make_memref_region(func, index, field, false)))
+    }
+
+    fn make_memref_locks(
+        &mut self,
+        func: &mut ir::Function,
+        index: MemoryIndex,
+    ) -> WasmResult<Option<ir::GlobalValue>> {
+        // `memref.alloc` and `memref.dealloc` use the lock table of the
+        // memory named by the memref's attributes, which a module may not
+        // declare.
+        match self.module.memory_plans.get(index) {
+            Some(plan) if plan.memref_locks => {}
+            _ => return Ok(None),
+        }
+        // The lock table is never moved once allocated, so its pointer may be
+        // loaded once per function.
+        let field = self.offsets.ptr.vmmemory_definition_memref_locks();
+        Ok(Some(self.make_memref_region(func, index, field, true)))
+    }
+
     fn make_global(
         &mut self,
         func: &mut ir::Function,
@@ -1718,6 +1874,68 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
         Ok(pos.ins().call_indirect(sig_ref, func_addr, &real_call_args))
     }
 
+    fn translate_memref_out_of_bounds(
+        &mut self,
+        mut pos: FuncCursor,
+        addr: ir::Value,
+        base: ir::Value,
+        end: ir::Value,
+    ) -> WasmResult<()> {
+        // The libcall raises a trap carrying the details of the violation,
+        // unless it only records it in audit mode.
+        let sig = self
+            .builtin_function_signatures
+            .memref_out_of_bounds(&mut pos.func);
+        let (vmctx, func_addr) = self.translate_load_builtin_function_address(
+            &mut pos,
+            BuiltinFunctionIndex::memref_out_of_bounds(),
+        );
+        pos.ins()
+            .call_indirect(sig, func_addr, &[vmctx, addr, base, end]);
+        if !self.tunables.memref_audit {
+            pos.ins().trap(ir::TrapCode::MemRefOutOfBounds);
+        }
+        Ok(())
+    }
+
+    fn translate_memref_count(
+        &mut self,
+        mut pos: FuncCursor,
+        counter: MemRefCounter,
+    ) -> WasmResult<()> {
+        if !self.tunables.memref_stats {
+            return Ok(());
+        }
+        let pointer_type = self.pointer_type();
+        let vmctx = self.vmctx(&mut pos.func);
+        let base = pos.ins().global_value(pointer_type, vmctx);
+        let offset = i32::try_from(self.offsets.vmctx_memref_counter(counter)).unwrap();
+        let flags = ir::MemFlags::trusted();
+        let count = pos.ins().load(ir::types::I64, flags, base, offset);
+        let count = pos.ins().iadd_imm(count, 1);
+        pos.ins().store(flags, count, base, offset);
+        Ok(())
+    }
+
+    fn translate_memref_hook_memory(
+        &mut self,
+        mut pos: FuncCursor,
+        index: MemoryIndex,
+    ) -> WasmResult<()> {
+        // with a single memory the hooks always get metadata for it
+        if !self.offsets.has_memref_hook_memory {
+            return Ok(());
+        }
+        let pointer_type = self.pointer_type();
+        let vmctx = self.vmctx(&mut pos.func);
+        let base = pos.ins().global_value(pointer_type, vmctx);
+        let offset = i32::try_from(self.offsets.vmctx_memref_hook_memory()).unwrap();
+        let index = pos.ins().iconst(I32, i64::from(index.as_u32()));
+        pos.ins()
+            .store(ir::MemFlags::trusted(), index, base, offset);
+        Ok(())
+    }
+
     fn translate_memory_grow(
         &mut self,
         mut pos: FuncCursor<'_>,
@@ -32,6 +32,12 @@ macro_rules! foreach_builtin_function {
             table_fill_externref(vmctx: vmctx, table: i32, dst: i32, val: reference, len: i32);
             /// Returns an index for Wasm's `table.fill` instruction for `funcref`s.
             table_fill_funcref(vmctx: vmctx, table: i32, dst: i32, val: pointer, len: i32);
+            /// Returns an index for Wasm's `table.grow` instruction for `memref`s,
+            /// which are passed as their low and high 64 bits.
+            table_grow_memref(vmctx: vmctx, table: i32, delta: i32, init_lo: i64, init_hi: i64) -> i32;
+            /// Returns an index for Wasm's `table.fill` instruction for `memref`s,
+            /// which are passed as their low and high 64 bits.
+            table_fill_memref(vmctx: vmctx, table: i32, dst: i32, val_lo: i64, val_hi: i64, len: i32);
             /// Returns an index to drop a `VMExternRef`.
             drop_externref(vmctx: vmctx, val: pointer);
             /// Returns an index to do a GC and then insert a `VMExternRef` into the
@@ -51,6 +57,8 @@ macro_rules! foreach_builtin_function {
             out_of_gas(vmctx: vmctx);
             /// Invoked when we reach a new epoch.
             new_epoch(vmctx: vmctx) -> i64;
+            /// Invoked when a memref bounds check fails.
+            memref_out_of_bounds(vmctx: vmctx, addr: i32, base: i32, end: i32);
         }
     };
 }
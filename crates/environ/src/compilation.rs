@@ -1,6 +1,7 @@
 //! A `Compilation` contains the compiled function bodies for a WebAssembly
 //! module.
 
+use crate::memref::MemRefCheckStats;
 use crate::obj;
 use crate::{
     DefinedFuncIndex, FilePos, FuncIndex, FunctionBodyData, ModuleTranslation, ModuleTypes,
@@ -24,6 +25,7 @@ use thiserror::Error;
 pub struct WasmFunctionInfo {
     pub start_srcloc: FilePos,
     pub stack_maps: Box<[StackMapInformation]>,
+    pub memref_checks: MemRefCheckStats,
 }
 
 /// Description of where a function is located in the text section of a
@@ -80,6 +80,12 @@ pub struct MemoryPlan {
     pub pre_guard_size: u64,
     /// Our chosen offset-guard size.
     pub offset_guard_size: u64,
+    /// Whether a shadow region holding memref metadata is reserved alongside
+    /// this memory.
+    pub memref_shadow: bool,
+    /// Whether a lock table tracking live memref allocations is reserved
+    /// alongside this memory.
+    pub memref_locks: bool,
 }
 
 impl MemoryPlan {
@@ -95,6 +101,8 @@ impl MemoryPlan {
             } else {
                 0
             },
+            memref_shadow: tunables.memref_shadow_metadata && !memory.shared,
+            memref_locks: tunables.memref_temporal_safety && !memory.shared,
         }
     }
 }
@@ -428,10 +436,18 @@ impl ModuleTranslation<'_> {
 
             // If this is not a funcref table, then we can't support a
             // pre-computed table of function indices.
-            if self.module.table_plans[segment.table_index].table.wasm_ty != WasmType::FuncRef {
-                leftovers.push(segment.clone());
-                continue;
-            }
+            let funcs = match &segment.elements {
+                TableSegmentElements::Functions(funcs)
+                    if self.module.table_plans[segment.table_index].table.wasm_ty
+                        == WasmType::FuncRef =>
+                {
+                    funcs
+                }
+                _ => {
+                    leftovers.push(segment.clone());
+                    continue;
+                }
+            };
 
             // If the base of this segment is dynamic, then we can't
             // include it in the statically-built array of initial
@@ -467,7 +483,7 @@ impl ModuleTranslation<'_> {
             }
 
             let dst = &mut elements[(segment.offset as usize)..(top as usize)];
-            dst.copy_from_slice(&segment.elements[..]);
+            dst.copy_from_slice(&funcs[..]);
         }
 
         self.module.table_initialization = TableInitialization::FuncTable {
@@ -689,7 +705,33 @@ pub struct TableInitializer {
     /// The offset to add to the base.
     pub offset: u32,
     /// The values to write into the table elements.
-    pub elements: Box<[FuncIndex]>,
+    pub elements: TableSegmentElements,
+}
+
+/// The values of an element segment.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum TableSegmentElements {
+    /// Functions, or `FuncIndex::reserved_value()` for null references, of a
+    /// segment for a `funcref` or `externref` table.
+    Functions(Box<[FuncIndex]>),
+    /// Memrefs, in their 128-bit representation, of a segment for a `memref`
+    /// table.
+    MemRefs(Box<[u128]>),
+}
+
+impl TableSegmentElements {
+    /// Returns the number of elements in this segment.
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Functions(funcs) => funcs.len(),
+            Self::MemRefs(memrefs) => memrefs.len(),
+        }
+    }
+
+    /// Returns whether this segment has no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 }
 
 /// Table initialization data for all tables in the module.
@@ -785,7 +827,7 @@ pub struct Module {
     pub memory_initialization: MemoryInitialization,
 
     /// WebAssembly passive elements.
-    pub passive_elements: Vec<Box<[FuncIndex]>>,
+    pub passive_elements: Vec<TableSegmentElements>,
 
     /// The map from passive element index (element segment index space) to index in `passive_elements`.
     pub passive_elements_map: BTreeMap<ElemIndex, usize>,
@@ -815,6 +857,13 @@ pub struct Module {
     /// an `anyfunc` index (and is the maximum anyfunc index).
     pub num_escaped_funcs: usize,
 
+    /// Whether instances keep counters of memref checks and metadata traffic
+    /// in their `VMContext`.
+    pub memref_stats: bool,
+
+    /// Whether the module imports any of the memref metadata hooks.
+    pub memref_hooks: bool,
+
     /// Types of functions, imported and local.
     pub functions: PrimaryMap<FuncIndex, FunctionType>,
 
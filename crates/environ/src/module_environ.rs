@@ -1,11 +1,12 @@
 use crate::module::{
     AnyfuncIndex, Initializer, MemoryInitialization, MemoryInitializer, MemoryPlan, Module,
-    ModuleType, TableInitializer, TablePlan,
+    ModuleType, TableInitializer, TablePlan, TableSegmentElements,
 };
 use crate::{
-    DataIndex, DefinedFuncIndex, ElemIndex, EntityIndex, EntityType, FuncIndex, Global,
+    memref, DataIndex, DefinedFuncIndex, ElemIndex, EntityIndex, EntityType, FuncIndex, Global,
     GlobalIndex, GlobalInit, MemoryIndex, ModuleTypesBuilder, PrimaryMap, SignatureIndex,
     TableIndex, TableInitialization, Tunables, TypeIndex, WasmError, WasmFuncType, WasmResult,
+    WasmType,
 };
 use cranelift_entity::packed_option::ReservedValue;
 use std::borrow::Cow;
@@ -95,11 +96,17 @@ pub struct ModuleTranslation<'data> {
     /// validation process.
     types: Option<Types>,
 
-    /// function index in wasm module for function (import "__host" "__set_value")
+    /// function index in wasm module of the `set_value` memref hook import,
+    /// `__host::__set_value` by default
     pub metadata_set_value_func: Option<u32>,
 
-    /// function index in wasm module for function (import "__host" "__get_value")
+    /// function index in wasm module of the `get_value` memref hook import,
+    /// `__host::__get_value` by default
     pub metadata_get_value_func: Option<u32>,
+
+    /// function index in wasm module of the `get_record` memref hook import,
+    /// `__host::__get_record` by default
+    pub metadata_get_record_func: Option<u32>,
 }
 
 impl<'data> ModuleTranslation<'data> {
@@ -189,6 +196,7 @@ impl<'a, 'data> ModuleEnvironment<'a, 'data> {
         data: &'data [u8],
     ) -> WasmResult<ModuleTranslation<'data>> {
         self.result.wasm = data;
+        self.result.module.memref_stats = self.tunables.memref_stats;
 
         for payload in parser.parse_all(data) {
             self.translate_payload(payload?)?;
@@ -257,21 +265,15 @@ impl<'a, 'data> ModuleEnvironment<'a, 'data> {
                 let cnt = usize::try_from(imports.count()).unwrap();
                 self.result.module.initializers.reserve(cnt);
 
-                for entry in imports {
-                    let import = entry?;
+                for entry in imports.into_iter_with_offsets() {
+                    let (offset, import) = entry?;
                     let ty = match import.ty {
                         TypeRef::Func(index) => {
-                            if import.module == "__host" {
-                                if import.name == "__set_value" {
-                                    self.result.metadata_set_value_func = Some(self.result.module.num_imported_funcs as u32);
-                                } else if import.name == "__get_value" {
-                                    self.result.metadata_get_value_func = Some(self.result.module.num_imported_funcs as u32);
-                                } else {
-                                    unreachable!();
-                                }
-                            }
                             let index = TypeIndex::from_u32(index);
                             let sig_index = self.result.module.types[index].unwrap_function();
+                            if import.module == self.tunables.memref_hooks.module {
+                                self.declare_memref_hook(import.name, sig_index, offset)?;
+                            }
                             self.result.module.num_imported_funcs += 1;
                             self.result.debuginfo.wasm_file.imported_func_count += 1;
                             EntityType::Function(sig_index)
@@ -375,22 +377,10 @@ impl<'a, 'data> ModuleEnvironment<'a, 'data> {
                             GlobalInit::GetGlobal(GlobalIndex::from_u32(global_index))
                         }
                         // no check, compiler does it
-                        Operator::MemrefConst {addr, size, attr} => {
-                            // according to https://github.com/WebAssembly/spec/blob/f5a260a2025ba4d7d398654581c7c532e3a2c319/proposals/simd/SIMD.md
-                            // Lane n corresponds to bits 32n – 32n+31.
-                            // I32x4.0 = addr, I32x4.1 = base, I32x4.2 = size/end, I32x4.3 = attr
-                            let addr_ = u128::from(addr);
-                            let base_ = u128::from(addr) << 32;
-                            // let size_ = u128::from(size) << 64;
-                            let end_ = u128::from(addr + size) << 64;
-                            let attr_ = u128::from(attr) << 96;
-                            // let base_bytes :[u8;4] = addr.to_le_bytes();
-                            // let size_bytes :[u8;4] = size.to_le_bytes();
-                            // let attr_bytes :[u8;4] = attr.to_le_bytes();
-                            // let addr_bytes :[u8;4] = addr.to_le_bytes();
-
-                            GlobalInit::MemRefConst(addr_ | base_ | end_ | attr_)
+                        Operator::MemrefConst { addr, size, attr } => {
+                            GlobalInit::MemRefConst(memref::constant(addr, size, attr))
                         }
+                        Operator::MemrefNull {} => GlobalInit::MemRefConst(memref::NULL),
                         s => {
                             return Err(WasmError::Unsupported(format!(
                                 "unsupported init expr in global section: {:?}",
@@ -447,7 +437,7 @@ impl<'a, 'data> ModuleEnvironment<'a, 'data> {
                     let wasmparser::Element {
                         kind,
                         items,
-                        ty: _,
+                        ty,
                         range: _,
                     } = entry?;
 
@@ -456,18 +446,42 @@ impl<'a, 'data> ModuleEnvironment<'a, 'data> {
                     // possible to create anything other than a `ref.null
                     // extern` for externref segments, so those just get
                     // translated to the reserved value of `FuncIndex`.
-                    let mut elements = Vec::new();
-                    match items {
+                    // Segments of memrefs instead hold the memrefs
+                    // themselves.
+                    let elements = match items {
                         ElementItems::Functions(funcs) => {
-                            elements.reserve(usize::try_from(funcs.count()).unwrap());
+                            let mut elements =
+                                Vec::with_capacity(usize::try_from(funcs.count()).unwrap());
                             for func in funcs {
                                 let func = FuncIndex::from_u32(func?);
                                 self.flag_func_escaped(func);
                                 elements.push(func);
                             }
+                            TableSegmentElements::Functions(elements.into())
+                        }
+                        ElementItems::Expressions(exprs) if ty == wasmparser::ValType::MemRef => {
+                            let mut elements =
+                                Vec::with_capacity(usize::try_from(exprs.count()).unwrap());
+                            for expr in exprs {
+                                let value = match expr?.get_binary_reader().read_operator()? {
+                                    Operator::MemrefNull {} => memref::NULL,
+                                    Operator::MemrefConst { addr, size, attr } => {
+                                        memref::constant(addr, size, attr)
+                                    }
+                                    s => {
+                                        return Err(WasmError::Unsupported(format!(
+                                            "unsupported init expr in element section: {:?}",
+                                            s
+                                        )));
+                                    }
+                                };
+                                elements.push(value);
+                            }
+                            TableSegmentElements::MemRefs(elements.into())
                         }
                         ElementItems::Expressions(funcs) => {
-                            elements.reserve(usize::try_from(funcs.count()).unwrap());
+                            let mut elements =
+                                Vec::with_capacity(usize::try_from(funcs.count()).unwrap());
                             for func in funcs {
                                 let func = match func?.get_binary_reader().read_operator()? {
                                     Operator::RefNull { .. } => FuncIndex::reserved_value(),
@@ -485,8 +499,9 @@ impl<'a, 'data> ModuleEnvironment<'a, 'data> {
                                 };
                                 elements.push(func);
                             }
+                            TableSegmentElements::Functions(elements.into())
                         }
-                    }
+                    };
 
                     match kind {
                         ElementKind::Active {
@@ -517,14 +532,14 @@ impl<'a, 'data> ModuleEnvironment<'a, 'data> {
                                 table_index,
                                 base,
                                 offset,
-                                elements: elements.into(),
+                                elements,
                             });
                         }
 
                         ElementKind::Passive => {
                             let elem_index = ElemIndex::from_u32(index as u32);
                             let index = self.result.module.passive_elements.len();
-                            self.result.module.passive_elements.push(elements.into());
+                            self.result.module.passive_elements.push(elements);
                             self.result
                                 .module
                                 .passive_elements_map
@@ -799,6 +814,61 @@ and for re-adding support for interface types you can see this issue:
         self.result.module.num_escaped_funcs += 1;
     }
 
+    /// Records the function import `name` from the memref hook module as
+    /// one of the hooks, checking that it has the type the translator calls
+    /// it with.
+    ///
+    /// Names starting with `__` are reserved for hooks in that module, so
+    /// unknown ones are rejected, while other imports from it are left to
+    /// the embedder.
+    fn declare_memref_hook(
+        &mut self,
+        name: &str,
+        sig_index: SignatureIndex,
+        offset: usize,
+    ) -> WasmResult<()> {
+        let hooks = &self.tunables.memref_hooks;
+        let ty = &self.types[sig_index];
+        let func = Some(self.result.module.num_imported_funcs as u32);
+        let invalid = |message: String| WasmError::InvalidWebAssembly { message, offset };
+        if name == hooks.set_value {
+            if ty.params() != [WasmType::I32; 5] || !ty.returns().is_empty() {
+                return Err(invalid(format!(
+                    "`{}::{}` must have type `(func (param i32 i32 i32 i32 i32))`",
+                    hooks.module, name
+                )));
+            }
+            self.result.metadata_set_value_func = func;
+        } else if name == hooks.get_value {
+            if ty.params() != [WasmType::I32] || ty.returns() != [WasmType::I64] {
+                return Err(invalid(format!(
+                    "`{}::{}` must have type `(func (param i32) (result i64))`",
+                    hooks.module, name
+                )));
+            }
+            self.result.metadata_get_value_func = func;
+        } else if name == hooks.get_record {
+            // the whole metadata record is returned so that bounds of any
+            // object size round-trip exactly
+            if ty.params() != [WasmType::I32] || ty.returns() != [WasmType::MemRef] {
+                return Err(invalid(format!(
+                    "`{}::{}` must have type `(func (param i32) (result memref))`",
+                    hooks.module, name
+                )));
+            }
+            self.result.metadata_get_record_func = func;
+        } else if name.starts_with("__") {
+            return Err(invalid(format!(
+                "unknown import `{}::{}` from the reserved memref hook module",
+                hooks.module, name
+            )));
+        } else {
+            return Ok(());
+        }
+        self.result.module.memref_hooks = true;
+        Ok(())
+    }
+
     fn declare_type_func(&mut self, wasm: WasmFuncType) -> WasmResult<()> {
         let sig_index = self.types.wasm_func_type(wasm);
         self.result
@@ -87,6 +87,27 @@ pub enum Trap {
 
     /// Used to indicate that a trap was raised by atomic wait operations on non shared memory.
     AtomicWaitNonSharedMemory,
+
+    /// A memref access or narrowing fell outside of the bounds of the object
+    /// the memref points into.
+    ///
+    /// When raised by compiled code the error carrying this trap also has a
+    /// [`MemRefViolation`] attached describing the offending access.
+    MemRefOutOfBounds,
+
+    /// A memref was used to access an allocation after it was deallocated.
+    MemRefUseAfterFree,
+
+    /// A memref was deallocated while its allocation was already deallocated.
+    MemRefDoubleFree,
+
+    /// A memref allocation couldn't be tracked for temporal safety as all
+    /// allocation slots of its memory held live allocations.
+    MemRefKeysExhausted,
+
+    /// A `memref.alloc` described an object extending past the end of its
+    /// linear memory.
+    MemRefAllocOutOfBounds,
     // if adding a variant here be sure to update the `check!` macro below
 }
 
@@ -109,6 +130,11 @@ impl fmt::Display for Trap {
             AlwaysTrapAdapter => "degenerate component adapter called",
             OutOfFuel => "all fuel consumed by WebAssembly",
             AtomicWaitNonSharedMemory => "atomic wait on non-shared memory",
+            MemRefOutOfBounds => "out of bounds memref access",
+            MemRefUseAfterFree => "memref access after free",
+            MemRefDoubleFree => "memref double free",
+            MemRefKeysExhausted => "memref allocation keys exhausted",
+            MemRefAllocOutOfBounds => "memref allocation out of bounds of linear memory",
         };
         write!(f, "wasm trap: {desc}")
     }
@@ -116,6 +142,29 @@ impl fmt::Display for Trap {
 
 impl std::error::Error for Trap {}
 
+/// Details of a memref bounds violation, attached as context to errors whose
+/// root cause is [`Trap::MemRefOutOfBounds`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct MemRefViolation {
+    /// The first address that was accessed, or the base of the sub-object a
+    /// memref was narrowed to.
+    pub addr: u32,
+    /// The first address of the object the memref may access.
+    pub base: u32,
+    /// One past the last address of the object the memref may access.
+    pub end: u32,
+}
+
+impl fmt::Display for MemRefViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "memref access at {:#x} is outside of its object [{:#x}, {:#x})",
+            self.addr, self.base, self.end
+        )
+    }
+}
+
 impl TrapEncodingBuilder {
     /// Appends trap information about a function into this section.
     ///
@@ -223,6 +272,11 @@ pub fn lookup_trap_code(section: &[u8], offset: usize) -> Option<Trap> {
         AlwaysTrapAdapter
         OutOfFuel
         AtomicWaitNonSharedMemory
+        MemRefOutOfBounds
+        MemRefUseAfterFree
+        MemRefDoubleFree
+        MemRefKeysExhausted
+        MemRefAllocOutOfBounds
     }
 
     if cfg!(debug_assertions) {
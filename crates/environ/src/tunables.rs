@@ -1,4 +1,7 @@
+use crate::memref::MemRefCheckPolicy;
+use crate::FuncIndex;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 /// Tunable parameters for WebAssembly compilation.
 #[derive(Clone, Hash, Serialize, Deserialize)]
@@ -49,11 +52,85 @@ pub struct Tunables {
     /// for memref type
     pub mem_ref: bool,
 
-    /// store check only
-    pub store_check_only: bool,
+    /// Which bounds checks are performed on accesses through memrefs.
+    pub memref_check_policy: MemRefCheckPolicy,
 
-    /// upper check only
-    pub upper_check_only: bool,
+    /// Per-function overrides of `memref_check_policy`.
+    pub memref_function_policies: MemRefFunctionPolicies,
+
+    /// Whether memref metadata is kept in a shadow region reserved next to
+    /// each linear memory and accessed inline by compiled code, instead of
+    /// being passed to the `__host` metadata imports.
+    pub memref_shadow_metadata: bool,
+
+    /// Whether memrefs carry the key of the allocation they were derived
+    /// from, checked against a per-memory lock table on each access so that
+    /// use-after-free and double-free are detected.
+    pub memref_temporal_safety: bool,
+
+    /// Whether failed memref bounds checks are recorded by the store and
+    /// execution continues, instead of trapping.
+    pub memref_audit: bool,
+
+    /// Whether compiled code counts memref operations in per-instance
+    /// counters.
+    pub memref_stats: bool,
+
+    /// Names of the imports which record and look up the metadata of
+    /// memrefs stored to linear memory.
+    pub memref_hooks: MemRefHooks,
+}
+
+/// Memref check policies of individual functions, overriding
+/// `Tunables::memref_check_policy`.
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemRefFunctionPolicies {
+    /// Policies of functions by their name in the name section.
+    pub by_name: BTreeMap<String, MemRefCheckPolicy>,
+    /// Policies of functions by index, taking precedence over names.
+    pub by_index: BTreeMap<u32, MemRefCheckPolicy>,
+}
+
+impl MemRefFunctionPolicies {
+    /// Returns the policy of the function at `index`, named `name` in the
+    /// name section, if it's overridden.
+    pub fn get(&self, index: FuncIndex, name: Option<&str>) -> Option<MemRefCheckPolicy> {
+        self.by_index
+            .get(&index.as_u32())
+            .or_else(|| self.by_name.get(name?))
+            .copied()
+    }
+}
+
+/// Names of the function imports used as memref metadata hooks.
+///
+/// Function imports from `module` with names starting with `__` are reserved
+/// for the hooks, and any other such name is rejected when the module is
+/// translated.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemRefHooks {
+    /// Module name reserved for the hooks.
+    pub module: String,
+    /// Name of the hook recording the metadata of a stored memref, of type
+    /// `(func (param i32 i32 i32 i32 i32))`.
+    pub set_value: String,
+    /// Name of the legacy hook looking up the metadata of a loaded memref
+    /// packed into an `i64`, of type `(func (param i32) (result i64))`.
+    pub get_value: String,
+    /// Name of the hook looking up the whole metadata record of a loaded
+    /// memref, of type `(func (param i32) (result memref))`.
+    pub get_record: String,
+}
+
+impl Default for MemRefHooks {
+    fn default() -> Self {
+        Self {
+            module: "__host".to_string(),
+            set_value: "__set_value".to_string(),
+            get_value: "__get_value".to_string(),
+            get_record: "__get_record".to_string(),
+        }
+    }
 }
 
 impl Default for Tunables {
@@ -101,8 +178,13 @@ impl Default for Tunables {
             generate_address_map: true,
             debug_adapter_modules: false,
             mem_ref: true,
-            store_check_only: false,
-            upper_check_only: false,
+            memref_check_policy: MemRefCheckPolicy::default(),
+            memref_function_policies: MemRefFunctionPolicies::default(),
+            memref_shadow_metadata: false,
+            memref_temporal_safety: false,
+            memref_audit: false,
+            memref_stats: false,
+            memref_hooks: MemRefHooks::default(),
         }
     }
 }
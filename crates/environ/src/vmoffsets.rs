@@ -21,6 +21,8 @@
 //      owned_memories: [VMMemoryDefinition; module.num_owned_memories],
 //      globals: [VMGlobalDefinition; module.num_defined_globals],
 //      anyfuncs: [VMCallerCheckedAnyfunc; module.num_escaped_funcs],
+//      memref_counters: [u64; num_memref_counters],
+//      memref_hook_memory: u32, // (Only with memref hooks and several memories)
 // }
 
 use crate::{
@@ -29,6 +31,7 @@ use crate::{
 };
 use cranelift_entity::packed_option::ReservedValue;
 use std::convert::TryFrom;
+use wasmtime_types::memref::MemRefCounter;
 use wasmtime_types::OwnedMemoryIndex;
 
 #[cfg(target_pointer_width = "32")]
@@ -71,6 +74,13 @@ pub struct VMOffsets<P> {
     /// The number of escaped functions in the module, the size of the anyfuncs
     /// array.
     pub num_escaped_funcs: u32,
+    /// The number of memref statistics counters, zero unless statistics are
+    /// enabled.
+    pub num_memref_counters: u32,
+    /// Whether the `VMContext` records the memory that memref metadata hooks
+    /// are called for, only when the module imports a hook and has more than
+    /// one memory.
+    pub has_memref_hook_memory: bool,
 
     // precalculated offsets of various member fields
     magic: u32,
@@ -90,6 +100,8 @@ pub struct VMOffsets<P> {
     owned_memories: u32,
     defined_globals: u32,
     defined_anyfuncs: u32,
+    memref_counters: u32,
+    memref_hook_memory: u32,
     size: u32,
 }
 
@@ -182,10 +194,22 @@ pub trait PtrSize {
         1 * self.size()
     }
 
+    /// The offset of the `memref_shadow` field.
+    #[inline]
+    fn vmmemory_definition_memref_shadow(&self) -> u8 {
+        2 * self.size()
+    }
+
+    /// The offset of the `memref_locks` field.
+    #[inline]
+    fn vmmemory_definition_memref_locks(&self) -> u8 {
+        3 * self.size()
+    }
+
     /// Return the size of `VMMemoryDefinition`.
     #[inline]
     fn size_of_vmmemory_definition(&self) -> u8 {
-        2 * self.size()
+        4 * self.size()
     }
 
     /// Return the size of `*mut VMMemoryDefinition`.
@@ -236,6 +260,12 @@ pub struct VMOffsetsFields<P> {
     /// The number of escaped functions in the module, the size of the anyfunc
     /// array.
     pub num_escaped_funcs: u32,
+    /// The number of memref statistics counters, zero unless statistics are
+    /// enabled.
+    pub num_memref_counters: u32,
+    /// Whether the `VMContext` records the memory that memref metadata hooks
+    /// are called for.
+    pub has_memref_hook_memory: bool,
 }
 
 impl<P: PtrSize> VMOffsets<P> {
@@ -262,6 +292,12 @@ impl<P: PtrSize> VMOffsets<P> {
             num_owned_memories,
             num_defined_globals: cast_to_u32(module.globals.len() - module.num_imported_globals),
             num_escaped_funcs: cast_to_u32(module.num_escaped_funcs),
+            num_memref_counters: if module.memref_stats {
+                MemRefCounter::COUNT
+            } else {
+                0
+            },
+            has_memref_hook_memory: module.memref_hooks && module.memory_plans.len() > 1,
         })
     }
 
@@ -291,6 +327,8 @@ impl<P: PtrSize> VMOffsets<P> {
                     num_defined_memories: _,
                     num_owned_memories: _,
                     num_escaped_funcs: _,
+                    num_memref_counters: _,
+                    has_memref_hook_memory: _,
 
                     // used as the initial size below
                     size,
@@ -316,6 +354,8 @@ impl<P: PtrSize> VMOffsets<P> {
         }
 
         calculate_sizes! {
+            memref_hook_memory: "memref hook memory",
+            memref_counters: "memref counters",
             defined_anyfuncs: "module functions",
             defined_globals: "defined globals",
             owned_memories: "owned memories",
@@ -350,6 +390,8 @@ impl<P: PtrSize> From<VMOffsetsFields<P>> for VMOffsets<P> {
             num_owned_memories: fields.num_owned_memories,
             num_defined_globals: fields.num_defined_globals,
             num_escaped_funcs: fields.num_escaped_funcs,
+            num_memref_counters: fields.num_memref_counters,
+            has_memref_hook_memory: fields.has_memref_hook_memory,
             magic: 0,
             runtime_limits: 0,
             callee: 0,
@@ -367,6 +409,8 @@ impl<P: PtrSize> From<VMOffsetsFields<P>> for VMOffsets<P> {
             owned_memories: 0,
             defined_globals: 0,
             defined_anyfuncs: 0,
+            memref_counters: 0,
+            memref_hook_memory: 0,
             size: 0,
         };
 
@@ -430,6 +474,9 @@ impl<P: PtrSize> From<VMOffsetsFields<P>> for VMOffsets<P> {
                 ret.num_escaped_funcs,
                 ret.ptr.size_of_vmcaller_checked_anyfunc(),
             ),
+            align(8),
+            size(memref_counters) = cmul(ret.num_memref_counters, 8),
+            size(memref_hook_memory) = if ret.has_memref_hook_memory { 4u32 } else { 0 },
         }
 
         ret.size = next_field_offset;
@@ -674,6 +721,27 @@ impl<P: PtrSize> VMOffsets<P> {
         self.defined_anyfuncs
     }
 
+    /// The offset of the `memref_counters` array.
+    #[inline]
+    pub fn vmctx_memref_counters_begin(&self) -> u32 {
+        self.memref_counters
+    }
+
+    /// Return the offset to the `u64` holding `counter`.
+    #[inline]
+    pub fn vmctx_memref_counter(&self, counter: MemRefCounter) -> u32 {
+        assert!((counter as u32) < self.num_memref_counters);
+        self.vmctx_memref_counters_begin() + counter as u32 * 8
+    }
+
+    /// The offset of the index of the memory that memref metadata hooks are
+    /// called for.
+    #[inline]
+    pub fn vmctx_memref_hook_memory(&self) -> u32 {
+        assert!(self.has_memref_hook_memory);
+        self.memref_hook_memory
+    }
+
     /// The offset of the builtin functions array.
     #[inline]
     pub fn vmctx_builtin_functions(&self) -> u32 {
@@ -814,6 +882,22 @@ impl<P: PtrSize> VMOffsets<P> {
             + u32::from(self.ptr.vmmemory_definition_current_length())
     }
 
+    /// Return the offset to the `memref_shadow` field in `VMMemoryDefinition`
+    /// index `index`.
+    #[inline]
+    pub fn vmctx_vmmemory_definition_memref_shadow(&self, index: OwnedMemoryIndex) -> u32 {
+        self.vmctx_vmmemory_definition(index)
+            + u32::from(self.ptr.vmmemory_definition_memref_shadow())
+    }
+
+    /// Return the offset to the `memref_locks` field in `VMMemoryDefinition`
+    /// index `index`.
+    #[inline]
+    pub fn vmctx_vmmemory_definition_memref_locks(&self, index: OwnedMemoryIndex) -> u32 {
+        self.vmctx_vmmemory_definition(index)
+            + u32::from(self.ptr.vmmemory_definition_memref_locks())
+    }
+
     /// Return the offset to the `from` field in `VMGlobalImport` index `index`.
     #[inline]
     pub fn vmctx_vmglobal_import_from(&self, index: GlobalIndex) -> u32 {
@@ -14,6 +14,7 @@ pub mod component_types;
 mod config;
 mod instance_allocation_strategy;
 mod memory;
+mod memref_ops;
 mod module;
 mod pooling_config;
 mod single_inst_module;
@@ -26,6 +27,7 @@ pub use codegen_settings::CodegenSettings;
 pub use config::{Config, WasmtimeConfig};
 pub use instance_allocation_strategy::InstanceAllocationStrategy;
 pub use memory::{MemoryConfig, NormalMemoryConfig, UnalignedMemory, UnalignedMemoryCreator};
+pub use memref_ops::MemRefOps;
 pub use module::ModuleConfig;
 pub use pooling_config::PoolingAllocationConfig;
 pub use single_inst_module::SingleInstModule;
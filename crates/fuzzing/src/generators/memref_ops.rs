@@ -0,0 +1,230 @@
+//! Generate Wasm programs accessing linear memory through memrefs.
+//!
+//! Each program allocates a handful of objects as memref globals and then
+//! loads from and stores to them with `i32.msload` and `i32.msstore`. The
+//! accesses are known up front to be either within the bounds of their object
+//! or outside of them, so that the result of running the program under
+//! different memref check policies can be predicted.
+
+use arbitrary::{Arbitrary, Result, Unstructured};
+use wasm_encoder::Instruction;
+use wasmtime::MemRefViolation;
+
+const MAX_OBJECTS: usize = 8;
+const MAX_OPS: usize = 200;
+/// Maximum size of an object, in 4-byte words.
+const MAX_OBJECT_WORDS: u32 = 64;
+/// Address of the first object. Objects are laid out after it in order, so
+/// that accesses just outside of an object still fall within linear memory.
+const OBJECTS_START: u32 = 0x1000;
+/// Number of bytes left unused between two objects.
+const OBJECT_GAP: u32 = 0x40;
+/// How far outside of its object a violating access may reach.
+const MAX_VIOLATION_DISTANCE: i32 = 0x40;
+
+/// Memref attributes of the objects, marking them as carrying bounds.
+const OBJECT_ATTR: u32 = 0x20;
+
+/// The `memref` value type.
+const MEMREF: u8 = 0x6e;
+const MEMREF_CONST: u8 = 0xda;
+const MEMREF_ADD: u8 = 0xdb;
+const I32_MSLOAD: u8 = 0xe0;
+const I32_MSSTORE: u8 = 0xf0;
+
+/// A Wasm program performing accesses through memrefs which are all within
+/// the bounds of their objects, optionally followed by one access which isn't.
+#[derive(Debug)]
+pub struct MemRefOps {
+    objects: Vec<Object>,
+    accesses: Vec<Access>,
+    violation: Option<Access>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Object {
+    base: u32,
+    size: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Access {
+    object: u32,
+    offset: i32,
+    /// The value to store, or `None` for a load.
+    store: Option<i32>,
+}
+
+impl<'a> Arbitrary<'a> for MemRefOps {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mut objects = Vec::new();
+        let mut base = OBJECTS_START;
+        for _ in 0..u.int_in_range(1..=MAX_OBJECTS)? {
+            let size = u.int_in_range(1..=MAX_OBJECT_WORDS)? * 4;
+            objects.push(Object { base, size });
+            base += size + OBJECT_GAP;
+        }
+
+        let mut accesses = Vec::new();
+        for _ in 0..u.arbitrary_len::<Access>()?.min(MAX_OPS) {
+            let object = u.choose_index(objects.len())?;
+            let size = objects[object].size as i32;
+            accesses.push(Access {
+                object: object as u32,
+                offset: u.int_in_range(0..=size - 4)?,
+                store: u.arbitrary()?,
+            });
+        }
+
+        let violation = if u.arbitrary()? {
+            let object = u.choose_index(objects.len())?;
+            let size = objects[object].size as i32;
+            // Either below the base of the object, or reaching past its end.
+            let offset = if u.arbitrary()? {
+                u.int_in_range(-MAX_VIOLATION_DISTANCE..=-1)?
+            } else {
+                u.int_in_range(size - 3..=size + MAX_VIOLATION_DISTANCE)?
+            };
+            Some(Access {
+                object: object as u32,
+                offset,
+                store: u.arbitrary()?,
+            })
+        } else {
+            None
+        };
+
+        Ok(MemRefOps {
+            objects,
+            accesses,
+            violation,
+        })
+    }
+}
+
+impl MemRefOps {
+    /// Returns the violation raised by the access outside of its object, if
+    /// this test case has one.
+    pub fn violation(&self) -> Option<MemRefViolation> {
+        let access = self.violation?;
+        let object = self.objects[access.object as usize];
+        Some(MemRefViolation {
+            addr: object.base.wrapping_add(access.offset as u32),
+            base: object.base,
+            end: object.base + object.size,
+        })
+    }
+
+    /// Get this test case's Wasm module.
+    ///
+    /// If `with_violation` is set, the access outside of its object is
+    /// performed after all of the others.
+    ///
+    /// The Wasm module has the following exports:
+    ///
+    /// * `run: [] -> [i32]`: Performs all of the accesses and returns the sum
+    ///   of the values loaded.
+    ///
+    /// * `memory`: The memory holding the objects.
+    pub fn wasm(&self, with_violation: bool) -> Vec<u8> {
+        let mut module = wasm_encoder::Module::new();
+
+        let mut types = wasm_encoder::TypeSection::new();
+        types.function(vec![], vec![wasm_encoder::ValType::I32]);
+        module.section(&types);
+
+        let mut funcs = wasm_encoder::FunctionSection::new();
+        funcs.function(0);
+        module.section(&funcs);
+
+        let mut mems = wasm_encoder::MemorySection::new();
+        mems.memory(wasm_encoder::MemoryType {
+            minimum: 1,
+            maximum: Some(1),
+            memory64: false,
+            shared: false,
+        });
+        module.section(&mems);
+
+        // `wasm-encoder` doesn't know about memrefs, so encode the globals
+        // holding the objects by hand.
+        let mut globals = Vec::new();
+        leb128(&mut globals, self.objects.len() as u32);
+        for object in &self.objects {
+            globals.extend([MEMREF, 0x00, MEMREF_CONST]);
+            leb128(&mut globals, object.base);
+            leb128(&mut globals, object.size);
+            leb128(&mut globals, OBJECT_ATTR);
+            globals.push(0x0b);
+        }
+        module.section(&wasm_encoder::RawSection {
+            id: wasm_encoder::SectionId::Global as u8,
+            data: &globals,
+        });
+
+        let mut exports = wasm_encoder::ExportSection::new();
+        exports.export("run", wasm_encoder::ExportKind::Func, 0);
+        exports.export("memory", wasm_encoder::ExportKind::Memory, 0);
+        module.section(&exports);
+
+        let mut body = wasm_encoder::Function::new(vec![(1, wasm_encoder::ValType::I32)]);
+        let violation = if with_violation { self.violation } else { None };
+        for access in self.accesses.iter().chain(&violation) {
+            body.instruction(&Instruction::GlobalGet(access.object))
+                .instruction(&Instruction::I32Const(access.offset))
+                .raw([MEMREF_ADD]);
+            match access.store {
+                Some(val) => {
+                    body.instruction(&Instruction::I32Const(val))
+                        .raw([I32_MSSTORE, 0x02, 0x00]);
+                }
+                None => {
+                    body.raw([I32_MSLOAD, 0x02, 0x00])
+                        .instruction(&Instruction::LocalGet(0))
+                        .instruction(&Instruction::I32Add)
+                        .instruction(&Instruction::LocalSet(0));
+                }
+            }
+        }
+        body.instruction(&Instruction::LocalGet(0))
+            .instruction(&Instruction::End);
+        let mut code = wasm_encoder::CodeSection::new();
+        code.function(&body);
+        module.section(&code);
+
+        module.finish()
+    }
+}
+
+fn leb128(bytes: &mut Vec<u8>, mut val: u32) {
+    loop {
+        let byte = (val & 0x7f) as u8;
+        val >>= 7;
+        if val == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::prelude::*;
+    use wasmparser::Validator;
+
+    #[test]
+    fn memref_ops_generates_valid_wasm_modules() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut buf = vec![0; 2048];
+        for _ in 0..1024 {
+            rng.fill_bytes(&mut buf);
+            let u = Unstructured::new(&buf);
+            if let Ok(ops) = MemRefOps::arbitrary_take_rest(u) {
+                Validator::new().validate_all(&ops.wasm(false)).unwrap();
+                Validator::new().validate_all(&ops.wasm(true)).unwrap();
+            }
+        }
+    }
+}
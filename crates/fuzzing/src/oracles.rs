@@ -16,12 +16,14 @@ pub mod diff_wasmi;
 pub mod diff_wasmtime;
 pub mod dummy;
 pub mod engine;
+mod memref_ops;
 mod stacks;
 
 use self::diff_wasmtime::WasmtimeInstance;
 use self::engine::{DiffEngine, DiffInstance};
 use crate::generators::{self, DiffValue, DiffValueType};
 use arbitrary::Arbitrary;
+pub use memref_ops::check_memref_ops;
 pub use stacks::check_stacks;
 use std::cell::Cell;
 use std::rc::Rc;
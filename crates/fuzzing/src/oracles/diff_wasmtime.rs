@@ -212,6 +212,7 @@ impl Into<DiffValue> for Val {
             Val::F32(n) => DiffValue::F32(n),
             Val::F64(n) => DiffValue::F64(n),
             Val::V128(n) => DiffValue::V128(n),
+            Val::MemRef(m) => DiffValue::V128(m.as_u128()),
             Val::FuncRef(f) => DiffValue::FuncRef { null: f.is_none() },
             Val::ExternRef(e) => DiffValue::ExternRef { null: e.is_none() },
         }
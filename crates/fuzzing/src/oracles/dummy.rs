@@ -49,6 +49,7 @@ pub fn dummy_value(val_ty: ValType) -> Val {
         ValType::V128 => Val::V128(0),
         ValType::ExternRef => Val::ExternRef(None),
         ValType::FuncRef => Val::FuncRef(None),
+        ValType::MemRef => Val::MemRef(MemRef::null()),
     }
 }
 
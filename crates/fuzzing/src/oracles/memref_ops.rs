@@ -0,0 +1,79 @@
+use crate::generators::MemRefOps;
+use anyhow::Result;
+use wasmtime::*;
+
+/// Run the given `MemRefOps` test case and assert that its accesses within
+/// bounds behave the same under every memref check policy, and that its access
+/// out of bounds, if any, traps when all accesses are checked.
+pub fn check_memref_ops(ops: MemRefOps) {
+    let wasm = ops.wasm(false);
+    crate::oracles::log_wasm(&wasm);
+
+    let (sum, memory) = run_memref_ops(MemRefCheckPolicy::Full, &wasm);
+    let sum = sum.expect("accesses within bounds should not trap");
+    for policy in [MemRefCheckPolicy::StoreOnly, MemRefCheckPolicy::UpperOnly] {
+        log::debug!("running with {policy:?} memref checks");
+        let (other_sum, other_memory) = run_memref_ops(policy, &wasm);
+        let other_sum = other_sum.expect("accesses within bounds should not trap");
+        assert_eq!(sum, other_sum);
+        assert!(
+            memory == other_memory,
+            "memory differs with {policy:?} checks"
+        );
+    }
+
+    if let Some(expected) = ops.violation() {
+        let wasm = ops.wasm(true);
+        crate::oracles::log_wasm(&wasm);
+        let err = run_memref_ops(MemRefCheckPolicy::Full, &wasm)
+            .0
+            .expect_err("access out of bounds should trap");
+        log::debug!("trap: {:?}", err);
+        assert_eq!(err.downcast_ref::<Trap>(), Some(&Trap::MemRefOutOfBounds));
+        assert_eq!(err.downcast_ref::<MemRefViolation>(), Some(&expected));
+    }
+}
+
+/// Runs the `run` export of `wasm` compiled with the memref check `policy`,
+/// returning its result and the final contents of its memory.
+fn run_memref_ops(policy: MemRefCheckPolicy, wasm: &[u8]) -> (Result<i32>, Vec<u8>) {
+    let mut config = Config::new();
+    config.memref_check_policy(policy);
+    let engine = Engine::new(&config).unwrap();
+    let module = Module::new(&engine, wasm).expect("should compile okay");
+
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[]).expect("should instantiate okay");
+    let run = instance
+        .get_typed_func::<(), i32>(&mut store, "run")
+        .expect("should export `run` function");
+    let result = run.call(&mut store, ());
+
+    let memory = instance
+        .get_memory(&mut store, "memory")
+        .expect("should have `memory` export");
+    (result, memory.data(&store).to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arbitrary::{Arbitrary, Unstructured};
+    use rand::prelude::*;
+
+    #[test]
+    fn smoke_test() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut buf = vec![0; 2048];
+        let mut violations = 0;
+        for _ in 0..100 {
+            rng.fill_bytes(&mut buf);
+            let u = Unstructured::new(&buf);
+            if let Ok(ops) = MemRefOps::arbitrary_take_rest(u) {
+                violations += ops.violation().is_some() as usize;
+                check_memref_ops(ops);
+            }
+        }
+        assert!(violations > 0, "never generated an access out of bounds");
+    }
+}
@@ -0,0 +1,98 @@
+//! Adds support for profiling JIT-ed code using `perf` with perf map files.
+//! To enable it at runtime, use the `--perfmap` CLI flag.
+//!
+//! A perf map is a text file at `/tmp/perf-<pid>.map` listing the address,
+//! size and name of every function generated at runtime, one per line. It's
+//! documented in `tools/perf/Documentation/jit-interface.txt` of the Linux
+//! source tree.
+//!
+//! ### Profile
+//!
+//! ```ignore
+//! perf record -g target/debug/wasmtime --perfmap test.wasm
+//! perf report
+//! ```
+
+use crate::{CompiledModule, ProfilingAgent};
+use anyhow::Result;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::process;
+use std::sync::Mutex;
+use wasmtime_environ::EntityRef;
+
+/// The perf map file of this process. Perf reads a single file per process,
+/// so it's shared by every agent.
+static PERFMAP_FILE: Mutex<Option<File>> = Mutex::new(None);
+
+/// Interface for driving the creation of perf map files
+#[derive(Debug)]
+pub struct PerfMapAgent {
+    _private: (),
+}
+
+impl PerfMapAgent {
+    /// Initialize a PerfMapAgent, opening the perf map file of this process.
+    pub fn new() -> Result<Self> {
+        let mut file = PERFMAP_FILE.lock().unwrap();
+        if file.is_none() {
+            let filename = format!("/tmp/perf-{}.map", process::id());
+            *file = Some(File::create(filename)?);
+        }
+        Ok(PerfMapAgent { _private: () })
+    }
+
+    /// Appends the line of one code region to `writer`.
+    fn write_line(
+        writer: &mut dyn Write,
+        name: &str,
+        addr: *const u8,
+        len: usize,
+    ) -> io::Result<()> {
+        // Names come from the wasm module and may hold any character, but
+        // each entry must fit on one line.
+        let name = name.replace(|c: char| c == '\n' || c == '\r', "_");
+        writeln!(writer, "{:x} {:x} {}", addr as usize, len, name)
+    }
+
+    fn write_module(writer: &mut dyn Write, module: &CompiledModule) -> io::Result<()> {
+        for (idx, func) in module.finished_functions() {
+            let name = super::debug_name(module, idx);
+            Self::write_line(writer, &name, func.as_ptr(), func.len())?;
+        }
+
+        // Note: these are the trampolines into exported functions.
+        for (idx, func, len) in module.trampolines() {
+            let name = format!("wasm::trampoline[{}]", idx.index());
+            Self::write_line(writer, &name, func as usize as *const u8, len)?;
+        }
+        Ok(())
+    }
+}
+
+impl ProfilingAgent for PerfMapAgent {
+    fn module_load(&self, module: &CompiledModule, _dbg_image: Option<&[u8]>) {
+        let mut file = PERFMAP_FILE.lock().unwrap();
+        let mut writer = BufWriter::new(file.as_mut().unwrap());
+        if let Err(e) = Self::write_module(&mut writer, module).and_then(|()| writer.flush()) {
+            log::warn!("failed to write to the perf map file: {}", e);
+        }
+    }
+
+    fn load_single_trampoline(
+        &self,
+        name: &str,
+        addr: *const u8,
+        size: usize,
+        _pid: u32,
+        _tid: u32,
+    ) {
+        let mut file = PERFMAP_FILE.lock().unwrap();
+        let mut writer = BufWriter::new(file.as_mut().unwrap());
+        if let Err(e) =
+            Self::write_line(&mut writer, name, addr, size).and_then(|()| writer.flush())
+        {
+            log::warn!("failed to write to the perf map file: {}", e);
+        }
+    }
+}
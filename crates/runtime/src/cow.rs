@@ -197,6 +197,32 @@ impl MemoryImage {
         }
     }
 
+    /// Creates an image holding a snapshot of `data`, the current contents of
+    /// a region of memory starting at its base.
+    ///
+    /// Leading and trailing pages of zeros are left out of the image, since a
+    /// `MemoryImageSlot` maps zeros around the image anyway. Returns `None` on
+    /// platforms which can't represent the snapshot as an image.
+    pub(crate) fn from_snapshot(data: &[u8]) -> Result<Option<MemoryImage>> {
+        let page_size = crate::page_size();
+        assert_eq!(data.len() % page_size, 0);
+        let is_zero = |page: &[u8]| page.iter().all(|b| *b == 0);
+        let pages = data.len() / page_size;
+        let start = data
+            .chunks(page_size)
+            .position(|page| !is_zero(page))
+            .unwrap_or(pages);
+        let end = pages
+            - data
+                .chunks(page_size)
+                .rev()
+                .take(pages - start)
+                .position(|page| !is_zero(page))
+                .unwrap_or(0);
+        let data = &data[start * page_size..end * page_size];
+        MemoryImage::new(page_size as u32, (start * page_size) as u64, data, None)
+    }
+
     unsafe fn map_at(&self, base: usize) -> Result<()> {
         cfg_if::cfg_if! {
             if #[cfg(unix)] {
@@ -421,6 +447,20 @@ impl MemoryImageSlot {
         }
     }
 
+    /// Create a new MemoryImageSlot on top of memory which is already in use.
+    ///
+    /// The `accessible` bytes at `base_addr` may hold any contents, so the
+    /// slot starts out dirty and must be reset before it's instantiated.
+    pub(crate) fn create_dirty(
+        base_addr: *mut c_void,
+        accessible: usize,
+        static_size: usize,
+    ) -> Self {
+        let mut slot = MemoryImageSlot::create(base_addr, accessible, static_size);
+        slot.dirty = true;
+        slot
+    }
+
     #[cfg(feature = "pooling-allocator")]
     pub(crate) fn dummy() -> MemoryImageSlot {
         MemoryImageSlot {
@@ -553,6 +593,25 @@ impl MemoryImageSlot {
         Ok(())
     }
 
+    /// Resets this slot to `size_bytes` of accessible memory holding `image`,
+    /// whether or not the slot is dirty.
+    ///
+    /// This is how memory is rolled back to a snapshot: once `image` is
+    /// mapped in this slot, resetting it again on Linux only discards the
+    /// pages written in the meantime, so its cost scales with the number of
+    /// dirty pages rather than with the size of memory.
+    pub(crate) fn reset_to_image(
+        &mut self,
+        size_bytes: usize,
+        image: &Arc<MemoryImage>,
+        style: &MemoryStyle,
+    ) -> Result<()> {
+        if self.dirty {
+            self.clear_and_remain_ready(0)?;
+        }
+        self.instantiate(size_bytes, Some(image), style)
+    }
+
     pub(crate) fn remove_image(&mut self) -> Result<()> {
         if let Some(image) = &self.image {
             unsafe {
@@ -1026,4 +1085,43 @@ mod test {
         assert_eq!(&[0, 0, 0, 0], &slice[4096..4100]);
         assert_eq!(&[0, 0], &slice[initial..initial + 2]);
     }
+
+    #[test]
+    fn reset_to_snapshot() {
+        let style = MemoryStyle::Static { bound: 4 << 30 };
+        let page_size = crate::page_size();
+        let size = 16 * page_size;
+        let mut mmap = Mmap::accessible_reserved(0, 4 << 20).unwrap();
+        mmap.make_accessible(0, size).unwrap();
+
+        // Take a snapshot of memory which is already in use.
+        let slice = mmap.as_mut_slice();
+        slice[page_size + 1] = 1;
+        slice[2 * page_size + 2] = 2;
+        let image = MemoryImage::from_snapshot(&slice[..size]).unwrap();
+        let image = Arc::new(image.unwrap());
+        assert_eq!(image.linear_memory_offset, page_size);
+        assert_eq!(image.len, 2 * page_size);
+
+        let mut memfd = MemoryImageSlot::create_dirty(mmap.as_mut_ptr() as *mut _, size, 4 << 20);
+        memfd.no_clear_on_drop();
+        for _ in 0..2 {
+            let slice = mmap.as_mut_slice();
+            slice[0] = 5;
+            slice[page_size + 1] = 5;
+            memfd.reset_to_image(size, &image, &style).unwrap();
+            assert!(memfd.is_dirty());
+            let slice = mmap.as_slice();
+            assert_eq!(slice[0], 0);
+            assert_eq!(slice[page_size + 1], 1);
+            assert_eq!(slice[2 * page_size + 2], 2);
+        }
+
+        // Resetting to a smaller size than the current one shrinks memory.
+        memfd.set_heap_limit(2 * size).unwrap();
+        mmap.as_mut_slice()[size + 1] = 5;
+        memfd.reset_to_image(size, &image, &style).unwrap();
+        memfd.set_heap_limit(2 * size).unwrap();
+        assert_eq!(mmap.as_slice()[size + 1], 0);
+    }
 }
@@ -1013,6 +1013,8 @@ mod tests {
             num_owned_memories: 0,
             num_defined_globals: 0,
             num_escaped_funcs: 0,
+            num_memref_counters: 0,
+            has_memref_hook_memory: false,
         });
         assert_eq!(
             offsets.vm_extern_data_ref_count(),
@@ -1040,6 +1042,8 @@ mod tests {
             num_owned_memories: 0,
             num_defined_globals: 0,
             num_escaped_funcs: 0,
+            num_memref_counters: 0,
+            has_memref_hook_memory: false,
         });
         assert_eq!(
             offsets.vm_extern_ref_activation_table_next() as usize,
@@ -1067,6 +1071,8 @@ mod tests {
             num_owned_memories: 0,
             num_defined_globals: 0,
             num_escaped_funcs: 0,
+            num_memref_counters: 0,
+            has_memref_hook_memory: false,
         });
         assert_eq!(
             offsets.vm_extern_ref_activation_table_end() as usize,
@@ -4,8 +4,8 @@
 
 use crate::export::Export;
 use crate::externref::VMExternRefActivationsTable;
-use crate::memory::{Memory, RuntimeMemoryCreator};
-use crate::table::{Table, TableElement, TableElementType};
+use crate::memory::{Memory, MemorySnapshot, RuntimeMemoryCreator};
+use crate::table::{Table, TableElement, TableElementType, TableSnapshot};
 use crate::vmcontext::{
     VMBuiltinFunctionsArray, VMCallerCheckedAnyfunc, VMContext, VMFunctionImport,
     VMGlobalDefinition, VMGlobalImport, VMMemoryDefinition, VMMemoryImport, VMOpaqueContext,
@@ -13,7 +13,7 @@ use crate::vmcontext::{
 };
 use crate::{
     ExportFunction, ExportGlobal, ExportMemory, ExportTable, Imports, ModuleRuntimeInfo, Store,
-    VMFunctionBody, VMSharedSignatureIndex,
+    VMExternRef, VMFunctionBody, VMSharedSignatureIndex,
 };
 use anyhow::Error;
 use memoffset::offset_of;
@@ -26,11 +26,12 @@ use std::ptr::NonNull;
 use std::sync::atomic::AtomicU64;
 use std::sync::Arc;
 use std::{mem, ptr};
+use wasmtime_environ::memref::MemRefCounter;
 use wasmtime_environ::{
     packed_option::ReservedValue, DataIndex, DefinedGlobalIndex, DefinedMemoryIndex,
     DefinedTableIndex, ElemIndex, EntityIndex, EntityRef, EntitySet, FuncIndex, GlobalIndex,
     GlobalInit, HostPtr, MemoryIndex, Module, PrimaryMap, SignatureIndex, TableIndex,
-    TableInitialization, Trap, VMOffsets, WasmType,
+    TableInitialization, TableSegmentElements, Trap, VMOffsets, WasmType,
 };
 
 mod allocator;
@@ -206,6 +207,16 @@ impl Instance {
         }
     }
 
+    /// Get a pointer to the `VMMemoryDefinition` of a locally defined or
+    /// imported memory.
+    fn get_memory_ptr(&self, index: MemoryIndex) -> *mut VMMemoryDefinition {
+        if let Some(defined_index) = self.module().defined_memory_index(index) {
+            self.memory_ptr(defined_index)
+        } else {
+            self.imported_memory(index).from
+        }
+    }
+
     /// Return the indexed `VMMemoryDefinition`.
     fn memory(&self, index: DefinedMemoryIndex) -> VMMemoryDefinition {
         unsafe { VMMemoryDefinition::load(self.memory_ptr(index)) }
@@ -476,6 +487,75 @@ impl Instance {
         result
     }
 
+    /// Takes a snapshot of the memories, tables and mutable globals defined
+    /// by this instance.
+    pub(crate) fn snapshot(&mut self) -> Result<InstanceSnapshot, Error> {
+        let memories = self
+            .memories
+            .values_mut()
+            .map(|memory| memory.snapshot())
+            .collect::<Result<_, _>>()?;
+        let tables = self.tables.values().map(|table| table.snapshot()).collect();
+
+        let module = self.module().clone();
+        let mut globals = Vec::new();
+        for (index, global) in module.globals.iter() {
+            let index = match module.defined_global_index(index) {
+                Some(index) if global.mutability => index,
+                _ => continue,
+            };
+            let from = self.global(index);
+            // As when initializing globals, `externref`s need their reference
+            // count managed while everything else is just copy-able bits.
+            let value = unsafe {
+                match global.wasm_ty {
+                    WasmType::ExternRef => GlobalSnapshot::ExternRef(from.as_externref().clone()),
+                    _ => GlobalSnapshot::Bits(*from.as_u128()),
+                }
+            };
+            globals.push((index, value));
+        }
+
+        Ok(InstanceSnapshot {
+            memories,
+            tables,
+            globals,
+        })
+    }
+
+    /// Resets the memories, tables and mutable globals defined by this
+    /// instance to `snapshot`, which was taken from this instance.
+    pub(crate) fn restore(&mut self, snapshot: &InstanceSnapshot) -> Result<(), Error> {
+        let module = self.module().clone();
+        for (index, memory) in snapshot.memories.iter() {
+            let plan = &module.memory_plans[module.memory_index(index)];
+            unsafe {
+                self.memories[index].restore(memory, &plan.style)?;
+            }
+            // Keep the `VMContext` pointers used by compiled Wasm code up to
+            // date.
+            let vmmemory = self.memories[index].vmmemory();
+            self.set_memory(index, vmmemory);
+        }
+        for (index, table) in snapshot.tables.iter() {
+            unsafe {
+                self.tables[index].restore(table)?;
+            }
+            let vmtable = self.tables[index].vmtable();
+            self.set_table(index, vmtable);
+        }
+        for (index, value) in snapshot.globals.iter() {
+            let to = self.global_ptr(*index);
+            unsafe {
+                match value {
+                    GlobalSnapshot::ExternRef(x) => *(*to).as_externref_mut() = x.clone(),
+                    GlobalSnapshot::Bits(bits) => *(*to).as_u128_mut() = *bits,
+                }
+            }
+        }
+        Ok(())
+    }
+
     fn alloc_layout(offsets: &VMOffsets<HostPtr>) -> Layout {
         let size = mem::size_of::<Self>()
             .checked_add(usize::try_from(offsets.size_of_vmctx()).unwrap())
@@ -599,11 +679,12 @@ impl Instance {
         // disconnected from the lifetime of `self`.
         let module = self.module().clone();
 
+        let empty = TableSegmentElements::Functions(Box::new([]));
         let elements = match module.passive_elements_map.get(&elem_index) {
             Some(index) if !self.dropped_elements.contains(elem_index) => {
-                module.passive_elements[*index].as_ref()
+                &module.passive_elements[*index]
             }
-            _ => &[],
+            _ => &empty,
         };
         self.table_init_segment(table_index, elements, dst, src, len)
     }
@@ -611,7 +692,7 @@ impl Instance {
     pub(crate) fn table_init_segment(
         &mut self,
         table_index: TableIndex,
-        elements: &[FuncIndex],
+        elements: &TableSegmentElements,
         dst: u32,
         src: u32,
         len: u32,
@@ -620,12 +701,19 @@ impl Instance {
 
         let table = unsafe { &mut *self.get_table(table_index) };
 
-        let elements = match elements
-            .get(usize::try_from(src).unwrap()..)
-            .and_then(|s| s.get(..usize::try_from(len).unwrap()))
-        {
-            Some(elements) => elements,
-            None => return Err(Trap::TableOutOfBounds),
+        fn segment_range<T>(elements: &[T], src: u32, len: u32) -> Result<&[T], Trap> {
+            elements
+                .get(usize::try_from(src).unwrap()..)
+                .and_then(|s| s.get(..usize::try_from(len).unwrap()))
+                .ok_or(Trap::TableOutOfBounds)
+        }
+
+        let elements = match elements {
+            TableSegmentElements::Functions(funcs) => segment_range(funcs, src, len)?,
+            TableSegmentElements::MemRefs(memrefs) => {
+                let memrefs = segment_range(memrefs, src, len)?;
+                return table.init_memrefs(dst, memrefs.iter().copied());
+            }
         };
 
         match table.element_type() {
@@ -643,6 +731,13 @@ impl Instance {
                 debug_assert!(elements.iter().all(|e| *e == FuncIndex::reserved_value()));
                 table.fill(dst, TableElement::ExternRef(None), len)?;
             }
+
+            // Only a dropped segment, which is empty, can initialize a memref
+            // table with functions.
+            TableElementType::MemRef => {
+                debug_assert!(elements.is_empty());
+                table.init_memrefs(dst, std::iter::empty())?;
+            }
         }
         Ok(())
     }
@@ -985,6 +1080,43 @@ impl Instance {
 
         // Initialize the defined globals
         self.initialize_vmctx_globals(module);
+
+        // Memref counters start out at zero
+        ptr::write_bytes(
+            self.vmctx_plus_offset::<u64>(offsets.vmctx_memref_counters_begin()),
+            0,
+            offsets.num_memref_counters as usize,
+        );
+        if offsets.has_memref_hook_memory {
+            ptr::write(
+                self.vmctx_plus_offset(offsets.vmctx_memref_hook_memory()),
+                0u32,
+            );
+        }
+    }
+
+    /// Returns the current value of the memref `counter` of this instance.
+    fn memref_counter(&self, counter: MemRefCounter) -> u64 {
+        if self.offsets().num_memref_counters == 0 {
+            return 0;
+        }
+        unsafe { *self.vmctx_plus_offset(self.offsets().vmctx_memref_counter(counter)) }
+    }
+
+    /// Returns the `VMMemoryDefinition` of the memory the last memref
+    /// metadata hook call was for, or null if this instance has no memory.
+    fn memref_hook_memory(&self) -> *mut VMMemoryDefinition {
+        let offsets = self.offsets();
+        let index = if offsets.has_memref_hook_memory {
+            unsafe { *self.vmctx_plus_offset::<u32>(offsets.vmctx_memref_hook_memory()) }
+        } else {
+            0
+        };
+        let index = MemoryIndex::from_u32(index);
+        if self.module().memory_plans.get(index).is_none() {
+            return ptr::null_mut();
+        }
+        self.get_memory_ptr(index)
     }
 
     unsafe fn initialize_vmctx_globals(&mut self, module: &Module) {
@@ -1055,6 +1187,25 @@ impl Drop for Instance {
     }
 }
 
+/// A snapshot of the state of an instance, taken with
+/// [`InstanceHandle::snapshot`].
+///
+/// This holds the contents of the memories, tables and mutable globals defined
+/// by the instance. Imported items are part of the state of the instance
+/// defining them instead.
+pub struct InstanceSnapshot {
+    memories: PrimaryMap<DefinedMemoryIndex, MemorySnapshot>,
+    tables: PrimaryMap<DefinedTableIndex, TableSnapshot>,
+    globals: Vec<(DefinedGlobalIndex, GlobalSnapshot)>,
+}
+
+/// The value of a global at the time of a snapshot.
+enum GlobalSnapshot {
+    ExternRef(Option<VMExternRef>),
+    /// The bits of any other value, including the pointer of a `funcref`.
+    Bits(u128),
+}
+
 /// A handle holding an `Instance` of a WebAssembly module.
 #[derive(Hash, PartialEq, Eq)]
 pub struct InstanceHandle {
@@ -1097,6 +1248,23 @@ impl InstanceHandle {
         self.instance().vmctx_ptr()
     }
 
+    /// Returns the current value of the memref `counter` of this instance.
+    ///
+    /// Counters are only allocated, and incremented, when the module was
+    /// compiled with memref statistics enabled; otherwise this returns zero.
+    pub fn memref_counter(&self, counter: MemRefCounter) -> u64 {
+        self.instance().memref_counter(counter)
+    }
+
+    /// Returns the `VMMemoryDefinition` of the memory, defined by this
+    /// instance or imported, which the memref metadata hook being called from
+    /// this instance records or looks up metadata for.
+    ///
+    /// This is null if the instance has no memory at all.
+    pub fn memref_hook_memory(&self) -> *mut VMMemoryDefinition {
+        self.instance().memref_hook_memory()
+    }
+
     /// Return a reference to a module.
     pub fn module(&self) -> &Arc<Module> {
         self.instance().module()
@@ -1172,6 +1340,21 @@ impl InstanceHandle {
         self.instance_mut().get_table_with_lazy_init(index, range)
     }
 
+    /// Takes a snapshot of the memories, tables and mutable globals defined
+    /// by this instance, which it can later be reset to with
+    /// [`InstanceHandle::restore`].
+    pub fn snapshot(&mut self) -> Result<InstanceSnapshot, Error> {
+        self.instance_mut().snapshot()
+    }
+
+    /// Resets the memories, tables and mutable globals defined by this
+    /// instance to `snapshot`.
+    ///
+    /// The snapshot must have been taken from this instance.
+    pub fn restore(&mut self, snapshot: &InstanceSnapshot) -> Result<(), Error> {
+        self.instance_mut().restore(snapshot)
+    }
+
     /// Return a reference to the contained `Instance`.
     #[inline]
     pub(crate) fn instance(&self) -> &Instance {
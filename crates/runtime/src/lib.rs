@@ -23,7 +23,7 @@
 use anyhow::Error;
 use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
-use wasmtime_environ::{DefinedFuncIndex, DefinedMemoryIndex, HostPtr, VMOffsets};
+use wasmtime_environ::{DefinedFuncIndex, DefinedMemoryIndex, HostPtr, MemRefViolation, VMOffsets};
 
 #[macro_use]
 mod trampolines;
@@ -52,7 +52,7 @@ pub use crate::externref::*;
 pub use crate::imports::Imports;
 pub use crate::instance::{
     allocate_single_memory_instance, InstanceAllocationRequest, InstanceAllocator, InstanceHandle,
-    OnDemandInstanceAllocator, StorePtr,
+    InstanceSnapshot, OnDemandInstanceAllocator, StorePtr,
 };
 #[cfg(feature = "pooling-allocator")]
 pub use crate::instance::{
@@ -60,11 +60,12 @@ pub use crate::instance::{
     PoolingInstanceAllocatorConfig,
 };
 pub use crate::memory::{
-    DefaultMemoryCreator, Memory, RuntimeLinearMemory, RuntimeMemoryCreator, SharedMemory,
+    DefaultMemoryCreator, Memory, MemorySnapshot, RuntimeLinearMemory, RuntimeMemoryCreator,
+    SharedMemory,
 };
 pub use crate::mmap::Mmap;
 pub use crate::mmap_vec::MmapVec;
-pub use crate::table::{Table, TableElement};
+pub use crate::table::{Table, TableElement, TableSnapshot};
 pub use crate::trampolines::prepare_host_to_wasm_trampoline;
 pub use crate::traphandlers::{
     catch_traps, init_traps, raise_lib_trap, raise_user_trap, resume_panic, tls_eager_initialize,
@@ -150,6 +151,10 @@ pub unsafe trait Store {
     /// number. Cannot fail; cooperative epoch-based yielding is
     /// completely semantically transparent. Returns the new deadline.
     fn new_epoch(&mut self) -> Result<u64, Error>;
+    /// Callback invoked whenever a memref bounds check fails. Returns an
+    /// error to raise the violation as a trap, or `Ok(())` if it was only
+    /// recorded and execution should continue.
+    fn memref_out_of_bounds(&mut self, violation: MemRefViolation) -> Result<(), Error>;
 }
 
 /// Functionality required by this crate for a particular module. This
@@ -55,7 +55,7 @@
 //! ```
 
 use crate::externref::VMExternRef;
-use crate::table::{Table, TableElementType};
+use crate::table::{Table, TableElement, TableElementType};
 use crate::vmcontext::{VMCallerCheckedAnyfunc, VMContext};
 use crate::TrapReason;
 use anyhow::Result;
@@ -63,7 +63,7 @@ use std::mem;
 use std::ptr::{self, NonNull};
 use std::time::{Duration, Instant};
 use wasmtime_environ::{
-    DataIndex, ElemIndex, FuncIndex, GlobalIndex, MemoryIndex, TableIndex, Trap,
+    DataIndex, ElemIndex, FuncIndex, GlobalIndex, MemRefViolation, MemoryIndex, TableIndex, Trap,
 };
 
 /// Actually public trampolines which are used by the runtime as the entrypoint
@@ -214,6 +214,7 @@ unsafe fn table_grow(
             };
             init_value.into()
         }
+        TableElementType::MemRef => unreachable!("memref tables grow with `table_grow_memref`"),
     };
     Ok(match instance.table_grow(table_index, delta, element)? {
         Some(r) => r,
@@ -224,6 +225,23 @@ unsafe fn table_grow(
 use table_grow as table_grow_funcref;
 use table_grow as table_grow_externref;
 
+// Implementation of `table.grow` for `memref` tables.
+unsafe fn table_grow_memref(
+    vmctx: *mut VMContext,
+    table_index: u32,
+    delta: u32,
+    init_lo: u64,
+    init_hi: u64,
+) -> Result<u32> {
+    let instance = (*vmctx).instance_mut();
+    let table_index = TableIndex::from_u32(table_index);
+    let element = TableElement::MemRef(u128::from(init_hi) << 64 | u128::from(init_lo));
+    Ok(match instance.table_grow(table_index, delta, element)? {
+        Some(r) => r,
+        None => -1_i32 as u32,
+    })
+}
+
 // Implementation of `table.fill`.
 unsafe fn table_fill(
     vmctx: *mut VMContext,
@@ -250,12 +268,31 @@ unsafe fn table_fill(
             };
             table.fill(dst, val.into(), len)
         }
+        TableElementType::MemRef => {
+            unreachable!("memref tables are filled with `table_fill_memref`")
+        }
     }
 }
 
 use table_fill as table_fill_funcref;
 use table_fill as table_fill_externref;
 
+// Implementation of `table.fill` for `memref` tables.
+unsafe fn table_fill_memref(
+    vmctx: *mut VMContext,
+    table_index: u32,
+    dst: u32,
+    val_lo: u64,
+    val_hi: u64,
+    len: u32,
+) -> Result<(), Trap> {
+    let instance = (*vmctx).instance_mut();
+    let table_index = TableIndex::from_u32(table_index);
+    let table = &mut *instance.get_table(table_index);
+    let val = TableElement::MemRef(u128::from(val_hi) << 64 | u128::from(val_lo));
+    table.fill(dst, val, len)
+}
+
 // Implementation of `table.copy`.
 unsafe fn table_copy(
     vmctx: *mut VMContext,
@@ -492,3 +529,14 @@ unsafe fn out_of_gas(vmctx: *mut VMContext) -> Result<()> {
 unsafe fn new_epoch(vmctx: *mut VMContext) -> Result<u64> {
     (*(*vmctx).instance().store()).new_epoch()
 }
+
+// Hook for when a memref bounds check fails, raising the violation along with
+// the details of the offending access unless the store only records it.
+unsafe fn memref_out_of_bounds(
+    vmctx: *mut VMContext,
+    addr: u32,
+    base: u32,
+    end: u32,
+) -> Result<()> {
+    (*(*vmctx).instance().store()).memref_out_of_bounds(MemRefViolation { addr, base, end })
+}
@@ -9,10 +9,11 @@ use crate::{MemoryImage, MemoryImageSlot, Store, WaitResult};
 use anyhow::Error;
 use anyhow::{bail, format_err, Result};
 use std::convert::TryFrom;
+use std::ptr;
 use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use std::sync::{Arc, RwLock};
 use std::time::Instant;
-use wasmtime_environ::{MemoryPlan, MemoryStyle, Trap, WASM32_MAX_PAGES, WASM64_MAX_PAGES};
+use wasmtime_environ::{memref, MemoryPlan, MemoryStyle, Trap, WASM32_MAX_PAGES, WASM64_MAX_PAGES};
 
 const WASM_PAGE_SIZE: usize = wasmtime_environ::WASM_PAGE_SIZE as usize;
 const WASM_PAGE_SIZE_U64: u64 = wasmtime_environ::WASM_PAGE_SIZE as u64;
@@ -261,6 +262,34 @@ impl MmapMemory {
             memory_image,
         })
     }
+
+    /// Resets this memory to `size` bytes holding the contents of `image`.
+    fn reset_to_image(
+        &mut self,
+        size: usize,
+        image: &Arc<MemoryImage>,
+        style: &MemoryStyle,
+    ) -> Result<()> {
+        // Growing a dynamic memory beyond its reservation moves it, so that
+        // happens before a slot is set up on top of the final mapping.
+        if size > self.accessible {
+            self.grow_to(size)?;
+        }
+
+        let base = unsafe { self.mmap.as_mut_ptr().add(self.pre_guard_size) };
+        let static_size = self.mmap.len() - self.pre_guard_size - self.offset_guard_size;
+        let accessible = self.accessible;
+        let slot = self.memory_image.get_or_insert_with(|| {
+            let mut slot = MemoryImageSlot::create_dirty(base.cast(), accessible, static_size);
+            // As in `MmapMemory::new`, unmapping our mmap on drop takes care
+            // of the slot's mappings.
+            slot.no_clear_on_drop();
+            slot
+        });
+        slot.reset_to_image(size, image, style)?;
+        self.accessible = size;
+        Ok(())
+    }
 }
 
 impl RuntimeLinearMemory for MmapMemory {
@@ -326,6 +355,8 @@ impl RuntimeLinearMemory for MmapMemory {
         VMMemoryDefinition {
             base: unsafe { self.mmap.as_mut_ptr().add(self.pre_guard_size) },
             current_length: self.accessible.into(),
+            memref_shadow: ptr::null_mut(),
+            memref_locks: ptr::null_mut(),
         }
     }
 
@@ -383,6 +414,19 @@ impl StaticMemory {
             memory_image,
         })
     }
+
+    /// Resets this memory to `size` bytes holding the contents of `image`.
+    fn reset_to_image(
+        &mut self,
+        size: usize,
+        image: &Arc<MemoryImage>,
+        style: &MemoryStyle,
+    ) -> Result<()> {
+        assert!(size <= self.base.len());
+        self.memory_image.reset_to_image(size, image, style)?;
+        self.size = size;
+        Ok(())
+    }
 }
 
 impl RuntimeLinearMemory for StaticMemory {
@@ -410,6 +454,8 @@ impl RuntimeLinearMemory for StaticMemory {
         VMMemoryDefinition {
             base: self.base.as_mut_ptr().cast(),
             current_length: self.size.into(),
+            memref_shadow: ptr::null_mut(),
+            memref_locks: ptr::null_mut(),
         }
     }
 
@@ -479,7 +525,7 @@ impl SharedMemory {
 
     /// Convert this shared memory into a [`Memory`].
     pub fn as_memory(self) -> Memory {
-        Memory(Box::new(self))
+        Memory(Box::new(self), MemRefRegions::default())
     }
 
     /// Return a pointer to the shared memory's [VMMemoryDefinition].
@@ -622,8 +668,253 @@ impl RuntimeLinearMemory for SharedMemory {
     }
 }
 
+/// Regions reserved alongside a linear memory to support memref checks.
+#[derive(Default)]
+struct MemRefRegions {
+    /// The shadow region holding memref metadata, present if requested by
+    /// the memory's `MemoryPlan`.
+    shadow: Option<MemRefRegion>,
+    /// The lock table tracking live memref allocations, present if requested
+    /// by the memory's `MemoryPlan`.
+    locks: Option<MemRefRegion>,
+}
+
+impl MemRefRegions {
+    /// Allocates the regions needed by a memory created from `plan` which
+    /// starts out `size` bytes large.
+    fn new(plan: &MemoryPlan, size: usize) -> Result<Self> {
+        Ok(MemRefRegions {
+            shadow: Self::new_shadow(plan, size)?.map(MemRefRegion::new),
+            locks: Self::new_locks(plan)?.map(MemRefRegion::new),
+        })
+    }
+
+    /// Allocates the shadow region holding memref metadata for a memory
+    /// created from `plan` which starts out `size` bytes large, if it needs
+    /// one.
+    ///
+    /// The region only covers the addresses of the memory at its current
+    /// size and is moved to a larger one as the memory grows, see
+    /// `MemRefRegions::grow_shadow`. Its pages are only backed once compiled
+    /// code writes metadata to them.
+    fn new_shadow(plan: &MemoryPlan, size: usize) -> Result<Option<Mmap>> {
+        if !plan.memref_shadow {
+            return Ok(None);
+        }
+        Ok(Some(Mmap::sparse(shadow_mapping_size(size)?)?))
+    }
+
+    /// Allocates the zeroed lock table tracking live memref allocations for a
+    /// memory created from `plan`, if it needs one.
+    fn new_locks(plan: &MemoryPlan) -> Result<Option<Mmap>> {
+        if !plan.memref_locks {
+            return Ok(None);
+        }
+        let size = usize::try_from(memref::LOCK_TABLE_SIZE)
+            .map_err(|_| format_err!("memref lock table too large for this host"))?;
+        Ok(Some(Mmap::with_at_least(size)?))
+    }
+
+    /// Makes the shadow region large enough to hold the records of a memory
+    /// of `size` bytes, moving it to a larger mapping if needed.
+    ///
+    /// The new mapping is at least twice as large as the old one, so that
+    /// memories growing a page at a time don't move their records each time,
+    /// and only the pages of the old one which hold records are copied.
+    fn grow_shadow(&mut self, size: usize) -> Result<()> {
+        let shadow = match &mut self.shadow {
+            Some(shadow) => shadow,
+            None => return Ok(()),
+        };
+        let needed = shadow_mapping_size(size)?;
+        if needed <= shadow.mmap.len() {
+            return Ok(());
+        }
+        let len = needed
+            .max(shadow.mmap.len().saturating_mul(2))
+            .min(shadow_mapping_size(usize::MAX)?);
+        let mut mmap = Mmap::sparse(len)?;
+        let old = shadow.mmap.as_slice();
+        let new = &mut mmap.as_mut_slice()[..old.len()];
+        for (page, old_page) in nonzero_pages(old) {
+            new[page..page + old_page.len()].copy_from_slice(old_page);
+        }
+        *shadow = MemRefRegion::new(mmap);
+        Ok(())
+    }
+
+    /// Returns the length of the part of the shadow region which holds the
+    /// records of a memory of `size` bytes.
+    fn shadow_len(shadow: &MemRefRegion, size: usize) -> usize {
+        let len = memref::shadow_size(size as u64);
+        usize::try_from(len).map_or(shadow.mmap.len(), |len| len.min(shadow.mmap.len()))
+    }
+
+    /// Snapshots the regions of a memory of `size` bytes.
+    fn snapshot(
+        &self,
+        size: usize,
+    ) -> Result<(Option<MemRefRegionSnapshot>, Option<MemRefRegionSnapshot>)> {
+        let shadow = match &self.shadow {
+            Some(shadow) => Some(shadow.snapshot(Self::shadow_len(shadow, size))?),
+            None => None,
+        };
+        let locks = match &self.locks {
+            Some(locks) => Some(locks.snapshot(locks.mmap.len())?),
+            None => None,
+        };
+        Ok((shadow, locks))
+    }
+
+    /// Restores the regions to `snapshot`, where the memory was `size` bytes
+    /// large before being restored.
+    fn restore(&mut self, snapshot: &MemorySnapshot, size: usize) -> Result<()> {
+        if let (Some(shadow), Some(contents)) = (&mut self.shadow, &snapshot.shadow) {
+            // Records only exist for addresses within the memory, so only
+            // those of the largest of the two sizes need to be cleared.
+            let len = Self::shadow_len(shadow, size.max(snapshot.size));
+            shadow.restore(contents, len)?;
+        }
+        if let (Some(locks), Some(contents)) = (&mut self.locks, &snapshot.locks) {
+            let len = locks.mmap.len();
+            locks.restore(contents, len)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the size of the mapping holding the shadow records of a memory of
+/// `size` bytes.
+fn shadow_mapping_size(size: usize) -> Result<usize> {
+    let len = memref::shadow_size(size as u64);
+    let len = usize::try_from(len)
+        .map_err(|_| format_err!("memref shadow metadata requires a 64-bit host"))?;
+    let page_size = crate::page_size();
+    Ok((len + page_size - 1) & !(page_size - 1))
+}
+
+/// Returns the pages of `data` which aren't all zero, along with their
+/// offsets.
+///
+/// Reading the untouched pages of a sparse mapping doesn't back them, so this
+/// is how the records of a shadow region are found without tracking them.
+fn nonzero_pages(data: &[u8]) -> impl Iterator<Item = (usize, &[u8])> {
+    let page_size = crate::page_size();
+    data.chunks(page_size)
+        .enumerate()
+        .filter(|(_, page)| page.iter().any(|b| *b != 0))
+        .map(move |(i, page)| (i * page_size, page))
+}
+
+/// A region reserved alongside a linear memory to support memref checks.
+struct MemRefRegion {
+    mmap: Mmap,
+    /// The slot used to map snapshot images back into the region, set up the
+    /// first time the region is restored from one.
+    image: Option<MemoryImageSlot>,
+}
+
+impl MemRefRegion {
+    fn new(mmap: Mmap) -> Self {
+        MemRefRegion { mmap, image: None }
+    }
+
+    /// Snapshots the first `len` bytes of the region.
+    fn snapshot(&self, len: usize) -> Result<MemRefRegionSnapshot> {
+        let data = &self.mmap.as_slice()[..len];
+        Ok(match MemoryImage::from_snapshot(data)? {
+            Some(image) => MemRefRegionSnapshot::Image(Arc::new(image)),
+            None => MemRefRegionSnapshot::Pages(
+                nonzero_pages(data)
+                    .map(|(offset, page)| (offset, page.to_vec()))
+                    .collect(),
+            ),
+        })
+    }
+
+    /// Restores the region to `snapshot`, making sure that the rest of its
+    /// first `len` bytes are zero.
+    fn restore(&mut self, snapshot: &MemRefRegionSnapshot, len: usize) -> Result<()> {
+        match snapshot {
+            MemRefRegionSnapshot::Image(image) => {
+                let base = self.mmap.as_mut_ptr();
+                let size = self.mmap.len();
+                let slot = self.image.get_or_insert_with(|| {
+                    let mut slot = MemoryImageSlot::create_dirty(base.cast(), size, size);
+                    slot.no_clear_on_drop();
+                    slot
+                });
+                // The whole region always remains accessible.
+                slot.reset_to_image(size, image, &MemoryStyle::Dynamic { reserve: 0 })
+            }
+            MemRefRegionSnapshot::Pages(pages) => {
+                let page_size = crate::page_size();
+                let data = self.mmap.as_mut_slice();
+                // Only the pages written since the snapshot are cleared, so
+                // that the untouched ones stay unbacked.
+                let len = pages
+                    .last()
+                    .map_or(len, |(offset, page)| len.max(offset + page.len()));
+                let mut pages = pages.iter().peekable();
+                for offset in (0..len).step_by(page_size) {
+                    let page = &mut data[offset..(offset + page_size).min(len)];
+                    match pages.next_if(|(o, _)| *o == offset) {
+                        Some((_, contents)) => {
+                            // The last page of the snapshot may be shorter
+                            // if the memory was smaller then.
+                            page[..contents.len()].copy_from_slice(contents);
+                            page[contents.len()..].fill(0);
+                        }
+                        None if page.iter().any(|b| *b != 0) => page.fill(0),
+                        None => {}
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A snapshot of the contents of a linear memory, taken with
+/// [`Memory::snapshot`].
+pub struct MemorySnapshot {
+    size: usize,
+    contents: RegionSnapshot,
+    shadow: Option<MemRefRegionSnapshot>,
+    locks: Option<MemRefRegionSnapshot>,
+}
+
+/// The contents of a region of memory at the time of a snapshot.
+enum RegionSnapshot {
+    /// An image which is mapped copy-on-write into the region to restore it.
+    Image(Arc<MemoryImage>),
+    /// A copy of the region, on platforms which don't support images.
+    Bytes(Vec<u8>),
+}
+
+impl RegionSnapshot {
+    fn new(data: &[u8]) -> Result<RegionSnapshot> {
+        Ok(match MemoryImage::from_snapshot(data)? {
+            Some(image) => RegionSnapshot::Image(Arc::new(image)),
+            None => RegionSnapshot::Bytes(data.to_vec()),
+        })
+    }
+}
+
+/// The contents of a memref region at the time of a snapshot.
+///
+/// These regions are sparse, and mostly zero, so only the pages holding
+/// records are kept.
+enum MemRefRegionSnapshot {
+    /// An image which is mapped copy-on-write into the region to restore it.
+    Image(Arc<MemoryImage>),
+    /// The offsets and contents of the pages which aren't all zero, on
+    /// platforms which don't support images.
+    Pages(Vec<(usize, Vec<u8>)>),
+}
+
 /// Representation of a runtime wasm linear memory.
-pub struct Memory(Box<dyn RuntimeLinearMemory>);
+pub struct Memory(Box<dyn RuntimeLinearMemory>, MemRefRegions);
 
 impl Memory {
     /// Create a new dynamic (movable) memory instance for the specified plan.
@@ -640,7 +931,8 @@ impl Memory {
         } else {
             allocation
         };
-        Ok(Memory(allocation))
+        let regions = MemRefRegions::new(plan, allocation.byte_size())?;
+        Ok(Memory(allocation, regions))
     }
 
     /// Create a new static (immovable) memory instance for the specified plan.
@@ -662,7 +954,8 @@ impl Memory {
         } else {
             allocation
         };
-        Ok(Memory(allocation))
+        let regions = MemRefRegions::new(plan, allocation.byte_size())?;
+        Ok(Memory(allocation, regions))
     }
 
     /// Calls the `store`'s limiter to optionally prevent a memory from being allocated.
@@ -802,6 +1095,18 @@ impl Memory {
         delta_pages: u64,
         store: Option<&mut dyn Store>,
     ) -> Result<Option<usize>, Error> {
+        // The shadow region must cover the new size before compiled code
+        // sees it, so it's grown first; memories which then fail to grow are
+        // simply left with a larger shadow region.
+        let new_size = delta_pages
+            .checked_mul(WASM_PAGE_SIZE_U64)
+            .and_then(|delta| usize::try_from(delta).ok())
+            .and_then(|delta| self.byte_size().checked_add(delta));
+        if let Some(new_size) = new_size {
+            if self.1.grow_shadow(new_size).is_err() {
+                return Ok(None);
+            }
+        }
         self.0
             .grow(delta_pages, store)
             .map(|opt| opt.map(|(old, _new)| old))
@@ -809,7 +1114,84 @@ impl Memory {
 
     /// Return a `VMMemoryDefinition` for exposing the memory to compiled wasm code.
     pub fn vmmemory(&mut self) -> VMMemoryDefinition {
-        self.0.vmmemory()
+        let mut vmmemory = self.0.vmmemory();
+        if let Some(shadow) = &self.1.shadow {
+            vmmemory.memref_shadow = shadow.mmap.as_mut_ptr();
+        }
+        if let Some(locks) = &self.1.locks {
+            vmmemory.memref_locks = locks.mmap.as_mut_ptr();
+        }
+        vmmemory
+    }
+
+    /// Takes a snapshot of the contents of this memory, including its memref
+    /// metadata, which it can later be reset to with [`Memory::restore`].
+    ///
+    /// Where possible the snapshot is held in a [`MemoryImage`], so that
+    /// restoring it only needs to discard the pages written since.
+    pub fn snapshot(&mut self) -> Result<MemorySnapshot> {
+        if self.as_shared_memory().is_some() {
+            bail!("cannot snapshot a shared memory");
+        }
+        let size = self.byte_size();
+        let vmmemory = self.0.vmmemory();
+        let data = unsafe { std::slice::from_raw_parts(vmmemory.base, size) };
+
+        // Images can only be mapped into memories which manage their own
+        // mappings, any other memory is restored by copying its contents.
+        let any = self.0.as_any_mut();
+        let contents = if any.is::<MmapMemory>() || any.is::<StaticMemory>() {
+            RegionSnapshot::new(data)?
+        } else {
+            RegionSnapshot::Bytes(data.to_vec())
+        };
+        let (shadow, locks) = self.1.snapshot(size)?;
+        Ok(MemorySnapshot {
+            size,
+            contents,
+            shadow,
+            locks,
+        })
+    }
+
+    /// Resets the size and contents of this memory to those of `snapshot`,
+    /// which was taken from this memory. `style` is the style of the memory's
+    /// plan.
+    ///
+    /// # Safety
+    ///
+    /// As with [`Memory::grow`], restoring can move the memory, so an
+    /// instance's `VMContext` needs to be updated afterwards.
+    pub unsafe fn restore(&mut self, snapshot: &MemorySnapshot, style: &MemoryStyle) -> Result<()> {
+        let size = self.byte_size();
+        self.1.grow_shadow(snapshot.size)?;
+        match &snapshot.contents {
+            RegionSnapshot::Image(image) => {
+                let any = self.0.as_any_mut();
+                if let Some(memory) = any.downcast_mut::<MmapMemory>() {
+                    memory.reset_to_image(snapshot.size, image, style)?;
+                } else if let Some(memory) = any.downcast_mut::<StaticMemory>() {
+                    memory.reset_to_image(snapshot.size, image, style)?;
+                } else {
+                    unreachable!("only images of mmap'd memories are snapshotted");
+                }
+            }
+            RegionSnapshot::Bytes(bytes) => {
+                if size > snapshot.size {
+                    bail!(
+                        "cannot shrink memory from {} to {} bytes to restore a snapshot",
+                        size,
+                        snapshot.size
+                    );
+                }
+                if size < snapshot.size {
+                    self.0.grow_to(snapshot.size)?;
+                }
+                let vmmemory = self.0.vmmemory();
+                std::slice::from_raw_parts_mut(vmmemory.base, snapshot.size).copy_from_slice(bytes);
+            }
+        }
+        self.1.restore(snapshot, size)
     }
 
     /// Consume the memory, returning its [`MemoryImageSlot`] if any is present.
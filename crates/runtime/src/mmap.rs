@@ -273,6 +273,57 @@ impl Mmap {
         })
     }
 
+    /// Create a new read-write `Mmap` of `size` bytes whose pages are only
+    /// backed by physical memory once they're first touched. `size` must be a
+    /// native page-size multiple.
+    ///
+    /// Unlike `accessible_reserved` no swap space is reserved for the
+    /// mapping where the host supports that, so very large, sparsely used
+    /// mappings can be created.
+    #[cfg(not(target_os = "windows"))]
+    pub fn sparse(size: usize) -> Result<Self> {
+        let page_size = crate::page_size();
+        assert_eq!(size & (page_size - 1), 0);
+
+        if size == 0 {
+            return Ok(Self::new());
+        }
+
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        let flags = rustix::mm::MapFlags::PRIVATE | rustix::mm::MapFlags::NORESERVE;
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        let flags = rustix::mm::MapFlags::PRIVATE;
+
+        let ptr = unsafe {
+            rustix::mm::mmap_anonymous(
+                ptr::null_mut(),
+                size,
+                rustix::mm::ProtFlags::READ | rustix::mm::ProtFlags::WRITE,
+                flags,
+            )
+            .context(format!("mmap failed to allocate {:#x} bytes", size))?
+        };
+
+        Ok(Self {
+            ptr: ptr as usize,
+            len: size,
+            file: None,
+        })
+    }
+
+    /// Create a new read-write `Mmap` of `size` bytes whose pages are only
+    /// backed by physical memory once they're first touched.
+    ///
+    /// This isn't supported on Windows where committed memory is always
+    /// charged up-front.
+    #[cfg(target_os = "windows")]
+    pub fn sparse(size: usize) -> Result<Self> {
+        anyhow::bail!(
+            "cannot allocate a sparse mapping of {:#x} bytes on this platform",
+            size
+        )
+    }
+
     /// Make the memory starting at `start` and extending for `len` bytes accessible.
     /// `start` and `len` must be native page-size multiples and describe a range within
     /// `self`'s reserved memory.
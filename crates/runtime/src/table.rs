@@ -8,17 +8,20 @@ use anyhow::{bail, format_err, Error, Result};
 use std::convert::{TryFrom, TryInto};
 use std::ops::Range;
 use std::ptr;
-use wasmtime_environ::{TablePlan, Trap, WasmType, FUNCREF_INIT_BIT, FUNCREF_MASK};
+use wasmtime_environ::{memref, TablePlan, Trap, WasmType, FUNCREF_INIT_BIT, FUNCREF_MASK};
 
 /// An element going into or coming out of a table.
 ///
-/// Table elements are stored as pointers and are default-initialized with `ptr::null_mut`.
+/// Table elements are stored as pointers and are default-initialized with `ptr::null_mut`,
+/// except for memrefs which are stored as-is and default-initialized with `memref.null`.
 #[derive(Clone)]
 pub enum TableElement {
     /// A `funcref`.
     FuncRef(*mut VMCallerCheckedAnyfunc),
     /// An `exrernref`.
     ExternRef(Option<VMExternRef>),
+    /// A `memref`, in its 128-bit representation.
+    MemRef(u128),
     /// An uninitialized funcref value. This should never be exposed
     /// beyond the `wasmtime` crate boundary; the upper-level code
     /// (which has access to the info needed for lazy initialization)
@@ -30,6 +33,7 @@ pub enum TableElement {
 pub enum TableElementType {
     Func,
     Extern,
+    MemRef,
 }
 
 // The usage of `*mut VMCallerCheckedAnyfunc` is safe w.r.t. thread safety, this
@@ -53,6 +57,7 @@ impl TableElement {
             (TableElementType::Extern, ptr) => {
                 Self::ExternRef(Some(VMExternRef::from_raw(ptr as *mut u8)))
             }
+            (TableElementType::MemRef, _) => unreachable!("memrefs are not stored as pointers"),
         }
     }
 
@@ -69,6 +74,7 @@ impl TableElement {
             (TableElementType::Extern, ptr) => {
                 Self::ExternRef(Some(VMExternRef::clone_from_raw(ptr as *mut u8)))
             }
+            (TableElementType::MemRef, _) => unreachable!("memrefs are not stored as pointers"),
         }
     }
 
@@ -87,6 +93,7 @@ impl TableElement {
             Self::UninitFunc => 0,
             Self::FuncRef(e) => (e as usize) | FUNCREF_INIT_BIT,
             Self::ExternRef(e) => e.map_or(0, |e| e.into_raw() as usize),
+            Self::MemRef(_) => unreachable!("memrefs are not stored as pointers"),
         }
     }
 
@@ -106,6 +113,7 @@ impl TableElement {
             Self::FuncRef(e) => e as usize,
             Self::ExternRef(e) => e.map_or(0, |e| e.into_raw() as usize),
             Self::UninitFunc => panic!("Uninitialized table element value outside of table slot"),
+            Self::MemRef(_) => panic!("memref table element value is not a reference"),
         }
     }
 
@@ -137,6 +145,12 @@ impl From<VMExternRef> for TableElement {
     }
 }
 
+/// A snapshot of the elements of a table, taken with [`Table::snapshot`].
+///
+/// Lazily-initialized function references are recorded as such, so they're
+/// initialized again on their first use after the snapshot is restored.
+pub struct TableSnapshot(Vec<TableElement>);
+
 /// Represents an instance's table.
 pub enum Table {
     /// A "static" table where storage space is managed externally, currently
@@ -161,12 +175,23 @@ pub enum Table {
         /// Maximum size that `elements` can grow to.
         maximum: Option<u32>,
     },
+    /// A table of memrefs. Memrefs don't fit the pointer-sized slots of the
+    /// other tables, so their storage is always dynamically allocated and
+    /// holds them in their 128-bit representation.
+    MemRef {
+        /// Dynamically managed storage space for this table. The length of this
+        /// vector is the current size of the table.
+        elements: Vec<u128>,
+        /// Maximum size that `elements` can grow to.
+        maximum: Option<u32>,
+    },
 }
 
 fn wasm_to_table_type(ty: WasmType) -> Result<TableElementType> {
     match ty {
         WasmType::FuncRef => Ok(TableElementType::Func),
         WasmType::ExternRef => Ok(TableElementType::Extern),
+        WasmType::MemRef => Ok(TableElementType::MemRef),
         ty => bail!("invalid table element type {:?}", ty),
     }
 }
@@ -175,10 +200,15 @@ impl Table {
     /// Create a new dynamic (movable) table instance for the specified table plan.
     pub fn new_dynamic(plan: &TablePlan, store: &mut dyn Store) -> Result<Self> {
         Self::limit_new(plan, store)?;
-        let elements = vec![0; plan.table.minimum as usize];
         let ty = wasm_to_table_type(plan.table.wasm_ty)?;
         let maximum = plan.table.maximum;
 
+        if ty == TableElementType::MemRef {
+            let elements = vec![memref::NULL; plan.table.minimum as usize];
+            return Ok(Table::MemRef { elements, maximum });
+        }
+
+        let elements = vec![0; plan.table.minimum as usize];
         Ok(Table::Dynamic {
             elements,
             ty,
@@ -195,6 +225,9 @@ impl Table {
         Self::limit_new(plan, store)?;
         let size = plan.table.minimum;
         let ty = wasm_to_table_type(plan.table.wasm_ty)?;
+        if ty == TableElementType::MemRef {
+            bail!("memref tables are not supported by the pooling instance allocator");
+        }
         if data.len() < (plan.table.minimum as usize) {
             bail!(
                 "initial table size of {} exceeds the pooling allocator's \
@@ -226,6 +259,7 @@ impl Table {
         match self {
             Table::Static { ty, .. } => *ty,
             Table::Dynamic { ty, .. } => *ty,
+            Table::MemRef { .. } => TableElementType::MemRef,
         }
     }
 
@@ -244,6 +278,7 @@ impl Table {
         match self {
             Table::Static { size, .. } => *size,
             Table::Dynamic { elements, .. } => elements.len().try_into().unwrap(),
+            Table::MemRef { elements, .. } => elements.len().try_into().unwrap(),
         }
     }
 
@@ -257,6 +292,7 @@ impl Table {
         match self {
             Table::Static { data, .. } => Some(data.len() as u32),
             Table::Dynamic { maximum, .. } => maximum.clone(),
+            Table::MemRef { maximum, .. } => maximum.clone(),
         }
     }
 
@@ -287,6 +323,32 @@ impl Table {
         Ok(())
     }
 
+    /// Fill `table[dst..]` with the memrefs from `items`.
+    ///
+    /// Returns a trap error on out-of-bounds accesses.
+    pub fn init_memrefs(
+        &mut self,
+        dst: u32,
+        items: impl ExactSizeIterator<Item = u128>,
+    ) -> Result<(), Trap> {
+        let elements = match self {
+            Table::MemRef { elements, .. } => elements,
+            _ => panic!("not a memref table"),
+        };
+        let elements = match elements
+            .get_mut(usize::try_from(dst).unwrap()..)
+            .and_then(|s| s.get_mut(..items.len()))
+        {
+            Some(elements) => elements,
+            None => return Err(Trap::TableOutOfBounds),
+        };
+
+        for (item, slot) in items.zip(elements) {
+            *slot = item;
+        }
+        Ok(())
+    }
+
     /// Fill `table[dst..dst + len]` with `val`.
     ///
     /// Returns a trap error on out-of-bounds accesses.
@@ -302,6 +364,11 @@ impl Table {
 
         debug_assert!(self.type_matches(&val));
 
+        if let (Table::MemRef { elements, .. }, TableElement::MemRef(val)) = (&mut *self, &val) {
+            elements[start..end].fill(*val);
+            return Ok(());
+        }
+
         let ty = self.element_type();
         if let Some((last, elements)) = self.elements_mut()[start..end].split_last_mut() {
             for e in elements {
@@ -366,6 +433,9 @@ impl Table {
             Table::Dynamic { elements, .. } => {
                 elements.resize(new_size as usize, 0);
             }
+            Table::MemRef { elements, .. } => {
+                elements.resize(new_size as usize, memref::NULL);
+            }
         }
 
         self.fill(old_size, init_value, delta)
@@ -378,6 +448,11 @@ impl Table {
     ///
     /// Returns `None` if the index is out of bounds.
     pub fn get(&self, index: u32) -> Option<TableElement> {
+        if let Table::MemRef { elements, .. } = self {
+            return elements
+                .get(index as usize)
+                .map(|m| TableElement::MemRef(*m));
+        }
         self.elements()
             .get(index as usize)
             .map(|p| unsafe { TableElement::clone_from_table_value(self.element_type(), *p) })
@@ -394,6 +469,11 @@ impl Table {
             return Err(());
         }
 
+        if let (Table::MemRef { elements, .. }, TableElement::MemRef(m)) = (&mut *self, &elem) {
+            *elements.get_mut(index as usize).ok_or(())? = *m;
+            return Ok(());
+        }
+
         let ty = self.element_type();
         let e = self.elements_mut().get_mut(index as usize).ok_or(())?;
         Self::set_raw(ty, e, elem);
@@ -443,6 +523,66 @@ impl Table {
         Ok(())
     }
 
+    /// Takes a snapshot of the elements of this table, which it can later be
+    /// reset to with [`Table::restore`].
+    pub fn snapshot(&self) -> TableSnapshot {
+        TableSnapshot((0..self.size()).map(|i| self.get(i).unwrap()).collect())
+    }
+
+    /// Resets the size and elements of this table to those of `snapshot`,
+    /// which was taken from this table.
+    ///
+    /// # Unsafety
+    ///
+    /// As with [`Table::grow`], restoring can reallocate the elements of this
+    /// table, so its instance's `VMContext` needs to be updated afterwards.
+    pub unsafe fn restore(&mut self, snapshot: &TableSnapshot) -> Result<()> {
+        let new_size = u32::try_from(snapshot.0.len()).unwrap();
+        if let Some(max) = self.maximum() {
+            if new_size > max {
+                bail!(
+                    "table snapshot of {} elements exceeds table maximum",
+                    new_size
+                );
+            }
+        }
+
+        let ty = self.element_type();
+        match self {
+            Table::Static { data, size, .. } => {
+                // Elements past the end of a static table must be zero, so
+                // those dropped by shrinking the table are cleared.
+                for e in data[new_size.min(*size) as usize..*size as usize].iter_mut() {
+                    Self::set_raw(ty, e, TableElement::UninitFunc);
+                }
+                *size = new_size;
+            }
+            Table::Dynamic { elements, .. } => {
+                for e in elements.drain(new_size.min(elements.len() as u32) as usize..) {
+                    drop(TableElement::from_table_value(ty, e));
+                }
+                elements.resize(new_size as usize, 0);
+            }
+            Table::MemRef { elements, .. } => {
+                elements.resize(new_size as usize, memref::NULL);
+            }
+        }
+
+        if let Table::MemRef { elements, .. } = self {
+            for (e, elem) in elements.iter_mut().zip(&snapshot.0) {
+                match elem {
+                    TableElement::MemRef(m) => *e = *m,
+                    _ => unreachable!("memref table snapshot holds other elements"),
+                }
+            }
+            return Ok(());
+        }
+        for (e, elem) in self.elements_mut().iter_mut().zip(&snapshot.0) {
+            Self::set_raw(ty, e, elem.clone());
+        }
+        Ok(())
+    }
+
     /// Return a `VMTableDefinition` for exposing the table to compiled wasm code.
     pub fn vmtable(&mut self) -> VMTableDefinition {
         match self {
@@ -454,6 +594,10 @@ impl Table {
                 base: elements.as_mut_ptr().cast(),
                 current_elements: elements.len().try_into().unwrap(),
             },
+            Table::MemRef { elements, .. } => VMTableDefinition {
+                base: elements.as_mut_ptr().cast(),
+                current_elements: elements.len().try_into().unwrap(),
+            },
         }
     }
 
@@ -461,6 +605,7 @@ impl Table {
         match (&val, self.element_type()) {
             (TableElement::FuncRef(_), TableElementType::Func) => true,
             (TableElement::ExternRef(_), TableElementType::Extern) => true,
+            (TableElement::MemRef(_), TableElementType::MemRef) => true,
             _ => false,
         }
     }
@@ -469,6 +614,7 @@ impl Table {
         match self {
             Table::Static { data, size, .. } => &data[..*size as usize],
             Table::Dynamic { elements, .. } => &elements[..],
+            Table::MemRef { .. } => unreachable!("memrefs are not stored as pointers"),
         }
     }
 
@@ -476,6 +622,21 @@ impl Table {
         match self {
             Table::Static { data, size, .. } => &mut data[..*size as usize],
             Table::Dynamic { elements, .. } => &mut elements[..],
+            Table::MemRef { .. } => unreachable!("memrefs are not stored as pointers"),
+        }
+    }
+
+    fn memrefs(&self) -> &[u128] {
+        match self {
+            Table::MemRef { elements, .. } => &elements[..],
+            _ => unreachable!("not a memref table"),
+        }
+    }
+
+    fn memrefs_mut(&mut self) -> &mut [u128] {
+        match self {
+            Table::MemRef { elements, .. } => &mut elements[..],
+            _ => unreachable!("not a memref table"),
         }
     }
 
@@ -515,11 +676,18 @@ impl Table {
                     Self::set_raw(ty, &mut dst[d], elem);
                 }
             }
+            TableElementType::MemRef => {
+                dst_table.memrefs_mut()[dst_range].copy_from_slice(&src_table.memrefs()[src_range]);
+            }
         }
     }
 
     fn copy_elements_within(&mut self, dst_range: Range<usize>, src_range: Range<usize>) {
         let ty = self.element_type();
+        if ty == TableElementType::MemRef {
+            self.memrefs_mut().copy_within(src_range, dst_range.start);
+            return;
+        }
         let dst = self.elements_mut();
         match ty {
             TableElementType::Func => {
@@ -541,6 +709,7 @@ impl Table {
                     }
                 }
             }
+            TableElementType::MemRef => unreachable!(),
         }
     }
 }
@@ -549,8 +718,8 @@ impl Drop for Table {
     fn drop(&mut self) {
         let ty = self.element_type();
 
-        // funcref tables can skip this
-        if let TableElementType::Func = ty {
+        // funcref and memref tables can skip this
+        if let TableElementType::Func | TableElementType::MemRef = ty {
             return;
         }
 
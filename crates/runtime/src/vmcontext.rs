@@ -225,6 +225,14 @@ pub struct VMMemoryDefinition {
     /// atomically. For relaxed access, see
     /// [`VMMemoryDefinition::current_length()`].
     pub current_length: AtomicUsize,
+
+    /// The start address of the shadow region holding memref metadata for
+    /// this memory, or null if the memory has none.
+    pub memref_shadow: *mut u8,
+
+    /// The start address of the lock table tracking live memref allocations
+    /// of this memory, or null if the memory has none.
+    pub memref_locks: *mut u8,
 }
 
 impl VMMemoryDefinition {
@@ -246,6 +254,8 @@ impl VMMemoryDefinition {
         VMMemoryDefinition {
             base: other.base,
             current_length: other.current_length().into(),
+            memref_shadow: other.memref_shadow,
+            memref_locks: other.memref_locks,
         }
     }
 }
@@ -273,6 +283,14 @@ mod test_vmmemory_definition {
             offset_of!(VMMemoryDefinition, current_length),
             usize::from(offsets.ptr.vmmemory_definition_current_length())
         );
+        assert_eq!(
+            offset_of!(VMMemoryDefinition, memref_shadow),
+            usize::from(offsets.ptr.vmmemory_definition_memref_shadow())
+        );
+        assert_eq!(
+            offset_of!(VMMemoryDefinition, memref_locks),
+            usize::from(offsets.ptr.vmmemory_definition_memref_locks())
+        );
         /* TODO: Assert that the size of `current_length` matches.
         assert_eq!(
             size_of::<VMMemoryDefinition::current_length>(),
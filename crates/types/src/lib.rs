@@ -12,6 +12,8 @@ use std::fmt;
 mod error;
 pub use error::*;
 
+pub mod memref;
+
 /// WebAssembly value type -- equivalent of `wasmparser`'s Type.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum WasmType {
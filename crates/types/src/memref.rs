@@ -0,0 +1,275 @@
+//! Layout of memref values and their metadata shared between the compiler
+//! and the runtime.
+//!
+//! A memref is carried as four 32-bit lanes: the address it points at, the
+//! base and end of the object it may access, and a set of attribute flags.
+//!
+//! The attributes lane is laid out as:
+//!
+//! * bits 0-7: the attribute flags, such as [`HAS_METADATA_FLAG`],
+//! * bits 8-11: the index of the linear memory the memref points into, see
+//!   [`MEMORY_INDEX_SHIFT`],
+//! * bits 12-31: the key of the allocation the memref was derived from, see
+//!   [`ALLOC_KEY_SHIFT`].
+//!
+//! Memref addresses are 32 bits wide, so memrefs into 64-bit linear memories
+//! only point into their first 4GiB, and only the first 16 memories of a
+//! module may be accessed through memrefs.
+
+use serde::{Deserialize, Serialize};
+
+/// Lane of a memref holding the address it points at.
+pub const ADDR_LANE: u8 = 0;
+/// Lane of a memref holding the first address of its object.
+pub const BASE_LANE: u8 = 1;
+/// Lane of a memref holding one past the last address of its object.
+pub const END_LANE: u8 = 2;
+/// Lane of a memref holding its attribute flags.
+pub const ATTR_LANE: u8 = 3;
+
+/// Attribute flag set when a memref carries bounds which must be checked.
+pub const HAS_METADATA_FLAG: u32 = 0x20;
+/// Attribute flag set when a memref is known to be a valid pointer.
+pub const VALID_POINTER_FLAG: u32 = 0x10;
+/// Attribute flag set when a memref was narrowed to a sub-object.
+pub const SUB_OBJ_FLAG: u32 = 0x04;
+/// Attribute flag set when a memref points into the guest's heap.
+pub const HEAP_VARIABLE_FLAG: u32 = 0x02;
+/// Attribute flag set when a memref points to a guest global.
+pub const GLOBAL_VARIABLE_FLAG: u32 = 0x01;
+
+/// `memarg` metadata flag of `*.msload`/`*.msstore` disabling the check.
+pub const NO_CHECK_FLAG: u32 = 0x01;
+/// `memarg` metadata flag of `*.msload`/`*.msstore` requesting only the lower
+/// bound check.
+pub const LOWER_CHECK_FLAG: u32 = 0x02;
+/// `memarg` metadata flag of `*.msload`/`*.msstore` requesting only the upper
+/// bound check.
+pub const UPPER_CHECK_FLAG: u32 = 0x04;
+
+/// Which bounds checks compiled code performs on accesses through memrefs
+/// with `*.msload` and `*.msstore`.
+///
+/// The `memarg` metadata flags of an individual access still take precedence
+/// over the policy.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MemRefCheckPolicy {
+    /// No access is checked against the bounds of its memref.
+    None,
+    /// Only `*.msstore` accesses are checked.
+    StoreOnly,
+    /// Only the upper bound of accesses is checked.
+    UpperOnly,
+    /// Both bounds of every access are checked.
+    Full,
+}
+
+impl Default for MemRefCheckPolicy {
+    fn default() -> Self {
+        MemRefCheckPolicy::Full
+    }
+}
+
+impl std::str::FromStr for MemRefCheckPolicy {
+    type Err = String;
+
+    /// Parses one of `none`, `store-only`, `upper-only` or `full`.
+    fn from_str(s: &str) -> Result<Self, String> {
+        Ok(match s {
+            "none" => MemRefCheckPolicy::None,
+            "store-only" => MemRefCheckPolicy::StoreOnly,
+            "upper-only" => MemRefCheckPolicy::UpperOnly,
+            "full" => MemRefCheckPolicy::Full,
+            _ => {
+                return Err(format!(
+                    "unknown memref check policy `{s}`, expected one of \
+                     `none`, `store-only`, `upper-only` or `full`"
+                ))
+            }
+        })
+    }
+}
+
+impl MemRefCheckPolicy {
+    /// Returns whether `*.msload` accesses are checked.
+    pub fn checks_loads(&self) -> bool {
+        matches!(self, MemRefCheckPolicy::UpperOnly | MemRefCheckPolicy::Full)
+    }
+
+    /// Returns whether `*.msstore` accesses are checked.
+    pub fn checks_stores(&self) -> bool {
+        *self != MemRefCheckPolicy::None
+    }
+
+    /// Returns whether the lower bound of accesses is checked.
+    pub fn checks_lower_bound(&self) -> bool {
+        *self != MemRefCheckPolicy::UpperOnly
+    }
+}
+
+/// Size, in bytes, of a record in the shadow metadata region of a linear
+/// memory.
+///
+/// Each record is four little-endian `u32`s: the base, end and attributes of
+/// the stored memref followed by a word which is non-zero once the record has
+/// been written.
+pub const SHADOW_RECORD_SIZE: u32 = 16;
+/// Offset of the base within a shadow metadata record.
+pub const SHADOW_BASE_OFFSET: i32 = 0;
+/// Offset of the end within a shadow metadata record.
+pub const SHADOW_END_OFFSET: i32 = 4;
+/// Offset of the attributes within a shadow metadata record.
+pub const SHADOW_ATTR_OFFSET: i32 = 8;
+/// Offset of the presence word within a shadow metadata record.
+pub const SHADOW_PRESENT_OFFSET: i32 = 12;
+
+/// Size, in bytes, of the shadow metadata region needed by a linear memory of
+/// `size` bytes.
+///
+/// Like the table kept by the `__host` hooks, the region holds a record for
+/// every address a memref may point at: each address up to and including the
+/// current size of the memory, so that pointers one past the end of an
+/// object at the end of memory can be stored as well. The region grows along
+/// with the memory.
+pub fn shadow_size(size: u64) -> u64 {
+    let addrs = size.saturating_add(1).min(1 << 32);
+    addrs * u64::from(SHADOW_RECORD_SIZE)
+}
+
+/// Returns the offset within the shadow metadata region of the record for
+/// `addr`.
+pub fn shadow_record_offset(addr: u32) -> u64 {
+    u64::from(addr) * u64::from(SHADOW_RECORD_SIZE)
+}
+
+/// Number of low bits of a memref's attributes holding its flags. The bits
+/// above hold the index of the linear memory the memref points into.
+pub const MEMORY_INDEX_SHIFT: u32 = 8;
+/// Mask of a memory index once shifted down out of a memref's attributes.
+///
+/// Memrefs may point into any of the first 16 memories of a module.
+pub const MEMORY_INDEX_MASK: u32 = 0xf;
+
+/// Position of the key of the allocation a memref was derived from within
+/// its attributes, if temporal safety is enabled.
+pub const ALLOC_KEY_SHIFT: u32 = 12;
+/// Mask of an allocation key once shifted down out of a memref's attributes.
+///
+/// Key `0` means the memref isn't tracked by temporal safety checks. Other
+/// keys are made of the slot of the allocation in the lock table, see
+/// [`ALLOC_SLOT_MASK`], and the generation of that slot, above it.
+pub const ALLOC_KEY_MASK: u32 = 0x000f_ffff;
+/// Mask of the lock table slot within an allocation key.
+///
+/// Slot `0` is never handed out, so at most this many allocations of a memory
+/// may be live at once.
+pub const ALLOC_SLOT_MASK: u32 = 0xffff;
+/// Amount added to an allocation key to bump the generation of its slot.
+///
+/// Slots are reused once their allocation is released, with the next
+/// generation, so that memrefs to the released allocation don't match the
+/// new one. Generations wrap around after 16 reuses.
+pub const ALLOC_GENERATION_STEP: u32 = ALLOC_SLOT_MASK + 1;
+
+/// Size, in bytes, of the header of the allocation lock table.
+///
+/// The header holds, at [`LOCK_FRESH_OFFSET`], [`LOCK_HEAD_OFFSET`] and
+/// [`LOCK_TAIL_OFFSET`], the number of slots handed out so far and the first
+/// and last slot of the queue of released slots, or `0` if it's empty.
+/// Released slots are reused in the order they were released in, so that
+/// each generation of a slot lasts as long as possible.
+pub const LOCK_HEADER_SIZE: u32 = 16;
+/// Offset within the lock table of the number of slots handed out so far.
+pub const LOCK_FRESH_OFFSET: i32 = 0;
+/// Offset within the lock table of the first slot of the released queue.
+pub const LOCK_HEAD_OFFSET: i32 = 4;
+/// Offset within the lock table of the last slot of the released queue.
+pub const LOCK_TAIL_OFFSET: i32 = 8;
+
+/// Size, in bytes, of an entry of the allocation lock table, which follow its
+/// header with one per slot.
+pub const LOCK_ENTRY_SIZE: u32 = 8;
+/// Offset within a lock table entry of the state of its slot: the key of the
+/// allocation along with [`LOCK_LIVE_FLAG`] while it's live and the key to
+/// hand out next once it was released.
+pub const LOCK_STATE_OFFSET: i32 = 0;
+/// Offset within a lock table entry of the next slot in the released queue.
+pub const LOCK_NEXT_OFFSET: i32 = 4;
+/// Flag set in the state of a lock table entry while its allocation is live.
+pub const LOCK_LIVE_FLAG: u32 = 0x8000_0000;
+/// Size, in bytes, of the allocation lock table.
+pub const LOCK_TABLE_SIZE: u64 =
+    LOCK_HEADER_SIZE as u64 + (ALLOC_SLOT_MASK as u64 + 1) * LOCK_ENTRY_SIZE as u64;
+
+/// Returns the index of the memory a memref with attributes `attr` points
+/// into.
+pub fn memory_index(attr: u32) -> u32 {
+    (attr >> MEMORY_INDEX_SHIFT) & MEMORY_INDEX_MASK
+}
+
+/// Returns the allocation key carried in a memref's attributes `attr`.
+pub fn alloc_key(attr: u32) -> u32 {
+    (attr >> ALLOC_KEY_SHIFT) & ALLOC_KEY_MASK
+}
+
+/// The 128-bit representation of `memref.null`, which is also the initial
+/// value of the elements of a `memref` table.
+pub const NULL: u128 = (HAS_METADATA_FLAG as u128) << (32 * ATTR_LANE);
+
+/// Returns the 128-bit representation of a memref, with each value in its
+/// lane.
+pub fn pack(addr: u32, base: u32, end: u32, attr: u32) -> u128 {
+    u128::from(addr) << (32 * ADDR_LANE)
+        | u128::from(base) << (32 * BASE_LANE)
+        | u128::from(end) << (32 * END_LANE)
+        | u128::from(attr) << (32 * ATTR_LANE)
+}
+
+/// Returns the 128-bit representation of `memref.const addr size attr`, a
+/// memref pointing at the start of the `size`-byte object at `addr`.
+pub fn constant(addr: u32, size: u32, attr: u32) -> u128 {
+    pack(addr, addr, addr.wrapping_add(size), attr)
+}
+
+/// Runtime counters of memref operations, kept per instance as an array of
+/// `u64`s in the `VMContext` and incremented by compiled code when
+/// statistics are enabled.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MemRefCounter {
+    /// Bounds checks executed by compiled code, on accesses through memrefs
+    /// with metadata and on memrefs stored to a shadow metadata region.
+    BoundsChecks = 0,
+    /// Metadata recorded for memrefs stored to linear memory.
+    MetadataStores = 1,
+    /// Metadata looked up for memrefs loaded from linear memory.
+    MetadataLoads = 2,
+    /// Memrefs narrowed to a sub-object.
+    Narrows = 3,
+}
+
+impl MemRefCounter {
+    /// Number of counters.
+    pub const COUNT: u32 = 4;
+}
+
+/// Counts of the bounds checks of `*.msload`/`*.msstore` instructions in
+/// compiled code.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct MemRefCheckStats {
+    /// Checks emitted in the compiled code.
+    pub emitted: u32,
+    /// Checks left out because a dominating check already covered the same
+    /// memref and range.
+    pub elided: u32,
+    /// Checks within loops which are proven once before the loop, and only
+    /// made again if that failed.
+    pub hoisted: u32,
+}
+
+impl std::ops::AddAssign for MemRefCheckStats {
+    fn add_assign(&mut self, other: MemRefCheckStats) {
+        self.emitted += other.emitted;
+        self.elided += other.elided;
+        self.hoisted += other.hoisted;
+    }
+}
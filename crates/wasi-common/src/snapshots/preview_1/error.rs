@@ -237,6 +237,7 @@ impl From<wiggle::GuestError> for Error {
             InvalidEnumValue { .. } => Errno::Inval.into(),
             PtrOverflow { .. } => Errno::Fault.into(),
             PtrOutOfBounds { .. } => Errno::Fault.into(),
+            PtrOutsideObject { .. } => Errno::Fault.into(),
             PtrNotAligned { .. } => Errno::Inval.into(),
             PtrBorrowed { .. } => Errno::Fault.into(),
             InvalidUtf8 { .. } => Errno::Ilseq.into(),
@@ -1,6 +1,6 @@
 use crate::memory::MemoryCreator;
 use crate::trampoline::MemoryCreatorProxy;
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::fmt;
@@ -11,8 +11,9 @@ use target_lexicon::Architecture;
 use wasmparser::WasmFeatures;
 #[cfg(feature = "cache")]
 use wasmtime_cache::CacheConfig;
-use wasmtime_environ::Tunables;
-use wasmtime_jit::{JitDumpAgent, NullProfilerAgent, ProfilingAgent, VTuneAgent};
+use wasmtime_environ::memref::MemRefCheckPolicy;
+use wasmtime_environ::{MemRefHooks, Tunables};
+use wasmtime_jit::{JitDumpAgent, NullProfilerAgent, PerfMapAgent, ProfilingAgent, VTuneAgent};
 use wasmtime_runtime::{InstanceAllocator, OnDemandInstanceAllocator, RuntimeMemoryCreator};
 
 pub use wasmtime_environ::CacheStore;
@@ -90,6 +91,7 @@ pub struct Config {
     pub(crate) features: WasmFeatures,
     pub(crate) wasm_backtrace: bool,
     pub(crate) wasm_backtrace_details_env_used: bool,
+    pub(crate) coredump_on_trap: bool,
     pub(crate) native_unwind_info: bool,
     #[cfg(feature = "async")]
     pub(crate) async_stack_size: usize,
@@ -176,6 +178,7 @@ impl Config {
             max_wasm_stack: 512 * 1024,
             wasm_backtrace: true,
             wasm_backtrace_details_env_used: false,
+            coredump_on_trap: false,
             native_unwind_info: true,
             features: WasmFeatures::default(),
             #[cfg(feature = "async")]
@@ -398,6 +401,28 @@ impl Config {
         self
     }
 
+    /// Configures whether a [`WasmCoreDump`] will be present in the context of
+    /// errors returned from Wasmtime when WebAssembly traps.
+    ///
+    /// A coredump is a snapshot of the store at the time of the trap: the
+    /// WebAssembly frames on the stack along with the globals and linear
+    /// memories of every instance in the store. It can be acquired via
+    /// [`anyhow::Error::downcast_ref`] and written out in the standard
+    /// WebAssembly coredump format with [`WasmCoreDump::serialize`].
+    ///
+    /// Collecting a coredump copies the contents of every linear memory in the
+    /// store, so it's only recommended to enable this when the dump will be
+    /// used.
+    ///
+    /// This option is `false` by default.
+    ///
+    /// [`WasmCoreDump`]: crate::WasmCoreDump
+    /// [`WasmCoreDump::serialize`]: crate::WasmCoreDump::serialize
+    pub fn coredump_on_trap(&mut self, enable: bool) -> &mut Self {
+        self.coredump_on_trap = enable;
+        self
+    }
+
     /// Configures whether to generate native unwind information
     /// (e.g. `.eh_frame` on Linux).
     ///
@@ -1433,6 +1458,11 @@ impl Config {
         {
             bail!("static memory guard size cannot be smaller than dynamic memory guard size");
         }
+        if self.tunables.memref_shadow_metadata
+            && (cfg!(not(target_pointer_width = "64")) || cfg!(windows))
+        {
+            bail!("memref shadow metadata is only supported on 64-bit unix hosts");
+        }
 
         Ok(())
     }
@@ -1465,6 +1495,7 @@ impl Config {
         Ok(match self.profiling_strategy {
             ProfilingStrategy::JitDump => Box::new(JitDumpAgent::new()?) as Box<dyn ProfilingAgent>,
             ProfilingStrategy::VTune => Box::new(VTuneAgent::new()?) as Box<dyn ProfilingAgent>,
+            ProfilingStrategy::PerfMap => Box::new(PerfMapAgent::new()?) as Box<dyn ProfilingAgent>,
             ProfilingStrategy::None => Box::new(NullProfilerAgent),
         })
     }
@@ -1561,15 +1592,240 @@ impl Config {
         self
     }
 
-    /// store check only
-    pub fn store_check_only(&mut self, set_value: bool) -> &mut Self {
-        self.tunables.store_check_only = set_value;
+    /// Configures which bounds checks are performed on accesses through
+    /// memrefs with `*.msload` and `*.msstore`.
+    ///
+    /// Weaker policies trade safety for speed. The policy is recorded in
+    /// precompiled modules, which can only be loaded by an engine configured
+    /// with the same policy.
+    ///
+    /// The default is [`MemRefCheckPolicy::Full`].
+    pub fn memref_check_policy(&mut self, policy: MemRefCheckPolicy) -> &mut Self {
+        self.tunables.memref_check_policy = policy;
         self
     }
 
-    /// upper bound check only
-    pub fn upper_check_only(&mut self, set_value: bool) -> &mut Self {
-        self.tunables.upper_check_only = set_value;
+    /// Configures the memref check policy of the functions named `name` in
+    /// the name section of a module, overriding
+    /// [`Config::memref_check_policy`] for them.
+    ///
+    /// This allows, for example, checking untrusted parsing code fully while
+    /// only lightly checking hot numeric kernels of the same module.
+    pub fn memref_function_check_policy(
+        &mut self,
+        name: &str,
+        policy: MemRefCheckPolicy,
+    ) -> &mut Self {
+        self.tunables
+            .memref_function_policies
+            .by_name
+            .insert(name.to_string(), policy);
+        self
+    }
+
+    /// Configures the memref check policy of the function at `index` in the
+    /// function index space of a module, overriding both
+    /// [`Config::memref_check_policy`] and policies configured by name with
+    /// [`Config::memref_function_check_policy`].
+    pub fn memref_function_index_check_policy(
+        &mut self,
+        index: u32,
+        policy: MemRefCheckPolicy,
+    ) -> &mut Self {
+        self.tunables
+            .memref_function_policies
+            .by_index
+            .insert(index, policy);
+        self
+    }
+
+    /// Loads per-function memref check policies from the file at `path`.
+    ///
+    /// Each line of the file holds a function and the policy to compile it
+    /// with, separated by whitespace, for example:
+    ///
+    /// ```text
+    /// # parse untrusted input with every check
+    /// parse_header full
+    /// matmul upper-only
+    /// 12 none
+    /// ```
+    ///
+    /// A function given as an integer is the function at that index,
+    /// configured as with [`Config::memref_function_index_check_policy`],
+    /// and any other function is looked up by name, as with
+    /// [`Config::memref_function_check_policy`]. Policies are one of `none`,
+    /// `store-only`, `upper-only` or `full`. Empty lines and lines starting
+    /// with `#` are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be read or a line is malformed.
+    pub fn memref_check_policy_file(&mut self, path: impl AsRef<Path>) -> Result<&mut Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read memref policy file `{}`", path.display()))?;
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.split_whitespace();
+            let (func, policy) = match (parts.next(), parts.next(), parts.next()) {
+                (Some(func), Some(policy), None) => (func, policy),
+                _ => bail!(
+                    "{}:{}: expected a function and a memref check policy",
+                    path.display(),
+                    i + 1
+                ),
+            };
+            let policy = policy
+                .parse()
+                .map_err(|e| anyhow!("{}:{}: {}", path.display(), i + 1, e))?;
+            match func.parse::<u32>() {
+                Ok(index) => self.memref_function_index_check_policy(index, policy),
+                Err(_) => self.memref_function_check_policy(func, policy),
+            };
+        }
+        Ok(self)
+    }
+
+    /// Configures whether memref metadata is kept inline in a shadow region
+    /// next to each linear memory.
+    ///
+    /// By default the bounds of a memref stored to memory are recorded by
+    /// calling the module's `__host::__set_value` import and recovered with
+    /// `__host::__get_record`, which costs a wasm-to-host call for every
+    /// pointer stored or loaded. When this is enabled a shadow region is
+    /// allocated for each non-shared linear memory and compiled code reads
+    /// and writes the metadata there with plain loads and stores. Only the
+    /// pages actually touched are backed by physical memory. Metadata recorded this way is not visible through
+    /// [`Memory::memref_metadata`](crate::Memory::memref_metadata).
+    ///
+    /// As with the `__host` hooks, metadata is recorded for the exact address
+    /// a memref points at. The shadow region holds a 16-byte record for every
+    /// address up to the current size of the memory, so it's 16 times as large
+    /// as the memory, and it's moved to a larger region as the memory grows.
+    /// Memrefs pointing past the current end of the memory are loaded back
+    /// without metadata.
+    ///
+    /// Shared memories keep using the `__host` imports.
+    ///
+    /// This option is only supported on 64-bit unix hosts and is disabled by
+    /// default.
+    pub fn memref_shadow_metadata(&mut self, enable: bool) -> &mut Self {
+        self.tunables.memref_shadow_metadata = enable;
+        self
+    }
+
+    /// Configures whether memrefs are checked for use-after-free and
+    /// double-free.
+    ///
+    /// By default memrefs only guarantee spatial safety: an access through a
+    /// memref is checked against the bounds of its object, but nothing stops
+    /// it from being used once `memref.dealloc` released that object. When
+    /// this is enabled each `memref.alloc` with metadata hands out a key,
+    /// kept in the upper 20 bits of the memref's attributes, and marks it
+    /// live in a 512KiB lock table reserved next to each non-shared linear
+    /// memory. Keys are handed out from the lock table of the memory the
+    /// allocation is in. `memref.dealloc` releases the key, trapping with
+    /// [`Trap::MemRefDoubleFree`](crate::Trap::MemRefDoubleFree) if it was
+    /// already released, and every checked `*.msload`/`*.msstore` traps with
+    /// [`Trap::MemRefUseAfterFree`](crate::Trap::MemRefUseAfterFree) if the
+    /// memref's key is no longer live in the lock table of the accessed
+    /// memory.
+    ///
+    /// A key is made of one of 65535 slots of the lock table and of the
+    /// generation of that slot. Released slots are reused in the order they
+    /// were released in, with their next generation, so that stale memrefs
+    /// don't match the new allocation. Generations wrap around after 16
+    /// reuses of a slot, so a stale memref may only be accepted again after
+    /// about a million further allocations. Allocations trap with
+    /// [`Trap::MemRefKeysExhausted`](crate::Trap::MemRefKeysExhausted) while
+    /// all 65535 slots of their memory hold live allocations.
+    ///
+    /// This is disabled by default.
+    pub fn memref_temporal_safety(&mut self, enable: bool) -> &mut Self {
+        self.tunables.memref_temporal_safety = enable;
+        self
+    }
+
+    /// Configures whether failed memref bounds checks are recorded instead of
+    /// trapping.
+    ///
+    /// By default an access through a memref outside of its object, or a
+    /// memref stored with `memref.msstore` while pointing outside of its
+    /// object, traps with
+    /// [`Trap::MemRefOutOfBounds`](crate::Trap::MemRefOutOfBounds). When
+    /// this is enabled the violation is instead appended, along with the
+    /// [`WasmBacktrace`](crate::WasmBacktrace) of where it happened, to the
+    /// store's [`Store::memref_audit_records`](crate::Store::memref_audit_records)
+    /// and execution carries on as if the check had passed. This makes it
+    /// possible to survey every violation of a program in a single run.
+    /// Violations repeated at the same instruction are only counted, and at
+    /// most [`MEMREF_AUDIT_RECORD_LIMIT`](crate::MEMREF_AUDIT_RECORD_LIMIT)
+    /// sites are recorded.
+    ///
+    /// Use-after-free and double-free detected by
+    /// [`Config::memref_temporal_safety`] still trap: once a key was released
+    /// its lock table slot may already be handed out again, so carrying on
+    /// would corrupt the tracking of live allocations.
+    ///
+    /// This is disabled by default.
+    pub fn memref_audit(&mut self, enable: bool) -> &mut Self {
+        self.tunables.memref_audit = enable;
+        self
+    }
+
+    /// Configures whether compiled code counts the memref operations it
+    /// performs.
+    ///
+    /// When this is enabled each instance keeps counters of the bounds
+    /// checks, metadata stores and loads, and narrowings executed by its
+    /// functions, readable with
+    /// [`Instance::memref_stats`](crate::Instance::memref_stats). Each
+    /// counted operation costs an extra load and store, and each instance
+    /// reserves 32 bytes for the counters.
+    ///
+    /// This is disabled by default.
+    pub fn memref_stats(&mut self, enable: bool) -> &mut Self {
+        self.tunables.memref_stats = enable;
+        self
+    }
+
+    /// Configures the names of the imports through which memref metadata is
+    /// recorded and looked up.
+    ///
+    /// Modules import the hook recording the metadata of a stored memref as
+    /// `module::set_value`, of type `(func (param i32 i32 i32 i32 i32))`, and
+    /// the hook looking it up as `module::get_record`, of type
+    /// `(func (param i32) (result memref))`. Modules built for the first
+    /// version of the hooks may instead import `module::get_value`, of type
+    /// `(func (param i32) (result i64))`, which packs the metadata as
+    /// `base << 32 | attr << 24 | (end - base)` and so only keeps the low 8
+    /// bits of the attributes and 24 bits of the object size. Names starting
+    /// with `__` are reserved for hooks, so any other function import from
+    /// `module` with such a name, or a hook of the wrong type, makes
+    /// compilation fail with a validation error. Imports from `module` with
+    /// other names are resolved like any other import.
+    /// [`Linker::define_memref_hooks`](crate::Linker::define_memref_hooks)
+    /// defines the hooks under these names.
+    ///
+    /// The default names are `__host::__set_value`, `__host::__get_value` and
+    /// `__host::__get_record`.
+    pub fn memref_hook_names(
+        &mut self,
+        module: &str,
+        set_value: &str,
+        get_value: &str,
+        get_record: &str,
+    ) -> &mut Self {
+        self.tunables.memref_hooks = MemRefHooks {
+            module: module.to_string(),
+            set_value: set_value.to_string(),
+            get_value: get_value.to_string(),
+            get_record: get_record.to_string(),
+        };
         self
     }
 }
@@ -1672,6 +1928,10 @@ pub enum ProfilingStrategy {
 
     /// Collect profiling info using the "ittapi", used with `VTune` on Linux.
     VTune,
+
+    /// Collect function names and addresses in a "perf map" file at
+    /// `/tmp/perf-<pid>.map`, used with `perf` on Linux.
+    PerfMap,
 }
 
 /// Select how wasm backtrace detailed information is handled.
@@ -0,0 +1,394 @@
+use crate::store::StoreOpaque;
+use crate::FrameInfo;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+use std::sync::Arc;
+use wasm_encoder::{
+    ConstExpr, CustomSection, DataSection, Encode, GlobalSection, GlobalType, MemorySection,
+    MemoryType, ValType,
+};
+use wasmtime_environ::{Memory, WasmType, WASM_PAGE_SIZE};
+use wasmtime_jit::demangle_function_name_or_index;
+
+/// Size of the chunks of linear memory which are skipped when serializing a
+/// coredump or pre-initializing a module if they only contain zeros.
+const DATA_CHUNK_SIZE: usize = 1024;
+
+/// A snapshot of a store taken when WebAssembly trapped, which can be written
+/// out as a [WebAssembly coredump].
+///
+/// This structure is attached to the [`anyhow::Error`] returned when
+/// WebAssembly traps if [`Config::coredump_on_trap`] is enabled. It can be
+/// acquired with the [`anyhow::Error::downcast`] family of methods.
+///
+/// The coredump records the WebAssembly frames on the stack at the time of
+/// the trap, along with the modules, instances, globals and linear memories of
+/// the store. Wasmtime doesn't keep track of the values of locals or of the
+/// operand stack of compiled functions, so these are absent from the frames.
+///
+/// [WebAssembly coredump]: https://github.com/WebAssembly/tool-conventions/blob/main/Coredump.md
+/// [`Config::coredump_on_trap`]: crate::Config::coredump_on_trap
+///
+/// # Examples
+///
+/// ```
+/// # use wasmtime::*;
+/// # use anyhow::Result;
+/// # fn main() -> Result<()> {
+/// let mut config = Config::new();
+/// config.coredump_on_trap(true);
+/// let engine = Engine::new(&config)?;
+/// let module = Module::new(
+///     &engine,
+///     r#"
+///         (module
+///             (memory 1)
+///             (func (export "run")
+///                 unreachable)
+///         )
+///     "#,
+/// )?;
+/// let mut store = Store::new(&engine, ());
+/// let instance = Instance::new(&mut store, &module, &[])?;
+/// let func = instance.get_typed_func::<(), ()>(&mut store, "run")?;
+/// let error = func.call(&mut store, ()).unwrap_err();
+/// let coredump = error.downcast_ref::<WasmCoreDump>().unwrap();
+/// assert_eq!(coredump.frames().len(), 1);
+/// assert_eq!(coredump.memories().len(), 1);
+/// let bytes = coredump.serialize("example");
+/// # Ok(())
+/// # }
+/// ```
+pub struct WasmCoreDump {
+    modules: Vec<String>,
+    instances: Vec<CoreDumpInstance>,
+    memories: Vec<CoreDumpMemory>,
+    globals: Vec<CoreDumpGlobal>,
+    frames: Vec<FrameInfo>,
+    /// The index within `instances` of the instance of each frame.
+    frame_instances: Vec<u32>,
+}
+
+struct CoreDumpInstance {
+    module: u32,
+    memories: Vec<u32>,
+    globals: Vec<u32>,
+}
+
+struct CoreDumpMemory {
+    ty: Memory,
+    data: Vec<u8>,
+}
+
+struct CoreDumpGlobal {
+    ty: WasmType,
+    mutable: bool,
+    /// The bits of the value of the global, or zero for references which
+    /// can't be recorded.
+    value: u128,
+}
+
+impl WasmCoreDump {
+    pub(crate) fn new(
+        store: &mut StoreOpaque,
+        backtrace: &wasmtime_runtime::Backtrace,
+        trap_pc: Option<usize>,
+    ) -> WasmCoreDump {
+        // Frames are looked up the same way as in
+        // `WasmBacktrace::from_captured`, along with the module they belong
+        // to so that they can be matched with an instance below.
+        let mut frames = Vec::new();
+        let mut frame_modules = Vec::new();
+        for frame in backtrace.frames() {
+            let pc = if Some(frame.pc()) == trap_pc {
+                frame.pc()
+            } else {
+                frame.pc() - 1
+            };
+            if let Some((info, module)) = store.modules().lookup_frame_info(pc) {
+                frame_modules.push(module.env_module() as *const wasmtime_environ::Module);
+                frames.push(info);
+            }
+        }
+
+        let mut modules = Vec::new();
+        let mut instances = Vec::new();
+        let mut memories = Vec::new();
+        let mut globals = Vec::new();
+        let mut module_indices = HashMap::new();
+        let mut module_instances = HashMap::new();
+        let mut memory_indices = HashMap::new();
+        let mut global_indices = HashMap::new();
+        for handle in store.all_instances_mut() {
+            let module = handle.module().clone();
+            let module_index = *module_indices
+                .entry(Arc::as_ptr(&module))
+                .or_insert_with(|| {
+                    let name = module.name.as_deref().unwrap_or("<unknown>");
+                    modules.push(name.to_string());
+                    modules.len() as u32 - 1
+                });
+            // Frames can only be attributed to a module, so the frames of a
+            // module instantiated more than once are attributed to its first
+            // instance.
+            module_instances
+                .entry(Arc::as_ptr(&module))
+                .or_insert(instances.len() as u32);
+
+            // Imported memories and globals share their definition with the
+            // instance exporting them, so they're only recorded once.
+            let mut instance = CoreDumpInstance {
+                module: module_index,
+                memories: Vec::new(),
+                globals: Vec::new(),
+            };
+            for index in module.memory_plans.keys() {
+                let export = handle.get_exported_memory(index);
+                let index = *memory_indices.entry(export.definition).or_insert_with(|| {
+                    let data = unsafe {
+                        let definition = &*export.definition;
+                        std::slice::from_raw_parts(definition.base, definition.current_length())
+                            .to_vec()
+                    };
+                    memories.push(CoreDumpMemory {
+                        ty: export.memory.memory,
+                        data,
+                    });
+                    memories.len() as u32 - 1
+                });
+                instance.memories.push(index);
+            }
+            for index in module.globals.keys() {
+                let export = handle.get_exported_global(index);
+                let index = *global_indices.entry(export.definition).or_insert_with(|| {
+                    let value = unsafe {
+                        let definition = &*export.definition;
+                        match export.global.wasm_ty {
+                            WasmType::I32 | WasmType::F32 => u128::from(*definition.as_u32()),
+                            WasmType::I64 | WasmType::F64 => u128::from(*definition.as_u64()),
+                            WasmType::V128 | WasmType::MemRef => *definition.as_u128(),
+                            WasmType::FuncRef | WasmType::ExternRef => 0,
+                        }
+                    };
+                    globals.push(CoreDumpGlobal {
+                        ty: export.global.wasm_ty,
+                        mutable: export.global.mutability,
+                        value,
+                    });
+                    globals.len() as u32 - 1
+                });
+                instance.globals.push(index);
+            }
+            instances.push(instance);
+        }
+
+        let mut frame_instances = Vec::new();
+        let mut frame_infos = Vec::new();
+        for (info, module) in frames.into_iter().zip(frame_modules) {
+            if let Some(instance) = module_instances.get(&module) {
+                frame_instances.push(*instance);
+                frame_infos.push(info);
+            }
+        }
+
+        WasmCoreDump {
+            modules,
+            instances,
+            memories,
+            globals,
+            frames: frame_infos,
+            frame_instances,
+        }
+    }
+
+    /// Returns the WebAssembly frames on the stack at the time of the trap,
+    /// starting with the frame which trapped.
+    pub fn frames(&self) -> &[FrameInfo] {
+        &self.frames
+    }
+
+    /// Returns the contents of the linear memories of the store at the time
+    /// of the trap.
+    ///
+    /// Memories are listed in the order of the instances defining them,
+    /// followed by the order in which each instance defines them.
+    pub fn memories(&self) -> impl ExactSizeIterator<Item = &[u8]> + '_ {
+        self.memories.iter().map(|m| &m.data[..])
+    }
+
+    /// Serializes this coredump in the [WebAssembly coredump] format.
+    ///
+    /// The `name` is recorded as the name of the executable which trapped.
+    ///
+    /// The values of reference-typed globals can't be represented in a
+    /// coredump, so they're recorded as null. Memrefs are recorded as the
+    /// `v128` holding their lanes, since they can't always be expressed with a
+    /// `memref.const`.
+    ///
+    /// [WebAssembly coredump]: https://github.com/WebAssembly/tool-conventions/blob/main/Coredump.md
+    pub fn serialize(&self, name: &str) -> Vec<u8> {
+        let mut module = wasm_encoder::Module::new();
+
+        let mut core = vec![0x00];
+        name.encode(&mut core);
+        module.section(&CustomSection {
+            name: "core",
+            data: &core,
+        });
+
+        let mut coremodules = Vec::new();
+        (self.modules.len() as u32).encode(&mut coremodules);
+        for name in &self.modules {
+            coremodules.push(0x00);
+            name.encode(&mut coremodules);
+        }
+        module.section(&CustomSection {
+            name: "coremodules",
+            data: &coremodules,
+        });
+
+        let mut coreinstances = Vec::new();
+        (self.instances.len() as u32).encode(&mut coreinstances);
+        for instance in &self.instances {
+            coreinstances.push(0x00);
+            instance.module.encode(&mut coreinstances);
+            for indices in [&instance.memories, &instance.globals] {
+                (indices.len() as u32).encode(&mut coreinstances);
+                for index in indices {
+                    index.encode(&mut coreinstances);
+                }
+            }
+        }
+        module.section(&CustomSection {
+            name: "coreinstances",
+            data: &coreinstances,
+        });
+
+        let mut memories = MemorySection::new();
+        for memory in &self.memories {
+            memories.memory(MemoryType {
+                minimum: memory.data.len() as u64 / u64::from(WASM_PAGE_SIZE),
+                maximum: memory.ty.maximum,
+                memory64: memory.ty.memory64,
+                shared: memory.ty.shared,
+            });
+        }
+        module.section(&memories);
+
+        let mut globals = GlobalSection::new();
+        for global in &self.globals {
+            let value = global.value;
+            let (val_type, init) = match global.ty {
+                WasmType::I32 => (ValType::I32, ConstExpr::i32_const(value as u32 as i32)),
+                WasmType::I64 => (ValType::I64, ConstExpr::i64_const(value as u64 as i64)),
+                WasmType::F32 => (
+                    ValType::F32,
+                    ConstExpr::f32_const(f32::from_bits(value as u32)),
+                ),
+                WasmType::F64 => (
+                    ValType::F64,
+                    ConstExpr::f64_const(f64::from_bits(value as u64)),
+                ),
+                WasmType::V128 | WasmType::MemRef => {
+                    (ValType::V128, ConstExpr::v128_const(value as i128))
+                }
+                WasmType::FuncRef => (ValType::FuncRef, ConstExpr::ref_null(ValType::FuncRef)),
+                WasmType::ExternRef => {
+                    (ValType::ExternRef, ConstExpr::ref_null(ValType::ExternRef))
+                }
+            };
+            let ty = GlobalType {
+                val_type,
+                mutable: global.mutable,
+            };
+            globals.global(ty, &init);
+        }
+        module.section(&globals);
+
+        // Only the chunks of memory which aren't zeroed are recorded.
+        let mut data = DataSection::new();
+        for (index, memory) in self.memories.iter().enumerate() {
+            for range in nonzero_chunks(&memory.data) {
+                let offset = if memory.ty.memory64 {
+                    ConstExpr::i64_const(range.start as i64)
+                } else {
+                    ConstExpr::i32_const(range.start as i32)
+                };
+                data.active(index as u32, &offset, memory.data[range].iter().copied());
+            }
+        }
+        module.section(&data);
+
+        let mut corestack = vec![0x00];
+        "main".encode(&mut corestack);
+        (self.frames.len() as u32).encode(&mut corestack);
+        for (frame, instance) in self.frames.iter().zip(&self.frame_instances) {
+            corestack.push(0x00);
+            instance.encode(&mut corestack);
+            frame.func_index().encode(&mut corestack);
+            (frame.func_offset().unwrap_or(0) as u32).encode(&mut corestack);
+            // Neither locals nor the operand stack are available.
+            0u32.encode(&mut corestack);
+            0u32.encode(&mut corestack);
+        }
+        module.section(&CustomSection {
+            name: "corestack",
+            data: &corestack,
+        });
+
+        module.finish()
+    }
+}
+
+impl fmt::Display for WasmCoreDump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "wasm coredump generated while executing:")?;
+        for (i, frame) in self.frames.iter().enumerate() {
+            let name = frame.module_name().unwrap_or("<unknown>");
+            write!(f, "\n  {:>3}: {}!", i, name)?;
+            demangle_function_name_or_index(f, frame.func_name(), frame.func_index() as usize)?;
+        }
+        write!(
+            f,
+            "\nwith {} instances, {} memories and {} globals",
+            self.instances.len(),
+            self.memories.len(),
+            self.globals.len()
+        )
+    }
+}
+
+impl fmt::Debug for WasmCoreDump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WasmCoreDump")
+            .field("modules", &self.modules)
+            .field("instances", &self.instances.len())
+            .field("memories", &self.memories.len())
+            .field("globals", &self.globals.len())
+            .field("frames", &self.frames)
+            .finish()
+    }
+}
+
+/// Returns the ranges of `data` which aren't zeroed, as runs of adjacent
+/// chunks of `DATA_CHUNK_SIZE` bytes.
+pub(crate) fn nonzero_chunks(data: &[u8]) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut start = None;
+    for (i, chunk) in data.chunks(DATA_CHUNK_SIZE).enumerate() {
+        let offset = i * DATA_CHUNK_SIZE;
+        match (chunk.iter().all(|b| *b == 0), start) {
+            (false, None) => start = Some(offset),
+            (true, Some(s)) => {
+                ranges.push(s..offset);
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        ranges.push(s..data.len());
+    }
+    ranges
+}
@@ -305,6 +305,11 @@ impl Metadata {
             epoch_interruption,
             static_memory_bound_is_maximum,
             guard_before_linear_memory,
+            memref_shadow_metadata,
+            memref_temporal_safety,
+            memref_audit,
+            memref_stats,
+            ref memref_hooks,
 
             // This doesn't affect compilation, it's just a runtime setting.
             dynamic_memory_growth_reserve: _,
@@ -318,9 +323,10 @@ impl Metadata {
             // Just a debugging aid, doesn't affect functionality at all.
             debug_adapter_modules: _,
 
+            memref_check_policy,
+            ref memref_function_policies,
+
             mem_ref: _,
-            store_check_only: _,
-            upper_check_only: _,
         } = self.tunables;
 
         Self::check_int(
@@ -364,6 +370,40 @@ impl Metadata {
             other.guard_before_linear_memory,
             "guard before linear memory",
         )?;
+        Self::check_bool(
+            memref_shadow_metadata,
+            other.memref_shadow_metadata,
+            "memref shadow metadata",
+        )?;
+        Self::check_bool(
+            memref_temporal_safety,
+            other.memref_temporal_safety,
+            "memref temporal safety",
+        )?;
+        Self::check_bool(memref_audit, other.memref_audit, "memref audit")?;
+        Self::check_bool(memref_stats, other.memref_stats, "memref statistics")?;
+        if memref_check_policy != other.memref_check_policy {
+            bail!(
+                "Module was compiled with memref check policy {:?} but the host uses {:?}",
+                memref_check_policy,
+                other.memref_check_policy
+            );
+        }
+        if *memref_function_policies != other.memref_function_policies {
+            bail!(
+                "Module was compiled with per-function memref check policies {:?} \
+                 but the host uses {:?}",
+                memref_function_policies,
+                other.memref_function_policies
+            );
+        }
+        if *memref_hooks != other.memref_hooks {
+            bail!(
+                "Module was compiled with memref hooks {:?} but the host uses {:?}",
+                memref_hooks,
+                other.memref_hooks
+            );
+        }
 
         Ok(())
     }
@@ -440,7 +480,7 @@ impl Metadata {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::Config;
+    use crate::{Config, MemRefCheckPolicy};
 
     #[test]
     fn test_architecture_mismatch() -> Result<()> {
@@ -576,6 +616,80 @@ Caused by:
         Ok(())
     }
 
+    #[test]
+    fn test_memref_shadow_mismatch() -> Result<()> {
+        let mut config = Config::new();
+        config.memref_shadow_metadata(true);
+
+        let engine = Engine::new(&config)?;
+        let mut metadata = Metadata::new(&engine);
+        metadata.tunables.memref_shadow_metadata = false;
+
+        match metadata.check_compatible(&engine) {
+            Ok(_) => unreachable!(),
+            Err(e) => assert_eq!(
+                e.to_string(),
+                "Module was compiled without memref shadow metadata but it is enabled for the host"
+            ),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_memref_temporal_safety_mismatch() -> Result<()> {
+        let engine = Engine::default();
+        let mut metadata = Metadata::new(&engine);
+        metadata.tunables.memref_temporal_safety = true;
+
+        match metadata.check_compatible(&engine) {
+            Ok(_) => unreachable!(),
+            Err(e) => assert_eq!(
+                e.to_string(),
+                "Module was compiled with memref temporal safety but it is not enabled for the host"
+            ),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_memref_check_policy_mismatch() -> Result<()> {
+        let engine = Engine::default();
+        let mut metadata = Metadata::new(&engine);
+        metadata.tunables.memref_check_policy = MemRefCheckPolicy::StoreOnly;
+
+        match metadata.check_compatible(&engine) {
+            Ok(_) => unreachable!(),
+            Err(e) => assert_eq!(
+                e.to_string(),
+                "Module was compiled with memref check policy StoreOnly but the host uses Full"
+            ),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_memref_audit_mismatch() -> Result<()> {
+        let mut config = Config::new();
+        config.memref_audit(true);
+
+        let engine = Engine::new(&config)?;
+        let mut metadata = Metadata::new(&engine);
+        metadata.tunables.memref_audit = false;
+
+        match metadata.check_compatible(&engine) {
+            Ok(_) => unreachable!(),
+            Err(e) => assert_eq!(
+                e.to_string(),
+                "Module was compiled without memref audit but it is enabled for the host"
+            ),
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn test_feature_mismatch() -> Result<()> {
         let mut config = Config::new();
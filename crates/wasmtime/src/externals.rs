@@ -1,8 +1,8 @@
 use crate::store::{StoreData, StoreOpaque, Stored};
 use crate::trampoline::{generate_global_export, generate_table_export};
 use crate::{
-    AsContext, AsContextMut, Engine, ExternRef, ExternType, Func, GlobalType, Memory, Mutability,
-    SharedMemory, TableType, Val, ValType,
+    AsContext, AsContextMut, Engine, ExternRef, ExternType, Func, GlobalType, MemRef, Memory,
+    Mutability, SharedMemory, TableType, Val, ValType,
 };
 use anyhow::{anyhow, bail, Result};
 use std::mem;
@@ -292,7 +292,8 @@ impl Global {
                 ValType::FuncRef => {
                     Val::FuncRef(Func::from_raw(store, definition.as_anyfunc() as usize))
                 }
-                ValType::V128 | ValType::MemRef => Val::V128(*definition.as_u128()),
+                ValType::V128 => Val::V128(*definition.as_u128()),
+                ValType::MemRef => Val::MemRef(MemRef::from_u128(*definition.as_u128())),
             }
         }
     }
@@ -338,6 +339,7 @@ impl Global {
                     drop(old);
                 }
                 Val::V128(i) => *definition.as_u128_mut() = i,
+                Val::MemRef(m) => *definition.as_u128_mut() = m.as_u128(),
             }
         }
         Ok(())
@@ -367,6 +369,8 @@ impl Global {
 /// it's an array of WebAssembly reference type values rather than bytes. One of
 /// the most common usages of a table is a function table for wasm modules (a
 /// `funcref` table), where each element has the `ValType::FuncRef` type.
+/// Tables may also hold [`MemRef`]s, with the `ValType::MemRef` type, in
+/// which case each element keeps the bounds of its memref.
 ///
 /// A [`Table`] "belongs" to the store that it was originally created within
 /// (either via [`Table::new`] or via instantiating a
@@ -513,6 +517,7 @@ impl Table {
                 runtime::TableElement::ExternRef(Some(x)) => {
                     Some(Val::ExternRef(Some(ExternRef { inner: x })))
                 }
+                runtime::TableElement::MemRef(m) => Some(Val::MemRef(MemRef::from_u128(m))),
                 runtime::TableElement::UninitFunc => {
                     unreachable!("lazy init above should have converted UninitFunc")
                 }
@@ -1303,9 +1303,10 @@ pub(crate) fn invoke_wasm_and_catch_traps<T>(
             exit_wasm(store, exit);
             return Err(trap);
         }
+        let config = store.0.engine().config();
         let result = wasmtime_runtime::catch_traps(
             store.0.signal_handler(),
-            store.0.engine().config().wasm_backtrace,
+            config.wasm_backtrace || config.coredump_on_trap,
             store.0.default_caller(),
             closure,
         );
@@ -1703,7 +1704,7 @@ pub trait IntoFunc<T, Params, Results>: Send + Sync + 'static {
 /// recommended to use this type.
 pub struct Caller<'a, T> {
     pub(crate) store: StoreContextMut<'a, T>,
-    caller: &'a InstanceHandle,
+    pub(crate) caller: &'a InstanceHandle,
 }
 
 impl<T> Caller<'_, T> {
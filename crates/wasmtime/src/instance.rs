@@ -2,8 +2,8 @@ use crate::linker::Definition;
 use crate::store::{InstanceId, StoreOpaque, Stored};
 use crate::types::matching;
 use crate::{
-    AsContextMut, Engine, Export, Extern, Func, Global, Memory, Module, SharedMemory,
-    StoreContextMut, Table, TypedFunc,
+    AsContext, AsContextMut, Engine, Export, Extern, Func, Global, MemRefMetadataTable,
+    MemRefStats, Memory, Module, SharedMemory, StoreContextMut, Table, TypedFunc,
 };
 use anyhow::{anyhow, bail, Context, Result};
 use std::mem;
@@ -11,7 +11,7 @@ use std::sync::Arc;
 use wasmtime_environ::{EntityType, FuncIndex, GlobalIndex, MemoryIndex, PrimaryMap, TableIndex};
 use wasmtime_runtime::{
     Imports, InstanceAllocationRequest, StorePtr, VMContext, VMFunctionBody, VMFunctionImport,
-    VMGlobalImport, VMMemoryImport, VMOpaqueContext, VMTableImport,
+    VMGlobalImport, VMMemoryDefinition, VMMemoryImport, VMOpaqueContext, VMTableImport,
 };
 
 /// An instantiated WebAssembly module.
@@ -351,6 +351,103 @@ impl Instance {
         Ok(())
     }
 
+    /// Returns the memref operations counted by this instance so far.
+    ///
+    /// Operations are only counted when
+    /// [`Config::memref_stats`](crate::Config::memref_stats) is enabled,
+    /// otherwise every count is zero.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `store` does not own this instance.
+    pub fn memref_stats(&self, store: impl AsContext) -> MemRefStats {
+        let store = store.as_context().0;
+        MemRefStats::from_instance(store.instance(store[self.0].id))
+    }
+
+    /// Takes a snapshot of the state of this instance, which it can later be
+    /// reset to with [`Instance::restore`].
+    ///
+    /// The snapshot holds the contents of the memories, tables and mutable
+    /// globals defined by this instance, including the memref metadata of its
+    /// memories. This is their shadow region if they have one, and otherwise
+    /// the [`MemRefMetadataTable`] the store keeps for each of them. Imported
+    /// items are part of the state of the instance defining them instead.
+    /// This is meant for guests which are initialized once and then serve
+    /// many requests, rolling back to the snapshot after each one.
+    ///
+    /// On Linux the contents of memories are captured in copy-on-write images,
+    /// so that restoring them only discards the pages written since and costs
+    /// time proportional to the number of those pages rather than to the size
+    /// of memory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if this instance defines a shared memory or if the
+    /// snapshot of a memory couldn't be created.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `store` does not own this instance.
+    pub fn snapshot(&self, mut store: impl AsContextMut) -> Result<InstanceSnapshot> {
+        let store = store.as_context_mut().0;
+        let id = store[self.0].id;
+        let inner = store.instance_mut(id).snapshot()?;
+        let memref_metadata = self
+            .defined_memories(store)
+            .into_iter()
+            .map(|memory| store.memref_metadata(memory).clone())
+            .collect();
+        Ok(InstanceSnapshot {
+            instance: *self,
+            inner,
+            memref_metadata,
+        })
+    }
+
+    /// Resets the memories, tables and mutable globals defined by this
+    /// instance to `snapshot`.
+    ///
+    /// Memories and tables which grew since the snapshot was taken are shrunk
+    /// back to their size at that time.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `snapshot` was taken from another instance. On
+    /// platforms other than Linux an error is also returned if a memory grew
+    /// since the snapshot was taken, as memories can't be shrunk there.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `store` does not own this instance.
+    pub fn restore(&self, mut store: impl AsContextMut, snapshot: &InstanceSnapshot) -> Result<()> {
+        let store = store.as_context_mut().0;
+        if snapshot.instance.0 != self.0 {
+            bail!("snapshot was taken from a different instance");
+        }
+        let id = store[self.0].id;
+        store.instance_mut(id).restore(&snapshot.inner)?;
+        let memories = self.defined_memories(store);
+        for (memory, table) in memories.into_iter().zip(&snapshot.memref_metadata) {
+            *store.memref_metadata_mut(memory) = table.clone();
+        }
+        Ok(())
+    }
+
+    /// Returns the definitions of the memories defined by this instance,
+    /// which identify their memref metadata tables in the store.
+    pub(crate) fn defined_memories(&self, store: &mut StoreOpaque) -> Vec<*mut VMMemoryDefinition> {
+        let id = store[self.0].id;
+        let handle = store.instance_mut(id);
+        let module = handle.module().clone();
+        module
+            .memory_plans
+            .keys()
+            .filter(|index| module.defined_memory_index(*index).is_some())
+            .map(|index| handle.get_exported_memory(index).definition)
+            .collect()
+    }
+
     /// Returns the list of exported items from this [`Instance`].
     ///
     /// # Panics
@@ -526,6 +623,19 @@ impl Instance {
     }
 }
 
+/// A snapshot of the state of an [`Instance`], taken with
+/// [`Instance::snapshot`].
+///
+/// Snapshots are owned values which can be restored any number of times with
+/// [`Instance::restore`].
+pub struct InstanceSnapshot {
+    instance: Instance,
+    inner: wasmtime_runtime::InstanceSnapshot,
+    /// The memref metadata tables of the memories defined by the instance,
+    /// in the order of their definitions.
+    memref_metadata: Vec<MemRefMetadataTable>,
+}
+
 pub(crate) struct OwnedImports {
     functions: PrimaryMap<FuncIndex, VMFunctionImport>,
     tables: PrimaryMap<TableIndex, VMTableImport>,
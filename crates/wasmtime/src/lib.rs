@@ -395,13 +395,18 @@ mod func;
 
 mod code;
 mod config;
+mod coredump;
 mod engine;
 mod externals;
 mod instance;
 mod limits;
 mod linker;
 mod memory;
+mod memref;
 mod module;
+#[cfg(compiler)]
+mod preinit;
+mod profiling;
 mod r#ref;
 mod signatures;
 mod store;
@@ -411,14 +416,17 @@ mod types;
 mod values;
 
 pub use crate::config::*;
+pub use crate::coredump::*;
 pub use crate::engine::*;
 pub use crate::externals::*;
 pub use crate::func::*;
-pub use crate::instance::{Instance, InstancePre};
+pub use crate::instance::{Instance, InstancePre, InstanceSnapshot};
 pub use crate::limits::*;
 pub use crate::linker::*;
 pub use crate::memory::*;
+pub use crate::memref::*;
 pub use crate::module::Module;
+pub use crate::profiling::GuestProfiler;
 pub use crate::r#ref::ExternRef;
 #[cfg(feature = "async")]
 pub use crate::store::CallHookHandler;
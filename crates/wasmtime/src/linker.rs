@@ -1026,6 +1026,77 @@ impl<T> Linker<T> {
             .await
     }
 
+    /// Pre-initializes the WebAssembly module `wasm` by running its
+    /// `init_func` export and returns a new module starting from the
+    /// resulting state.
+    ///
+    /// The module is instantiated with this linker within `store`, which runs
+    /// its start function, after which the `init_func` export, of type
+    /// `[] -> []`, is called. The contents of the memories, the values of the
+    /// mutable globals and the elements of the tables defined by the instance
+    /// are then baked into the data segments, global initializers and element
+    /// segments of the returned module, and its start function and the
+    /// `init_func` export are removed. Instantiating the returned module
+    /// therefore skips the work done by `init_func`, and with
+    /// [`Config::memory_init_cow`](crate::Config::memory_init_cow) its
+    /// memories are mapped straight from the snapshot.
+    ///
+    /// Items imported by the module, as well as any state kept by the host,
+    /// aren't part of the snapshot, so the active segments initializing
+    /// imported memories and tables are kept. Passive data and element
+    /// segments are kept as they were in `wasm`, even if `init_func` dropped
+    /// them.
+    ///
+    /// The `wasm` may be in the text format if the `wat` feature is enabled.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the module fails to compile or instantiate, if
+    /// `init_func` isn't an exported `[] -> []` function or traps, or if the
+    /// state of the instance can't be expressed in a module. That's the case
+    /// for shared memories, non-null `externref`s, `funcref`s to functions of
+    /// other instances, memrefs whose address isn't their base, and memref
+    /// metadata kept by the host or in shadow regions.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let engine = Engine::default();
+    /// let linker = Linker::new(&engine);
+    /// let mut store = Store::new(&engine, ());
+    /// let wasm = linker.preinitialize(
+    ///     &mut store,
+    ///     br#"
+    ///         (module
+    ///             (global $g (export "g") (mut i32) (i32.const 0))
+    ///             (func (export "init")
+    ///                 (global.set $g (i32.const 42)))
+    ///         )
+    ///     "#,
+    ///     "init",
+    /// )?;
+    ///
+    /// let module = Module::new(&engine, &wasm)?;
+    /// let instance = linker.instantiate(&mut store, &module)?;
+    /// let g = instance.get_global(&mut store, "g").unwrap();
+    /// assert_eq!(g.get(&mut store).unwrap_i32(), 42);
+    /// assert!(instance.get_func(&mut store, "init").is_none());
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(compiler)]
+    #[cfg_attr(nightlydoc, doc(cfg(feature = "cranelift")))] // see build.rs
+    pub fn preinitialize(
+        &self,
+        store: impl AsContextMut<Data = T>,
+        wasm: &[u8],
+        init_func: &str,
+    ) -> Result<Vec<u8>> {
+        crate::preinit::preinitialize(self, store, wasm, init_func)
+    }
+
     /// Performs all checks necessary for instantiating `module` with this
     /// linker within `store`, except that instantiation doesn't actually
     /// finish.
@@ -1,13 +1,16 @@
 use crate::store::{StoreData, StoreOpaque, Stored};
 use crate::trampoline::generate_memory_export;
 use crate::Trap;
-use crate::{AsContext, AsContextMut, Engine, MemoryType, StoreContext, StoreContextMut};
+use crate::{
+    AsContext, AsContextMut, Engine, MemRefMetadataTable, MemRefObjects, MemoryType, StoreContext,
+    StoreContextMut,
+};
 use anyhow::{bail, Result};
 use std::cell::UnsafeCell;
 use std::convert::TryFrom;
 use std::slice;
 use std::time::Instant;
-use wasmtime_environ::MemoryPlan;
+use wasmtime_environ::{memref, MemoryPlan};
 use wasmtime_runtime::{RuntimeLinearMemory, VMMemoryImport};
 
 pub use wasmtime_runtime::WaitResult;
@@ -414,6 +417,81 @@ impl Memory {
         }
     }
 
+    /// Same as [`Memory::data_and_store_mut`], but also returns the memref
+    /// metadata recorded for this memory, see [`MemRefObjects`].
+    ///
+    /// This allows host functions to check pointers passed by the guest
+    /// against the bounds of the objects they were derived from.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this memory doesn't belong to `store`.
+    pub fn data_store_and_memref_metadata<'a, T: 'a>(
+        &self,
+        store: impl Into<StoreContextMut<'a, T>>,
+    ) -> (&'a mut [u8], &'a mut T, MemRefObjects<'a>) {
+        let store = store.into().0;
+        debug_assert!(!self.wasmtime_ty(store.store_data()).shared);
+        // The memory and its shadow region are allocated outside of the
+        // store, so borrowing them doesn't overlap with the borrows of the
+        // store's fields below.
+        let (data, shadow) = unsafe {
+            let definition = &*store[self.0].definition;
+            let len = definition.current_length();
+            let data = slice::from_raw_parts_mut(definition.base, len);
+            let shadow = if definition.memref_shadow.is_null() {
+                None
+            } else {
+                let shadow_len = memref::shadow_size(len as u64) as usize;
+                Some(slice::from_raw_parts(
+                    definition.memref_shadow as *const u8,
+                    shadow_len,
+                ))
+            };
+            (data, shadow)
+        };
+        let definition = store[self.0].definition;
+        let (data_t, metadata) = store.data_and_memref_metadata_mut(definition);
+        (data, data_t, MemRefObjects::new(metadata, shadow))
+    }
+
+    /// Returns the memref metadata recorded for the memrefs stored to this
+    /// memory.
+    ///
+    /// The table is populated by the hooks defined with
+    /// [`Linker::define_memref_hooks`](crate::Linker::define_memref_hooks)
+    /// whenever a memref is stored to this memory by an instance defining or
+    /// importing it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this memory doesn't belong to `store`.
+    pub fn memref_metadata<'a, T: 'a>(
+        &self,
+        store: impl Into<StoreContext<'a, T>>,
+    ) -> &'a MemRefMetadataTable {
+        let store = store.into().0;
+        store.memref_metadata(store[self.0].definition)
+    }
+
+    /// Returns a mutable reference to the memref metadata recorded for the
+    /// memrefs stored to this memory.
+    ///
+    /// This can be used to reset the table between runs of a guest which
+    /// reuse the same store.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this memory doesn't belong to `store`.
+    pub fn memref_metadata_mut<'a, T: 'a>(
+        &self,
+        store: impl Into<StoreContextMut<'a, T>>,
+    ) -> &'a mut MemRefMetadataTable {
+        let store = store.into().0;
+        let definition = store[self.0].definition;
+        store.memref_metadata_mut(definition)
+    }
+
     /// Returns the base pointer, in the host's address space, that the memory
     /// is located at.
     ///
@@ -0,0 +1,663 @@
+//! Memref values and the bounds metadata of memrefs stored to linear memory.
+//!
+//! A [`MemRef`] is a pointer into linear memory carried together with the
+//! bounds of the object it may access. When a memref is written to linear
+//! memory with `memref.msstore` only its address lane ends up in memory; its
+//! bounds are handed off to the `__host::__set_value` import and recovered
+//! again by `__host::__get_record` when the pointer is loaded back with
+//! `memref.msload`. The tables backing those two hooks live in each
+//! [`Store`](crate::Store), one for each linear memory, so that neither
+//! guests running in different stores nor memories of the same store ever
+//! observe each other's metadata, and all of it is released when the store is
+//! dropped.
+
+use crate::store::StoreOpaque;
+use crate::{Caller, Linker, MemRefViolation, ValRaw, ValType, WasmBacktrace, WasmTy};
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::fmt;
+use wasmtime_environ::memref::{
+    self, MemRefCounter, HAS_METADATA_FLAG, NO_CHECK_FLAG, SUB_OBJ_FLAG,
+};
+use wasmtime_runtime::InstanceHandle;
+
+pub use wasmtime_environ::memref::{MemRefCheckPolicy, MemRefCheckStats};
+
+/// Default module name of the imports which provide memref metadata to wasm,
+/// see [`Config::memref_hook_names`](crate::Config::memref_hook_names).
+pub const MEMREF_HOOK_MODULE: &str = "__host";
+/// Default name of the import used to record the metadata of a stored memref.
+pub const MEMREF_SET_VALUE: &str = "__set_value";
+/// Default name of the legacy import used to look up the metadata of a
+/// loaded memref packed into an `i64`.
+pub const MEMREF_GET_VALUE: &str = "__get_value";
+/// Default name of the import used to look up the metadata of a loaded
+/// memref.
+pub const MEMREF_GET_RECORD: &str = "__get_record";
+
+/// A WebAssembly `memref` value.
+///
+/// A memref is a 32-bit address into linear memory along with the bounds of
+/// the object it may access and a set of attribute flags. In compiled code
+/// it's carried as a 128-bit vector whose four 32-bit lanes are, from lowest
+/// to highest, the address, the base and end of the object, and the
+/// attributes.
+///
+/// Memrefs may be passed to and returned from [`TypedFunc`](crate::TypedFunc)s
+/// and host functions defined with [`Func::wrap`](crate::Func::wrap) on
+/// x86_64 and aarch64 hosts, and through [`Val::MemRef`](crate::Val::MemRef)
+/// everywhere.
+#[derive(Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct MemRef {
+    addr: u32,
+    base: u32,
+    end: u32,
+    attr: u32,
+}
+
+impl MemRef {
+    /// Creates a memref pointing at `addr` which may access the object
+    /// `[base, end)`.
+    ///
+    /// The bounds are only checked by compiled code if `attr` has the
+    /// has-metadata flag set, see [`MemRef::with_bounds`].
+    pub fn new(addr: u32, base: u32, end: u32, attr: u32) -> MemRef {
+        MemRef {
+            addr,
+            base,
+            end,
+            attr,
+        }
+    }
+
+    /// Creates a memref pointing at `addr` whose accesses are checked against
+    /// the object `[base, end)`.
+    pub fn with_bounds(addr: u32, base: u32, end: u32) -> MemRef {
+        MemRef::new(addr, base, end, HAS_METADATA_FLAG)
+    }
+
+    /// Creates a memref pointing at `addr` without any bounds, so accesses
+    /// through it are only checked against the bounds of linear memory.
+    pub fn unchecked(addr: u32) -> MemRef {
+        MemRef::new(addr, 0, 0, 0)
+    }
+
+    /// Returns the null memref, the same value produced by `memref.null`.
+    ///
+    /// Any access through the null memref fails its bounds check.
+    pub fn null() -> MemRef {
+        MemRef::with_bounds(0, 0, 0)
+    }
+
+    /// Returns the address this memref points at.
+    pub fn addr(&self) -> u32 {
+        self.addr
+    }
+
+    /// Returns the first address of the object this memref may access.
+    pub fn base(&self) -> u32 {
+        self.base
+    }
+
+    /// Returns one past the last address of the object this memref may
+    /// access.
+    pub fn end(&self) -> u32 {
+        self.end
+    }
+
+    /// Returns the attributes of this memref.
+    ///
+    /// The low 8 bits hold its flags, bits 8-11 the index of the linear
+    /// memory it points into and bits 12-31 the key of the allocation it was
+    /// derived from.
+    pub fn attr(&self) -> u32 {
+        self.attr
+    }
+
+    /// Returns a copy of this memref pointing at `addr` instead, keeping its
+    /// bounds.
+    pub fn with_addr(&self, addr: u32) -> MemRef {
+        MemRef { addr, ..*self }
+    }
+
+    /// Returns whether accesses through this memref are checked against its
+    /// bounds.
+    pub fn has_metadata(&self) -> bool {
+        self.attr & HAS_METADATA_FLAG != 0
+    }
+
+    /// Returns the index of the linear memory this memref points into.
+    pub fn memory_index(&self) -> u32 {
+        memref::memory_index(self.attr)
+    }
+
+    /// Returns the key of the allocation this memref was derived from, or
+    /// `0` if it isn't tracked.
+    ///
+    /// Keys are only handed out by `memref.alloc` when
+    /// [`Config::memref_temporal_safety`](crate::Config::memref_temporal_safety)
+    /// is enabled.
+    pub fn alloc_key(&self) -> u32 {
+        memref::alloc_key(self.attr)
+    }
+
+    /// Returns the bounds metadata of this memref, as it would be recorded
+    /// when storing it to linear memory.
+    pub fn metadata(&self) -> MemRefMetadata {
+        MemRefMetadata {
+            base: self.base,
+            end: self.end,
+            attr: self.attr,
+        }
+    }
+
+    /// Creates a memref from its 128-bit representation.
+    pub fn from_u128(bits: u128) -> MemRef {
+        MemRef {
+            addr: bits as u32,
+            base: (bits >> 32) as u32,
+            end: (bits >> 64) as u32,
+            attr: (bits >> 96) as u32,
+        }
+    }
+
+    /// Returns the 128-bit representation of this memref.
+    pub fn as_u128(&self) -> u128 {
+        u128::from(self.addr)
+            | u128::from(self.base) << 32
+            | u128::from(self.end) << 64
+            | u128::from(self.attr) << 96
+    }
+}
+
+impl fmt::Debug for MemRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemRef")
+            .field("addr", &format_args!("{:#x}", self.addr))
+            .field("base", &format_args!("{:#x}", self.base))
+            .field("end", &format_args!("{:#x}", self.end))
+            .field("attr", &format_args!("{:#x}", self.attr))
+            .finish()
+    }
+}
+
+impl From<u128> for MemRef {
+    fn from(bits: u128) -> MemRef {
+        MemRef::from_u128(bits)
+    }
+}
+
+impl From<MemRef> for u128 {
+    fn from(memref: MemRef) -> u128 {
+        memref.as_u128()
+    }
+}
+
+// Memrefs are passed to and from compiled code in vector registers, so the
+// native ABI type used for them depends on the host architecture.
+#[cfg(target_arch = "x86_64")]
+type MemRefAbi = std::arch::x86_64::__m128i;
+#[cfg(target_arch = "aarch64")]
+type MemRefAbi = std::arch::aarch64::uint8x16_t;
+
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+unsafe impl WasmTy for MemRef {
+    type Abi = MemRefAbi;
+
+    #[inline]
+    fn valtype() -> ValType {
+        ValType::MemRef
+    }
+
+    #[inline]
+    fn compatible_with_store(&self, _store: &StoreOpaque) -> bool {
+        true
+    }
+
+    #[inline]
+    fn is_externref(&self) -> bool {
+        false
+    }
+
+    #[inline]
+    unsafe fn abi_from_raw(raw: *mut ValRaw) -> Self::Abi {
+        std::mem::transmute::<u128, MemRefAbi>((*raw).get_v128())
+    }
+
+    #[inline]
+    unsafe fn abi_into_raw(abi: Self::Abi, raw: *mut ValRaw) {
+        *raw = ValRaw::v128(std::mem::transmute::<MemRefAbi, u128>(abi));
+    }
+
+    #[inline]
+    fn into_abi(self, _store: &mut StoreOpaque) -> Self::Abi {
+        unsafe { std::mem::transmute::<u128, MemRefAbi>(self.as_u128()) }
+    }
+
+    #[inline]
+    unsafe fn from_abi(abi: Self::Abi, _store: &mut StoreOpaque) -> Self {
+        MemRef::from_u128(std::mem::transmute::<MemRefAbi, u128>(abi))
+    }
+}
+
+/// Bounds metadata of a single memref stored to linear memory.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct MemRefMetadata {
+    /// The first address of the object the memref points into.
+    pub base: u32,
+    /// One past the last address of the object the memref points into.
+    pub end: u32,
+    /// Attribute flags of the memref.
+    pub attr: u32,
+}
+
+impl MemRefMetadata {
+    /// Returns whether the memref carries bounds that should be checked.
+    pub fn has_metadata(&self) -> bool {
+        self.attr & HAS_METADATA_FLAG != 0
+    }
+
+    /// Returns whether the memref points to a sub-object narrowed out of a
+    /// larger allocation.
+    pub fn is_sub_object(&self) -> bool {
+        self.attr & SUB_OBJ_FLAG != 0
+    }
+
+    /// Returns the violation of storing a memref pointing at `addr` with this
+    /// metadata, if it is checked by the `imm` flags of `memref.msstore` and
+    /// points outside of its object.
+    fn violation(&self, addr: u32, imm: u32) -> Option<MemRefViolation> {
+        if !self.has_metadata() || imm & NO_CHECK_FLAG != 0 {
+            return None;
+        }
+        if addr >= self.base && addr <= self.end {
+            return None;
+        }
+        Some(MemRefViolation {
+            addr,
+            base: self.base,
+            end: self.end,
+        })
+    }
+
+    /// Packs this metadata into the `i64` returned by the legacy
+    /// `__host::__get_value` hook.
+    ///
+    /// The layout is `base << 32 | attr << 24 | (end - base)`, so only the
+    /// low 8 bits of the attributes and 24 bits of the object size are
+    /// preserved.
+    fn encode(&self) -> i64 {
+        let size = self.end.wrapping_sub(self.base) & 0x00ff_ffff;
+        (i64::from(self.base) << 32) | i64::from(size | (self.attr << 24))
+    }
+
+    /// Returns the record returned by `__host::__get_record` for a memref
+    /// pointing at `addr` with this metadata.
+    ///
+    /// The record is laid out like a memref, so the bounds and attributes
+    /// are preserved exactly.
+    fn to_record(&self, addr: u32) -> MemRef {
+        MemRef::new(addr, self.base, self.end, self.attr)
+    }
+}
+
+/// Table of the metadata of the memrefs stored to one linear memory, keyed by
+/// the address held in the memref's address lane.
+///
+/// A [`Store`](crate::Store) keeps one table for each memory its instances
+/// define or import. They are populated by the hooks defined with
+/// [`Linker::define_memref_hooks`] and are accessible through
+/// [`Memory::memref_metadata`](crate::Memory::memref_metadata).
+#[derive(Clone, Default, Debug)]
+pub struct MemRefMetadataTable {
+    entries: BTreeMap<u32, MemRefMetadata>,
+}
+
+/// The table of memories for which no metadata was recorded yet.
+pub(crate) static EMPTY_MEMREF_METADATA: MemRefMetadataTable = MemRefMetadataTable {
+    entries: BTreeMap::new(),
+};
+
+impl MemRefMetadataTable {
+    /// Returns the metadata recorded for a memref pointing at `addr`, if any.
+    pub fn get(&self, addr: u32) -> Option<MemRefMetadata> {
+        self.entries.get(&addr).copied()
+    }
+
+    /// Records `metadata` for memrefs pointing at `addr`, returning the
+    /// previous entry if there was one.
+    pub fn insert(&mut self, addr: u32, metadata: MemRefMetadata) -> Option<MemRefMetadata> {
+        self.entries.insert(addr, metadata)
+    }
+
+    /// Removes the metadata recorded for `addr`.
+    pub fn remove(&mut self, addr: u32) -> Option<MemRefMetadata> {
+        self.entries.remove(&addr)
+    }
+
+    /// Removes all recorded metadata.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Returns the number of addresses with recorded metadata.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether no metadata has been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Iterates over all recorded `(addr, metadata)` pairs in increasing
+    /// order of their address.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, MemRefMetadata)> + '_ {
+        self.entries.iter().map(|(addr, m)| (*addr, *m))
+    }
+
+    /// Returns the metadata of the object a memref pointing at `addr` was
+    /// derived from, even if no memref pointing exactly at `addr` was
+    /// recorded.
+    ///
+    /// See [`MemRefObjects::object_containing`] for how the object is found.
+    pub fn object_containing(&self, addr: u32) -> Option<MemRefMetadata> {
+        object_containing(
+            addr,
+            self.entries.range(..=addr).rev().map(|(a, m)| (*a, *m)),
+        )
+    }
+
+    /// Implementation of `__host::__set_value`, once the stored memref was
+    /// checked.
+    fn set_value(&mut self, addr: u32, metadata: MemRefMetadata) {
+        // A narrowed sub-object never overwrites the bounds of the enclosing
+        // object which were recorded for the same address.
+        if metadata.is_sub_object() && self.entries.contains_key(&addr) {
+            return;
+        }
+        self.entries.insert(addr, metadata);
+    }
+
+    /// Implementation of `__host::__get_value`.
+    fn get_value(&self, addr: u32) -> i64 {
+        self.get(addr).map(|m| m.encode()).unwrap_or(0)
+    }
+
+    /// Implementation of `__host::__get_record`.
+    fn get_record(&self, addr: u32) -> MemRef {
+        self.get(addr)
+            .map(|m| m.to_record(addr))
+            .unwrap_or(MemRef::from_u128(0))
+    }
+}
+
+/// How far below a pointer the shadow metadata region is searched for the
+/// record of the object containing it, one wasm page.
+const SHADOW_SEARCH_LIMIT: u32 = 0x1_0000;
+
+/// The memref metadata recorded for the memrefs stored to a linear memory,
+/// which host functions can check the pointers passed by the guest against.
+///
+/// This is returned by [`Memory::data_store_and_memref_metadata`] and reads
+/// the shadow region of the memory if it has one, see
+/// [`Config::memref_shadow_metadata`](crate::Config::memref_shadow_metadata),
+/// and the memory's [`MemRefMetadataTable`] otherwise.
+///
+/// [`Memory::data_store_and_memref_metadata`]: crate::Memory::data_store_and_memref_metadata
+#[derive(Copy, Clone)]
+pub struct MemRefObjects<'a> {
+    table: &'a MemRefMetadataTable,
+    shadow: Option<&'a [u8]>,
+}
+
+impl<'a> MemRefObjects<'a> {
+    pub(crate) fn new(table: &'a MemRefMetadataTable, shadow: Option<&'a [u8]>) -> Self {
+        MemRefObjects { table, shadow }
+    }
+
+    /// Returns the metadata of the object a memref pointing at `addr` was
+    /// derived from, if it has bounds.
+    ///
+    /// The record of `addr` itself is used if there is one. Otherwise the
+    /// records of lower addresses are searched for one whose bounds contain
+    /// `addr`, such as the one recorded by `memref.alloc` for the start of
+    /// the object, skipping those of sub-objects which don't. The search ends
+    /// at the first record of any other object, and after 64KiB of addresses
+    /// in a shadow region.
+    pub fn object_containing(&self, addr: u32) -> Option<MemRefMetadata> {
+        let shadow = match self.shadow {
+            Some(shadow) => shadow,
+            None => return self.table.object_containing(addr),
+        };
+        let records = (addr.saturating_sub(SHADOW_SEARCH_LIMIT)..=addr)
+            .rev()
+            .filter_map(|a| Some((a, shadow_record(shadow, a)?)));
+        object_containing(addr, records)
+    }
+}
+
+impl fmt::Debug for MemRefObjects<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemRefObjects")
+            .field("table", self.table)
+            .field("shadow", &self.shadow.map(|shadow| shadow.len()))
+            .finish()
+    }
+}
+
+impl<'a> From<&'a MemRefMetadataTable> for MemRefObjects<'a> {
+    fn from(table: &'a MemRefMetadataTable) -> Self {
+        MemRefObjects::new(table, None)
+    }
+}
+
+/// Returns the metadata of the object containing `addr` out of `records`, the
+/// records of the addresses from `addr` downwards.
+fn object_containing(
+    addr: u32,
+    records: impl Iterator<Item = (u32, MemRefMetadata)>,
+) -> Option<MemRefMetadata> {
+    for (record_addr, metadata) in records {
+        if !metadata.has_metadata() {
+            continue;
+        }
+        if record_addr == addr || (metadata.base <= addr && addr < metadata.end) {
+            return Some(metadata);
+        }
+        // Any record other than those of narrowed pointers into the object
+        // belongs to an object below it.
+        if !metadata.is_sub_object() {
+            return None;
+        }
+    }
+    None
+}
+
+/// Reads the record for `addr` out of the shadow metadata region `shadow`.
+fn shadow_record(shadow: &[u8], addr: u32) -> Option<MemRefMetadata> {
+    let start = usize::try_from(memref::shadow_record_offset(addr)).ok()?;
+    let record = shadow.get(start..start + memref::SHADOW_RECORD_SIZE as usize)?;
+    let field = |offset: i32| {
+        let offset = offset as usize;
+        u32::from_ne_bytes(record[offset..offset + 4].try_into().unwrap())
+    };
+    if field(memref::SHADOW_PRESENT_OFFSET) == 0 {
+        return None;
+    }
+    Some(MemRefMetadata {
+        base: field(memref::SHADOW_BASE_OFFSET),
+        end: field(memref::SHADOW_END_OFFSET),
+        attr: field(memref::SHADOW_ATTR_OFFSET),
+    })
+}
+
+/// Maximum number of distinct sites a [`Store`](crate::Store) keeps
+/// [`MemRefAuditRecord`]s for, see
+/// [`Store::memref_audit_dropped`](crate::Store::memref_audit_dropped).
+pub const MEMREF_AUDIT_RECORD_LIMIT: usize = 1024;
+
+/// A memref bounds violation recorded by a [`Store`](crate::Store) instead of
+/// trapping, see [`Config::memref_audit`](crate::Config::memref_audit).
+///
+/// A store keeps a single record for each instruction violating the bounds
+/// of a memref: the first violation it caught, along with how many times it
+/// happened since.
+#[derive(Debug)]
+pub struct MemRefAuditRecord {
+    /// The offending access and the bounds of the object it fell outside of,
+    /// the first time it happened.
+    pub violation: MemRefViolation,
+    /// The wasm frames on the stack when the violation first happened.
+    pub backtrace: WasmBacktrace,
+    /// The number of violations caught at this site.
+    pub count: u64,
+}
+
+impl fmt::Display for MemRefAuditRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.violation)?;
+        if self.count > 1 {
+            write!(f, " ({} times)", self.count)?;
+        }
+        write!(f, "\n{}", self.backtrace)
+    }
+}
+
+/// Counts of the memref operations performed by compiled code, see
+/// [`Config::memref_stats`](crate::Config::memref_stats).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct MemRefStats {
+    /// Bounds checks of accesses through memrefs with metadata and of memrefs
+    /// stored to a shadow metadata region.
+    pub bounds_checks: u64,
+    /// Metadata recorded for memrefs stored to linear memory.
+    pub metadata_stores: u64,
+    /// Metadata looked up for memrefs loaded from linear memory.
+    pub metadata_loads: u64,
+    /// Memrefs narrowed to a sub-object.
+    pub narrows: u64,
+}
+
+impl MemRefStats {
+    /// Reads the counters kept in the `VMContext` of `handle`.
+    pub(crate) fn from_instance(handle: &InstanceHandle) -> MemRefStats {
+        MemRefStats {
+            bounds_checks: handle.memref_counter(MemRefCounter::BoundsChecks),
+            metadata_stores: handle.memref_counter(MemRefCounter::MetadataStores),
+            metadata_loads: handle.memref_counter(MemRefCounter::MetadataLoads),
+            narrows: handle.memref_counter(MemRefCounter::Narrows),
+        }
+    }
+}
+
+impl std::ops::AddAssign for MemRefStats {
+    fn add_assign(&mut self, other: MemRefStats) {
+        self.bounds_checks += other.bounds_checks;
+        self.metadata_stores += other.metadata_stores;
+        self.metadata_loads += other.metadata_loads;
+        self.narrows += other.narrows;
+    }
+}
+
+impl fmt::Display for MemRefStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "bounds checks:   {}", self.bounds_checks)?;
+        writeln!(f, "metadata stores: {}", self.metadata_stores)?;
+        writeln!(f, "metadata loads:  {}", self.metadata_loads)?;
+        write!(f, "narrows:         {}", self.narrows)
+    }
+}
+
+impl<T> Linker<T> {
+    /// Defines the `__host::__set_value`, `__host::__get_value` and
+    /// `__host::__get_record` imports used by modules compiled with memref
+    /// metadata.
+    ///
+    /// The imports are defined under the names configured with
+    /// [`Config::memref_hook_names`](crate::Config::memref_hook_names) for
+    /// this linker's engine.
+    ///
+    /// The definitions record and look up metadata in the
+    /// [`MemRefMetadataTable`] that the [`Store`](crate::Store) of the calling
+    /// instance keeps for the memory the memref was stored to or loaded from,
+    /// so the same [`Linker`] may be used to instantiate modules in any number
+    /// of stores without them sharing metadata, and neither do the memories
+    /// of a store. An imported memory shares its table with the instance
+    /// defining it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any of the imports is already defined in this
+    /// linker and shadowing is disallowed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let engine = Engine::default();
+    /// let mut linker = Linker::new(&engine);
+    /// linker.define_memref_hooks()?;
+    ///
+    /// let wat = r#"
+    ///     (module
+    ///         (import "__host" "__set_value" (func (param i32 i32 i32 i32 i32)))
+    ///         (import "__host" "__get_value" (func (param i32) (result i64)))
+    ///     )
+    /// "#;
+    /// let module = Module::new(&engine, wat)?;
+    /// let mut store = Store::new(&engine, ());
+    /// linker.instantiate(&mut store, &module)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn define_memref_hooks(&mut self) -> Result<&mut Self> {
+        let hooks = self.engine().config().tunables.memref_hooks.clone();
+        self.func_wrap(
+            &hooks.module,
+            &hooks.set_value,
+            |mut caller: Caller<'_, T>, addr: i32, base: i32, end: i32, attr: i32, imm: i32| {
+                let metadata = MemRefMetadata {
+                    base: base as u32,
+                    end: end as u32,
+                    attr: attr as u32,
+                };
+                let memory = caller.caller.memref_hook_memory();
+                let store = &mut caller.store.0;
+                if let Some(violation) = metadata.violation(addr as u32, imm as u32) {
+                    store.memref_out_of_bounds(violation)?;
+                }
+                store
+                    .memref_metadata_mut(memory)
+                    .set_value(addr as u32, metadata);
+                Ok(())
+            },
+        )?;
+        self.func_wrap(
+            &hooks.module,
+            &hooks.get_value,
+            |caller: Caller<'_, T>, addr: i32| -> i64 {
+                let memory = caller.caller.memref_hook_memory();
+                caller
+                    .store
+                    .0
+                    .memref_metadata(memory)
+                    .get_value(addr as u32)
+            },
+        )?;
+        self.func_wrap(
+            &hooks.module,
+            &hooks.get_record,
+            |caller: Caller<'_, T>, addr: i32| -> MemRef {
+                let memory = caller.caller.memref_hook_memory();
+                caller
+                    .store
+                    .0
+                    .memref_metadata(memory)
+                    .get_record(addr as u32)
+            },
+        )?;
+        Ok(self)
+    }
+}
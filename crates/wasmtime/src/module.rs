@@ -2,7 +2,7 @@ use crate::code::CodeObject;
 use crate::{
     signatures::SignatureCollection,
     types::{ExportType, ExternType, ImportType},
-    Engine,
+    Engine, MemRefCheckStats,
 };
 use anyhow::{bail, Context, Result};
 use once_cell::sync::OnceCell;
@@ -1003,6 +1003,19 @@ impl Module {
         &*self.inner
     }
 
+    /// Returns how many bounds checks of `*.msload` and `*.msstore`
+    /// instructions were emitted in the compiled code of this module, how many
+    /// were left out because an earlier check already covered them, and how
+    /// many within loops are proven once before the loop.
+    pub fn memref_check_stats(&self) -> MemRefCheckStats {
+        let compiled = self.compiled_module();
+        let mut stats = MemRefCheckStats::default();
+        for (index, _) in compiled.finished_functions() {
+            stats += compiled.wasm_func_info(index).memref_checks;
+        }
+        stats
+    }
+
     /// Returns the range of bytes in memory where this module's compilation
     /// image resides.
     ///
@@ -0,0 +1,536 @@
+//! Pre-initialization of WebAssembly modules, see `Linker::preinitialize`.
+//!
+//! The module is instantiated and its initialization function run, after
+//! which the state of the instance is written back into the module: memories
+//! become active data segments, mutable globals get their current value as
+//! initializer and tables become active element segments. Every other section
+//! of the original module is copied over as-is.
+
+use crate::coredump::nonzero_chunks;
+use crate::store::StoreOpaque;
+use crate::{AsContextMut, Instance, Linker, MemRef, Module};
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+use wasm_encoder::{
+    ConstExpr, DataCountSection, DataSection, Encode, ExportKind, ExportSection, MemorySection,
+    MemoryType, RawSection, SectionId,
+};
+use wasmparser::{
+    DataKind, DataSectionReader, ElementItems, ElementKind, ElementSectionReader, Operator, Parser,
+    Payload,
+};
+use wasmtime_environ::{
+    memref, EntityIndex, GlobalIndex, GlobalInit, Memory, MemoryIndex, Table, TableIndex, WasmType,
+    WASM_PAGE_SIZE,
+};
+use wasmtime_runtime::{TableElement, VMCallerCheckedAnyfunc};
+
+pub(crate) fn preinitialize<T>(
+    linker: &Linker<T>,
+    mut store: impl AsContextMut<Data = T>,
+    wasm: &[u8],
+    init_func: &str,
+) -> Result<Vec<u8>> {
+    #[cfg(feature = "wat")]
+    let wasm = wat::parse_bytes(wasm)?;
+    #[cfg(feature = "wat")]
+    let wasm = &wasm[..];
+
+    let tunables = &linker.engine().config().tunables;
+    if tunables.memref_shadow_metadata || tunables.memref_temporal_safety {
+        bail!("modules can't be pre-initialized with memref shadow metadata or temporal safety");
+    }
+
+    let module = Module::from_binary(linker.engine(), wasm)?;
+    let instance = linker.instantiate(&mut store, &module)?;
+    let init = instance.get_typed_func::<(), ()>(&mut store, init_func)?;
+    init.call(&mut store, ())?;
+
+    let store = store.as_context_mut().0;
+    let memories = instance.defined_memories(store);
+    if memories
+        .into_iter()
+        .any(|memory| !store.memref_metadata(memory).is_empty())
+    {
+        bail!("the metadata of memrefs stored to linear memory can't be pre-initialized");
+    }
+    let snapshot = Snapshot::new(store, &instance)?;
+    snapshot.rewrite(wasm, module.env_module(), init_func)
+}
+
+/// The state of an instance once initialized, expressed as the initializers
+/// of its module.
+struct Snapshot {
+    /// The type and contents of each memory defined by the instance.
+    memories: Vec<(MemoryIndex, Memory, Vec<u8>)>,
+    /// The values of the mutable globals defined by the instance.
+    globals: HashMap<GlobalIndex, GlobalInit>,
+    /// The type and elements of each table defined by the instance.
+    tables: Vec<(TableIndex, Table, Vec<GlobalInit>)>,
+}
+
+impl Snapshot {
+    fn new(store: &mut StoreOpaque, instance: &Instance) -> Result<Snapshot> {
+        let id = instance.id(store);
+        let handle = store.instance_mut(id);
+        let module = handle.module().clone();
+
+        // Function references are pointers to the anyfunc of a function,
+        // which are mapped back to the index of the function here. Only
+        // escaping functions have an anyfunc to refer to.
+        let funcs = module
+            .functions
+            .iter()
+            .filter(|(_, func)| func.is_escaping())
+            .map(|(index, _)| {
+                let anyfunc = handle.get_exported_func(index).anyfunc;
+                (anyfunc.as_ptr() as *const VMCallerCheckedAnyfunc, index)
+            })
+            .collect::<HashMap<_, _>>();
+        let funcref = |anyfunc: *const VMCallerCheckedAnyfunc| {
+            if anyfunc.is_null() {
+                return Ok(GlobalInit::RefNullConst);
+            }
+            match funcs.get(&anyfunc) {
+                Some(index) => Ok(GlobalInit::RefFunc(*index)),
+                None => {
+                    bail!("references to functions of other instances can't be pre-initialized")
+                }
+            }
+        };
+
+        let mut memories = Vec::new();
+        for index in module
+            .memory_plans
+            .keys()
+            .skip(module.num_imported_memories)
+        {
+            let export = handle.get_exported_memory(index);
+            if export.memory.memory.shared {
+                bail!("shared memories can't be pre-initialized");
+            }
+            let data = unsafe {
+                let definition = &*export.definition;
+                std::slice::from_raw_parts(definition.base, definition.current_length()).to_vec()
+            };
+            memories.push((index, export.memory.memory, data));
+        }
+
+        let mut globals = HashMap::new();
+        for (index, global) in module.globals.iter().skip(module.num_imported_globals) {
+            if !global.mutability {
+                continue;
+            }
+            let export = handle.get_exported_global(index);
+            let value = unsafe {
+                let definition = &*export.definition;
+                match global.wasm_ty {
+                    WasmType::I32 => GlobalInit::I32Const(*definition.as_i32()),
+                    WasmType::I64 => GlobalInit::I64Const(*definition.as_i64()),
+                    WasmType::F32 => GlobalInit::F32Const(*definition.as_f32_bits()),
+                    WasmType::F64 => GlobalInit::F64Const(*definition.as_f64_bits()),
+                    WasmType::V128 => GlobalInit::V128Const(*definition.as_u128()),
+                    WasmType::MemRef => memref_const(*definition.as_u128())?,
+                    WasmType::FuncRef => funcref(definition.as_anyfunc())?,
+                    WasmType::ExternRef => match definition.as_externref() {
+                        Some(_) => bail!("non-null externrefs can't be pre-initialized"),
+                        None => GlobalInit::RefNullConst,
+                    },
+                }
+            };
+            globals.insert(index, value);
+        }
+
+        let mut tables = Vec::new();
+        for (index, plan) in module.table_plans.iter().skip(module.num_imported_tables) {
+            let defined = module.defined_table_index(index).unwrap();
+            let table = unsafe {
+                let size = (*handle.get_defined_table(defined)).size();
+                &*handle.get_defined_table_with_lazy_init(defined, 0..size)
+            };
+            let elements = (0..table.size())
+                .map(|i| match table.get(i).unwrap() {
+                    TableElement::FuncRef(anyfunc) => funcref(anyfunc),
+                    TableElement::ExternRef(Some(_)) => {
+                        bail!("non-null externrefs can't be pre-initialized")
+                    }
+                    TableElement::ExternRef(None) => Ok(GlobalInit::RefNullConst),
+                    TableElement::MemRef(bits) => memref_const(bits),
+                    TableElement::UninitFunc => unreachable!(),
+                })
+                .collect::<Result<Vec<_>>>()?;
+            tables.push((index, plan.table, elements));
+        }
+
+        Ok(Snapshot {
+            memories,
+            globals,
+            tables,
+        })
+    }
+
+    /// Returns `wasm` with its initializers replaced by this snapshot.
+    fn rewrite(
+        &self,
+        wasm: &[u8],
+        module: &wasmtime_environ::Module,
+        init_func: &str,
+    ) -> Result<Vec<u8>> {
+        let mut result = wasm_encoder::Module::new();
+
+        let segments = self
+            .memories
+            .iter()
+            .flat_map(|(index, ty, data)| {
+                nonzero_chunks(data)
+                    .into_iter()
+                    .map(move |range| (*index, ty, range.start, &data[range]))
+            })
+            .collect::<Vec<_>>();
+
+        let mut wrote_elements = false;
+        let mut wrote_data = false;
+        for payload in Parser::new(0).parse_all(wasm) {
+            let payload = payload?;
+
+            // The snapshot may need element or data sections which the
+            // original module doesn't have, which are added just before the
+            // sections which follow them.
+            if !wrote_elements {
+                if let Payload::DataCountSection { .. }
+                | Payload::CodeSectionStart { .. }
+                | Payload::DataSection(_)
+                | Payload::End(_) = payload
+                {
+                    self.encode_elements(&mut result, wasm, module, None)?;
+                    wrote_elements = true;
+                }
+            }
+
+            match payload {
+                Payload::Version { .. } => {}
+
+                // `wasm-encoder` doesn't know about memrefs, so tables and
+                // globals are encoded by hand.
+                Payload::TableSection(_) => {
+                    let mut tables = Vec::new();
+                    (self.tables.len() as u32).encode(&mut tables);
+                    for (_, ty, elements) in &self.tables {
+                        tables.push(val_type(ty.wasm_ty));
+                        tables.push(ty.maximum.is_some().into());
+                        (elements.len() as u32).encode(&mut tables);
+                        if let Some(maximum) = ty.maximum {
+                            maximum.encode(&mut tables);
+                        }
+                    }
+                    result.section(&RawSection {
+                        id: SectionId::Table as u8,
+                        data: &tables,
+                    });
+                }
+
+                Payload::MemorySection(_) => {
+                    let mut memories = MemorySection::new();
+                    for (_, ty, data) in &self.memories {
+                        memories.memory(MemoryType {
+                            minimum: data.len() as u64 / u64::from(WASM_PAGE_SIZE),
+                            maximum: ty.maximum,
+                            memory64: ty.memory64,
+                            shared: false,
+                        });
+                    }
+                    result.section(&memories);
+                }
+
+                Payload::GlobalSection(_) => {
+                    let mut globals = Vec::new();
+                    let count = module.globals.len() - module.num_imported_globals;
+                    (count as u32).encode(&mut globals);
+                    for (index, global) in module.globals.iter().skip(module.num_imported_globals) {
+                        globals.extend([val_type(global.wasm_ty), global.mutability.into()]);
+                        let init = self.globals.get(&index).unwrap_or(&global.initializer);
+                        encode_const_expr(&mut globals, global.wasm_ty, init);
+                    }
+                    result.section(&RawSection {
+                        id: SectionId::Global as u8,
+                        data: &globals,
+                    });
+                }
+
+                // The initialization function has run already, so it isn't
+                // exported anymore.
+                Payload::ExportSection(_) => {
+                    let mut exports = ExportSection::new();
+                    for (name, index) in &module.exports {
+                        if name == init_func {
+                            continue;
+                        }
+                        let (kind, index) = match *index {
+                            EntityIndex::Function(i) => (ExportKind::Func, i.as_u32()),
+                            EntityIndex::Table(i) => (ExportKind::Table, i.as_u32()),
+                            EntityIndex::Memory(i) => (ExportKind::Memory, i.as_u32()),
+                            EntityIndex::Global(i) => (ExportKind::Global, i.as_u32()),
+                        };
+                        exports.export(name, kind, index);
+                    }
+                    result.section(&exports);
+                }
+
+                // Likewise the start function already ran during
+                // instantiation.
+                Payload::StartSection { .. } => {}
+
+                Payload::ElementSection(elements) => {
+                    self.encode_elements(&mut result, wasm, module, Some(elements))?;
+                    wrote_elements = true;
+                }
+
+                Payload::DataCountSection { count, .. } => {
+                    result.section(&DataCountSection {
+                        count: count + segments.len() as u32,
+                    });
+                }
+
+                Payload::DataSection(data) => {
+                    encode_data(&mut result, wasm, module, Some(data), &segments)?;
+                    wrote_data = true;
+                }
+
+                Payload::End(_) => {
+                    if !wrote_data {
+                        encode_data(&mut result, wasm, module, None, &segments)?;
+                    }
+                }
+
+                other => {
+                    if let Some((id, range)) = other.as_section() {
+                        result.section(&RawSection {
+                            id,
+                            data: &wasm[range],
+                        });
+                    }
+                }
+            }
+        }
+        Ok(result.finish())
+    }
+
+    /// Encodes the element segments of `elements` followed by active
+    /// segments initializing the tables of this snapshot.
+    ///
+    /// Like tables, element segments may hold memrefs and are encoded by
+    /// hand.
+    fn encode_elements(
+        &self,
+        result: &mut wasm_encoder::Module,
+        wasm: &[u8],
+        module: &wasmtime_environ::Module,
+        elements: Option<ElementSectionReader<'_>>,
+    ) -> Result<()> {
+        let mut count = 0;
+        let mut segments = Vec::new();
+        for element in elements.into_iter().flatten() {
+            let wasmparser::Element {
+                kind,
+                items,
+                ty,
+                range,
+            } = element?;
+            count += 1;
+            match kind {
+                // Imported tables aren't part of the snapshot, so their
+                // segments are still needed.
+                ElementKind::Active { table_index, .. }
+                    if (table_index as usize) < module.num_imported_tables =>
+                {
+                    segments.extend_from_slice(&wasm[range]);
+                }
+                // Active segments are dropped once instantiation applied them
+                // to their table, like declarative ones. Turning them into
+                // declarative segments keeps the indices of the following
+                // segments along with the functions declared by them.
+                ElementKind::Active { .. } if ty == wasmparser::ValType::FuncRef => {
+                    let mut funcs = Vec::new();
+                    match items {
+                        ElementItems::Functions(items) => {
+                            for func in items {
+                                funcs.push(func?);
+                            }
+                        }
+                        ElementItems::Expressions(items) => {
+                            for expr in items {
+                                if let Operator::RefFunc { function_index } =
+                                    expr?.get_binary_reader().read_operator()?
+                                {
+                                    funcs.push(function_index);
+                                }
+                            }
+                        }
+                    }
+                    segments.extend([0x07, 0x70]);
+                    (funcs.len() as u32).encode(&mut segments);
+                    for func in funcs {
+                        segments.push(0xd2);
+                        func.encode(&mut segments);
+                        segments.push(0x0b);
+                    }
+                }
+                // Other segments don't declare anything, so an empty passive
+                // segment behaves the same.
+                ElementKind::Active { .. } => {
+                    segments.extend([0x05, val_type(ty.try_into()?), 0x00]);
+                }
+                ElementKind::Passive | ElementKind::Declared => {
+                    segments.extend_from_slice(&wasm[range]);
+                }
+            }
+        }
+
+        for (index, ty, elements) in &self.tables {
+            let is_null = |init: &GlobalInit| match init {
+                GlobalInit::RefNullConst => true,
+                GlobalInit::MemRefConst(bits) => *bits == memref::NULL,
+                _ => false,
+            };
+            if elements.iter().all(is_null) {
+                continue;
+            }
+            count += 1;
+            segments.push(0x06);
+            index.as_u32().encode(&mut segments);
+            segments.extend([0x41, 0x00, 0x0b, val_type(ty.wasm_ty)]);
+            (elements.len() as u32).encode(&mut segments);
+            for init in elements {
+                encode_const_expr(&mut segments, ty.wasm_ty, init);
+            }
+        }
+
+        if count > 0 {
+            let mut section = Vec::new();
+            count.encode(&mut section);
+            section.extend_from_slice(&segments);
+            result.section(&RawSection {
+                id: SectionId::Element as u8,
+                data: &section,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Encodes the data segments of `data`, followed by the active `segments`
+/// initializing memories with their snapshot.
+fn encode_data(
+    result: &mut wasm_encoder::Module,
+    wasm: &[u8],
+    module: &wasmtime_environ::Module,
+    data: Option<DataSectionReader<'_>>,
+    segments: &[(MemoryIndex, &Memory, usize, &[u8])],
+) -> Result<()> {
+    let mut section = DataSection::new();
+    for entry in data.into_iter().flatten() {
+        let wasmparser::Data { kind, range, .. } = entry?;
+        match kind {
+            // Imported memories aren't part of the snapshot, so their
+            // segments are still needed.
+            DataKind::Active { memory_index, .. }
+                if (memory_index as usize) < module.num_imported_memories =>
+            {
+                section.raw(&wasm[range]);
+            }
+            // Active segments are dropped once instantiation copied them to
+            // memory, which an empty passive segment is equivalent to while
+            // keeping the indices of the following segments.
+            DataKind::Active { .. } => {
+                section.passive([]);
+            }
+            DataKind::Passive => {
+                section.raw(&wasm[range]);
+            }
+        }
+    }
+
+    for (index, ty, offset, data) in segments {
+        let offset = if ty.memory64 {
+            ConstExpr::i64_const(*offset as i64)
+        } else {
+            ConstExpr::i32_const(*offset as i32)
+        };
+        section.active(index.as_u32(), &offset, data.iter().copied());
+    }
+
+    if !section.is_empty() {
+        result.section(&section);
+    }
+    Ok(())
+}
+
+/// Returns the initializer of a global or table element holding the memref
+/// `bits`.
+fn memref_const(bits: u128) -> Result<GlobalInit> {
+    // `memref.const` always points at the base of its object.
+    let memref = MemRef::from_u128(bits);
+    if memref.addr() != memref.base() {
+        bail!(
+            "{:?} can't be pre-initialized as its address isn't its base",
+            memref
+        );
+    }
+    Ok(GlobalInit::MemRefConst(bits))
+}
+
+fn encode_const_expr(bytes: &mut Vec<u8>, ty: WasmType, init: &GlobalInit) {
+    match *init {
+        GlobalInit::I32Const(value) => {
+            bytes.push(0x41);
+            value.encode(bytes);
+        }
+        GlobalInit::I64Const(value) => {
+            bytes.push(0x42);
+            value.encode(bytes);
+        }
+        GlobalInit::F32Const(bits) => {
+            bytes.push(0x43);
+            bytes.extend_from_slice(&bits.to_le_bytes());
+        }
+        GlobalInit::F64Const(bits) => {
+            bytes.push(0x44);
+            bytes.extend_from_slice(&bits.to_le_bytes());
+        }
+        GlobalInit::V128Const(bits) => {
+            bytes.extend([0xfd, 0x0c]);
+            bytes.extend_from_slice(&bits.to_le_bytes());
+        }
+        GlobalInit::GetGlobal(index) => {
+            bytes.push(0x23);
+            index.as_u32().encode(bytes);
+        }
+        GlobalInit::RefNullConst => bytes.extend([0xd0, val_type(ty)]),
+        GlobalInit::RefFunc(index) => {
+            bytes.push(0xd2);
+            index.as_u32().encode(bytes);
+        }
+        GlobalInit::MemRefConst(bits) => {
+            let memref = MemRef::from_u128(bits);
+            bytes.push(0xda);
+            memref.addr().encode(bytes);
+            memref.end().wrapping_sub(memref.base()).encode(bytes);
+            memref.attr().encode(bytes);
+        }
+        GlobalInit::Import => unreachable!(),
+    }
+    bytes.push(0x0b);
+}
+
+fn val_type(ty: WasmType) -> u8 {
+    match ty {
+        WasmType::I32 => 0x7f,
+        WasmType::I64 => 0x7e,
+        WasmType::F32 => 0x7d,
+        WasmType::F64 => 0x7c,
+        WasmType::V128 => 0x7b,
+        WasmType::FuncRef => 0x70,
+        WasmType::ExternRef => 0x6f,
+        WasmType::MemRef => 0x6e,
+    }
+}
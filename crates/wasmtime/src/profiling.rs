@@ -0,0 +1,190 @@
+use crate::store::StoreOpaque;
+use crate::{AsContext, WasmBacktrace};
+use anyhow::Result;
+use serde_json::json;
+use std::collections::HashMap;
+use std::io::Write;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use wasmtime_jit::demangle_function_name_or_index;
+
+/// A sampling profiler of the WebAssembly frames executing in a store, which
+/// writes profiles in the format of the [Firefox Profiler].
+///
+/// Each sample records a [`WasmBacktrace`] of the store, with frames named
+/// after the `name` section of their module. Samples are taken either
+/// explicitly with [`GuestProfiler::sample`], for example from a host
+/// function, or periodically while WebAssembly runs once the profiler is
+/// installed in a store with [`Store::set_guest_profiler`].
+///
+/// The resulting profile can be loaded at <https://profiler.firefox.com/>.
+///
+/// [Firefox Profiler]: https://profiler.firefox.com/docs/
+/// [`Store::set_guest_profiler`]: crate::Store::set_guest_profiler
+pub struct GuestProfiler {
+    name: String,
+    interval: Duration,
+    start: Instant,
+    start_time: Duration,
+    /// The names of the frames seen so far, which make up both the string
+    /// table and the frame table of the profile.
+    frames: Vec<String>,
+    frame_indices: HashMap<String, usize>,
+    /// The stacks seen so far, as the index of their caller's stack and of
+    /// their innermost frame.
+    stacks: Vec<(Option<usize>, usize)>,
+    stack_indices: HashMap<(Option<usize>, usize), usize>,
+    /// The samples taken so far, as their stack and the time they were
+    /// taken at.
+    samples: Vec<(Option<usize>, Duration)>,
+}
+
+impl GuestProfiler {
+    /// Creates a new profiler for the program called `name`.
+    ///
+    /// The `interval` is the expected time between two samples, which is
+    /// recorded in the profile.
+    pub fn new(name: &str, interval: Duration) -> GuestProfiler {
+        GuestProfiler {
+            name: name.to_string(),
+            interval,
+            start: Instant::now(),
+            start_time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default(),
+            frames: Vec::new(),
+            frame_indices: HashMap::new(),
+            stacks: Vec::new(),
+            stack_indices: HashMap::new(),
+            samples: Vec::new(),
+        }
+    }
+
+    /// Records a sample of the WebAssembly frames currently executing in
+    /// `store`.
+    ///
+    /// This is meant to be called while WebAssembly is on the stack, for
+    /// example from a host function called by WebAssembly. A sample with an
+    /// empty stack is recorded otherwise.
+    pub fn sample(&mut self, store: impl AsContext) {
+        self.sample_store(store.as_context().0)
+    }
+
+    pub(crate) fn sample_store(&mut self, store: &StoreOpaque) {
+        let trace = WasmBacktrace::from_captured(store, wasmtime_runtime::Backtrace::new(), None);
+        let mut stack = None;
+        // Stacks are built from the outermost frame inwards, so that stacks
+        // sharing callers share their prefix.
+        for frame in trace.frames().iter().rev() {
+            let mut name = String::new();
+            name.push_str(frame.module_name().unwrap_or("<unknown>"));
+            name.push('!');
+            let _ = demangle_function_name_or_index(
+                &mut name,
+                frame.func_name(),
+                frame.func_index() as usize,
+            );
+            let frame = match self.frame_indices.get(&name) {
+                Some(frame) => *frame,
+                None => {
+                    self.frames.push(name.clone());
+                    self.frame_indices.insert(name, self.frames.len() - 1);
+                    self.frames.len() - 1
+                }
+            };
+            let key = (stack, frame);
+            let stacks = &mut self.stacks;
+            let index = *self.stack_indices.entry(key).or_insert_with(|| {
+                stacks.push(key);
+                stacks.len() - 1
+            });
+            stack = Some(index);
+        }
+        self.samples.push((stack, self.start.elapsed()));
+    }
+
+    /// Writes the profile to `output` in the Gecko profile format, which is
+    /// understood by the Firefox Profiler.
+    pub fn finish(&self, output: impl Write) -> Result<()> {
+        let millis = |d: Duration| d.as_secs_f64() * 1000.0;
+        let samples = self
+            .samples
+            .iter()
+            .map(|(stack, time)| json!([stack, millis(*time), 0]))
+            .collect::<Vec<_>>();
+        let stacks = self
+            .stacks
+            .iter()
+            .map(|(prefix, frame)| json!([prefix, frame]))
+            .collect::<Vec<_>>();
+        // Each frame is named by the string at the same index.
+        let frames = (0..self.frames.len())
+            .map(|i| json!([i, false, 0, null, null, null, 0, 0]))
+            .collect::<Vec<_>>();
+
+        let profile = json!({
+            "meta": {
+                "version": 24,
+                "interval": millis(self.interval),
+                "startTime": millis(self.start_time),
+                "shutdownTime": null,
+                "processType": 0,
+                "product": self.name,
+                "stackwalk": 1,
+                "debug": 0,
+                "gcpoison": 0,
+                "asyncstack": 0,
+                "presymbolicated": true,
+                "categories": [
+                    { "name": "Wasm", "color": "blue", "subcategories": ["Other"] },
+                ],
+                "markerSchema": [],
+            },
+            "libs": [],
+            "pausedRanges": [],
+            "processes": [],
+            "threads": [{
+                "name": "main",
+                "processType": "default",
+                "pid": 0,
+                "tid": 0,
+                "registerTime": 0,
+                "unregisterTime": null,
+                "samples": {
+                    "schema": { "stack": 0, "time": 1, "eventDelay": 2 },
+                    "data": samples,
+                },
+                "markers": {
+                    "schema": {
+                        "name": 0,
+                        "startTime": 1,
+                        "endTime": 2,
+                        "phase": 3,
+                        "category": 4,
+                        "data": 5,
+                    },
+                    "data": [],
+                },
+                "stackTable": {
+                    "schema": { "prefix": 0, "frame": 1 },
+                    "data": stacks,
+                },
+                "frameTable": {
+                    "schema": {
+                        "location": 0,
+                        "relevantForJS": 1,
+                        "innerWindowID": 2,
+                        "implementation": 3,
+                        "line": 4,
+                        "column": 5,
+                        "category": 6,
+                        "subcategory": 7,
+                    },
+                    "data": frames,
+                },
+                "stringTable": self.frames,
+            }],
+        });
+        serde_json::to_writer(output, &profile)?;
+        Ok(())
+    }
+}
@@ -77,8 +77,15 @@
 //! `wasmtime`, must uphold for the public interface to be safe.
 
 use crate::linker::Definition;
+use crate::memref::{
+    MemRefAuditRecord, MemRefMetadataTable, MemRefStats, EMPTY_MEMREF_METADATA,
+    MEMREF_AUDIT_RECORD_LIMIT,
+};
 use crate::module::BareModuleInfo;
-use crate::{module::ModuleRegistry, Engine, Module, Trap, Val, ValRaw};
+use crate::{
+    module::ModuleRegistry, Engine, GuestProfiler, MemRefViolation, Module, Trap, Val, ValRaw,
+    WasmBacktrace,
+};
 use anyhow::{anyhow, bail, Result};
 use std::cell::UnsafeCell;
 use std::collections::HashMap;
@@ -96,8 +103,8 @@ use std::task::{Context, Poll};
 use wasmtime_runtime::{
     InstanceAllocationRequest, InstanceAllocator, InstanceHandle, ModuleInfo,
     OnDemandInstanceAllocator, SignalHandler, StorePtr, VMCallerCheckedAnyfunc, VMContext,
-    VMExternRef, VMExternRefActivationsTable, VMRuntimeLimits, VMSharedSignatureIndex,
-    VMTrampoline,
+    VMExternRef, VMExternRefActivationsTable, VMMemoryDefinition, VMRuntimeLimits,
+    VMSharedSignatureIndex, VMTrampoline,
 };
 
 mod context;
@@ -327,6 +334,24 @@ pub struct StoreOpaque {
     /// Note that this is `ManuallyDrop` as it must be dropped after
     /// `store_data` above, where the function pointers are stored.
     rooted_host_funcs: ManuallyDrop<Vec<Arc<[Definition]>>>,
+
+    /// Bounds metadata of memrefs stored to linear memory by instances in
+    /// this store, see `Linker::define_memref_hooks`. Each memory has its own
+    /// table, keyed by the address of its `VMMemoryDefinition`, which is
+    /// shared by all instances importing it.
+    memref_metadata: HashMap<usize, MemRefMetadataTable>,
+
+    /// Memref bounds violations recorded instead of trapping, see
+    /// `Config::memref_audit`, along with the index of the record of each
+    /// faulting pc and the number of violations at sites which didn't fit in
+    /// `MEMREF_AUDIT_RECORD_LIMIT` records.
+    memref_audit_records: Vec<MemRefAuditRecord>,
+    memref_audit_sites: HashMap<usize, usize>,
+    memref_audit_dropped: u64,
+
+    /// Profiler sampled whenever the epoch deadline is reached, see
+    /// `Store::set_guest_profiler`.
+    guest_profiler: Option<GuestProfiler>,
 }
 
 #[cfg(feature = "async")]
@@ -496,6 +521,11 @@ impl<T> Store<T> {
                 hostcall_val_storage: Vec::new(),
                 wasm_val_raw_storage: Vec::new(),
                 rooted_host_funcs: ManuallyDrop::new(Vec::new()),
+                memref_metadata: HashMap::default(),
+                memref_audit_records: Vec::new(),
+                memref_audit_sites: HashMap::default(),
+                memref_audit_dropped: 0,
+                guest_profiler: None,
             },
             limiter: None,
             call_hook: None,
@@ -965,6 +995,71 @@ impl<T> Store<T> {
     pub fn epoch_deadline_async_yield_and_update(&mut self, delta: u64) {
         self.inner.epoch_deadline_async_yield_and_update(delta);
     }
+
+    /// Installs `profiler` in this store, so that it takes a sample every
+    /// time the epoch deadline is reached while WebAssembly runs.
+    ///
+    /// The sample is taken before the behavior configured for the deadline,
+    /// for example with [`Store::epoch_deadline_callback`], runs. Periodic
+    /// samples can be taken by enabling
+    /// [`Config::epoch_interruption`](crate::Config::epoch_interruption),
+    /// incrementing the epoch of the engine every sampling interval and
+    /// configuring a callback which extends the deadline by one tick.
+    ///
+    /// This replaces any profiler previously installed in this store.
+    pub fn set_guest_profiler(&mut self, profiler: GuestProfiler) {
+        self.inner.guest_profiler = Some(profiler);
+    }
+
+    /// Removes the profiler installed with [`Store::set_guest_profiler`] from
+    /// this store, returning it.
+    pub fn take_guest_profiler(&mut self) -> Option<GuestProfiler> {
+        self.inner.guest_profiler.take()
+    }
+
+    /// Returns the memref bounds violations recorded by instances in this
+    /// store, in the order they first happened.
+    ///
+    /// Violations are only recorded, rather than raised as traps, when
+    /// [`Config::memref_audit`](crate::Config::memref_audit) is enabled.
+    /// Repeated violations at the same instruction share a single record
+    /// counting them, and at most [`MEMREF_AUDIT_RECORD_LIMIT`] records are
+    /// kept; violations at further sites are only counted by
+    /// [`Store::memref_audit_dropped`].
+    ///
+    /// Use-after-free and double-free detected by
+    /// [`Config::memref_temporal_safety`](crate::Config::memref_temporal_safety)
+    /// are never recorded here, they trap even in audit mode.
+    pub fn memref_audit_records(&self) -> &[MemRefAuditRecord] {
+        self.inner.memref_audit_records()
+    }
+
+    /// Returns the number of memref bounds violations which weren't recorded
+    /// because [`MEMREF_AUDIT_RECORD_LIMIT`] records were already kept.
+    ///
+    /// For more information see [`Store::memref_audit_records`].
+    pub fn memref_audit_dropped(&self) -> u64 {
+        self.inner.memref_audit_dropped()
+    }
+
+    /// Removes and returns the memref bounds violations recorded by
+    /// instances in this store, resetting the count of dropped violations.
+    ///
+    /// For more information see [`Store::memref_audit_records`].
+    pub fn take_memref_audit_records(&mut self) -> Vec<MemRefAuditRecord> {
+        self.inner.take_memref_audit_records()
+    }
+
+    /// Returns the memref operations counted by all instances in this store
+    /// so far.
+    ///
+    /// Operations are only counted when
+    /// [`Config::memref_stats`](crate::Config::memref_stats) is enabled,
+    /// otherwise every count is zero. See also
+    /// [`Instance::memref_stats`](crate::Instance::memref_stats).
+    pub fn memref_stats(&self) -> MemRefStats {
+        self.inner.memref_stats()
+    }
 }
 
 impl<'a, T> StoreContext<'a, T> {
@@ -1070,6 +1165,38 @@ impl<'a, T> StoreContextMut<'a, T> {
         self.0.epoch_deadline_trap();
     }
 
+    /// Returns the memref bounds violations recorded by instances in this
+    /// store.
+    ///
+    /// For more information see [`Store::memref_audit_records`].
+    pub fn memref_audit_records(&self) -> &[MemRefAuditRecord] {
+        self.0.memref_audit_records()
+    }
+
+    /// Returns the number of memref bounds violations which weren't
+    /// recorded.
+    ///
+    /// For more information see [`Store::memref_audit_dropped`].
+    pub fn memref_audit_dropped(&self) -> u64 {
+        self.0.memref_audit_dropped()
+    }
+
+    /// Removes and returns the memref bounds violations recorded by
+    /// instances in this store.
+    ///
+    /// For more information see [`Store::take_memref_audit_records`].
+    pub fn take_memref_audit_records(&mut self) -> Vec<MemRefAuditRecord> {
+        self.0.take_memref_audit_records()
+    }
+
+    /// Returns the memref operations counted by all instances in this store
+    /// so far.
+    ///
+    /// For more information see [`Store::memref_stats`].
+    pub fn memref_stats(&self) -> MemRefStats {
+        self.0.memref_stats()
+    }
+
     #[cfg_attr(nightlydoc, doc(cfg(feature = "async")))]
     /// Configures epoch-deadline expiration to yield to the async
     /// caller and the update the deadline.
@@ -1093,6 +1220,16 @@ impl<T> StoreInner<T> {
         &mut self.data
     }
 
+    /// Borrows the `T` of this store along with the memref metadata table of
+    /// `memory`, which are disjoint fields.
+    #[inline]
+    pub(crate) fn data_and_memref_metadata_mut(
+        &mut self,
+        memory: *mut VMMemoryDefinition,
+    ) -> (&mut T, &MemRefMetadataTable) {
+        (&mut self.data, self.inner.memref_metadata(memory))
+    }
+
     pub fn call_hook(&mut self, s: CallHook) -> Result<()> {
         match &mut self.call_hook {
             Some(CallHookInner::Sync(hook)) => hook(&mut self.data, s),
@@ -1193,6 +1330,11 @@ impl StoreOpaque {
         &mut self.instances[id.0].handle
     }
 
+    /// Returns all instances in this store, in the order they were added.
+    pub fn all_instances_mut(&mut self) -> impl Iterator<Item = &mut InstanceHandle> {
+        self.instances.iter_mut().map(|i| &mut i.handle)
+    }
+
     #[cfg_attr(not(target_os = "linux"), allow(dead_code))] // not used on all platforms
     pub fn set_signal_handler(&mut self, handler: Option<Box<SignalHandler<'static>>>) {
         self.signal_handler = handler;
@@ -1208,6 +1350,78 @@ impl StoreOpaque {
         &mut self.externref_activations_table
     }
 
+    /// Returns the memref metadata table of the memory whose definition is
+    /// `memory`.
+    #[inline]
+    pub fn memref_metadata(&self, memory: *mut VMMemoryDefinition) -> &MemRefMetadataTable {
+        self.memref_metadata
+            .get(&(memory as usize))
+            .unwrap_or(&EMPTY_MEMREF_METADATA)
+    }
+
+    /// Returns the memref metadata table of the memory whose definition is
+    /// `memory`, creating it if nothing was recorded for the memory yet.
+    #[inline]
+    pub fn memref_metadata_mut(
+        &mut self,
+        memory: *mut VMMemoryDefinition,
+    ) -> &mut MemRefMetadataTable {
+        self.memref_metadata.entry(memory as usize).or_default()
+    }
+
+    #[inline]
+    pub fn memref_audit_records(&self) -> &[MemRefAuditRecord] {
+        &self.memref_audit_records
+    }
+
+    #[inline]
+    pub fn memref_audit_dropped(&self) -> u64 {
+        self.memref_audit_dropped
+    }
+
+    pub fn take_memref_audit_records(&mut self) -> Vec<MemRefAuditRecord> {
+        self.memref_audit_sites.clear();
+        self.memref_audit_dropped = 0;
+        mem::take(&mut self.memref_audit_records)
+    }
+
+    pub fn memref_stats(&self) -> MemRefStats {
+        let mut stats = MemRefStats::default();
+        for instance in self.instances.iter() {
+            stats += MemRefStats::from_instance(&instance.handle);
+        }
+        stats
+    }
+
+    /// Raises `violation` as a trap, or records it along with the current
+    /// backtrace if `Config::memref_audit` is enabled.
+    ///
+    /// Violations are told apart by the pc of the innermost wasm frame, and
+    /// only the first violation at each pc is symbolicated and kept.
+    pub fn memref_out_of_bounds(&mut self, violation: MemRefViolation) -> Result<()> {
+        if !self.engine().config().tunables.memref_audit {
+            return Err(anyhow::Error::new(Trap::MemRefOutOfBounds).context(violation));
+        }
+        let trace = wasmtime_runtime::Backtrace::new();
+        let pc = trace.frames().next().map_or(0, |frame| frame.pc());
+        if let Some(&index) = self.memref_audit_sites.get(&pc) {
+            self.memref_audit_records[index].count += 1;
+            return Ok(());
+        }
+        if self.memref_audit_records.len() >= MEMREF_AUDIT_RECORD_LIMIT {
+            self.memref_audit_dropped += 1;
+            return Ok(());
+        }
+        let backtrace = WasmBacktrace::from_captured(self, trace, None);
+        self.memref_audit_sites.insert(pc, self.memref_audit_records.len());
+        self.memref_audit_records.push(MemRefAuditRecord {
+            violation,
+            backtrace,
+            count: 1,
+        });
+        Ok(())
+    }
+
     pub fn gc(&mut self) {
         // For this crate's API, we ensure that `set_stack_canary` invariants
         // are upheld for all host-->Wasm calls.
@@ -1912,7 +2126,15 @@ unsafe impl<T> wasmtime_runtime::Store for StoreInner<T> {
         };
     }
 
+    fn memref_out_of_bounds(&mut self, violation: MemRefViolation) -> Result<(), anyhow::Error> {
+        <StoreOpaque>::memref_out_of_bounds(self, violation)
+    }
+
     fn new_epoch(&mut self) -> Result<u64, anyhow::Error> {
+        if let Some(mut profiler) = self.inner.guest_profiler.take() {
+            profiler.sample_store(&self.inner);
+            self.inner.guest_profiler = Some(profiler);
+        }
         return match &mut self.epoch_deadline_behavior {
             EpochDeadline::Trap => Err(Trap::Interrupt.into()),
             EpochDeadline::Callback(callback) => {
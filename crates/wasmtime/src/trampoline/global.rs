@@ -25,6 +25,7 @@ pub fn create_global(store: &mut StoreOpaque, gt: &GlobalType, val: Val) -> Resu
             Val::F32(f) => GlobalInit::F32Const(f),
             Val::F64(f) => GlobalInit::F64Const(f),
             Val::V128(i) => GlobalInit::V128Const(i.into()),
+            Val::MemRef(m) => GlobalInit::MemRefConst(m.as_u128()),
             Val::ExternRef(None) | Val::FuncRef(None) => GlobalInit::RefNullConst,
             Val::ExternRef(Some(x)) => {
                 // There is no `GlobalInit` variant for using an existing
@@ -4,6 +4,7 @@ use crate::store::{InstanceId, StoreOpaque};
 use crate::MemoryType;
 use anyhow::{anyhow, Result};
 use std::convert::TryFrom;
+use std::ptr;
 use std::sync::Arc;
 use wasmtime_environ::{EntityIndex, MemoryPlan, MemoryStyle, Module, WASM_PAGE_SIZE};
 use wasmtime_runtime::{
@@ -101,6 +102,8 @@ impl RuntimeLinearMemory for LinearMemoryProxy {
         VMMemoryDefinition {
             base: self.mem.as_ptr(),
             current_length: self.mem.byte_size().into(),
+            memref_shadow: ptr::null_mut(),
+            memref_locks: ptr::null_mut(),
         }
     }
 
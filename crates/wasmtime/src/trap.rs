@@ -1,5 +1,5 @@
 use crate::store::StoreOpaque;
-use crate::{AsContext, Module};
+use crate::{AsContext, Module, WasmCoreDump};
 use anyhow::Error;
 use std::fmt;
 use wasmtime_environ::{EntityRef, FilePos};
@@ -67,6 +67,13 @@ use wasmtime_jit::{demangle_function_name, demangle_function_name_or_index};
 /// ```
 pub use wasmtime_environ::Trap;
 
+/// Details of a memref bounds violation.
+///
+/// Errors whose root cause is [`Trap::MemRefOutOfBounds`] raised by compiled
+/// code carry this as [`context`](anyhow::Error::context), which can be
+/// inspected with [`downcast_ref`](anyhow::Error::downcast_ref).
+pub use wasmtime_environ::MemRefViolation;
+
 // Same safety requirements and caveats as
 // `wasmtime_runtime::raise_user_trap`.
 pub(crate) unsafe fn raise(error: anyhow::Error) -> ! {
@@ -76,11 +83,11 @@ pub(crate) unsafe fn raise(error: anyhow::Error) -> ! {
 
 #[cold] // traps are exceptional, this helps move handling off the main path
 pub(crate) fn from_runtime_box(
-    store: &StoreOpaque,
+    store: &mut StoreOpaque,
     runtime_trap: Box<wasmtime_runtime::Trap>,
 ) -> Error {
     let wasmtime_runtime::Trap { reason, backtrace } = *runtime_trap;
-    let (error, pc) = match reason {
+    let (mut error, pc) = match reason {
         // For user-defined errors they're already an `anyhow::Error` so no
         // conversion is really necessary here, but a `backtrace` may have
         // been captured so it's attempted to get inserted here.
@@ -110,17 +117,25 @@ pub(crate) fn from_runtime_box(
         }
         wasmtime_runtime::TrapReason::Wasm(trap_code) => (trap_code.into(), None),
     };
-    match backtrace {
-        Some(bt) => {
+    if let Some(bt) = backtrace {
+        // The backtrace may have only been captured for the coredump, in
+        // which case it's not attached to the error itself.
+        let coredump = if store.engine().config().coredump_on_trap {
+            Some(WasmCoreDump::new(store, &bt, pc))
+        } else {
+            None
+        };
+        if store.engine().config().wasm_backtrace {
             let bt = WasmBacktrace::from_captured(store, bt, pc);
-            if bt.wasm_trace.is_empty() {
-                error
-            } else {
-                error.context(bt)
+            if !bt.wasm_trace.is_empty() {
+                error = error.context(bt);
             }
         }
-        None => error,
+        if let Some(coredump) = coredump {
+            error = error.context(coredump);
+        }
     }
+    error
 }
 
 /// Representation of a backtrace of function frames in a WebAssembly module for
@@ -255,7 +270,7 @@ impl WasmBacktrace {
         Self::from_captured(store.0, wasmtime_runtime::Backtrace::new(), None)
     }
 
-    fn from_captured(
+    pub(crate) fn from_captured(
         store: &StoreOpaque,
         runtime_trace: wasmtime_runtime::Backtrace,
         trap_pc: Option<usize>,
@@ -1,6 +1,6 @@
 use crate::r#ref::ExternRef;
 use crate::store::StoreOpaque;
-use crate::{AsContextMut, Func, ValType};
+use crate::{AsContextMut, Func, MemRef, ValType};
 use anyhow::{bail, Result};
 use std::ptr;
 use wasmtime_runtime::TableElement;
@@ -46,6 +46,10 @@ pub enum Val {
     /// `ExternRef(None)` is the null external reference, created by `ref.null
     /// extern` in Wasm.
     ExternRef(Option<ExternRef>),
+
+    /// A pointer into linear memory along with the bounds of the object it
+    /// may access.
+    MemRef(MemRef),
 }
 
 macro_rules! accessors {
@@ -92,6 +96,7 @@ impl Val {
             Val::ExternRef(_) => ValType::ExternRef,
             Val::FuncRef(_) => ValType::FuncRef,
             Val::V128(_) => ValType::V128,
+            Val::MemRef(_) => ValType::MemRef,
         }
     }
 
@@ -108,6 +113,7 @@ impl Val {
             Val::F32(u) => ValRaw::f32(*u),
             Val::F64(u) => ValRaw::f64(*u),
             Val::V128(b) => ValRaw::v128(*b),
+            Val::MemRef(m) => ValRaw::v128(m.as_u128()),
             Val::ExternRef(e) => {
                 let externref = match e {
                     Some(e) => e.to_raw(store),
@@ -138,7 +144,8 @@ impl Val {
             ValType::I64 => Val::I64(raw.get_i64()),
             ValType::F32 => Val::F32(raw.get_f32()),
             ValType::F64 => Val::F64(raw.get_f64()),
-            ValType::V128 | ValType::MemRef => Val::V128(raw.get_v128()),
+            ValType::V128 => Val::V128(raw.get_v128()),
+            ValType::MemRef => Val::MemRef(MemRef::from_u128(raw.get_v128())),
             ValType::ExternRef => Val::ExternRef(ExternRef::from_raw(raw.get_externref())),
             ValType::FuncRef => Val::FuncRef(Func::from_raw(store, raw.get_funcref())),
         }
@@ -152,6 +159,7 @@ impl Val {
         (F64(f64) f64 unwrap_f64 f64::from_bits(*e))
         (FuncRef(Option<&Func>) funcref unwrap_funcref e.as_ref())
         (V128(u128) v128 unwrap_v128 *e)
+        (MemRef(MemRef) memref unwrap_memref *e)
     }
 
     /// Attempt to access the underlying `externref` value of this `Val`.
@@ -203,6 +211,7 @@ impl Val {
                 Ok(TableElement::ExternRef(Some(x.inner)))
             }
             (Val::ExternRef(None), ValType::ExternRef) => Ok(TableElement::ExternRef(None)),
+            (Val::MemRef(m), ValType::MemRef) => Ok(TableElement::MemRef(m.as_u128())),
             _ => bail!("value does not match table element type"),
         }
     }
@@ -213,14 +222,15 @@ impl Val {
             Val::FuncRef(Some(f)) => f.comes_from_same_store(store),
             Val::FuncRef(None) => true,
 
-            // Integers, floats, vectors, and `externref`s have no association
-            // with any particular store, so they're always considered as "yes I
-            // came from that store",
+            // Integers, floats, vectors, memrefs, and `externref`s have no
+            // association with any particular store, so they're always
+            // considered as "yes I came from that store",
             Val::I32(_)
             | Val::I64(_)
             | Val::F32(_)
             | Val::F64(_)
             | Val::V128(_)
+            | Val::MemRef(_)
             | Val::ExternRef(_) => true,
         }
     }
@@ -288,3 +298,10 @@ impl From<u128> for Val {
         Val::V128(val)
     }
 }
+
+impl From<MemRef> for Val {
+    #[inline]
+    fn from(val: MemRef) -> Val {
+        Val::MemRef(val)
+    }
+}
@@ -1,6 +1,6 @@
 use anyhow::{bail, Context, Result};
 use std::fmt::{Display, LowerHex};
-use wasmtime::{ExternRef, Val};
+use wasmtime::{ExternRef, MemRef, Val, ValType};
 use wast::core::{HeapType, NanPattern, V128Pattern, WastArgCore, WastRetCore};
 use wast::token::{Float32, Float64};
 
@@ -21,6 +21,17 @@ pub fn val(v: &WastArgCore<'_>) -> Result<Val> {
     })
 }
 
+/// Translate from a `script::Value` to a `RuntimeValue` of type `ty`.
+///
+/// The text format has no memref constants, so memrefs are written as
+/// `v128.const i32x4 addr base end attr` and converted here.
+pub fn val_of_type(v: &WastArgCore<'_>, ty: &ValType) -> Result<Val> {
+    Ok(match (val(v)?, ty) {
+        (Val::V128(x), ValType::MemRef) => Val::MemRef(MemRef::from_u128(x)),
+        (v, _) => v,
+    })
+}
+
 fn extract_lane_as_i8(bytes: u128, lane: usize) -> i8 {
     (bytes >> (lane * 8)) as i8
 }
@@ -46,6 +57,7 @@ pub fn match_val(actual: &Val, expected: &WastRetCore) -> Result<()> {
         (Val::F32(a), WastRetCore::F32(b)) => match_f32(*a, b),
         (Val::F64(a), WastRetCore::F64(b)) => match_f64(*a, b),
         (Val::V128(a), WastRetCore::V128(b)) => match_v128(*a, b),
+        (Val::MemRef(a), WastRetCore::V128(b)) => match_v128(a.as_u128(), b),
         (Val::ExternRef(x), WastRetCore::RefNull(Some(HeapType::Extern))) => {
             if let Some(x) = x {
                 let x = x
@@ -139,6 +139,13 @@ impl<T> WastContext<T> {
         Ok(())
     }
 
+    /// Register the `__host` imports used by modules compiled with memref
+    /// metadata.
+    pub fn register_memref_hooks(&mut self) -> Result<()> {
+        self.core_linker.define_memref_hooks()?;
+        Ok(())
+    }
+
     /// Perform the action portion of a command.
     fn perform_execute(&mut self, exec: WastExecute<'_>) -> Result<Outcome> {
         match exec {
@@ -164,16 +171,21 @@ impl<T> WastContext<T> {
                 let func = export
                     .into_func()
                     .ok_or_else(|| anyhow!("no function named `{}`", exec.name))?;
+                let ty = func.ty(&self.store);
+                if exec.args.len() != ty.params().len() {
+                    bail!("mismatched number of parameters")
+                }
                 let values = exec
                     .args
                     .iter()
-                    .map(|v| match v {
-                        WastArg::Core(v) => core::val(v),
+                    .zip(ty.params())
+                    .map(|(v, ty)| match v {
+                        WastArg::Core(v) => core::val_of_type(v, &ty),
                         WastArg::Component(_) => bail!("expected component function, found core"),
                     })
                     .collect::<Result<Vec<_>>>()?;
 
-                let mut results = vec![Val::null(); func.ty(&self.store).results().len()];
+                let mut results = vec![Val::null(); ty.results().len()];
                 Ok(match func.call(&mut self.store, &values, &mut results) {
                     Ok(()) => Outcome::Ok(Results::Core(results.into())),
                     Err(e) => Outcome::Trap(e),
@@ -332,6 +344,13 @@ impl<T> WastContext<T> {
             Outcome::Ok(values) => bail!("expected trap, got {:?}", values),
             Outcome::Trap(t) => t,
         };
+        // Memref traps carry the offending access as context, so match the
+        // trap itself rather than only the outermost message.
+        if let Some(trap) = trap.downcast_ref::<Trap>() {
+            if trap.to_string() == expected {
+                return Ok(());
+            }
+        }
         let actual = format!("{trap:?}");
         if actual.contains(expected)
             // `bulk-memory-operations/bulk.wast` checks for a message that
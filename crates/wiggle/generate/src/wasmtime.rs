@@ -115,9 +115,11 @@ fn generate_func(
         let export = caller.get_export("memory");
         let (mem, ctx) = match &export {
             Some(wiggle::wasmtime_crate::Extern::Memory(m)) => {
-                let (mem, ctx) = m.data_and_store_mut(&mut caller);
+                let (mem, ctx, metadata) = m.data_store_and_memref_metadata(&mut caller);
                 let ctx = get_cx(ctx);
-                (wiggle::wasmtime::WasmtimeGuestMemory::new(mem), ctx)
+                let mem = wiggle::wasmtime::WasmtimeGuestMemory::new(mem)
+                    .with_memref_metadata(metadata);
+                (mem, ctx)
             }
             Some(wiggle::wasmtime_crate::Extern::SharedMemory(m)) => {
                 let ctx = get_cx(caller.data_mut());
@@ -11,6 +11,8 @@ pub enum GuestError {
     PtrOverflow,
     #[error("Pointer out of bounds: {0:?}")]
     PtrOutOfBounds(Region),
+    #[error("Pointer outside of its memref object {1:?}: {0:?}")]
+    PtrOutsideObject(Region, Region),
     #[error("Pointer not aligned to {1}: {0:?}")]
     PtrNotAligned(Region, u32),
     #[error("Pointer already borrowed: {0:?}")]
@@ -71,7 +71,7 @@ macro_rules! integer_primitives {
                 // indicating that the memory is valid, and next safety checks
                 // are required to access it.
                 let offset = ptr.offset();
-                let (host_ptr, region) = super::validate_size_align::<Self>(ptr.mem(), offset, 1)?;
+                let (host_ptr, region) = super::validate_size_align::<Self>(ptr.mem(), offset, 1, ptr.memref_bounds())?;
                 let host_ptr = &host_ptr[0];
 
                 // If this memory is mutable borrowed then it cannot be read
@@ -102,7 +102,7 @@ macro_rules! integer_primitives {
                 // See `read` above for various checks here.
                 let val = val.to_le();
                 let offset = ptr.offset();
-                let (host_ptr, region) = super::validate_size_align::<Self>(ptr.mem(), offset, 1)?;
+                let (host_ptr, region) = super::validate_size_align::<Self>(ptr.mem(), offset, 1, ptr.memref_bounds())?;
                 let host_ptr = &host_ptr[0];
                 if ptr.mem().is_shared_borrowed(region) || ptr.mem().is_mut_borrowed(region) {
                     return Err(GuestError::PtrBorrowed(region));
@@ -133,6 +133,7 @@ macro_rules! float_primitives {
                     ptr.mem(),
                     offset,
                     1,
+                    ptr.memref_bounds(),
                 )?;
                 let host_ptr = &host_ptr[0];
                 if ptr.mem().is_mut_borrowed(region) {
@@ -152,6 +153,7 @@ macro_rules! float_primitives {
                     ptr.mem(),
                     offset,
                     1,
+                    ptr.memref_bounds(),
                 )?;
                 let host_ptr = &host_ptr[0];
                 if ptr.mem().is_shared_borrowed(region) || ptr.mem().is_mut_borrowed(region) {
@@ -151,6 +151,18 @@ pub unsafe trait GuestMemory: Send + Sync {
     fn is_shared_memory(&self) -> bool {
         false
     }
+
+    /// Returns the bounds of the object a guest memref pointing at `pointer`
+    /// was derived from, if the guest recorded any for `pointer` itself or
+    /// for the object containing it.
+    ///
+    /// A `GuestPtr` created with [`GuestPtr::new`] picks up these bounds, and
+    /// every access through it, or through pointers derived from it, must
+    /// then stay within the object rather than just within the memory.
+    fn memref_bounds(&self, pointer: u32) -> Option<Region> {
+        let _ = pointer;
+        None
+    }
 }
 
 /// Validates a guest-relative pointer given various attributes, and returns
@@ -161,6 +173,8 @@ pub unsafe trait GuestMemory: Send + Sync {
 ///   base.
 /// * `len` - this is the number of length, in units of `T`, to return
 ///   in the resulting slice.
+/// * `bounds` - this is the object the pointer was derived from, if the
+///   guest passed it as a memref.
 ///
 /// If the parameters are valid then this function will return a slice into
 /// `mem` for units of `T`, assuming everything is in-bounds and properly
@@ -170,6 +184,7 @@ fn validate_size_align<'a, T: GuestTypeTransparent<'a>>(
     mem: &'a dyn GuestMemory,
     offset: u32,
     len: u32,
+    bounds: Option<Region>,
 ) -> Result<(&[UnsafeCell<T>], Region), GuestError> {
     let base = mem.base();
     let byte_len = len
@@ -179,6 +194,11 @@ fn validate_size_align<'a, T: GuestTypeTransparent<'a>>(
         start: offset,
         len: byte_len,
     };
+    if let Some(object) = bounds {
+        if !object.contains(region) {
+            return Err(GuestError::PtrOutsideObject(region, object));
+        }
+    }
     let offset = usize::try_from(offset)?;
     let byte_len = usize::try_from(byte_len)?;
 
@@ -233,6 +253,9 @@ unsafe impl<'a, T: ?Sized + GuestMemory> GuestMemory for &'a T {
     fn shared_unborrow(&self, h: BorrowHandle) {
         T::shared_unborrow(self, h)
     }
+    fn memref_bounds(&self, pointer: u32) -> Option<Region> {
+        T::memref_bounds(self, pointer)
+    }
 }
 
 unsafe impl<'a, T: ?Sized + GuestMemory> GuestMemory for &'a mut T {
@@ -260,6 +283,9 @@ unsafe impl<'a, T: ?Sized + GuestMemory> GuestMemory for &'a mut T {
     fn shared_unborrow(&self, h: BorrowHandle) {
         T::shared_unborrow(self, h)
     }
+    fn memref_bounds(&self, pointer: u32) -> Option<Region> {
+        T::memref_bounds(self, pointer)
+    }
 }
 
 unsafe impl<T: ?Sized + GuestMemory> GuestMemory for Box<T> {
@@ -287,6 +313,9 @@ unsafe impl<T: ?Sized + GuestMemory> GuestMemory for Box<T> {
     fn shared_unborrow(&self, h: BorrowHandle) {
         T::shared_unborrow(self, h)
     }
+    fn memref_bounds(&self, pointer: u32) -> Option<Region> {
+        T::memref_bounds(self, pointer)
+    }
 }
 
 unsafe impl<T: ?Sized + GuestMemory> GuestMemory for Arc<T> {
@@ -314,6 +343,9 @@ unsafe impl<T: ?Sized + GuestMemory> GuestMemory for Arc<T> {
     fn shared_unborrow(&self, h: BorrowHandle) {
         T::shared_unborrow(self, h)
     }
+    fn memref_bounds(&self, pointer: u32) -> Option<Region> {
+        T::memref_bounds(self, pointer)
+    }
 }
 
 /// A *guest* pointer into host memory.
@@ -369,6 +401,7 @@ unsafe impl<T: ?Sized + GuestMemory> GuestMemory for Arc<T> {
 pub struct GuestPtr<'a, T: ?Sized + Pointee> {
     mem: &'a (dyn GuestMemory + 'a),
     pointer: T::Pointer,
+    bounds: Option<Region>,
 }
 
 impl<'a, T: ?Sized + Pointee> GuestPtr<'a, T> {
@@ -377,8 +410,26 @@ impl<'a, T: ?Sized + Pointee> GuestPtr<'a, T> {
     /// Note that for sized types like `u32`, `GuestPtr<T>`, etc, the `pointer`
     /// value is a `u32` offset into guest memory. For slices and strings,
     /// `pointer` is a `(u32, u32)` offset/length pair.
+    ///
+    /// If `mem` knows the bounds of a memref pointing at `pointer`, see
+    /// [`GuestMemory::memref_bounds`], accesses through the returned pointer
+    /// are checked against them.
     pub fn new(mem: &'a (dyn GuestMemory + 'a), pointer: T::Pointer) -> GuestPtr<'a, T> {
-        GuestPtr { mem, pointer }
+        let bounds = mem.memref_bounds(T::start(pointer));
+        GuestPtr {
+            mem,
+            pointer,
+            bounds,
+        }
+    }
+
+    /// Returns a pointer to `pointer` within the same object as this one.
+    fn derive<U: ?Sized + Pointee>(&self, pointer: U::Pointer) -> GuestPtr<'a, U> {
+        GuestPtr {
+            mem: self.mem,
+            pointer,
+            bounds: self.bounds,
+        }
     }
 
     /// Returns the offset of this pointer in guest memory.
@@ -394,6 +445,18 @@ impl<'a, T: ?Sized + Pointee> GuestPtr<'a, T> {
         self.mem
     }
 
+    /// Returns the bounds of the object this pointer was derived from, if the
+    /// guest passed it as a memref.
+    pub fn memref_bounds(&self) -> Option<Region> {
+        self.bounds
+    }
+
+    /// Returns this pointer restricted to accesses within `bounds`, or
+    /// without any object bounds if `bounds` is `None`.
+    pub fn with_memref_bounds(self, bounds: Option<Region>) -> GuestPtr<'a, T> {
+        GuestPtr { bounds, ..self }
+    }
+
     /// Casts this `GuestPtr` type to a different type.
     ///
     /// This is a safe method which is useful for simply reinterpreting the type
@@ -404,7 +467,7 @@ impl<'a, T: ?Sized + Pointee> GuestPtr<'a, T> {
     where
         T: Pointee<Pointer = u32>,
     {
-        GuestPtr::new(self.mem, self.pointer)
+        self.derive(self.pointer)
     }
 
     /// Safely read a value from this pointer.
@@ -471,7 +534,7 @@ impl<'a, T: ?Sized + Pointee> GuestPtr<'a, T> {
             Some(o) => o,
             None => return Err(GuestError::PtrOverflow),
         };
-        Ok(GuestPtr::new(self.mem, offset))
+        Ok(self.derive(offset))
     }
 
     /// Returns a `GuestPtr` for an array of `T`s using this pointer as the
@@ -480,7 +543,7 @@ impl<'a, T: ?Sized + Pointee> GuestPtr<'a, T> {
     where
         T: GuestType<'a> + Pointee<Pointer = u32>,
     {
-        GuestPtr::new(self.mem, (self.pointer, elems))
+        self.derive((self.pointer, elems))
     }
 }
 
@@ -572,7 +635,8 @@ impl<'a, T> GuestPtr<'a, [T]> {
     where
         T: GuestTypeTransparent<'a>,
     {
-        let (ptr, region) = validate_size_align(self.mem, self.pointer.0, self.pointer.1)?;
+        let (ptr, region) =
+            validate_size_align(self.mem, self.pointer.0, self.pointer.1, self.bounds)?;
 
         Ok(UnsafeGuestSlice {
             ptr,
@@ -652,7 +716,7 @@ impl<'a, T> GuestPtr<'a, [T]> {
     /// Returns a `GuestPtr` pointing to the base of the array for the interior
     /// type `T`.
     pub fn as_ptr(&self) -> GuestPtr<'a, T> {
-        GuestPtr::new(self.mem, self.offset_base())
+        self.derive(self.offset_base())
     }
 
     pub fn get(&self, index: u32) -> Option<GuestPtr<'a, T>>
@@ -706,7 +770,7 @@ impl<'a> GuestPtr<'a, str> {
     /// Returns a raw pointer for the underlying slice of bytes that this
     /// pointer points to.
     pub fn as_bytes(&self) -> GuestPtr<'a, [u8]> {
-        GuestPtr::new(self.mem, self.pointer)
+        self.derive(self.pointer)
     }
 
     /// Attempts to create a [`GuestStr<'_>`] from this pointer, performing
@@ -754,7 +818,7 @@ impl<'a> GuestPtr<'a, [u8]> {
     /// Returns a pointer to the string represented by a `[u8]` without
     /// validating whether each u8 is a utf-8 codepoint.
     pub fn as_str_ptr(&self) -> GuestPtr<'a, str> {
-        GuestPtr::new(self.mem, self.pointer)
+        self.derive(self.pointer)
     }
 }
 
@@ -1006,6 +1070,8 @@ pub trait Pointee: private::Sealed {
     type Pointer: Copy;
     #[doc(hidden)]
     fn debug(pointer: Self::Pointer, f: &mut fmt::Formatter) -> fmt::Result;
+    #[doc(hidden)]
+    fn start(pointer: Self::Pointer) -> u32;
 }
 
 impl<T> Pointee for T {
@@ -1013,6 +1079,9 @@ impl<T> Pointee for T {
     fn debug(pointer: Self::Pointer, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "*guest {:#x}", pointer)
     }
+    fn start(pointer: Self::Pointer) -> u32 {
+        pointer
+    }
 }
 
 impl<T> Pointee for [T] {
@@ -1020,6 +1089,9 @@ impl<T> Pointee for [T] {
     fn debug(pointer: Self::Pointer, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "*guest {:#x}/{}", pointer.0, pointer.1)
     }
+    fn start(pointer: Self::Pointer) -> u32 {
+        pointer.0
+    }
 }
 
 impl Pointee for str {
@@ -1027,6 +1099,9 @@ impl Pointee for str {
     fn debug(pointer: Self::Pointer, f: &mut fmt::Formatter) -> fmt::Result {
         <[u8]>::debug(pointer, f)
     }
+    fn start(pointer: Self::Pointer) -> u32 {
+        pointer.0
+    }
 }
 
 pub fn run_in_dummy_executor<F: std::future::Future>(future: F) -> Result<F::Output> {
@@ -30,6 +30,13 @@ impl Region {
         }
     }
 
+    /// Checks if `rhs` `Region` lies entirely within this `Region`.
+    pub fn contains(&self, rhs: Region) -> bool {
+        let self_end = self.start as u64 + self.len as u64;
+        let rhs_end = rhs.start as u64 + rhs.len as u64;
+        rhs.start >= self.start && rhs_end <= self_end
+    }
+
     pub fn extend(&self, times: u32) -> Self {
         let len = self.len * times;
         Self {
@@ -1,6 +1,7 @@
 use crate::borrow::BorrowChecker;
 use crate::{BorrowHandle, GuestError, GuestMemory, Region};
 use std::cell::UnsafeCell;
+use wasmtime::MemRefObjects;
 
 /// Lightweight `wasmtime::Memory` wrapper so we can implement the
 /// `wiggle::GuestMemory` trait on it.
@@ -8,6 +9,7 @@ pub struct WasmtimeGuestMemory<'a> {
     mem: &'a [UnsafeCell<u8>],
     bc: BorrowChecker,
     shared: bool,
+    memref_objects: Option<MemRefObjects<'a>>,
 }
 
 // These need to be reapplied due to the usage of `UnsafeCell` internally.
@@ -38,6 +40,7 @@ impl<'a> WasmtimeGuestMemory<'a> {
             // https://github.com/bytecodealliance/wasmtime/issues/1917
             bc: BorrowChecker::new(),
             shared: false,
+            memref_objects: None,
         }
     }
 
@@ -46,8 +49,17 @@ impl<'a> WasmtimeGuestMemory<'a> {
             mem,
             bc: BorrowChecker::new(),
             shared: true,
+            memref_objects: None,
         }
     }
+
+    /// Checks pointers passed by the guest against the bounds of the objects
+    /// recorded in `metadata`, see
+    /// `wasmtime::Memory::data_store_and_memref_metadata`.
+    pub fn with_memref_metadata(mut self, metadata: impl Into<MemRefObjects<'a>>) -> Self {
+        self.memref_objects = Some(metadata.into());
+        self
+    }
 }
 
 unsafe impl GuestMemory for WasmtimeGuestMemory<'_> {
@@ -78,4 +90,11 @@ unsafe impl GuestMemory for WasmtimeGuestMemory<'_> {
     fn is_shared_memory(&self) -> bool {
         self.shared
     }
+    fn memref_bounds(&self, pointer: u32) -> Option<Region> {
+        let metadata = self.memref_objects?.object_containing(pointer)?;
+        Some(Region::new(
+            metadata.base,
+            metadata.end.saturating_sub(metadata.base),
+        ))
+    }
 }
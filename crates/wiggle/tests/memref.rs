@@ -0,0 +1,97 @@
+use wasmtime::{MemRefMetadata, MemRefMetadataTable};
+use wiggle::wasmtime::WasmtimeGuestMemory;
+use wiggle::{GuestError, GuestPtr, Region};
+
+fn metadata() -> MemRefMetadataTable {
+    let mut metadata = MemRefMetadataTable::default();
+    metadata.insert(
+        0x100,
+        MemRefMetadata {
+            base: 0x100,
+            end: 0x110,
+            attr: 0x20,
+        },
+    );
+    metadata
+}
+
+#[test]
+fn slices_are_checked_against_their_object() {
+    let mut bytes = vec![0; 4096];
+    let metadata = metadata();
+    let mem = WasmtimeGuestMemory::new(&mut bytes).with_memref_metadata(&metadata);
+
+    let buf = GuestPtr::<[u8]>::new(&mem, (0x100, 16));
+    assert_eq!(buf.memref_bounds(), Some(Region::new(0x100, 16)));
+    buf.copy_from_slice(&[1; 16]).unwrap();
+
+    // A host write of a buffer larger than the object no longer overflows it,
+    // even though it is within the memory.
+    let buf = GuestPtr::<[u8]>::new(&mem, (0x100, 17));
+    assert_eq!(
+        buf.copy_from_slice(&[1; 17]),
+        Err(GuestError::PtrOutsideObject(
+            Region::new(0x100, 17),
+            Region::new(0x100, 16)
+        ))
+    );
+    assert_eq!(buf.get_range(0..16).unwrap().to_vec().unwrap(), [1; 16]);
+}
+
+#[test]
+fn derived_pointers_keep_their_object() {
+    let mut bytes = vec![0; 4096];
+    let metadata = metadata();
+    let mem = WasmtimeGuestMemory::new(&mut bytes).with_memref_metadata(&metadata);
+
+    let word = GuestPtr::<u32>::new(&mem, 0x100);
+    word.add(3).unwrap().write(7).unwrap();
+    assert_eq!(word.as_array(4).to_vec().unwrap(), [0, 0, 0, 7]);
+    assert!(matches!(
+        word.add(4).unwrap().read(),
+        Err(GuestError::PtrOutsideObject(..))
+    ));
+    assert!(matches!(
+        word.cast::<u64>().add(2).unwrap().read(),
+        Err(GuestError::PtrOutsideObject(..))
+    ));
+
+    // Pointers without recorded bounds are only checked against the memory.
+    let word = GuestPtr::<u32>::new(&mem, 0x200);
+    assert_eq!(word.memref_bounds(), None);
+    word.add(100).unwrap().write(1).unwrap();
+    assert!(word.with_memref_bounds(None).read().is_ok());
+}
+
+#[test]
+fn interior_pointers_use_their_object() {
+    let mut bytes = vec![0; 4096];
+    let mut metadata = metadata();
+    metadata.insert(
+        0x104,
+        MemRefMetadata {
+            base: 0x104,
+            end: 0x108,
+            attr: 0x24,
+        },
+    );
+    let mem = WasmtimeGuestMemory::new(&mut bytes).with_memref_metadata(&metadata);
+
+    // Pointers into the middle of the object have no record of their own,
+    // and narrowed pointers into it don't hide it.
+    let buf = GuestPtr::<[u8]>::new(&mem, (0x10c, 4));
+    assert_eq!(buf.memref_bounds(), Some(Region::new(0x100, 16)));
+    buf.copy_from_slice(&[1; 4]).unwrap();
+    let buf = GuestPtr::<[u8]>::new(&mem, (0x10c, 5));
+    assert!(matches!(
+        buf.copy_from_slice(&[1; 5]),
+        Err(GuestError::PtrOutsideObject(..))
+    ));
+
+    // The record of the pointer itself takes precedence.
+    let word = GuestPtr::<u32>::new(&mem, 0x104);
+    assert_eq!(word.memref_bounds(), Some(Region::new(0x104, 4)));
+
+    // Pointers past the end of the object aren't part of it.
+    assert_eq!(GuestPtr::<u8>::new(&mem, 0x110).memref_bounds(), None);
+}
@@ -0,0 +1,10 @@
+//! Check that memref bounds checks catch exactly the accesses out of bounds.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use wasmtime_fuzzing::{generators::MemRefOps, oracles::check_memref_ops};
+
+fuzz_target!(|ops: MemRefOps| {
+    check_memref_ops(ops);
+});
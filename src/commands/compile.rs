@@ -6,8 +6,9 @@ use once_cell::sync::Lazy;
 use std::fs;
 use std::path::PathBuf;
 use target_lexicon::Triple;
-use wasmtime::Engine;
+use wasmtime::{Engine, Linker, Store};
 use wasmtime_cli_flags::CommonOptions;
+use wasmtime_wasi::sync::WasiCtxBuilder;
 
 static AFTER_HELP: Lazy<String> = Lazy::new(|| {
     format!(
@@ -27,7 +28,11 @@ static AFTER_HELP: Lazy<String> = Lazy::new(|| {
         \n\
         Compiling for a specific platform (Linux) and CPU preset (Skylake):\n\
         \n  \
-        wasmtime compile --target x86_64-unknown-linux --cranelift-enable skylake foo.wasm\n",
+        wasmtime compile --target x86_64-unknown-linux --cranelift-enable skylake foo.wasm\n\
+        \n\
+        Pre-initializing a module by running its `_initialize` function:\n\
+        \n  \
+        wasmtime compile --init-func _initialize foo.wasm\n",
         crate::FLAG_EXPLANATIONS.as_str()
     )
 });
@@ -51,6 +56,11 @@ pub struct CompileCommand {
     #[clap(short = 'o', long, value_name = "OUTPUT", parse(from_os_str))]
     output: Option<PathBuf>,
 
+    /// The name of an exported function to run with WASI before compiling,
+    /// whose resulting memories, globals and tables the module starts from
+    #[clap(long, value_name = "NAME")]
+    init_func: Option<String>,
+
     /// The path of the WebAssembly to compile
     #[clap(index = 1, value_name = "MODULE", parse(from_os_str))]
     module: PathBuf,
@@ -91,16 +101,40 @@ impl CompileCommand {
         #[cfg(feature = "component-model")]
         {
             if input.starts_with(b"\0asm\x0a\0\x01\0") {
+                if self.init_func.is_some() {
+                    bail!("components can't be pre-initialized");
+                }
                 fs::write(output, engine.precompile_component(&input)?)?;
                 return Ok(());
             }
         }
+
+        let input = match &self.init_func {
+            Some(init_func) => {
+                if target.parse::<Triple>()? != Triple::host() {
+                    bail!("`--init-func` is only supported when compiling for the host");
+                }
+                preinitialize(&engine, &input, init_func)
+                    .with_context(|| format!("failed to pre-initialize with `{}`", init_func))?
+            }
+            None => input,
+        };
         fs::write(output, engine.precompile_module(&input)?)?;
 
         Ok(())
     }
 }
 
+/// Runs the `init_func` export of the module `wasm` with WASI and returns the
+/// module starting from the state it leaves the instance in.
+fn preinitialize(engine: &Engine, wasm: &[u8], init_func: &str) -> Result<Vec<u8>> {
+    let mut linker = Linker::new(engine);
+    wasmtime_wasi::add_to_linker(&mut linker, |cx| cx)?;
+    linker.define_memref_hooks()?;
+    let mut store = Store::new(engine, WasiCtxBuilder::new().inherit_stdio().build());
+    linker.preinitialize(&mut store, wasm, init_func)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -139,6 +173,51 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_preinitialized_compile() -> Result<()> {
+        let (mut input, input_path) = NamedTempFile::new()?.into_parts();
+        input.write_all(
+            r#"
+                (module
+                    (memory (export "memory") 1)
+                    (global $g (mut i32) (i32.const 0))
+                    (func (export "_initialize")
+                        (i32.store (i32.const 8) (i32.const 1234))
+                        (global.set $g (i32.const 5678)))
+                    (func (export "get") (result i32)
+                        (i32.add (i32.load (i32.const 8)) (global.get $g)))
+                )
+            "#
+            .as_bytes(),
+        )?;
+        drop(input);
+
+        let output_path = NamedTempFile::new()?.into_temp_path();
+
+        let command = CompileCommand::try_parse_from(vec![
+            "compile",
+            "--disable-logging",
+            "--init-func",
+            "_initialize",
+            "-o",
+            output_path.to_str().unwrap(),
+            input_path.to_str().unwrap(),
+        ])?;
+
+        command.execute()?;
+
+        let engine = Engine::default();
+        let contents = std::fs::read(output_path)?;
+        let module = unsafe { Module::deserialize(&engine, contents)? };
+        assert!(module.get_export("_initialize").is_none());
+        let mut store = Store::new(&engine, ());
+        let instance = Instance::new(&mut store, &module, &[])?;
+        let get = instance.get_typed_func::<(), i32>(&mut store, "get")?;
+        assert_eq!(get.call(&mut store, ()).unwrap(), 1234 + 5678);
+
+        Ok(())
+    }
+
     #[cfg(target_arch = "x86_64")]
     #[test]
     fn test_x64_flags_compile() -> Result<()> {
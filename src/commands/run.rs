@@ -4,16 +4,16 @@ use anyhow::{anyhow, bail, Context as _, Result};
 use clap::Parser;
 use once_cell::sync::Lazy;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::{
     ffi::OsStr,
     path::{Component, Path, PathBuf},
     process,
 };
-use lazy_static::lazy_static;
-use std::collections::HashMap;
-use std::sync::Mutex;
-use wasmtime::{Engine, Func, Linker, Module, Store, Trap, Val, ValType};
+use wasmtime::{
+    Engine, Func, GuestProfiler, Linker, MemRefCheckPolicy, Module, Store, Trap, Val, ValType,
+    WasmCoreDump,
+};
 use wasmtime_cli_flags::{CommonOptions, WasiModules};
 use wasmtime_wasi::sync::{ambient_authority, Dir, TcpListener, WasiCtxBuilder};
 use wasmtime_wasi::I32Exit;
@@ -52,6 +52,25 @@ fn parse_map_dirs(s: &str) -> Result<(String, String)> {
     Ok((parts[0].into(), parts[1].into()))
 }
 
+fn parse_profile(s: &str) -> Result<Profile> {
+    let parts = s.split(',').collect::<Vec<_>>();
+    match &parts[..] {
+        ["guest"] => Ok(Profile::Guest {
+            path: "wasmtime-guest-profile.json".into(),
+            interval: Duration::from_millis(10),
+        }),
+        ["guest", path] => Ok(Profile::Guest {
+            path: path.into(),
+            interval: Duration::from_millis(10),
+        }),
+        ["guest", path, dur] => Ok(Profile::Guest {
+            path: path.into(),
+            interval: parse_dur(dur)?,
+        }),
+        _ => bail!("unknown profiling strategy: {}", s),
+    }
+}
+
 fn parse_dur(s: &str) -> Result<Duration> {
     // assume an integer without a unit specified is a number of seconds ...
     if let Ok(val) = s.parse() {
@@ -70,6 +89,14 @@ fn parse_preloads(s: &str) -> Result<(String, PathBuf)> {
     Ok((parts[0].into(), parts[1].into()))
 }
 
+/// How the guest is profiled with `--profile`.
+#[derive(Clone)]
+enum Profile {
+    /// Sample the WebAssembly stack every `interval`, writing a profile for
+    /// the Firefox Profiler to `path`.
+    Guest { path: PathBuf, interval: Duration },
+}
+
 static AFTER_HELP: Lazy<String> = Lazy::new(|| crate::FLAG_EXPLANATIONS.to_string());
 
 /// Runs a WebAssembly module
@@ -88,14 +115,35 @@ pub struct RunCommand {
     #[clap(long = "trap-unknown-imports")]
     trap_unknown_imports: bool,
 
-    /// store check only option
-    #[clap(long = "store-check-only")]
+    /// Only check the bounds of memref accesses by stores
+    #[clap(long = "store-check-only", conflicts_with = "upper-check-only")]
     store_check_only: bool,
 
-    /// upper bound check only option
+    /// Only check the upper bound of memref accesses
     #[clap(long = "upper-check-only")]
     upper_check_only: bool,
 
+    /// Keep memref metadata in a shadow region next to each linear memory
+    /// instead of calling the `__host` imports
+    #[clap(long = "memref-shadow-metadata")]
+    memref_shadow_metadata: bool,
+
+    /// Trap on accesses through memrefs whose allocation was deallocated, and
+    /// on deallocating it twice
+    #[clap(long = "memref-temporal-safety")]
+    memref_temporal_safety: bool,
+
+    /// Record memref bounds violations and keep running instead of trapping,
+    /// printing a report of all of them at exit; use-after-free and
+    /// double-free still trap
+    #[clap(long = "memref-audit")]
+    memref_audit: bool,
+
+    /// Count memref bounds checks and metadata traffic, printing a summary
+    /// at exit
+    #[clap(long = "memref-stats")]
+    memref_stats: bool,
+
     /// Allow executing precompiled WebAssembly modules as `*.cwasm` files.
     ///
     /// Note that this option is not safe to pass if the module being passed in
@@ -159,66 +207,57 @@ pub struct RunCommand {
     )]
     wasm_timeout: Option<Duration>,
 
+    /// Profile the guest, writing the profile when it exits
+    ///
+    /// `guest[,path[,interval]]` samples the WebAssembly stack every
+    /// `interval` (10ms by default) and writes a profile for the Firefox
+    /// Profiler to `path` (`wasmtime-guest-profile.json` by default).
+    #[clap(
+        long = "profile",
+        value_name = "STRATEGY",
+        parse(try_from_str = parse_profile),
+    )]
+    profile: Option<Profile>,
+
+    /// Write a WebAssembly coredump to the given path if the module traps
+    #[clap(long = "coredump-on-trap", value_name = "PATH")]
+    coredump_on_trap: Option<PathBuf>,
+
     // NOTE: this must come last for trailing varargs
     /// The arguments to pass to the module
     #[clap(value_name = "ARGS")]
     module_args: Vec<String>,
 }
 
-lazy_static! {
-    static ref METADATA_MAP: Mutex<HashMap<i32, i64>> = Mutex::new(HashMap::new());
-}
-
-fn __host__set_value(addr: i32, base:i32, end:i32, attr:i32, imm:i32) {
-    // imm is store imm metadata
-    let mut map = METADATA_MAP.lock().unwrap();
-    const SubObjFlag:i32 = 0x04;
-    const HasMetadataFlag:i32 = 0x20;
-    const NoCheckFlag:i32 = 0x01;
-    // check if has metadata and need check
-    if (attr & HasMetadataFlag) != 0 && (imm & NoCheckFlag) == 0 {
-        if (addr < base) || (addr > end) {
-            eprintln!("Error:out of bounds when store metadata");
-            process::exit(1);
-        }
-    }
-
-    match map.get(&addr) {
-        None => {
-            let value = ((base as i64) << 32) | (((end - base) | (attr << 24)) as i64);
-            map.insert(addr, value);
-        }
-        Some(v) => {
-            if (attr & SubObjFlag) == 0 {
-                let value = ((base as i64) << 32) | ( ((end - base) | (attr << 24)) as i64);
-                map.insert(addr, value);
-            }
-        }
-    }
-}
-
-fn __host__get_value(key: i32) -> i64 {
-    // println!("get k:{:X}", key);
-    let map = METADATA_MAP.lock().unwrap();
-    let ret = *map.get(&key).unwrap_or(&0i64);
-    // println!("get v:{:X}", ret);
-    ret
-}
-
 impl RunCommand {
     /// Executes the command.
     pub fn execute(&self) -> Result<()> {
         self.common.init_logging();
 
         let mut config = self.common.config(None)?;
-        if self.wasm_timeout.is_some() {
+        if self.wasm_timeout.is_some() || self.profile.is_some() {
             config.epoch_interruption(true);
         }
         if self.store_check_only {
-            config.store_check_only(true);
+            config.memref_check_policy(MemRefCheckPolicy::StoreOnly);
         }
         if self.upper_check_only {
-            config.upper_check_only(true);
+            config.memref_check_policy(MemRefCheckPolicy::UpperOnly);
+        }
+        if self.memref_shadow_metadata {
+            config.memref_shadow_metadata(true);
+        }
+        if self.memref_temporal_safety {
+            config.memref_temporal_safety(true);
+        }
+        if self.memref_audit {
+            config.memref_audit(true);
+        }
+        if self.memref_stats {
+            config.memref_stats(true);
+        }
+        if self.coredump_on_trap.is_some() {
+            config.coredump_on_trap(true);
         }
         let engine = Engine::new(&config)?;
         let mut store = Store::new(&engine, Host::default());
@@ -262,17 +301,36 @@ impl RunCommand {
             ))?;
         }
 
-        // test: add my host function
-        METADATA_MAP.lock().unwrap().clear();
-        linker.func_wrap("__host", "__set_value", __host__set_value)?;
-        linker.func_wrap("__host", "__get_value", __host__get_value)?;
+        linker.define_memref_hooks()?;
+
         // Load the main wasm module.
-        match self
+        let result = self
             .load_main_module(&mut store, &mut linker)
-            .with_context(|| format!("failed to run main module `{}`", self.module.display()))
-        {
+            .with_context(|| format!("failed to run main module `{}`", self.module.display()));
+
+        // Write the profile and report recorded violations before the process
+        // may exit below.
+        if let Some(Profile::Guest { path, .. }) = &self.profile {
+            if let Some(profiler) = store.take_guest_profiler() {
+                self.write_guest_profile(path, &profiler);
+            }
+        }
+        if self.memref_audit {
+            print_memref_audit_report(&mut store);
+        }
+        if self.memref_stats {
+            eprintln!("memref statistics:\n{}", store.memref_stats());
+        }
+
+        match result {
             Ok(()) => (),
             Err(e) => {
+                if let Some(path) = &self.coredump_on_trap {
+                    if let Some(coredump) = e.downcast_ref::<WasmCoreDump>() {
+                        self.write_coredump(path, coredump);
+                    }
+                }
+
                 // If a specific WASI error code was requested then that's
                 // forwarded through to the process here without printing any
                 // extra error information.
@@ -311,6 +369,30 @@ impl RunCommand {
         Ok(())
     }
 
+    fn write_guest_profile(&self, path: &Path, profiler: &GuestProfiler) {
+        let result = std::fs::File::create(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| profiler.finish(std::io::BufWriter::new(file)));
+        if let Err(e) = result {
+            eprintln!(
+                "warning: failed to write guest profile to `{}`: {}",
+                path.display(),
+                e
+            );
+        }
+    }
+
+    fn write_coredump(&self, path: &Path, coredump: &WasmCoreDump) {
+        let name = self.module.display().to_string();
+        if let Err(e) = std::fs::write(path, coredump.serialize(&name)) {
+            eprintln!(
+                "warning: failed to write coredump to `{}`: {}",
+                path.display(),
+                e
+            );
+        }
+    }
+
     fn compute_preopen_dirs(&self) -> Result<Vec<(String, Dir)>> {
         let mut preopen_dirs = Vec::new();
 
@@ -371,7 +453,28 @@ impl RunCommand {
     }
 
     fn load_main_module(&self, store: &mut Store<Host>, linker: &mut Linker<Host>) -> Result<()> {
-        if let Some(timeout) = self.wasm_timeout {
+        if let Some(Profile::Guest { interval, .. }) = &self.profile {
+            // The profiler samples whenever the epoch deadline is reached, so
+            // the epoch is incremented every interval and the deadline
+            // extended past it, unless the timeout elapsed.
+            let interval = *interval;
+            let timeout = self.wasm_timeout;
+            let start = Instant::now();
+            let name = self.module.display().to_string();
+            store.set_guest_profiler(GuestProfiler::new(&name, interval));
+            store.epoch_deadline_callback(move |_| {
+                if timeout.map_or(false, |timeout| start.elapsed() >= timeout) {
+                    return Err(Trap::Interrupt.into());
+                }
+                Ok(1)
+            });
+            store.set_epoch_deadline(1);
+            let engine = store.engine().clone();
+            thread::spawn(move || loop {
+                thread::sleep(interval);
+                engine.increment_epoch();
+            });
+        } else if let Some(timeout) = self.wasm_timeout {
             store.set_epoch_deadline(1);
             let engine = store.engine().clone();
             thread::spawn(move || {
@@ -477,6 +580,7 @@ impl RunCommand {
                 Val::ExternRef(_) => println!("<externref>"),
                 Val::FuncRef(_) => println!("<funcref>"),
                 Val::V128(i) => println!("{}", i),
+                Val::MemRef(m) => println!("{:?}", m),
             }
         }
 
@@ -502,6 +606,22 @@ struct Host {
     wasi_crypto: Option<WasiCryptoCtx>,
 }
 
+/// Prints every memref bounds violation recorded in `store` to stderr.
+fn print_memref_audit_report(store: &mut Store<Host>) {
+    let dropped = store.memref_audit_dropped();
+    let records = store.take_memref_audit_records();
+    eprintln!(
+        "memref audit: violations recorded at {} site(s)",
+        records.len()
+    );
+    if dropped > 0 {
+        eprintln!("memref audit: {dropped} violation(s) at further sites not recorded");
+    }
+    for (i, record) in records.iter().enumerate() {
+        eprintln!("\n#{i}: {record}");
+    }
+}
+
 /// Populates the given `Linker` with WASI APIs.
 fn populate_with_wasi(
     store: &mut Store<Host>,
@@ -38,6 +38,9 @@ impl WastCommand {
         wast_context
             .register_spectest(true)
             .expect("error instantiating \"spectest\"");
+        wast_context
+            .register_memref_hooks()
+            .expect("error defining memref hooks");
 
         for script in self.scripts.iter() {
             wast_context
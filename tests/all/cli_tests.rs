@@ -171,6 +171,28 @@ fn run_wasmtime_unreachable_wat() -> Result<()> {
     Ok(())
 }
 
+// Running a wat that traps writes a coredump when requested.
+#[test]
+fn run_wasmtime_unreachable_coredump() -> Result<()> {
+    let wasm = build_wasm("tests/all/cli_tests/unreachable.wat")?;
+    let td = TempDir::new()?;
+    let coredump = td.path().join("unreachable.coredump");
+    let output = run_wasmtime_for_output(
+        &[
+            wasm.path().to_str().unwrap(),
+            "--disable-cache",
+            "--coredump-on-trap",
+            coredump.to_str().unwrap(),
+        ],
+        None,
+    )?;
+    assert!(!output.status.success());
+
+    let bytes = std::fs::read(&coredump)?;
+    assert!(bytes.starts_with(b"\0asm"));
+    Ok(())
+}
+
 // Run a simple WASI hello world, snapshot0 edition.
 #[test]
 fn hello_wasi_snapshot0() -> Result<()> {
@@ -0,0 +1,147 @@
+use anyhow::Result;
+use wasmtime::*;
+
+fn coredump_engine() -> Result<Engine> {
+    let mut config = Config::new();
+    config.coredump_on_trap(true);
+    Engine::new(&config)
+}
+
+#[test]
+fn coredump_attached_to_trap() -> Result<()> {
+    let engine = coredump_engine()?;
+    let mut store = Store::new(&engine, ());
+    let wat = r#"
+        (module $hello_mod
+            (memory (export "memory") 1)
+            (global $g (mut i32) (i32.const 1))
+            (data (i32.const 16) "hello")
+            (func (export "run") (call $hello))
+            (func $hello
+                (i32.store8 (i32.const 2048) (i32.const 7))
+                (global.set $g (i32.const 42))
+                (unreachable))
+        )
+    "#;
+
+    let module = Module::new(&engine, wat)?;
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let run_func = instance.get_typed_func::<(), ()>(&mut store, "run")?;
+
+    let e = run_func.call(&mut store, ()).unwrap_err();
+    assert_eq!(
+        e.downcast_ref::<Trap>(),
+        Some(&Trap::UnreachableCodeReached)
+    );
+    assert!(e.downcast_ref::<WasmBacktrace>().is_some());
+    let coredump = e.downcast_ref::<WasmCoreDump>().unwrap();
+
+    let frames = coredump.frames();
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[0].func_name(), Some("hello"));
+    assert_eq!(frames[0].module_name(), Some("hello_mod"));
+    assert_eq!(frames[1].func_index(), 0);
+
+    let memories = coredump.memories().collect::<Vec<_>>();
+    assert_eq!(memories.len(), 1);
+    assert_eq!(&memories[0][16..21], b"hello");
+    assert_eq!(memories[0][2048], 7);
+
+    // The coredump is itself a valid module holding the memory and globals.
+    let bytes = coredump.serialize("hello");
+    Module::validate(&engine, &bytes)?;
+    let dump = Module::new(&engine, &bytes)?;
+    let instance = Instance::new(&mut store, &dump, &[])?;
+    assert_eq!(instance.exports(&mut store).count(), 0);
+    for section in ["core", "coremodules", "coreinstances", "corestack"] {
+        let name = section.as_bytes();
+        assert!(bytes.windows(name.len()).any(|w| w == name));
+    }
+
+    // The global is recorded with the value it had when trapping.
+    let mut globals = Vec::new();
+    for payload in wasmparser::Parser::new(0).parse_all(&bytes) {
+        if let wasmparser::Payload::GlobalSection(reader) = payload? {
+            for global in reader {
+                let wasmparser::Global { ty, init_expr } = global?;
+                let init = init_expr.get_binary_reader().read_operator()?;
+                globals.push((ty.content_type, ty.mutable, init));
+            }
+        }
+    }
+    assert_eq!(globals.len(), 1);
+    assert_eq!(globals[0].0, wasmparser::ValType::I32);
+    assert!(globals[0].1);
+    assert!(matches!(
+        globals[0].2,
+        wasmparser::Operator::I32Const { value: 42 }
+    ));
+    Ok(())
+}
+
+#[test]
+fn coredump_snapshots_imported_items_once() -> Result<()> {
+    let engine = coredump_engine()?;
+    let mut store = Store::new(&engine, ());
+    let memory = Memory::new(&mut store, MemoryType::new(1, None))?;
+    let global = Global::new(
+        &mut store,
+        GlobalType::new(ValType::I64, Mutability::Var),
+        Val::I64(-1),
+    )?;
+    let wat = r#"
+        (module
+            (import "" "memory" (memory 1))
+            (import "" "global" (global (mut i64)))
+            (func (export "run")
+                (i32.store (i32.const 0) (i32.const 0x01020304))
+                (unreachable))
+        )
+    "#;
+
+    let module = Module::new(&engine, wat)?;
+    let instance = Instance::new(&mut store, &module, &[memory.into(), global.into()])?;
+    let run_func = instance.get_typed_func::<(), ()>(&mut store, "run")?;
+
+    let e = run_func.call(&mut store, ()).unwrap_err();
+    let coredump = e.downcast_ref::<WasmCoreDump>().unwrap();
+    assert_eq!(coredump.frames().len(), 1);
+    let memories = coredump.memories().collect::<Vec<_>>();
+    assert_eq!(memories.len(), 1);
+    assert_eq!(&memories[0][..4], [4, 3, 2, 1]);
+    Module::validate(&engine, &coredump.serialize("imports"))?;
+    Ok(())
+}
+
+#[test]
+#[allow(deprecated)]
+fn coredump_without_backtrace() -> Result<()> {
+    let mut config = Config::new();
+    config.wasm_backtrace(false);
+    config.coredump_on_trap(true);
+    let engine = Engine::new(&config)?;
+    let mut store = Store::new(&engine, ());
+    let module = Module::new(&engine, r#"(module (func (export "run") unreachable))"#)?;
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let run_func = instance.get_typed_func::<(), ()>(&mut store, "run")?;
+
+    let e = run_func.call(&mut store, ()).unwrap_err();
+    assert!(e.downcast_ref::<WasmBacktrace>().is_none());
+    assert_eq!(e.downcast_ref::<WasmCoreDump>().unwrap().frames().len(), 1);
+    Ok(())
+}
+
+#[test]
+fn coredump_disabled_by_default() -> Result<()> {
+    let mut store = Store::<()>::default();
+    let module = Module::new(
+        store.engine(),
+        r#"(module (func (export "run") unreachable))"#,
+    )?;
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let run_func = instance.get_typed_func::<(), ()>(&mut store, "run")?;
+
+    let e = run_func.call(&mut store, ()).unwrap_err();
+    assert!(e.downcast_ref::<WasmCoreDump>().is_none());
+    Ok(())
+}
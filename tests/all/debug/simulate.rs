@@ -97,3 +97,65 @@ fn test_debug_dwarf_simulate_with_invalid_name_x86_64() -> Result<()> {
 )"#,
     )
 }
+
+#[test]
+#[ignore]
+#[cfg(all(
+    any(target_os = "linux", target_os = "macos"),
+    target_pointer_width = "64"
+))]
+fn test_debug_dwarf_simulate_memref_x86_64() -> Result<()> {
+    // The text format has no memref type, so this is the binary form of
+    //
+    //   (module
+    //     (func (param memref) (result memref)
+    //       (local memref)
+    //       local.get 0
+    //       local.set 1
+    //       local.get 1))
+    let wasm = b"\0asm\x01\0\0\0\
+        \x01\x06\x01\x60\x01\x6e\x01\x6e\
+        \x03\x02\x01\x00\
+        \x0a\x0c\x01\x0a\x01\x01\x6e\x20\x00\x21\x01\x20\x01\x0b";
+    let obj_file = NamedTempFile::new()?;
+    let obj_path = obj_file.path().to_str().unwrap();
+    compile_cranelift(wasm, None, obj_path)?;
+    let dump = get_dwarfdump(obj_path, DwarfDumpSection::DebugInfo)?;
+    let mut builder = CheckerBuilder::new();
+    builder
+        .text(
+            r#"
+check: DW_TAG_structure_type
+check:   DW_AT_name	("memref")
+check:   DW_AT_byte_size	(0x10)
+check:   DW_TAG_member
+check:     DW_AT_name	("addr")
+check:     DW_AT_data_member_location	(0x00)
+check:   DW_TAG_member
+check:     DW_AT_name	("base")
+check:     DW_AT_data_member_location	(0x04)
+check:   DW_TAG_member
+check:     DW_AT_name	("end")
+check:     DW_AT_data_member_location	(0x08)
+check:   DW_TAG_member
+check:     DW_AT_name	("attr")
+check:     DW_AT_data_member_location	(0x0c)
+check: DW_TAG_subprogram
+check:   DW_TAG_formal_parameter
+check:     DW_AT_name	("var0")
+check:     DW_AT_type
+sameln:	            "memref"
+check:   DW_TAG_variable
+check:     DW_AT_name	("var1")
+check:     DW_AT_type
+sameln:	            "memref"
+"#,
+        )
+        .map_err(|e| format_err!("unable to build checker: {:?}", e))?;
+    let checker = builder.finish();
+    let check = checker
+        .explain(&dump, NO_VARIABLES)
+        .map_err(|e| format_err!("{:?}", e))?;
+    assert!(check.0, "didn't pass check {}", check.1);
+    Ok(())
+}
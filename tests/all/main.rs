@@ -2,6 +2,7 @@ mod async_functions;
 mod call_hook;
 mod cli_tests;
 mod component_model;
+mod coredump;
 mod custom_signal_handler;
 mod debug;
 mod epoch_interruption;
@@ -21,11 +22,15 @@ mod limits;
 mod linker;
 mod memory;
 mod memory_creator;
+mod memref;
 mod module;
 mod module_serialize;
 mod name;
 mod pooling_allocator;
+mod preinit;
+mod profiling;
 mod relocs;
+mod snapshot;
 mod stack_overflow;
 mod store;
 mod table;
@@ -0,0 +1,1260 @@
+use anyhow::Result;
+use wasmtime::*;
+
+const HOOKS: &str = r#"
+    (module
+        (import "__host" "__set_value" (func $set (param i32 i32 i32 i32 i32)))
+        (import "__host" "__get_value" (func $get (param i32) (result i64)))
+        (memory (export "memory") 1)
+        (func (export "set") (param i32 i32 i32 i32 i32)
+            local.get 0
+            local.get 1
+            local.get 2
+            local.get 3
+            local.get 4
+            call $set)
+        (func (export "get") (param i32) (result i64)
+            local.get 0
+            call $get)
+    )
+"#;
+
+/// Calls the export `name` of `instance`, returning its results.
+pub(crate) fn call(
+    store: &mut Store<()>,
+    instance: &Instance,
+    name: &str,
+    args: &[Val],
+) -> Result<Vec<Val>> {
+    let func = instance.get_func(&mut *store, name).unwrap();
+    let mut results = vec![Val::I32(0); func.ty(&*store).results().len()];
+    func.call(&mut *store, args, &mut results)?;
+    Ok(results)
+}
+
+fn instantiate(linker: &Linker<()>, module: &Module) -> Result<(Store<()>, Instance)> {
+    let mut store = Store::new(module.engine(), ());
+    let instance = linker.instantiate(&mut store, module)?;
+    Ok((store, instance))
+}
+
+/// Returns the memref metadata recorded for the memory `instance` exports as
+/// `memory`.
+fn metadata<'a>(store: &'a mut Store<()>, instance: &Instance) -> &'a MemRefMetadataTable {
+    let memory = instance.get_memory(&mut *store, "memory").unwrap();
+    memory.memref_metadata(&*store)
+}
+
+/// Like `HOOKS`, but looking metadata up through `__host::__get_record`:
+///
+///   (module
+///     (import "__host" "__set_value" (func $set (param i32 i32 i32 i32 i32)))
+///     (import "__host" "__get_record" (func $get (param i32) (result memref)))
+///     (func (export "set") (param i32 i32 i32 i32 i32)
+///       (call $set (local.get 0) (local.get 1) (local.get 2) (local.get 3) (local.get 4)))
+///     (func (export "get") (param i32) (result memref)
+///       (call $get (local.get 0))))
+const RECORD_HOOKS: &[u8] = b"\
+    \x00\x61\x73\x6d\x01\x00\x00\x00\
+    \x01\x0e\x02\x60\x05\x7f\x7f\x7f\x7f\x7f\x00\x60\x01\x7f\x01\x6e\
+    \x02\x2c\x02\x06\x5f\x5f\x68\x6f\x73\x74\x0b\x5f\x5f\x73\x65\x74\x5f\x76\x61\x6c\
+    \x75\x65\x00\x00\x06\x5f\x5f\x68\x6f\x73\x74\x0c\x5f\x5f\x67\x65\x74\x5f\x72\x65\
+    \x63\x6f\x72\x64\x00\x01\
+    \x03\x03\x02\x00\x01\
+    \x07\x0d\x02\x03\x73\x65\x74\x00\x02\x03\x67\x65\x74\x00\x03\
+    \x0a\x17\x02\x0e\x00\x20\x00\x20\x01\x20\x02\x20\x03\x20\x04\x10\x00\x0b\x06\x00\
+    \x20\x00\x10\x01\x0b";
+
+fn get_record(store: &mut Store<()>, instance: &Instance, addr: u32) -> Result<MemRef> {
+    Ok(call(store, instance, "get", &[Val::I32(addr as i32)])?[0].unwrap_memref())
+}
+
+#[test]
+fn metadata_is_store_scoped() -> Result<()> {
+    let engine = Engine::default();
+    let module = Module::new(&engine, HOOKS)?;
+    let mut linker = Linker::new(&engine);
+    linker.define_memref_hooks()?;
+
+    let (mut store1, instance1) = instantiate(&linker, &module)?;
+    let (mut store2, instance2) = instantiate(&linker, &module)?;
+
+    let set = instance1.get_typed_func::<(i32, i32, i32, i32, i32), ()>(&mut store1, "set")?;
+    set.call(&mut store1, (0x100, 0x40, 0x80, 0x20, 0))?;
+
+    let expected = MemRefMetadata {
+        base: 0x40,
+        end: 0x80,
+        attr: 0x20,
+    };
+    assert_eq!(metadata(&mut store1, &instance1).get(0x100), Some(expected));
+    assert_eq!(metadata(&mut store1, &instance1).len(), 1);
+    assert!(metadata(&mut store2, &instance2).is_empty());
+
+    let get = instance1.get_typed_func::<i32, i64>(&mut store1, "get")?;
+    assert_eq!(get.call(&mut store1, 0x100)?, 0x40_2000_0040);
+    let get = instance2.get_typed_func::<i32, i64>(&mut store2, "get")?;
+    assert_eq!(get.call(&mut store2, 0x100)?, 0);
+
+    let memory = instance1.get_memory(&mut store1, "memory").unwrap();
+    memory.memref_metadata_mut(&mut store1).clear();
+    assert!(metadata(&mut store1, &instance1).is_empty());
+    Ok(())
+}
+
+#[test]
+fn metadata_is_memory_scoped() -> Result<()> {
+    let engine = Engine::default();
+    let module = Module::new(&engine, HOOKS)?;
+    let mut linker = Linker::new(&engine);
+    linker.define_memref_hooks()?;
+    let mut store = Store::new(&engine, ());
+    let instance1 = linker.instantiate(&mut store, &module)?;
+    let instance2 = linker.instantiate(&mut store, &module)?;
+
+    // Instances of the same store record metadata for their own memory.
+    let set = instance1.get_typed_func::<(i32, i32, i32, i32, i32), ()>(&mut store, "set")?;
+    set.call(&mut store, (0x100, 0x40, 0x80, 0x20, 0))?;
+    let set = instance2.get_typed_func::<(i32, i32, i32, i32, i32), ()>(&mut store, "set")?;
+    set.call(&mut store, (0x100, 0x100, 0x200, 0x20, 0))?;
+    assert_eq!(
+        metadata(&mut store, &instance1).get(0x100).unwrap().end,
+        0x80
+    );
+    assert_eq!(
+        metadata(&mut store, &instance2).get(0x100).unwrap().end,
+        0x200
+    );
+
+    // An imported memory shares the metadata of the instance defining it.
+    let importer = Module::new(
+        &engine,
+        r#"
+            (module
+                (import "__host" "__get_value" (func $get (param i32) (result i64)))
+                (import "env" "memory" (memory 1))
+                (func (export "get") (param i32) (result i64)
+                    local.get 0
+                    call $get)
+            )
+        "#,
+    )?;
+    let memory = instance1.get_memory(&mut store, "memory").unwrap();
+    linker.define(&store, "env", "memory", memory)?;
+    let instance3 = linker.instantiate(&mut store, &importer)?;
+    let get = instance3.get_typed_func::<i32, i64>(&mut store, "get")?;
+    assert_eq!(get.call(&mut store, 0x100)?, 0x40_2000_0040);
+
+    // Host-created memories start out without any metadata.
+    let memory = Memory::new(&mut store, MemoryType::new(1, None))?;
+    assert!(memory.memref_metadata(&store).is_empty());
+    Ok(())
+}
+
+#[test]
+fn sub_object_keeps_outer_bounds() -> Result<()> {
+    let engine = Engine::default();
+    let module = Module::new(&engine, HOOKS)?;
+    let mut linker = Linker::new(&engine);
+    linker.define_memref_hooks()?;
+    let (mut store, instance) = instantiate(&linker, &module)?;
+
+    let set = instance.get_typed_func::<(i32, i32, i32, i32, i32), ()>(&mut store, "set")?;
+    set.call(&mut store, (0x100, 0x100, 0x200, 0x20, 0))?;
+    set.call(&mut store, (0x100, 0x100, 0x110, 0x24, 0))?;
+    assert_eq!(
+        metadata(&mut store, &instance).get(0x100).unwrap().end,
+        0x200
+    );
+
+    // A sub-object stored to a fresh address is recorded as-is.
+    set.call(&mut store, (0x180, 0x180, 0x190, 0x24, 0))?;
+    assert_eq!(
+        metadata(&mut store, &instance).get(0x180).unwrap().end,
+        0x190
+    );
+    Ok(())
+}
+
+#[test]
+fn large_objects_keep_exact_bounds() -> Result<()> {
+    let engine = Engine::default();
+    let mut linker = Linker::new(&engine);
+    linker.define_memref_hooks()?;
+    let end = 0x0300_0010u32;
+
+    let module = Module::new(&engine, RECORD_HOOKS)?;
+    let (mut store, instance) = instantiate(&linker, &module)?;
+    let set = instance.get_typed_func::<(i32, i32, i32, i32, i32), ()>(&mut store, "set")?;
+    set.call(&mut store, (0x1000, 0x10, end as i32, 0x2a_0020, 0))?;
+    assert_eq!(
+        get_record(&mut store, &instance, 0x1000)?,
+        MemRef::new(0x1000, 0x10, end, 0x2a_0020)
+    );
+    assert_eq!(get_record(&mut store, &instance, 0x2000)?.as_u128(), 0);
+
+    // The legacy hook only keeps 24 bits of the size and 8 of the attributes.
+    let module = Module::new(&engine, HOOKS)?;
+    let (mut store, instance) = instantiate(&linker, &module)?;
+    let set = instance.get_typed_func::<(i32, i32, i32, i32, i32), ()>(&mut store, "set")?;
+    set.call(&mut store, (0x1000, 0x10, end as i32, 0x2a_0020, 0))?;
+    let get = instance.get_typed_func::<i32, i64>(&mut store, "get")?;
+    assert_eq!(get.call(&mut store, 0x1000)?, 0x10_2000_0000);
+    Ok(())
+}
+
+#[test]
+fn get_hook_signatures_are_validated() {
+    let engine = Engine::default();
+    let err = Module::new(
+        &engine,
+        r#"(module (import "__host" "__get_value" (func (param i32) (result v128))))"#,
+    )
+    .unwrap_err();
+    assert!(format!("{err:?}").contains("__host::__get_value"));
+
+    // The record is only accepted with the type the linker defines it with.
+    let err = Module::new(
+        &engine,
+        r#"(module (import "__host" "__get_record" (func (param i32) (result v128))))"#,
+    )
+    .unwrap_err();
+    assert!(format!("{err:?}").contains("__host::__get_record"));
+}
+
+#[test]
+fn unknown_hook_imports_are_rejected() {
+    let engine = Engine::default();
+    let err = Module::new(&engine, r#"(module (import "__host" "__other" (func)))"#).unwrap_err();
+    assert!(format!("{err:?}").contains("__host::__other"));
+    assert!(format!("{err:?}").contains("Invalid input WebAssembly"));
+
+    let err = Module::new(
+        &engine,
+        r#"(module (import "__host" "__set_value" (func (param i32))))"#,
+    )
+    .unwrap_err();
+    assert!(format!("{err:?}").contains("__host::__set_value"));
+    assert!(format!("{err:?}").contains("Invalid input WebAssembly"));
+
+    // Only `__`-prefixed names are reserved for hooks.
+    Module::new(&engine, r#"(module (import "__host" "other" (func)))"#).unwrap();
+}
+
+#[test]
+fn configurable_hook_names() -> Result<()> {
+    let mut config = Config::new();
+    config.memref_hook_names("env", "set_bounds", "get_bounds", "get_bounds_record");
+    let engine = Engine::new(&config)?;
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (import "env" "set_bounds" (func $set (param i32 i32 i32 i32 i32)))
+                (import "env" "get_bounds" (func (param i32) (result i64)))
+                (import "__host" "other" (func))
+                (import "env" "abort" (func))
+                (memory (export "memory") 1)
+                (func (export "set") (param i32 i32 i32 i32 i32)
+                    local.get 0
+                    local.get 1
+                    local.get 2
+                    local.get 3
+                    local.get 4
+                    call $set)
+            )
+        "#,
+    )?;
+    let mut linker = Linker::new(&engine);
+    linker.define_memref_hooks()?;
+    linker.func_wrap("__host", "other", || {})?;
+    linker.func_wrap("env", "abort", || {})?;
+    let (mut store, instance) = instantiate(&linker, &module)?;
+
+    let set = instance.get_typed_func::<(i32, i32, i32, i32, i32), ()>(&mut store, "set")?;
+    set.call(&mut store, (0x100, 0x40, 0x80, 0x20, 0))?;
+    assert_eq!(metadata(&mut store, &instance).len(), 1);
+
+    // The reserved names are the `__`-prefixed ones of the configured module.
+    let err = Module::new(&engine, r#"(module (import "env" "__other" (func)))"#).unwrap_err();
+    assert!(format!("{err:?}").contains("env::__other"));
+    Ok(())
+}
+
+#[test]
+fn out_of_bounds_store_traps() -> Result<()> {
+    let engine = Engine::default();
+    let module = Module::new(&engine, HOOKS)?;
+    let mut linker = Linker::new(&engine);
+    linker.define_memref_hooks()?;
+    let (mut store, instance) = instantiate(&linker, &module)?;
+
+    let set = instance.get_typed_func::<(i32, i32, i32, i32, i32), ()>(&mut store, "set")?;
+    let err = set
+        .call(&mut store, (0x300, 0x100, 0x200, 0x20, 0))
+        .unwrap_err();
+    assert_eq!(
+        *err.downcast_ref::<Trap>().unwrap(),
+        Trap::MemRefOutOfBounds
+    );
+    let violation = err.downcast_ref::<MemRefViolation>().unwrap();
+    assert_eq!(
+        *violation,
+        MemRefViolation {
+            addr: 0x300,
+            base: 0x100,
+            end: 0x200,
+        }
+    );
+    assert!(metadata(&mut store, &instance).is_empty());
+
+    // The no-check flag of the store immediate skips validation.
+    set.call(&mut store, (0x300, 0x100, 0x200, 0x20, 1))?;
+    assert_eq!(metadata(&mut store, &instance).len(), 1);
+    Ok(())
+}
+
+#[test]
+fn audit_records_violations() -> Result<()> {
+    let mut config = Config::new();
+    config.memref_audit(true);
+    let engine = Engine::new(&config)?;
+    let module = Module::new(&engine, HOOKS)?;
+    let mut linker = Linker::new(&engine);
+    linker.define_memref_hooks()?;
+    let (mut store, instance) = instantiate(&linker, &module)?;
+
+    let set = instance.get_typed_func::<(i32, i32, i32, i32, i32), ()>(&mut store, "set")?;
+    set.call(&mut store, (0x300, 0x100, 0x200, 0x20, 0))?;
+    set.call(&mut store, (0x80, 0x100, 0x200, 0x20, 0))?;
+
+    // Execution carried on past both violations, which happened at the same
+    // call and so share a record.
+    assert_eq!(metadata(&mut store, &instance).len(), 2);
+    let records = store.memref_audit_records();
+    assert_eq!(records.len(), 1);
+    assert_eq!(
+        records[0].violation,
+        MemRefViolation {
+            addr: 0x300,
+            base: 0x100,
+            end: 0x200,
+        }
+    );
+    assert_eq!(records[0].count, 2);
+    assert!(records[0].backtrace.frames().len() > 0);
+
+    assert_eq!(store.take_memref_audit_records().len(), 1);
+    assert!(store.memref_audit_records().is_empty());
+    Ok(())
+}
+
+#[test]
+fn audit_records_are_capped() -> Result<()> {
+    // A function storing a memref outside of its object at more sites than
+    // are recorded.
+    let sites = MEMREF_AUDIT_RECORD_LIMIT + 2;
+    let args = "i32.const 0x300 i32.const 0x100 i32.const 0x200 i32.const 0x20 i32.const 0\n";
+    let body = format!("{args}call $set\n").repeat(sites);
+    let wat = format!(
+        r#"
+            (module
+                (import "__host" "__set_value" (func $set (param i32 i32 i32 i32 i32)))
+                (memory 1)
+                (func (export "run") {body})
+            )
+        "#
+    );
+
+    let mut config = Config::new();
+    config.memref_audit(true);
+    let engine = Engine::new(&config)?;
+    let module = Module::new(&engine, &wat)?;
+    let mut linker = Linker::new(&engine);
+    linker.define_memref_hooks()?;
+    let (mut store, instance) = instantiate(&linker, &module)?;
+    let run = instance.get_typed_func::<(), ()>(&mut store, "run")?;
+
+    run.call(&mut store, ())?;
+    assert_eq!(
+        store.memref_audit_records().len(),
+        MEMREF_AUDIT_RECORD_LIMIT
+    );
+    assert_eq!(store.memref_audit_dropped(), 2);
+
+    // Violations at recorded sites are counted, the others still dropped.
+    run.call(&mut store, ())?;
+    let records = store.memref_audit_records();
+    assert_eq!(records.len(), MEMREF_AUDIT_RECORD_LIMIT);
+    assert!(records.iter().all(|record| record.count == 2));
+    assert_eq!(store.memref_audit_dropped(), 4);
+
+    assert_eq!(
+        store.take_memref_audit_records().len(),
+        MEMREF_AUDIT_RECORD_LIMIT
+    );
+    assert_eq!(store.memref_audit_dropped(), 0);
+    Ok(())
+}
+
+#[test]
+fn audit_from_compiled_code() -> Result<()> {
+    // An object extending past the end of the memory:
+    //
+    // (module
+    //   (memory 1)
+    //   (global $obj memref (memref.const 0xfff0 0x20 0x20))
+    //   (func (export "load") (param i32) (result i32)
+    //     (i32.msload (memref.add (global.get $obj) (local.get 0)))))
+    let wasm = b"\
+        \x00\x61\x73\x6d\x01\x00\x00\x00\
+        \x01\x06\x01\x60\x01\x7f\x01\x7f\
+        \x03\x02\x01\x00\
+        \x05\x03\x01\x00\x01\
+        \x06\x0a\x01\x6e\x00\xda\xf0\xff\x03\x20\x20\x0b\
+        \x07\x08\x01\x04\x6c\x6f\x61\x64\x00\x00\
+        \x0a\x0c\x01\x0a\x00\x23\x00\x20\x00\xdb\xe0\x02\x00\x0b";
+
+    let mut dynamic = Config::new();
+    dynamic.static_memory_maximum_size(0);
+    for mut config in [Config::new(), dynamic] {
+        config.memref_audit(true);
+        let engine = Engine::new(&config)?;
+        let module = Module::new(&engine, &wasm)?;
+        let (mut store, instance) = instantiate(&Linker::new(&engine), &module)?;
+
+        // A violation within the memory is recorded and the access goes ahead.
+        let results = call(&mut store, &instance, "load", &[Val::I32(-4)])?;
+        assert_eq!(results[0].unwrap_i32(), 0);
+        let records = store.take_memref_audit_records();
+        assert_eq!(records.len(), 1);
+        assert_eq!(
+            records[0].violation,
+            MemRefViolation {
+                addr: 0xffec,
+                base: 0xfff0,
+                end: 0x10010,
+            }
+        );
+
+        // The access is still checked against the memory once the violation
+        // was recorded.
+        let err = call(&mut store, &instance, "load", &[Val::I32(0x20)]).unwrap_err();
+        assert_eq!(
+            *err.downcast_ref::<Trap>().unwrap(),
+            Trap::MemoryOutOfBounds
+        );
+        assert_eq!(store.memref_audit_records().len(), 1);
+        assert_eq!(store.memref_audit_records()[0].violation.addr, 0x10010);
+    }
+    Ok(())
+}
+
+#[test]
+#[cfg(all(unix, target_pointer_width = "64"))]
+fn shadow_metadata_memories() -> Result<()> {
+    let mut config = Config::new();
+    config.memref_shadow_metadata(true);
+    let engine = Engine::new(&config)?;
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (memory (export "m") 1)
+                (func (export "grow") (result i32)
+                    i32.const 1
+                    memory.grow)
+            )
+        "#,
+    )?;
+    let mut linker = Linker::new(&engine);
+    linker.define_memref_hooks()?;
+    let (mut store, instance) = instantiate(&linker, &module)?;
+
+    let grow = instance.get_typed_func::<(), i32>(&mut store, "grow")?;
+    assert_eq!(grow.call(&mut store, ())?, 1);
+    let memory = instance.get_memory(&mut store, "m").unwrap();
+    assert_eq!(memory.size(&store), 2);
+
+    // Host-created memories get a shadow region as well.
+    let memory = Memory::new(&mut store, MemoryType::new(1, None))?;
+    assert_eq!(memory.size(&store), 1);
+    assert!(memory.memref_metadata(&store).is_empty());
+    Ok(())
+}
+
+#[test]
+#[cfg(all(unix, target_pointer_width = "64"))]
+fn shadow_metadata_from_compiled_code() -> Result<()> {
+    // Pointers to `$obj`, `$other` and `$raw` are stored to and loaded back
+    // from the slots of `$slots`:
+    //
+    // (module
+    //   (memory (export "memory") 1 1)
+    //   (global $slots memref (memref.const 0x1000 0x100 0x20))
+    //   (global $obj memref (memref.const 0x100 0x11 0x20))
+    //   (global $other memref (memref.const 0x111 0xf 0x20))
+    //   (global $raw memref (memref.const 0x20000 0 0))
+    //   (func (export "store_obj") (param i32 i32)
+    //     (memref.msstore
+    //       (memref.add (global.get $slots) (local.get 0))
+    //       (memref.add (global.get $obj) (local.get 1))))
+    //   (func (export "store_other") (param i32 i32)
+    //     (memref.msstore
+    //       (memref.add (global.get $slots) (local.get 0))
+    //       (memref.add (global.get $other) (local.get 1))))
+    //   (func (export "store_raw") (param i32)
+    //     (memref.msstore (memref.add (global.get $slots) (local.get 0)) (global.get $raw)))
+    //   (func (export "load_ptr") (param i32) (result memref)
+    //     (memref.msload (memref.add (global.get $slots) (local.get 0)))))
+    let wasm = b"\
+        \x00\x61\x73\x6d\x01\x00\x00\x00\
+        \x01\x0f\x03\x60\x02\x7f\x7f\x00\x60\x01\x7f\x00\x60\x01\x7f\x01\x6e\
+        \x03\x05\x04\x00\x00\x01\x02\
+        \x05\x04\x01\x01\x01\x01\
+        \x06\x23\x04\x6e\x00\xda\x80\x20\x80\x02\x20\x0b\x6e\x00\xda\x80\x02\x11\x20\x0b\
+        \x6e\x00\xda\x91\x02\x0f\x20\x0b\x6e\x00\xda\x80\x80\x08\x00\x00\x0b\
+        \x07\x3b\x05\x06\x6d\x65\x6d\x6f\x72\x79\x02\x00\x09\x73\x74\x6f\x72\x65\x5f\x6f\
+        \x62\x6a\x00\x00\x0b\x73\x74\x6f\x72\x65\x5f\x6f\x74\x68\x65\x72\x00\x01\x09\x73\
+        \x74\x6f\x72\x65\x5f\x72\x61\x77\x00\x02\x08\x6c\x6f\x61\x64\x5f\x70\x74\x72\x00\
+        \x03\
+        \x0a\x39\x04\x0f\x00\x23\x00\x20\x00\xdb\x23\x01\x20\x01\xdb\xf4\x02\x00\x0b\x0f\
+        \x00\x23\x00\x20\x00\xdb\x23\x02\x20\x01\xdb\xf4\x02\x00\x0b\x0c\x00\x23\x00\x20\
+        \x00\xdb\x23\x03\xf4\x02\x00\x0b\x0a\x00\x23\x00\x20\x00\xdb\xe4\x02\x00\x0b";
+
+    let mut pool = PoolingAllocationConfig::default();
+    pool.instance_count(1).instance_memory_pages(1);
+    let mut pooling = Config::new();
+    pooling.allocation_strategy(InstanceAllocationStrategy::Pooling(pool));
+    pooling.static_memory_maximum_size(65536);
+
+    for mut config in [Config::new(), pooling] {
+        config.memref_shadow_metadata(true);
+        let engine = Engine::new(&config)?;
+        let module = Module::new(&engine, &wasm)?;
+        let (mut store, instance) = instantiate(&Linker::new(&engine), &module)?;
+        let load_ptr = |store: &mut Store<()>, slot: i32| -> Result<MemRef> {
+            Ok(call(store, &instance, "load_ptr", &[Val::I32(slot)])?[0].unwrap_memref())
+        };
+
+        // Metadata is recorded for the exact address of the stored pointer,
+        // so pointers to neighbouring bytes keep their own bounds.
+        let args = [Val::I32(0), Val::I32(0x10)];
+        call(&mut store, &instance, "store_obj", &args)?;
+        let args = [Val::I32(4), Val::I32(0)];
+        call(&mut store, &instance, "store_other", &args)?;
+        assert_eq!(
+            load_ptr(&mut store, 0)?,
+            MemRef::with_bounds(0x110, 0x100, 0x111)
+        );
+        assert_eq!(
+            load_ptr(&mut store, 4)?,
+            MemRef::with_bounds(0x111, 0x111, 0x120)
+        );
+
+        // Pointers past the end of the memory have no record.
+        call(&mut store, &instance, "store_raw", &[Val::I32(8)])?;
+        assert_eq!(load_ptr(&mut store, 8)?, MemRef::unchecked(0x20000));
+
+        // Storing a pointer outside of its object is still checked.
+        let args = [Val::I32(12), Val::I32(0x20)];
+        let err = call(&mut store, &instance, "store_obj", &args).unwrap_err();
+        assert_eq!(
+            *err.downcast_ref::<Trap>().unwrap(),
+            Trap::MemRefOutOfBounds
+        );
+
+        // None of this went through the host.
+        assert!(metadata(&mut store, &instance).is_empty());
+    }
+    Ok(())
+}
+
+#[test]
+fn wasi_calls_check_guest_pointers() -> Result<()> {
+    use wasmtime_wasi::sync::WasiCtxBuilder;
+
+    // `random_get` fills the buffer the guest passes it, which must then lie
+    // within the object allocated for it:
+    //
+    // (module
+    //   (import "wasi_snapshot_preview1" "random_get"
+    //     (func $random_get (param i32 i32) (result i32)))
+    //   (import "__host" "__set_value" (func (param i32 i32 i32 i32 i32)))
+    //   (memory (export "memory") 1)
+    //   (func (export "alloc") (param i32 i32)
+    //     (drop (memref.alloc 0x20 (local.get 0) (local.get 1))))
+    //   (func (export "random_get") (param i32 i32) (result i32)
+    //     (call $random_get (local.get 0) (local.get 1))))
+    let wasm = b"\
+        \x00\x61\x73\x6d\x01\x00\x00\x00\
+        \x01\x14\x03\x60\x02\x7f\x7f\x01\x7f\x60\x05\x7f\x7f\x7f\x7f\x7f\x00\x60\x02\x7f\
+        \x7f\x00\
+        \x02\x3a\x02\x16\x77\x61\x73\x69\x5f\x73\x6e\x61\x70\x73\x68\x6f\x74\x5f\x70\x72\
+        \x65\x76\x69\x65\x77\x31\x0a\x72\x61\x6e\x64\x6f\x6d\x5f\x67\x65\x74\x00\x00\x06\
+        \x5f\x5f\x68\x6f\x73\x74\x0b\x5f\x5f\x73\x65\x74\x5f\x76\x61\x6c\x75\x65\x00\x01\
+        \x03\x03\x02\x02\x00\
+        \x05\x03\x01\x00\x01\
+        \x07\x1f\x03\x06\x6d\x65\x6d\x6f\x72\x79\x02\x00\x05\x61\x6c\x6c\x6f\x63\x00\x02\
+        \x0a\x72\x61\x6e\x64\x6f\x6d\x5f\x67\x65\x74\x00\x03\
+        \x0a\x14\x02\x09\x00\x20\x00\x20\x01\xdc\x20\x1a\x0b\x08\x00\x20\x00\x20\x01\x10\
+        \x00\x0b";
+    const FAULT: i32 = 21;
+
+    let mut shadow = vec![false];
+    if cfg!(all(unix, target_pointer_width = "64")) {
+        shadow.push(true);
+    }
+    for shadow in shadow {
+        let mut config = Config::new();
+        config.memref_shadow_metadata(shadow);
+        let engine = Engine::new(&config)?;
+        let module = Module::new(&engine, &wasm)?;
+        let mut linker = Linker::new(&engine);
+        wasmtime_wasi::add_to_linker(&mut linker, |s| s)?;
+        linker.define_memref_hooks()?;
+        let mut store = Store::new(&engine, WasiCtxBuilder::new().build());
+        let instance = linker.instantiate(&mut store, &module)?;
+        let alloc = instance.get_typed_func::<(i32, i32), ()>(&mut store, "alloc")?;
+        let random_get = instance.get_typed_func::<(i32, i32), i32>(&mut store, "random_get")?;
+        alloc.call(&mut store, (0x100, 0x10))?;
+
+        // Buffers within the object are filled, including those starting in
+        // the middle of it.
+        assert_eq!(random_get.call(&mut store, (0x100, 0x10))?, 0);
+        assert_eq!(random_get.call(&mut store, (0x108, 0x8))?, 0);
+
+        // Buffers overflowing the object fail, even though they're within
+        // the memory.
+        assert_eq!(random_get.call(&mut store, (0x100, 0x11))?, FAULT);
+        assert_eq!(random_get.call(&mut store, (0x108, 0x9))?, FAULT);
+
+        // Buffers outside of any object are only checked against the memory.
+        assert_eq!(random_get.call(&mut store, (0x200, 0x100))?, 0);
+        assert_eq!(random_get.call(&mut store, (0xfff0, 0x11))?, FAULT);
+    }
+    Ok(())
+}
+
+#[test]
+fn memref_values() {
+    let m = MemRef::with_bounds(0x104, 0x100, 0x200);
+    assert_eq!(m.addr(), 0x104);
+    assert_eq!(m.base(), 0x100);
+    assert_eq!(m.end(), 0x200);
+    assert!(m.has_metadata());
+    assert_eq!(m.as_u128(), 0x20_0000_0200_0000_0100_0000_0104);
+    assert_eq!(MemRef::from_u128(m.as_u128()), m);
+    assert_eq!(m.with_addr(0x108).base(), 0x100);
+    assert!(!MemRef::unchecked(0x104).has_metadata());
+
+    let val = Val::from(m);
+    assert_eq!(val.ty(), ValType::MemRef);
+    assert_eq!(val.unwrap_memref(), m);
+    assert!(val.v128().is_none());
+}
+
+#[test]
+fn memref_globals() -> Result<()> {
+    let mut store = Store::<()>::default();
+    let m = MemRef::with_bounds(0x10, 0x10, 0x20);
+    let ty = GlobalType::new(ValType::MemRef, Mutability::Var);
+    let global = Global::new(&mut store, ty, m.into())?;
+    assert_eq!(global.get(&mut store).unwrap_memref(), m);
+
+    global.set(&mut store, m.with_addr(0x18).into())?;
+    assert_eq!(global.get(&mut store).unwrap_memref().addr(), 0x18);
+    assert!(global.set(&mut store, Val::V128(0)).is_err());
+    Ok(())
+}
+
+#[test]
+fn memref_tables() -> Result<()> {
+    let engine = Engine::default();
+    let mut store = Store::new(&engine, ());
+    let ty = TableType::new(ValType::MemRef, 2, Some(4));
+    let table = Table::new(&mut store, ty.clone(), MemRef::null().into())?;
+    assert_eq!(
+        table.get(&mut store, 1).unwrap().unwrap_memref(),
+        MemRef::null()
+    );
+    assert!(Table::new(&mut store, ty, Val::FuncRef(None)).is_err());
+
+    let m = MemRef::with_bounds(0x10, 0x10, 0x20);
+    table.set(&mut store, 1, m.into())?;
+    assert!(table.set(&mut store, 0, Val::V128(0)).is_err());
+    assert_eq!(table.grow(&mut store, 2, m.with_addr(0x18).into())?, 2);
+    assert_eq!(
+        table.get(&mut store, 3).unwrap().unwrap_memref().addr(),
+        0x18
+    );
+    Table::copy(&mut store, &table, 0, &table, 1, 2)?;
+    assert_eq!(table.get(&mut store, 0).unwrap().unwrap_memref(), m);
+
+    // (module
+    //   (import "" "t" (table 1 memref))
+    //   (func (export "get") (param i32) (result memref)
+    //     (table.get 0 (local.get 0))))
+    let get = Module::new(
+        &engine,
+        [
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+            0x01, 0x06, 0x01, 0x60, 0x01, 0x7f, 0x01, 0x6e, // types
+            0x02, 0x08, 0x01, 0x00, 0x01, 0x74, 0x01, 0x6e, 0x00, 0x01, // imports
+            0x03, 0x02, 0x01, 0x00, // functions
+            0x07, 0x07, 0x01, 0x03, 0x67, 0x65, 0x74, 0x00, 0x00, // exports
+            0x0a, 0x08, 0x01, 0x06, 0x00, 0x20, 0x00, 0x25, 0x00, 0x0b, // code
+        ],
+    )?;
+    let instance = Instance::new(&mut store, &get, &[table.into()])?;
+    let get = instance.get_func(&mut store, "get").unwrap();
+    let mut results = [Val::I32(0)];
+    get.call(&mut store, &[Val::I32(3)], &mut results)?;
+    assert_eq!(results[0].unwrap_memref(), m.with_addr(0x18));
+    assert!(get.call(&mut store, &[Val::I32(4)], &mut results).is_err());
+
+    // (module
+    //   (table (export "t") 2 memref)
+    //   (elem (table 0) (i32.const 0) memref
+    //     (memref.const 0x100 0x10 0x20) (memref.const 0x200 0x8 0x20)))
+    let segment = Module::new(
+        &engine,
+        [
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+            0x04, 0x04, 0x01, 0x6e, 0x00, 0x02, // tables
+            0x07, 0x05, 0x01, 0x01, 0x74, 0x01, 0x00, // exports
+            0x09, 0x14, 0x01, 0x06, 0x00, 0x41, 0x00, 0x0b, 0x6e, 0x02, // elements
+            0xda, 0x80, 0x02, 0x10, 0x20, 0x0b, 0xda, 0x80, 0x04, 0x08, 0x20, 0x0b,
+        ],
+    )?;
+    let instance = Instance::new(&mut store, &segment, &[])?;
+    let table = instance.get_table(&mut store, "t").unwrap();
+    assert_eq!(table.ty(&store).element(), ValType::MemRef);
+    assert_eq!(
+        table.get(&mut store, 0).unwrap().unwrap_memref(),
+        MemRef::with_bounds(0x100, 0x100, 0x110)
+    );
+    assert_eq!(
+        table.get(&mut store, 1).unwrap().unwrap_memref(),
+        MemRef::with_bounds(0x200, 0x200, 0x208)
+    );
+    Ok(())
+}
+
+#[test]
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+fn memref_typed_funcs() -> Result<()> {
+    let mut store = Store::<()>::default();
+    let advance = Func::wrap(&mut store, |m: MemRef, n: i32| {
+        m.with_addr(m.addr() + n as u32)
+    });
+    assert_eq!(
+        advance.ty(&store).params().collect::<Vec<_>>(),
+        [ValType::MemRef, ValType::I32]
+    );
+
+    let m = MemRef::with_bounds(0x100, 0x100, 0x200);
+    let typed = advance.typed::<(MemRef, i32), MemRef>(&store)?;
+    assert_eq!(typed.call(&mut store, (m, 8))?, m.with_addr(0x108));
+    assert!(advance.typed::<(i32, i32), MemRef>(&store).is_err());
+
+    let mut results = [Val::I32(0)];
+    advance.call(&mut store, &[m.into(), Val::I32(4)], &mut results)?;
+    assert_eq!(results[0].unwrap_memref().addr(), 0x104);
+    Ok(())
+}
+
+#[test]
+fn temporal_safety_lock_tables() -> Result<()> {
+    let mut config = Config::new();
+    config.memref_temporal_safety(true);
+    let engine = Engine::new(&config)?;
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (memory (export "m") 1)
+                (func (export "grow") (result i32)
+                    i32.const 1
+                    memory.grow)
+            )
+        "#,
+    )?;
+    let mut linker = Linker::new(&engine);
+    linker.define_memref_hooks()?;
+    let (mut store, instance) = instantiate(&linker, &module)?;
+
+    let grow = instance.get_typed_func::<(), i32>(&mut store, "grow")?;
+    assert_eq!(grow.call(&mut store, ())?, 1);
+    let memory = Memory::new(&mut store, MemoryType::new(1, None))?;
+    assert_eq!(memory.size(&store), 1);
+
+    // Modules compiled without temporal safety can't be loaded.
+    let bytes = Engine::default().precompile_module(b"(module)")?;
+    assert!(unsafe { Module::deserialize(&engine, &bytes) }.is_err());
+    Ok(())
+}
+
+#[test]
+#[cfg(all(unix, target_pointer_width = "64"))]
+fn temporal_safety_from_compiled_code() -> Result<()> {
+    // (module
+    //   (memory 1)
+    //   (memory 1)
+    //   (func (export "alloc0") (param i32 i32) (result memref)
+    //     (memref.alloc 0x20 (local.get 0) (local.get 1)))
+    //   (func (export "alloc1") (param i32 i32) (result memref)
+    //     (memref.alloc 0x120 (local.get 0) (local.get 1)))
+    //   (func (export "dealloc") (param memref)
+    //     (memref.dealloc 0 (local.get 0)))
+    //   (func (export "load0") (param memref) (result i32)
+    //     (i32.msload (local.get 0)))
+    //   (func (export "load1") (param memref) (result i32)
+    //     (i32.msload 1 (local.get 0))))
+    let wasm = b"\
+        \x00\x61\x73\x6d\x01\x00\x00\x00\
+        \x01\x10\x03\x60\x02\x7f\x7f\x01\x6e\x60\x01\x6e\x00\x60\x01\x6e\x01\x7f\
+        \x03\x06\x05\x00\x00\x01\x02\x02\
+        \x05\x05\x02\x00\x01\x00\x01\
+        \x07\x2d\x05\x06\x61\x6c\x6c\x6f\x63\x30\x00\x00\x06\x61\x6c\x6c\x6f\x63\x31\x00\
+        \x01\x07\x64\x65\x61\x6c\x6c\x6f\x63\x00\x02\x05\x6c\x6f\x61\x64\x30\x00\x03\x05\
+        \x6c\x6f\x61\x64\x31\x00\x04\
+        \x0a\x2c\x05\x08\x00\x20\x00\x20\x01\xdc\x20\x0b\x09\x00\x20\x00\x20\x01\xdc\xa0\
+        \x02\x0b\x06\x00\x20\x00\xdd\x00\x0b\x07\x00\x20\x00\xe0\x02\x00\x0b\x08\x00\x20\
+        \x00\xe0\x42\x01\x00\x0b";
+
+    let mut config = Config::new();
+    config.memref_shadow_metadata(true);
+    config.memref_temporal_safety(true);
+    config.wasm_multi_memory(true);
+    let engine = Engine::new(&config)?;
+    let module = Module::new(&engine, &wasm)?;
+    let (mut store, instance) = instantiate(&Linker::new(&engine), &module)?;
+    let trap = |err: anyhow::Error| *err.downcast_ref::<Trap>().unwrap();
+
+    let args = [Val::I32(0x100), Val::I32(0x10)];
+    let m0 = call(&mut store, &instance, "alloc0", &args)?[0].unwrap_memref();
+    let m1 = call(&mut store, &instance, "alloc1", &args)?[0].unwrap_memref();
+    // Each memory hands out keys from its own lock table.
+    assert_eq!(m0.alloc_key(), 1);
+    assert_eq!(m1.alloc_key(), 1);
+    call(&mut store, &instance, "load0", &[m0.into()])?;
+    call(&mut store, &instance, "load1", &[m1.into()])?;
+
+    // Releasing the allocation in memory 0 leaves the one in memory 1 usable.
+    call(&mut store, &instance, "dealloc", &[m0.into()])?;
+    let err = call(&mut store, &instance, "load0", &[m0.into()]).unwrap_err();
+    assert_eq!(trap(err), Trap::MemRefUseAfterFree);
+    call(&mut store, &instance, "load1", &[m1.into()])?;
+    let err = call(&mut store, &instance, "dealloc", &[m0.into()]).unwrap_err();
+    assert_eq!(trap(err), Trap::MemRefDoubleFree);
+
+    call(&mut store, &instance, "dealloc", &[m1.into()])?;
+    let err = call(&mut store, &instance, "load1", &[m1.into()]).unwrap_err();
+    assert_eq!(trap(err), Trap::MemRefUseAfterFree);
+
+    // Released slots are reused with their next generation, which memrefs to
+    // the released allocation don't match.
+    let stale = m0;
+    let m0 = call(&mut store, &instance, "alloc0", &args)?[0].unwrap_memref();
+    assert_eq!(m0.alloc_key(), 0x1_0001);
+    call(&mut store, &instance, "load0", &[m0.into()])?;
+    let err = call(&mut store, &instance, "load0", &[stale.into()]).unwrap_err();
+    assert_eq!(trap(err), Trap::MemRefUseAfterFree);
+    Ok(())
+}
+
+#[test]
+#[cfg(all(unix, target_pointer_width = "64"))]
+fn temporal_safety_keys_exhausted() -> Result<()> {
+    // (module
+    //   (memory 1)
+    //   (func (export "alloc") (param i32)
+    //     (loop
+    //       (memref.dealloc 0 (memref.alloc 0x20 (i32.const 0x100) (i32.const 0x10)))
+    //       (br_if 0 (local.tee 0 (i32.sub (local.get 0) (i32.const 1))))))
+    //   (func (export "hold") (param i32)
+    //     (loop
+    //       (drop (memref.alloc 0x20 (i32.const 0x100) (i32.const 0x10)))
+    //       (br_if 0 (local.tee 0 (i32.sub (local.get 0) (i32.const 1)))))))
+    let wasm = b"\
+        \x00\x61\x73\x6d\x01\x00\x00\x00\
+        \x01\x05\x01\x60\x01\x7f\x00\
+        \x03\x03\x02\x00\x00\
+        \x05\x03\x01\x00\x01\
+        \x07\x10\x02\x05\x61\x6c\x6c\x6f\x63\x00\x00\x04\x68\x6f\x6c\x64\x00\x01\
+        \x0a\x30\x02\x17\x00\x03\x40\x41\x80\x02\x41\x10\xdc\x20\xdd\x00\x20\x00\x41\x01\
+        \x6b\x22\x00\x0d\x00\x0b\x0b\x16\x00\x03\x40\x41\x80\x02\x41\x10\xdc\x20\x1a\x20\
+        \x00\x41\x01\x6b\x22\x00\x0d\x00\x0b\x0b";
+
+    let mut config = Config::new();
+    config.memref_shadow_metadata(true);
+    config.memref_temporal_safety(true);
+    let engine = Engine::new(&config)?;
+    let module = Module::new(&engine, &wasm)?;
+    let (mut store, instance) = instantiate(&Linker::new(&engine), &module)?;
+
+    // Released slots are reused, so allocations can be made past the number
+    // of keys.
+    call(&mut store, &instance, "alloc", &[Val::I32(0x10_0000)])?;
+
+    // But only 65535 of them may be live at once.
+    call(&mut store, &instance, "hold", &[Val::I32(0xffff)])?;
+    let err = call(&mut store, &instance, "hold", &[Val::I32(1)]).unwrap_err();
+    assert_eq!(
+        *err.downcast_ref::<Trap>().unwrap(),
+        Trap::MemRefKeysExhausted
+    );
+    Ok(())
+}
+
+#[test]
+fn check_policy_is_serialized() -> Result<()> {
+    let mut config = Config::new();
+    config.memref_check_policy(MemRefCheckPolicy::UpperOnly);
+    let engine = Engine::new(&config)?;
+    let bytes = engine.precompile_module(b"(module)")?;
+    unsafe { Module::deserialize(&engine, &bytes)? };
+
+    // A module compiled with weakened checks isn't loaded by an engine
+    // performing all of them.
+    let err = unsafe { Module::deserialize(&Engine::default(), &bytes) }.unwrap_err();
+    assert!(format!("{err:?}").contains("memref check policy"));
+    Ok(())
+}
+
+#[test]
+fn function_check_policy_file() -> Result<()> {
+    let td = tempfile::TempDir::new()?;
+    let path = td.path().join("policy");
+    std::fs::write(
+        &path,
+        "# kernels\nmatmul upper-only\n\n3 none\nparse full\n",
+    )?;
+    let mut config = Config::new();
+    config.memref_check_policy_file(&path)?;
+    let engine = Engine::new(&config)?;
+    let bytes =
+        engine.precompile_module(br#"(module (func $matmul) (func $parse) (func) (func))"#)?;
+    unsafe { Module::deserialize(&engine, &bytes)? };
+    let err = unsafe { Module::deserialize(&Engine::default(), &bytes) }.unwrap_err();
+    assert!(format!("{err:?}").contains("per-function memref check policies"));
+
+    std::fs::write(&path, "matmul fast\n")?;
+    let err = Config::new().memref_check_policy_file(&path).unwrap_err();
+    assert!(format!("{err:?}").contains("unknown memref check policy `fast`"));
+    std::fs::write(&path, "matmul\n")?;
+    assert!(Config::new().memref_check_policy_file(&path).is_err());
+    Ok(())
+}
+
+#[test]
+fn function_check_policies_apply() -> Result<()> {
+    // The same access past the end of the object in two functions:
+    //
+    // (module
+    //   (memory 1)
+    //   (global $obj memref (memref.const 0x100 0x10 0x20))
+    //   (func $checked (export "checked") (result i32)
+    //     (i32.msload offset=0x10 (global.get $obj)))
+    //   (func $unchecked (export "unchecked") (result i32)
+    //     (i32.msload offset=0x10 (global.get $obj))))
+    let wasm = b"\
+        \x00\x61\x73\x6d\x01\x00\x00\x00\
+        \x01\x05\x01\x60\x00\x01\x7f\
+        \x03\x03\x02\x00\x00\
+        \x05\x03\x01\x00\x01\
+        \x06\x09\x01\x6e\x00\xda\x80\x02\x10\x20\x0b\
+        \x07\x17\x02\x07\x63\x68\x65\x63\x6b\x65\x64\x00\x00\x09\x75\x6e\x63\x68\x65\x63\
+        \x6b\x65\x64\x00\x01\
+        \x0a\x11\x02\x07\x00\x23\x00\xe0\x02\x10\x0b\x07\x00\x23\x00\xe0\x02\x10\x0b\
+        \x00\x1c\x04\x6e\x61\x6d\x65\x01\x15\x02\x00\x07\x63\x68\x65\x63\x6b\x65\x64\x01\
+        \x09\x75\x6e\x63\x68\x65\x63\x6b\x65\x64";
+    let td = tempfile::TempDir::new()?;
+    let path = td.path().join("policy");
+    std::fs::write(&path, "unchecked none\n")?;
+
+    let mut by_name = Config::new();
+    by_name.memref_function_check_policy("unchecked", MemRefCheckPolicy::None);
+    let mut by_index = Config::new();
+    by_index.memref_function_index_check_policy(1, MemRefCheckPolicy::None);
+    let mut by_file = Config::new();
+    by_file.memref_check_policy_file(&path)?;
+    let mut checked_by_name = Config::new();
+    checked_by_name
+        .memref_check_policy(MemRefCheckPolicy::None)
+        .memref_function_check_policy("checked", MemRefCheckPolicy::Full);
+    let mut checked_by_index = Config::new();
+    checked_by_index
+        .memref_check_policy(MemRefCheckPolicy::None)
+        .memref_function_index_check_policy(0, MemRefCheckPolicy::Full);
+
+    for config in [
+        by_name,
+        by_index,
+        by_file,
+        checked_by_name,
+        checked_by_index,
+    ] {
+        let engine = Engine::new(&config)?;
+        let module = Module::new(&engine, &wasm)?;
+        let (mut store, instance) = instantiate(&Linker::new(&engine), &module)?;
+        let err = call(&mut store, &instance, "checked", &[]).unwrap_err();
+        assert_eq!(
+            *err.downcast_ref::<Trap>().unwrap(),
+            Trap::MemRefOutOfBounds
+        );
+        let results = call(&mut store, &instance, "unchecked", &[])?;
+        assert_eq!(results[0].unwrap_i32(), 0);
+    }
+    Ok(())
+}
+
+#[test]
+fn memref_attributes() {
+    let m = MemRef::new(0x100, 0x100, 0x200, 0x2a << 12 | 1 << 8 | 0x20);
+    assert_eq!(m.alloc_key(), 0x2a);
+    assert_eq!(m.memory_index(), 1);
+    assert!(m.has_metadata());
+
+    let m = MemRef::with_bounds(0x100, 0x100, 0x200);
+    assert_eq!(m.alloc_key(), 0);
+    assert_eq!(m.memory_index(), 0);
+}
+
+#[test]
+#[cfg(all(unix, target_pointer_width = "64"))]
+fn shadow_metadata_multi_memory64() -> Result<()> {
+    let mut config = Config::new();
+    config.memref_shadow_metadata(true);
+    config.memref_temporal_safety(true);
+    config.wasm_multi_memory(true);
+    config.wasm_memory64(true);
+    let engine = Engine::new(&config)?;
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (memory (export "m32") 1)
+                (memory (export "m64") i64 1)
+                (func (export "grow") (result i64)
+                    i64.const 1
+                    memory.grow 1)
+            )
+        "#,
+    )?;
+    let mut linker = Linker::new(&engine);
+    linker.define_memref_hooks()?;
+    let (mut store, instance) = instantiate(&linker, &module)?;
+
+    let grow = instance.get_typed_func::<(), i64>(&mut store, "grow")?;
+    assert_eq!(grow.call(&mut store, ())?, 1);
+    let memory = instance.get_memory(&mut store, "m64").unwrap();
+    assert_eq!(memory.size(&store), 2);
+    Ok(())
+}
+
+#[test]
+fn multi_memory_accesses() -> Result<()> {
+    // An object in memory 1 extending past the end of the memory:
+    //
+    // (module
+    //   (memory (export "mem0") 1)
+    //   (memory (export "mem1") 1)
+    //   (global $obj memref (memref.const 0xfff0 0x20 0x120))
+    //   (func (export "store") (param i32 i32)
+    //     (i32.msstore 1 (memref.add (global.get $obj) (local.get 0)) (local.get 1)))
+    //   (func (export "load") (param i32) (result i32)
+    //     (i32.msload 1 (memref.add (global.get $obj) (local.get 0))))
+    //   (func (export "load_mem0") (param i32) (result i32)
+    //     (i32.msload (memref.add (global.get $obj) (local.get 0)))))
+    let wasm = b"\
+        \x00\x61\x73\x6d\x01\x00\x00\x00\
+        \x01\x0b\x02\x60\x02\x7f\x7f\x00\x60\x01\x7f\x01\x7f\
+        \x03\x04\x03\x00\x01\x01\
+        \x05\x05\x02\x00\x01\x00\x01\
+        \x06\x0b\x01\x6e\x00\xda\xf0\xff\x03\x20\xa0\x02\x0b\
+        \x07\x2a\x05\x04\x6d\x65\x6d\x30\x02\x00\x04\x6d\x65\x6d\x31\x02\x01\x05\x73\x74\
+        \x6f\x72\x65\x00\x00\x04\x6c\x6f\x61\x64\x00\x01\x09\x6c\x6f\x61\x64\x5f\x6d\x65\
+        \x6d\x30\x00\x02\
+        \x0a\x26\x03\x0d\x00\x23\x00\x20\x00\xdb\x20\x01\xf0\x42\x01\x00\x0b\x0b\x00\x23\
+        \x00\x20\x00\xdb\xe0\x42\x01\x00\x0b\x0a\x00\x23\x00\x20\x00\xdb\xe0\x02\x00\x0b";
+
+    let mut dynamic = Config::new();
+    dynamic.static_memory_maximum_size(0);
+    for mut config in [Config::new(), dynamic] {
+        config.wasm_multi_memory(true);
+        let engine = Engine::new(&config)?;
+        let module = Module::new(&engine, &wasm)?;
+        let (mut store, instance) = instantiate(&Linker::new(&engine), &module)?;
+        let trap = |err: anyhow::Error| *err.downcast_ref::<Trap>().unwrap();
+
+        call(&mut store, &instance, "store", &[Val::I32(4), Val::I32(42)])?;
+        let results = call(&mut store, &instance, "load", &[Val::I32(4)])?;
+        assert_eq!(results[0].unwrap_i32(), 42);
+        let mem0 = instance.get_memory(&mut store, "mem0").unwrap();
+        let mem1 = instance.get_memory(&mut store, "mem1").unwrap();
+        assert_eq!(mem1.data(&store)[0xfff4], 42);
+        assert_eq!(mem0.data(&store)[0xfff4], 0);
+
+        // The memref points into memory 1 only.
+        let err = call(&mut store, &instance, "load_mem0", &[Val::I32(4)]).unwrap_err();
+        assert_eq!(trap(err), Trap::MemRefOutOfBounds);
+
+        // Accesses within the object are still checked against the memory.
+        let args = [Val::I32(0x10), Val::I32(1)];
+        let err = call(&mut store, &instance, "store", &args).unwrap_err();
+        assert_eq!(trap(err), Trap::MemoryOutOfBounds);
+        let err = call(&mut store, &instance, "load", &[Val::I32(0x1c)]).unwrap_err();
+        assert_eq!(trap(err), Trap::MemoryOutOfBounds);
+    }
+    Ok(())
+}
+
+#[test]
+fn covered_checks_are_elided() -> Result<()> {
+    // (module
+    //   (memory 1)
+    //   (func (export "twice") (param memref) (result i32)
+    //     (i32.add (i32.msload (local.get 0)) (i32.msload (local.get 0))))
+    //   (func (export "offset") (param memref) (result i32)
+    //     (i32.msload offset=8 (local.get 0))))
+    let wasm = b"\
+        \x00\x61\x73\x6d\x01\x00\x00\x00\
+        \x01\x06\x01\x60\x01\x6e\x01\x7f\
+        \x03\x03\x02\x00\x00\
+        \x05\x03\x01\x00\x01\
+        \x07\x12\x02\x05\x74\x77\x69\x63\x65\x00\x00\x06\x6f\x66\x66\x73\x65\x74\x00\x01\
+        \x0a\x17\x02\x0d\x00\x20\x00\xe0\x02\x00\x20\x00\xe0\x02\x00\x6a\x0b\x07\x00\x20\
+        \x00\xe0\x02\x08\x0b";
+    let trap = |err: anyhow::Error| *err.downcast_ref::<Trap>().unwrap();
+
+    // The second load of `twice` is covered by the check of the first one.
+    let engine = Engine::default();
+    let module = Module::new(&engine, &wasm)?;
+    assert_eq!(
+        module.memref_check_stats(),
+        MemRefCheckStats {
+            emitted: 2,
+            elided: 1,
+            hoisted: 0
+        }
+    );
+    let (mut store, instance) = instantiate(&Linker::new(&engine), &module)?;
+    let memref = |addr, end| Val::MemRef(MemRef::new(addr, 0x100, end, 0x20));
+    call(&mut store, &instance, "twice", &[memref(0x100, 0x104)])?;
+    let err = call(&mut store, &instance, "twice", &[memref(0x100, 0x102)]).unwrap_err();
+    assert_eq!(trap(err), Trap::MemRefOutOfBounds);
+
+    // The accessed range doesn't wrap around the 32-bit address space.
+    let err = call(
+        &mut store,
+        &instance,
+        "offset",
+        &[memref(0xffff_fffc, u32::MAX)],
+    )
+    .unwrap_err();
+    assert_eq!(trap(err), Trap::MemRefOutOfBounds);
+
+    // Audited checks let violating accesses through, so nothing is elided.
+    let mut config = Config::new();
+    config.memref_audit(true);
+    let module = Module::new(&Engine::new(&config)?, &wasm)?;
+    assert_eq!(
+        module.memref_check_stats(),
+        MemRefCheckStats {
+            emitted: 3,
+            elided: 0,
+            hoisted: 0
+        }
+    );
+    Ok(())
+}
+
+#[test]
+fn stats_start_at_zero() -> Result<()> {
+    let mut config = Config::new();
+    config.memref_stats(true);
+    let engine = Engine::new(&config)?;
+    let module = Module::new(&engine, HOOKS)?;
+    let mut linker = Linker::new(&engine);
+    linker.define_memref_hooks()?;
+    let (mut store, instance) = instantiate(&linker, &module)?;
+
+    // Calling the hooks directly isn't a memref operation of compiled code.
+    let set = instance.get_typed_func::<(i32, i32, i32, i32, i32), ()>(&mut store, "set")?;
+    set.call(&mut store, (0x100, 0x40, 0x80, 0x20, 0))?;
+    assert_eq!(instance.memref_stats(&store), MemRefStats::default());
+    assert_eq!(store.memref_stats(), MemRefStats::default());
+
+    // Counting changes the compiled code.
+    let bytes = engine.precompile_module(b"(module)")?;
+    let err = unsafe { Module::deserialize(&Engine::default(), &bytes) }.unwrap_err();
+    assert!(format!("{err:?}").contains("memref statistics"));
+    Ok(())
+}
+
+#[test]
+fn stats_count_accesses_and_metadata() -> Result<()> {
+    // (module
+    //   (import "__host" "__set_value" (func (param i32 i32 i32 i32 i32)))
+    //   (import "__host" "__get_record" (func (param i32) (result memref)))
+    //   (memory 1)
+    //   (global $obj memref (memref.const 0x100 0x10 0x20))
+    //   (global $raw memref (memref.const 0x200 0 0))
+    //   (func (export "store_ptr")
+    //     (memref.msstore (global.get $obj) (global.get $obj)))
+    //   (func (export "load_ptr") (result memref)
+    //     (memref.msload (global.get $obj)))
+    //   (func (export "load") (result i32)
+    //     (i32.msload (global.get $obj)))
+    //   (func (export "load_raw") (result i32)
+    //     (i32.msload (global.get $raw))))
+    let wasm = b"\
+        \x00\x61\x73\x6d\x01\x00\x00\x00\
+        \x01\x19\x05\x60\x05\x7f\x7f\x7f\x7f\x7f\x00\x60\x01\x7f\x01\x6e\x60\x00\x00\x60\
+        \x00\x01\x6e\x60\x00\x01\x7f\
+        \x02\x2c\x02\x06\x5f\x5f\x68\x6f\x73\x74\x0b\x5f\x5f\x73\x65\x74\x5f\x76\x61\x6c\
+        \x75\x65\x00\x00\x06\x5f\x5f\x68\x6f\x73\x74\x0c\x5f\x5f\x67\x65\x74\x5f\x72\x65\
+        \x63\x6f\x72\x64\x00\x01\
+        \x03\x05\x04\x02\x03\x04\x04\
+        \x05\x03\x01\x00\x01\
+        \x06\x11\x02\x6e\x00\xda\x80\x02\x10\x20\x0b\x6e\x00\xda\x80\x04\x00\x00\x0b\
+        \x07\x2a\x04\x09\x73\x74\x6f\x72\x65\x5f\x70\x74\x72\x00\x02\x08\x6c\x6f\x61\x64\
+        \x5f\x70\x74\x72\x00\x03\x04\x6c\x6f\x61\x64\x00\x04\x08\x6c\x6f\x61\x64\x5f\x72\
+        \x61\x77\x00\x05\
+        \x0a\x23\x04\x09\x00\x23\x00\x23\x00\xf4\x02\x00\x0b\x07\x00\x23\x00\xe4\x02\x00\
+        \x0b\x07\x00\x23\x00\xe0\x02\x00\x0b\x07\x00\x23\x01\xe0\x02\x00\x0b";
+
+    let run = |config: &Config| -> Result<(MemRefStats, MemRefStats)> {
+        let engine = Engine::new(config)?;
+        let module = Module::new(&engine, &wasm)?;
+        let mut linker = Linker::new(&engine);
+        linker.define_memref_hooks()?;
+        let (mut store, instance) = instantiate(&linker, &module)?;
+        call(&mut store, &instance, "store_ptr", &[])?;
+        call(&mut store, &instance, "load_ptr", &[])?;
+        call(&mut store, &instance, "load", &[])?;
+        call(&mut store, &instance, "load", &[])?;
+        call(&mut store, &instance, "load_raw", &[])?;
+        Ok((instance.memref_stats(&store), store.memref_stats()))
+    };
+
+    // Each access through a memref with metadata is checked, and each stored
+    // or loaded memref goes through the hooks once. Memrefs without metadata
+    // aren't checked.
+    let mut config = Config::new();
+    config.memref_stats(true);
+    let expected = MemRefStats {
+        bounds_checks: 4,
+        metadata_stores: 1,
+        metadata_loads: 1,
+        narrows: 0,
+    };
+    assert_eq!(run(&config)?, (expected, expected));
+
+    // Without statistics nothing is counted.
+    let defaults = (MemRefStats::default(), MemRefStats::default());
+    assert_eq!(run(&Config::new())?, defaults);
+    Ok(())
+}
@@ -675,6 +675,21 @@ configured maximum of 16 bytes; breakdown of allocation requirement:
         Err(e) => assert_eq!(e.to_string(), expected),
     }
 
+    config.memref_stats(true);
+    let engine = Engine::new(&config)?;
+    let expected = "\
+instance allocation for this module requires 256 bytes which exceeds the \
+configured maximum of 16 bytes; breakdown of allocation requirement:
+
+ * 56.25% - 144 bytes - instance state management
+ * 12.50% - 32 bytes - memref counters
+ * 6.25% - 16 bytes - jit store state
+";
+    match Module::new(&engine, "(module)") {
+        Ok(_) => panic!("should have failed to compile"),
+        Err(e) => assert_eq!(e.to_string(), expected),
+    }
+
     Ok(())
 }
 
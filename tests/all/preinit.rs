@@ -0,0 +1,150 @@
+use anyhow::Result;
+use wasmtime::*;
+
+const MODULE: &str = r#"
+    (module
+        (memory (export "memory") 1)
+        (global $started (mut i32) (i32.const 0))
+        (global $g (export "g") (mut i64) (i64.const 0))
+        (global $f (mut funcref) (ref.null func))
+        (table (export "table") 1 funcref)
+        (elem (i32.const 0) $one)
+        (elem $passive func $one)
+        (elem declare func $two)
+        (data (i32.const 100) "hello")
+        (data $bytes "world")
+        (start $start)
+        (func $start
+            (global.set $started (i32.add (global.get $started) (i32.const 1))))
+        (func $one (result i32) i32.const 1)
+        (func $two (result i32) i32.const 2)
+        (func (export "init")
+            (i32.store8 (i32.const 100) (i32.const 0x48))
+            (drop (memory.grow (i32.const 1)))
+            (i32.store8 (i32.const 70000) (i32.const 7))
+            (global.set $g (i64.const -42))
+            (global.set $f (ref.func $two))
+            (drop (table.grow (ref.func $two) (i32.const 1))))
+        (func (export "started") (result i32) global.get $started)
+        (func (export "call") (param i32) (result i32)
+            (call_indirect (result i32) (local.get 0)))
+        (func (export "call_global") (result i32)
+            (table.set (i32.const 0) (global.get $f))
+            (call_indirect (result i32) (i32.const 0)))
+        (func (export "copy_passive")
+            (memory.init $bytes (i32.const 200) (i32.const 0) (i32.const 5))
+            (table.init $passive (i32.const 1) (i32.const 0) (i32.const 1)))
+    )
+"#;
+
+#[test]
+fn preinit_bakes_instance_state() -> Result<()> {
+    let engine = Engine::default();
+    let linker = Linker::new(&engine);
+    let mut store = Store::new(&engine, ());
+    let wasm = linker.preinitialize(&mut store, MODULE.as_bytes(), "init")?;
+    Module::validate(&engine, &wasm)?;
+
+    let module = Module::new(&engine, &wasm)?;
+    assert!(module.get_export("init").is_none());
+    let instance = linker.instantiate(&mut store, &module)?;
+
+    // The start function ran during pre-initialization only.
+    let started = instance.get_typed_func::<(), i32>(&mut store, "started")?;
+    assert_eq!(started.call(&mut store, ())?, 1);
+
+    let memory = instance.get_memory(&mut store, "memory").unwrap();
+    assert_eq!(memory.size(&store), 2);
+    assert_eq!(&memory.data(&store)[100..105], b"Hello");
+    assert_eq!(memory.data(&store)[70000], 7);
+
+    let g = instance.get_global(&mut store, "g").unwrap();
+    assert_eq!(g.get(&mut store).unwrap_i64(), -42);
+
+    let table = instance.get_table(&mut store, "table").unwrap();
+    assert_eq!(table.size(&store), 2);
+    let call = instance.get_typed_func::<i32, i32>(&mut store, "call")?;
+    assert_eq!(call.call(&mut store, 0)?, 1);
+    assert_eq!(call.call(&mut store, 1)?, 2);
+    let call_global = instance.get_typed_func::<(), i32>(&mut store, "call_global")?;
+    assert_eq!(call_global.call(&mut store, ())?, 2);
+
+    // Passive segments keep their index and contents.
+    let copy_passive = instance.get_typed_func::<(), ()>(&mut store, "copy_passive")?;
+    copy_passive.call(&mut store, ())?;
+    assert_eq!(&memory.data(&store)[200..205], b"world");
+    assert_eq!(call.call(&mut store, 1)?, 1);
+    Ok(())
+}
+
+#[test]
+fn preinit_keeps_imported_memory_segments() -> Result<()> {
+    let engine = Engine::default();
+    let mut store = Store::new(&engine, ());
+    let mut linker = Linker::new(&engine);
+    let memory = Memory::new(&mut store, MemoryType::new(1, None))?;
+    linker.define("", "memory", memory)?;
+    let wasm = linker.preinitialize(
+        &mut store,
+        br#"
+            (module
+                (import "" "memory" (memory 1))
+                (data (i32.const 0) "abc")
+                (func (export "init")
+                    (i32.store8 (i32.const 0) (i32.const 0x41)))
+            )
+        "#,
+        "init",
+    )?;
+    assert_eq!(&memory.data(&store)[..3], b"Abc");
+
+    // Imported memories aren't part of the snapshot, so they're initialized
+    // by the original segments again.
+    let module = Module::new(&engine, &wasm)?;
+    let memory = Memory::new(&mut store, MemoryType::new(1, None))?;
+    Instance::new(&mut store, &module, &[memory.into()])?;
+    assert_eq!(&memory.data(&store)[..3], b"abc");
+    Ok(())
+}
+
+#[test]
+fn preinit_rejects_externrefs() -> Result<()> {
+    let engine = Engine::default();
+    let mut store = Store::new(&engine, ());
+    let mut linker = Linker::new(&engine);
+    linker.func_wrap("", "make", || Some(ExternRef::new(1234u32)))?;
+    let err = linker
+        .preinitialize(
+            &mut store,
+            br#"
+                (module
+                    (import "" "make" (func $make (result externref)))
+                    (global $r (mut externref) (ref.null extern))
+                    (func (export "init")
+                        (global.set $r (call $make)))
+                )
+            "#,
+            "init",
+        )
+        .unwrap_err();
+    assert!(err.to_string().contains("externref"), "{:?}", err);
+    Ok(())
+}
+
+#[test]
+fn preinit_requires_init_func() -> Result<()> {
+    let engine = Engine::default();
+    let linker = Linker::new(&engine);
+    let mut store = Store::new(&engine, ());
+    assert!(linker
+        .preinitialize(&mut store, br#"(module (func (export "f")))"#, "init")
+        .is_err());
+    assert!(linker
+        .preinitialize(
+            &mut store,
+            br#"(module (func (export "init") (param i32)))"#,
+            "init",
+        )
+        .is_err());
+    Ok(())
+}
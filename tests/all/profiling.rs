@@ -0,0 +1,125 @@
+use anyhow::Result;
+use serde_json::Value;
+use wasmtime::*;
+
+fn profile_json(profiler: &GuestProfiler) -> Result<Value> {
+    let mut output = Vec::new();
+    profiler.finish(&mut output)?;
+    Ok(serde_json::from_slice(&output)?)
+}
+
+/// Returns the name of the innermost frame of each sample in `profile`.
+fn sampled_frames(profile: &Value) -> Vec<&str> {
+    let thread = &profile["threads"][0];
+    let strings = thread["stringTable"].as_array().unwrap();
+    let frames = thread["frameTable"]["data"].as_array().unwrap();
+    let stacks = thread["stackTable"]["data"].as_array().unwrap();
+    thread["samples"]["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|sample| {
+            let stack = &stacks[sample[0].as_u64().unwrap() as usize];
+            let frame = &frames[stack[1].as_u64().unwrap() as usize];
+            strings[frame[0].as_u64().unwrap() as usize]
+                .as_str()
+                .unwrap()
+        })
+        .collect()
+}
+
+#[test]
+fn guest_profiler_samples_from_host() -> Result<()> {
+    let mut store = Store::<Option<GuestProfiler>>::default();
+    let wat = r#"
+        (module $m
+            (import "" "sample" (func $sample))
+            (func (export "run") (call $inner))
+            (func $inner (call $sample))
+        )
+    "#;
+    let module = Module::new(store.engine(), wat)?;
+    let sample = Func::wrap(
+        &mut store,
+        |mut caller: Caller<'_, Option<GuestProfiler>>| {
+            let mut profiler = caller.data_mut().take().unwrap();
+            profiler.sample(&caller);
+            *caller.data_mut() = Some(profiler);
+        },
+    );
+    let instance = Instance::new(&mut store, &module, &[sample.into()])?;
+    let run = instance.get_typed_func::<(), ()>(&mut store, "run")?;
+
+    *store.data_mut() = Some(GuestProfiler::new("m", std::time::Duration::from_millis(1)));
+    run.call(&mut store, ())?;
+    run.call(&mut store, ())?;
+
+    let profile = profile_json(store.data().as_ref().unwrap())?;
+    assert_eq!(profile["meta"]["product"], "m");
+    assert_eq!(sampled_frames(&profile), ["m!inner", "m!inner"]);
+    // Both samples share the same stack.
+    let stacks = profile["threads"][0]["stackTable"]["data"]
+        .as_array()
+        .unwrap();
+    assert_eq!(stacks.len(), 2);
+    Ok(())
+}
+
+#[test]
+fn guest_profiler_samples_on_epoch_deadline() -> Result<()> {
+    let mut config = Config::new();
+    config.epoch_interruption(true);
+    let engine = Engine::new(&config)?;
+    let mut store = Store::new(&engine, ());
+    let wat = r#"
+        (module $m
+            (import "" "tick" (func $tick))
+            (func (export "run") (call $work) (call $work) (call $work))
+            (func $work (call $tick) (loop $l))
+        )
+    "#;
+    let module = Module::new(&engine, wat)?;
+    let tick = Func::wrap(&mut store, |caller: Caller<'_, ()>| {
+        caller.engine().increment_epoch();
+    });
+    let instance = Instance::new(&mut store, &module, &[tick.into()])?;
+    let run = instance.get_typed_func::<(), ()>(&mut store, "run")?;
+
+    store.set_guest_profiler(GuestProfiler::new("m", std::time::Duration::from_millis(1)));
+    store.epoch_deadline_callback(|_| Ok(1));
+    store.set_epoch_deadline(1);
+    run.call(&mut store, ())?;
+
+    let profiler = store.take_guest_profiler().unwrap();
+    assert!(store.take_guest_profiler().is_none());
+    let profile = profile_json(&profiler)?;
+    assert_eq!(sampled_frames(&profile), ["m!work", "m!work", "m!work"]);
+    Ok(())
+}
+
+#[test]
+#[cfg(unix)]
+fn perfmap_lists_compiled_functions() -> Result<()> {
+    let mut config = Config::new();
+    config.profiler(ProfilingStrategy::PerfMap);
+    let engine = Engine::new(&config)?;
+    Module::new(
+        &engine,
+        r#"(module (func $perfmap_test_function (export "f") (result i32) i32.const 1))"#,
+    )?;
+
+    let map = std::fs::read_to_string(format!("/tmp/perf-{}.map", std::process::id()))?;
+    let line = map
+        .lines()
+        .find(|line| line.ends_with(" perfmap_test_function"))
+        .expect("perf map should list the compiled function");
+    let mut fields = line.split(' ');
+    let addr = u64::from_str_radix(fields.next().unwrap(), 16)?;
+    let size = u64::from_str_radix(fields.next().unwrap(), 16)?;
+    assert!(addr != 0);
+    assert!(size != 0);
+    assert!(map
+        .lines()
+        .any(|line| line.ends_with(" wasm::trampoline[0]")));
+    Ok(())
+}
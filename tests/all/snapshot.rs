@@ -0,0 +1,294 @@
+use anyhow::Result;
+use wasmtime::*;
+
+const MODULE: &str = r#"
+    (module
+        (memory (export "memory") 1 4)
+        (global $g (export "g") (mut i32) (i32.const 0))
+        (global $r (export "r") (mut externref) (ref.null extern))
+        (table (export "table") 2 funcref)
+        (elem (i32.const 0) $one)
+        (data (i32.const 100) "hello")
+        (func $one (result i32) i32.const 1)
+        (func $two (result i32) i32.const 2)
+        (func (export "warm_up")
+            (i32.store8 (i32.const 200) (i32.const 42))
+            (global.set $g (i32.const 1)))
+        (func (export "scribble")
+            (i32.store8 (i32.const 100) (i32.const 0))
+            (i32.store8 (i32.const 200) (i32.const 0))
+            (drop (memory.grow (i32.const 1)))
+            (i32.store8 (i32.const 70000) (i32.const 7))
+            (global.set $g (i32.const 2))
+            (table.set (i32.const 1) (ref.func $two))
+            (drop (table.grow (ref.null func) (i32.const 3))))
+        (func (export "call") (param i32) (result i32)
+            (call_indirect (result i32) (local.get 0)))
+    )
+"#;
+
+fn check_restored(store: &mut Store<()>, instance: &Instance) -> Result<()> {
+    let memory = instance.get_memory(&mut *store, "memory").unwrap();
+    assert_eq!(memory.size(&*store), 1);
+    assert_eq!(&memory.data(&*store)[100..105], b"hello");
+    assert_eq!(memory.data(&*store)[200], 42);
+
+    let g = instance.get_global(&mut *store, "g").unwrap();
+    assert_eq!(g.get(&mut *store).unwrap_i32(), 1);
+
+    let table = instance.get_table(&mut *store, "table").unwrap();
+    assert_eq!(table.size(&*store), 2);
+    let call = instance.get_typed_func::<i32, i32>(&mut *store, "call")?;
+    assert_eq!(call.call(&mut *store, 0)?, 1);
+    assert!(call.call(&mut *store, 1).is_err());
+    Ok(())
+}
+
+fn snapshot_and_restore(engine: &Engine) -> Result<()> {
+    let mut store = Store::new(engine, ());
+    let module = Module::new(engine, MODULE)?;
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let warm_up = instance.get_typed_func::<(), ()>(&mut store, "warm_up")?;
+    let scribble = instance.get_typed_func::<(), ()>(&mut store, "scribble")?;
+    warm_up.call(&mut store, ())?;
+
+    let snapshot = instance.snapshot(&mut store)?;
+    for _ in 0..3 {
+        scribble.call(&mut store, ())?;
+        let call = instance.get_typed_func::<i32, i32>(&mut store, "call")?;
+        assert_eq!(call.call(&mut store, 1)?, 2);
+        let memory = instance.get_memory(&mut store, "memory").unwrap();
+        assert_eq!(memory.size(&store), 2);
+
+        instance.restore(&mut store, &snapshot)?;
+        check_restored(&mut store, &instance)?;
+        // Memory grown since the snapshot is zero once it's grown again.
+        let memory = instance.get_memory(&mut store, "memory").unwrap();
+        memory.grow(&mut store, 1)?;
+        assert_eq!(memory.data(&store)[70000], 0);
+        instance.restore(&mut store, &snapshot)?;
+    }
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(target_os = "linux"), ignore)]
+fn restore_dynamic_memory() -> Result<()> {
+    let mut config = Config::new();
+    config.static_memory_maximum_size(0);
+    snapshot_and_restore(&Engine::new(&config)?)
+}
+
+#[test]
+#[cfg_attr(not(target_os = "linux"), ignore)]
+fn restore_static_memory() -> Result<()> {
+    snapshot_and_restore(&Engine::default())
+}
+
+#[test]
+#[cfg_attr(not(target_os = "linux"), ignore)]
+fn restore_pooled_memory() -> Result<()> {
+    let mut config = Config::new();
+    config.allocation_strategy(InstanceAllocationStrategy::Pooling(
+        PoolingAllocationConfig::default(),
+    ));
+    snapshot_and_restore(&Engine::new(&config)?)
+}
+
+#[test]
+fn restore_without_memory_growth() -> Result<()> {
+    let mut store = Store::<()>::default();
+    let module = Module::new(store.engine(), MODULE)?;
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let warm_up = instance.get_typed_func::<(), ()>(&mut store, "warm_up")?;
+    warm_up.call(&mut store, ())?;
+
+    let snapshot = instance.snapshot(&mut store)?;
+    let memory = instance.get_memory(&mut store, "memory").unwrap();
+    memory.data_mut(&mut store)[100..105].copy_from_slice(b"world");
+    memory.data_mut(&mut store)[200] = 0;
+    let g = instance.get_global(&mut store, "g").unwrap();
+    g.set(&mut store, Val::I32(5))?;
+    let table = instance.get_table(&mut store, "table").unwrap();
+    table.set(&mut store, 0, Val::FuncRef(None))?;
+
+    instance.restore(&mut store, &snapshot)?;
+    check_restored(&mut store, &instance)
+}
+
+#[test]
+fn restore_externref_global() -> Result<()> {
+    let mut store = Store::<()>::default();
+    let module = Module::new(store.engine(), MODULE)?;
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let r = instance.get_global(&mut store, "r").unwrap();
+    let value = ExternRef::new(1234u32);
+    r.set(&mut store, Val::ExternRef(Some(value.clone())))?;
+
+    let snapshot = instance.snapshot(&mut store)?;
+    r.set(&mut store, Val::ExternRef(None))?;
+    assert_eq!(value.strong_count(), 2);
+
+    instance.restore(&mut store, &snapshot)?;
+    let restored = r.get(&mut store).unwrap_externref().unwrap();
+    assert!(restored.ptr_eq(&value));
+    drop(restored);
+    drop(snapshot);
+    assert_eq!(value.strong_count(), 2);
+    Ok(())
+}
+
+#[test]
+fn restore_rejects_other_instance() -> Result<()> {
+    let mut store = Store::<()>::default();
+    let module = Module::new(store.engine(), MODULE)?;
+    let instance1 = Instance::new(&mut store, &module, &[])?;
+    let instance2 = Instance::new(&mut store, &module, &[])?;
+
+    let snapshot = instance1.snapshot(&mut store)?;
+    let err = instance2.restore(&mut store, &snapshot).unwrap_err();
+    assert!(err.to_string().contains("different instance"));
+    instance1.restore(&mut store, &snapshot)?;
+    Ok(())
+}
+
+#[test]
+#[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+fn restore_memref_regions() -> Result<()> {
+    let mut config = Config::new();
+    config.memref_shadow_metadata(true);
+    config.memref_temporal_safety(true);
+    let engine = Engine::new(&config)?;
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (memory (export "m") 1)
+                (func (export "grow") (result i32)
+                    i32.const 1
+                    memory.grow)
+            )
+        "#,
+    )?;
+    let mut linker = Linker::new(&engine);
+    linker.define_memref_hooks()?;
+    let mut store = Store::new(&engine, ());
+    let instance = linker.instantiate(&mut store, &module)?;
+
+    let snapshot = instance.snapshot(&mut store)?;
+    let grow = instance.get_typed_func::<(), i32>(&mut store, "grow")?;
+    for _ in 0..2 {
+        assert_eq!(grow.call(&mut store, ())?, 1);
+        instance.restore(&mut store, &snapshot)?;
+        let memory = instance.get_memory(&mut store, "m").unwrap();
+        assert_eq!(memory.size(&store), 1);
+    }
+    Ok(())
+}
+
+/// A module recording memref metadata for its memory through the `__host`
+/// hooks.
+const MEMREF_HOOKS: &str = r#"
+    (module
+        (import "__host" "__set_value" (func $set (param i32 i32 i32 i32 i32)))
+        (memory (export "m") 1)
+        (func (export "set") (param i32 i32 i32 i32 i32)
+            local.get 0
+            local.get 1
+            local.get 2
+            local.get 3
+            local.get 4
+            call $set)
+    )
+"#;
+
+#[test]
+fn restore_memref_metadata_table() -> Result<()> {
+    let engine = Engine::default();
+    let module = Module::new(&engine, MEMREF_HOOKS)?;
+    let mut linker = Linker::new(&engine);
+    linker.define_memref_hooks()?;
+    let mut store = Store::new(&engine, ());
+    let instance = linker.instantiate(&mut store, &module)?;
+    let set = instance.get_typed_func::<(i32, i32, i32, i32, i32), ()>(&mut store, "set")?;
+    let memory = instance.get_memory(&mut store, "m").unwrap();
+    let host_memory = Memory::new(&mut store, MemoryType::new(1, None))?;
+
+    let object = MemRefMetadata {
+        base: 0x100,
+        end: 0x110,
+        attr: 0x20,
+    };
+    set.call(&mut store, (0x100, 0x100, 0x110, 0x20, 0))?;
+    let snapshot = instance.snapshot(&mut store)?;
+
+    for _ in 0..2 {
+        set.call(&mut store, (0x100, 0x100, 0x180, 0x20, 0))?;
+        set.call(&mut store, (0x200, 0x200, 0x210, 0x20, 0))?;
+        // The metadata of memories the instance doesn't define isn't part of
+        // the snapshot.
+        let other = MemRefMetadata {
+            base: 0x300,
+            end: 0x310,
+            attr: 0x20,
+        };
+        host_memory
+            .memref_metadata_mut(&mut store)
+            .insert(0x300, other);
+
+        instance.restore(&mut store, &snapshot)?;
+        let table = memory.memref_metadata(&store);
+        assert_eq!(table.get(0x100), Some(object));
+        assert_eq!(table.get(0x200), None);
+        assert_eq!(table.len(), 1);
+        let table = host_memory.memref_metadata(&store);
+        assert_eq!(table.get(0x300), Some(other));
+        host_memory.memref_metadata_mut(&mut store).clear();
+    }
+    Ok(())
+}
+
+#[test]
+fn restore_memref_metadata_of_one_instance() -> Result<()> {
+    let engine = Engine::default();
+    let module = Module::new(&engine, MEMREF_HOOKS)?;
+    let mut linker = Linker::new(&engine);
+    linker.define_memref_hooks()?;
+    let mut store = Store::new(&engine, ());
+    let instance1 = linker.instantiate(&mut store, &module)?;
+    let instance2 = linker.instantiate(&mut store, &module)?;
+    let set1 = instance1.get_typed_func::<(i32, i32, i32, i32, i32), ()>(&mut store, "set")?;
+    let set2 = instance2.get_typed_func::<(i32, i32, i32, i32, i32), ()>(&mut store, "set")?;
+    let memory1 = instance1.get_memory(&mut store, "m").unwrap();
+    let memory2 = instance2.get_memory(&mut store, "m").unwrap();
+
+    set1.call(&mut store, (0x100, 0x100, 0x110, 0x20, 0))?;
+    let snapshot = instance1.snapshot(&mut store)?;
+
+    // Both instances record metadata for the same addresses of their own
+    // memory, both before and after the first one is restored.
+    set1.call(&mut store, (0x100, 0x100, 0x180, 0x20, 0))?;
+    set2.call(&mut store, (0x100, 0x100, 0x140, 0x20, 0))?;
+    set2.call(&mut store, (0x200, 0x200, 0x210, 0x20, 0))?;
+    instance1.restore(&mut store, &snapshot)?;
+    set2.call(&mut store, (0x300, 0x300, 0x310, 0x20, 0))?;
+
+    let table = memory1.memref_metadata(&store);
+    assert_eq!(table.get(0x100).unwrap().end, 0x110);
+    assert_eq!(table.len(), 1);
+    let table = memory2.memref_metadata(&store);
+    assert_eq!(table.get(0x100).unwrap().end, 0x140);
+    assert_eq!(table.get(0x200).unwrap().end, 0x210);
+    assert_eq!(table.get(0x300).unwrap().end, 0x310);
+    assert_eq!(table.len(), 3);
+
+    // Restoring the second instance is independent of the first one.
+    let snapshot = instance2.snapshot(&mut store)?;
+    set1.call(&mut store, (0x200, 0x200, 0x220, 0x20, 0))?;
+    set2.call(&mut store, (0x400, 0x400, 0x410, 0x20, 0))?;
+    instance2.restore(&mut store, &snapshot)?;
+    assert_eq!(memory1.memref_metadata(&store).len(), 2);
+    assert_eq!(memory2.memref_metadata(&store).len(), 3);
+    assert_eq!(memory2.memref_metadata(&store).get(0x400), None);
+    Ok(())
+}
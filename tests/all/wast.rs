@@ -50,6 +50,9 @@ fn run_wast(wast: &str, strategy: Strategy, pooling: bool) -> anyhow::Result<()>
     if feature_found(wast, "canonicalize-nan") {
         cfg.cranelift_nan_canonicalization(true);
     }
+    if feature_found(wast, "shadow") && cfg!(all(unix, target_pointer_width = "64")) {
+        cfg.memref_shadow_metadata(true);
+    }
     let test_allocates_lots_of_memory = wast.ends_with("more-than-4gb.wast");
 
     // By default we'll allocate huge chunks (6gb) of the address space for each
@@ -112,6 +115,9 @@ fn run_wast(wast: &str, strategy: Strategy, pooling: bool) -> anyhow::Result<()>
     let mut wast_context = WastContext::new(store);
 
     wast_context.register_spectest(use_shared_memory)?;
+    if feature_found(wast, "memref") {
+        wast_context.register_memref_hooks()?;
+    }
     wast_context.run_buffer(wast.to_str().unwrap(), &wast_bytes)?;
 
     Ok(())
@@ -0,0 +1,59 @@
+;; Objects created with `memref.alloc`, and their metadata recorded through
+;; the `__host` hooks when they're stored to linear memory.
+;;
+;; The text format doesn't have memref instructions, so the module is given in
+;; binary form. It is equivalent to:
+;;
+;;   (module
+;;     (import "__host" "__set_value" (func (param i32 i32 i32 i32 i32)))
+;;     (import "__host" "__get_record" (func (param i32) (result memref)))
+;;     (memory (export "mem") 1)
+;;     (global memref (memref.const 0x1000 0x100 0x20))
+;;     (func (export "alloc") (param i32 i32) (result memref)
+;;       (memref.alloc 0x20 (local.get 0) (local.get 1)))
+;;     (func (export "alloc_raw") (param i32 i32) (result memref)
+;;       (memref.alloc 0 (local.get 0) (local.get 1)))
+;;     (func (export "alloc_load") (param i32 i32 i32) (result i32)
+;;       (i32.msload
+;;         (memref.add (memref.alloc 0x20 (local.get 0) (local.get 1)) (local.get 2))))
+;;     (func (export "store_ptr") (param i32 i32 i32)
+;;       (memref.msstore
+;;         (memref.add (global.get 0) (local.get 0))
+;;         (memref.alloc 0x20 (local.get 1) (local.get 2))))
+;;     (func (export "load_ptr") (param i32) (result memref)
+;;       (memref.msload (memref.add (global.get 0) (local.get 0)))))
+;;
+;; Memref values are written as `v128.const i32x4 addr base end attr`.
+(module binary
+  "\00\61\73\6d\01\00\00\00"
+  "\01\21\05\60\05\7f\7f\7f\7f\7f\00\60\01\7f\01\6e\60\02\7f\7f\01\6e\60\03\7f\7f\7f\01\7f\60\03\7f\7f\7f\00"
+  "\02\2c\02\06\5f\5f\68\6f\73\74\0b\5f\5f\73\65\74\5f\76\61\6c\75\65\00\00\06\5f\5f\68\6f\73\74\0c\5f\5f\67\65\74\5f\72\65\63\6f\72\64\00\01"
+  "\03\06\05\02\02\03\04\01"
+  "\05\03\01\00\01"
+  "\06\0a\01\6e\00\da\80\20\80\02\20\0b"
+  "\07\3f\06\03\6d\65\6d\02\00\05\61\6c\6c\6f\63\00\02\09\61\6c\6c\6f\63\5f\72\61\77\00\03\0a\61\6c\6c\6f\63\5f\6c\6f\61\64\00\04\09\73\74\6f\72\65\5f\70\74\72\00\05\08\6c\6f\61\64\5f\70\74\72\00\06"
+  "\0a\3e\05\08\00\20\00\20\01\dc\20\0b\08\00\20\00\20\01\dc\00\0b\0e\00\20\00\20\01\dc\20\20\02\db\e0\02\00\0b\10\00\23\00\20\00\db\20\01\20\02\dc\20\f4\02\00\0b\0a\00\23\00\20\00\db\e4\02\00\0b"
+)
+
+;; the object spans `[addr, addr + size)`
+(assert_return (invoke "alloc" (i32.const 0x100) (i32.const 0x10))
+  (v128.const i32x4 0x100 0x100 0x110 0x20))
+(assert_return (invoke "alloc_raw" (i32.const 0x100) (i32.const 0x10))
+  (v128.const i32x4 0x100 0x100 0 0))
+
+;; accesses through the allocated memref are checked against the object
+(assert_return (invoke "alloc_load" (i32.const 0x100) (i32.const 0x10) (i32.const 12)) (i32.const 0))
+(assert_trap (invoke "alloc_load" (i32.const 0x100) (i32.const 0x10) (i32.const 13)) "out of bounds memref access")
+
+;; objects must fit in the memory they're allocated in
+(assert_return (invoke "alloc" (i32.const 0xff00) (i32.const 0x100))
+  (v128.const i32x4 0xff00 0xff00 0x10000 0x20))
+(assert_trap (invoke "alloc" (i32.const 0xff00) (i32.const 0x101)) "memref allocation out of bounds of linear memory")
+(assert_trap (invoke "alloc" (i32.const -0x100) (i32.const 0x200)) "memref allocation out of bounds of linear memory")
+(assert_trap (invoke "alloc" (i32.const 0xfff0) (i32.const 0x20)) "memref allocation out of bounds of linear memory")
+(assert_trap (invoke "alloc" (i32.const -1) (i32.const 2)) "memref allocation out of bounds of linear memory")
+
+;; stored memrefs are loaded back with the bounds of their object
+(assert_return (invoke "store_ptr" (i32.const 0) (i32.const 0x200) (i32.const 0x10)))
+(assert_return (invoke "load_ptr" (i32.const 0)) (v128.const i32x4 0x200 0x200 0x210 0x20))
+(assert_return (invoke "load_ptr" (i32.const 4)) (v128.const i32x4 0 0 0 0))
@@ -0,0 +1,45 @@
+;; The check flags of the memory immediate of memref accesses.
+;;
+;; The text format doesn't have memref instructions, so the module is given in
+;; binary form. The flags are encoded in bits 3-5 of the alignment field of
+;; the memory immediate: 0x1 skips the check, 0x2 only checks the lower bound
+;; and 0x4 only checks the upper bound. It is equivalent to:
+;;
+;;   (module
+;;     (memory (export "mem") 1)
+;;     (global memref (memref.const 0x100 0x10 0x20))
+;;     (func (export "load") (param i32) (result i32)
+;;       (i32.msload (memref.add (global.get 0) (local.get 0))))
+;;     (func (export "load_unchecked") (param i32) (result i32)
+;;       (i32.msload flags=0x1 (memref.add (global.get 0) (local.get 0))))
+;;     (func (export "load_lower") (param i32) (result i32)
+;;       (i32.msload flags=0x2 (memref.add (global.get 0) (local.get 0))))
+;;     (func (export "load_upper") (param i32) (result i32)
+;;       (i32.msload flags=0x4 (memref.add (global.get 0) (local.get 0))))
+;;     (func (export "store_unchecked") (param i32 i32)
+;;       (i32.msstore flags=0x1 (memref.add (global.get 0) (local.get 0)) (local.get 1))))
+(module binary
+  "\00\61\73\6d\01\00\00\00"
+  "\01\0b\02\60\01\7f\01\7f\60\02\7f\7f\00"
+  "\03\06\05\00\00\00\00\01"
+  "\05\03\01\00\01"
+  "\06\09\01\6e\00\da\80\02\10\20\0b"
+  "\07\4b\06\03\6d\65\6d\02\00\04\6c\6f\61\64\00\00\0e\6c\6f\61\64\5f\75\6e\63\68\65\63\6b\65\64\00\01\0a\6c\6f\61\64\5f\6c\6f\77\65\72\00\02\0a\6c\6f\61\64\5f\75\70\70\65\72\00\03\0f\73\74\6f\72\65\5f\75\6e\63\68\65\63\6b\65\64\00\04"
+  "\0a\3a\05\0a\00\23\00\20\00\db\e0\02\00\0b\0a\00\23\00\20\00\db\e0\0a\00\0b\0a\00\23\00\20\00\db\e0\12\00\0b\0a\00\23\00\20\00\db\e0\22\00\0b\0c\00\23\00\20\00\db\20\01\f0\0a\00\0b"
+)
+
+;; both bounds are checked by default
+(assert_trap (invoke "load" (i32.const -4)) "out of bounds memref access")
+(assert_trap (invoke "load" (i32.const 0x10)) "out of bounds memref access")
+
+;; unchecked accesses are only kept within the linear memory
+(assert_return (invoke "store_unchecked" (i32.const 0x10) (i32.const 5)))
+(assert_return (invoke "load_unchecked" (i32.const 0x10)) (i32.const 5))
+(assert_return (invoke "load_unchecked" (i32.const -4)) (i32.const 0))
+(assert_trap (invoke "load_unchecked" (i32.const 0x10000)) "out of bounds memory access")
+
+;; only the flagged bound is checked
+(assert_trap (invoke "load_lower" (i32.const -4)) "out of bounds memref access")
+(assert_return (invoke "load_lower" (i32.const 0x10)) (i32.const 5))
+(assert_trap (invoke "load_upper" (i32.const 0x10)) "out of bounds memref access")
+(assert_return (invoke "load_upper" (i32.const -4)) (i32.const 0))
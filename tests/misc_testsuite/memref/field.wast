@@ -0,0 +1,33 @@
+;; Reading the lanes of memrefs with `memref.field`.
+;;
+;; The text format doesn't have memref instructions, so the module is given in
+;; binary form. It is equivalent to:
+;;
+;;   (module
+;;     (global memref (memref.const 0x100 0x10 0x20))
+;;     (func (export "addr") (param memref) (result i32) (memref.field 0 (local.get 0)))
+;;     (func (export "base") (param memref) (result i32) (memref.field 1 (local.get 0)))
+;;     (func (export "end") (param memref) (result i32) (memref.field 2 (local.get 0)))
+;;     (func (export "attr") (param memref) (result i32) (memref.field 3 (local.get 0)))
+;;     (func (export "obj_end") (result i32) (memref.field 2 (global.get 0)))
+;;     (func (export "advanced_addr") (param memref i32) (result i32)
+;;       (memref.field 0 (memref.add (local.get 0) (local.get 1)))))
+;;
+;; Memref values are written as `v128.const i32x4 addr base end attr`.
+(module binary
+  "\00\61\73\6d\01\00\00\00"
+  "\01\10\03\60\01\6e\01\7f\60\00\01\7f\60\02\6e\7f\01\7f"
+  "\03\07\06\00\00\00\00\01\02"
+  "\06\09\01\6e\00\da\80\02\10\20\0b"
+  "\07\36\06\04\61\64\64\72\00\00\04\62\61\73\65\00\01\03\65\6e\64\00\02\04\61\74\74\72\00\03\07\6f\62\6a\5f\65\6e\64\00\04\0d\61\64\76\61\6e\63\65\64\5f\61\64\64\72\00\05"
+  "\0a\2e\06\06\00\20\00\df\00\0b\06\00\20\00\df\01\0b\06\00\20\00\df\02\0b\06\00\20\00\df\03\0b\06\00\23\00\df\02\0b\09\00\20\00\20\01\db\df\00\0b"
+)
+
+(assert_return (invoke "addr" (v128.const i32x4 0x104 0x100 0x110 0x20)) (i32.const 0x104))
+(assert_return (invoke "base" (v128.const i32x4 0x104 0x100 0x110 0x20)) (i32.const 0x100))
+(assert_return (invoke "end" (v128.const i32x4 0x104 0x100 0x110 0x20)) (i32.const 0x110))
+(assert_return (invoke "attr" (v128.const i32x4 0x104 0x100 0x110 0x20)) (i32.const 0x20))
+(assert_return (invoke "obj_end") (i32.const 0x110))
+
+;; `memref.add` only moves the address
+(assert_return (invoke "advanced_addr" (v128.const i32x4 0x104 0x100 0x110 0x20) (i32.const 8)) (i32.const 0x10c))
@@ -0,0 +1,55 @@
+;; Memref checks within loops which are proven once before the loop. The
+;; checks of `sum` cover a memref offset by the loop's induction variable,
+;; and still trap at the first access out of bounds. `sum_guarded` only
+;; accesses the memref on some iterations, so it doesn't trap even though
+;; the check before the loop fails.
+;;
+;; The text format doesn't have memref instructions, so the module is given in
+;; binary form. It is equivalent to:
+;;
+;;   (module
+;;     (memory 1)
+;;     (data (i32.const 0x100) "\01\00\00\00\02\00\00\00\03\00\00\00\04\00\00\00\05\00\00\00")
+;;     (func (export "sum") (param memref i32) (result i32) (local i32 i32)
+;;       (loop
+;;         (local.set 3 (i32.add
+;;           (i32.msload (memref.add (local.get 0) (local.get 2)))
+;;           (local.get 3)))
+;;         (br_if 0 (i32.lt_u
+;;           (local.tee 2 (i32.add (local.get 2) (i32.const 4)))
+;;           (local.get 1))))
+;;       (local.get 3))
+;;     (func (export "sum_guarded") (param memref i32) (result i32) (local i32 i32)
+;;       (loop
+;;         (if (i32.lt_u (local.get 2) (i32.const 16))
+;;           (then
+;;             (local.set 3 (i32.add
+;;               (i32.msload (memref.add (local.get 0) (local.get 2)))
+;;               (local.get 3)))))
+;;         (br_if 0 (i32.lt_u
+;;           (local.tee 2 (i32.add (local.get 2) (i32.const 4)))
+;;           (local.get 1))))
+;;       (local.get 3)))
+;;
+;; Memref values are written as `v128.const i32x4 addr base end attr`.
+(module binary
+  "\00\61\73\6d\01\00\00\00"
+  "\01\07\01\60\02\6e\7f\01\7f"
+  "\03\03\02\00\00"
+  "\05\03\01\00\01"
+  "\07\15\02\03\73\75\6d\00\00\0b\73\75\6d\5f\67\75\61\72\64\65\64\00\01"
+  "\0a\4f\02\22\01\02\7f\03\40\20\00\20\02\db\e0\02\00\20\03\6a\21\03\20\02\41\04\6a\22\02\20\01\49\0d\00\0b\20\03\0b\2a\01\02\7f\03\40\20\02\41\10\49\04\40\20\00\20\02\db\e0\02\00\20\03\6a\21\03\0b\20\02\41\04\6a\22\02\20\01\49\0d\00\0b\20\03\0b"
+  "\0b\1b\01\00\41\80\02\0b\14\01\00\00\00\02\00\00\00\03\00\00\00\04\00\00\00\05\00\00\00"
+)
+
+(assert_return (invoke "sum" (v128.const i32x4 0x100 0x100 0x110 0x20) (i32.const 16)) (i32.const 10))
+(assert_return (invoke "sum" (v128.const i32x4 0x100 0x100 0x110 0x20) (i32.const 0)) (i32.const 1))
+(assert_return (invoke "sum" (v128.const i32x4 0x104 0x100 0x110 0x20) (i32.const 12)) (i32.const 9))
+(assert_trap (invoke "sum" (v128.const i32x4 0x100 0x100 0x110 0x20) (i32.const 20)) "out of bounds memref access")
+(assert_trap (invoke "sum" (v128.const i32x4 0x104 0x100 0x110 0x20) (i32.const 16)) "out of bounds memref access")
+(assert_trap (invoke "sum" (v128.const i32x4 0xfc 0x100 0x110 0x20) (i32.const 4)) "out of bounds memref access")
+
+;; memrefs without metadata aren't checked
+(assert_return (invoke "sum" (v128.const i32x4 0x100 0 0 0) (i32.const 20)) (i32.const 15))
+
+(assert_return (invoke "sum_guarded" (v128.const i32x4 0x100 0x100 0x110 0x20) (i32.const 32)) (i32.const 10))
@@ -0,0 +1,56 @@
+;; Accesses through memrefs with `i32.msload` and `i32.msstore`.
+;;
+;; The text format doesn't have memref instructions, so the module is given in
+;; binary form. It is equivalent to:
+;;
+;;   (module
+;;     (memory (export "mem") 1)
+;;     (global (export "obj") memref (memref.const 0x100 0x10 0x20))
+;;     (global (export "raw") memref (memref.const 0x200 0x10 0))
+;;     (func (export "load") (param i32) (result i32)
+;;       (i32.msload (memref.add (global.get 0) (local.get 0))))
+;;     (func (export "store") (param i32 i32)
+;;       (i32.msstore (memref.add (global.get 0) (local.get 0)) (local.get 1)))
+;;     (func (export "store_raw") (param i32 i32)
+;;       (i32.msstore (memref.add (global.get 1) (local.get 0)) (local.get 1)))
+;;     (func (export "load_from") (param memref) (result i32)
+;;       (i32.msload (local.get 0)))
+;;     (func (export "advance") (param memref i32) (result memref)
+;;       (memref.add (local.get 0) (local.get 1))))
+;;
+;; Memref values are written as `v128.const i32x4 addr base end attr`.
+(module binary
+  "\00\61\73\6d\01\00\00\00"
+  "\01\16\04\60\01\7f\01\7f\60\02\7f\7f\00\60\01\6e\01\7f\60\02\6e\7f\01\6e"
+  "\03\06\05\00\01\01\02\03"
+  "\05\03\01\00\01"
+  "\06\11\02\6e\00\da\80\02\10\20\0b\6e\00\da\80\04\10\00\0b"
+  "\07\44\08\03\6d\65\6d\02\00\03\6f\62\6a\03\00\03\72\61\77\03\01\04\6c\6f\61\64\00\00\05\73\74\6f\72\65\00\01\09\73\74\6f\72\65\5f\72\61\77\00\02\09\6c\6f\61\64\5f\66\72\6f\6d\00\03\07\61\64\76\61\6e\63\65\00\04"
+  "\0a\36\05\0a\00\23\00\20\00\db\e0\02\00\0b\0c\00\23\00\20\00\db\20\01\f0\02\00\0b\0c\00\23\01\20\00\db\20\01\f0\02\00\0b\07\00\20\00\e0\02\00\0b\07\00\20\00\20\01\db\0b"
+)
+
+(assert_return (get "obj") (v128.const i32x4 0x100 0x100 0x110 0x20))
+(assert_return (get "raw") (v128.const i32x4 0x200 0x200 0x210 0))
+
+;; accesses within the object
+(assert_return (invoke "store" (i32.const 4) (i32.const 42)))
+(assert_return (invoke "load" (i32.const 4)) (i32.const 42))
+(assert_return (invoke "load" (i32.const 0)) (i32.const 0))
+(assert_return (invoke "store" (i32.const 12) (i32.const 7)))
+(assert_return (invoke "load" (i32.const 12)) (i32.const 7))
+
+;; accesses past either bound of the object
+(assert_trap (invoke "store" (i32.const 16) (i32.const 1)) "out of bounds memref access")
+(assert_trap (invoke "load" (i32.const 14)) "out of bounds memref access")
+(assert_trap (invoke "load" (i32.const -4)) "out of bounds memref access")
+
+;; memrefs without metadata aren't checked
+(assert_return (invoke "store_raw" (i32.const 0x40) (i32.const 3)))
+
+;; memref arguments and results
+(assert_return (invoke "load_from" (v128.const i32x4 0x104 0x100 0x110 0x20)) (i32.const 42))
+(assert_return (invoke "load_from" (v128.const i32x4 0x240 0x200 0x210 0)) (i32.const 3))
+(assert_trap (invoke "load_from" (v128.const i32x4 0x10c 0x100 0x10c 0x20)) "out of bounds memref access")
+(assert_return
+  (invoke "advance" (v128.const i32x4 0x100 0x100 0x110 0x20) (i32.const 8))
+  (v128.const i32x4 0x108 0x100 0x110 0x20))
@@ -0,0 +1,36 @@
+;; The `__host` hooks keep the metadata of the memrefs stored to each linear
+;; memory apart, even when they're stored at the same address.
+;;
+;; The text format doesn't have memref instructions, so the module is given in
+;; binary form. It is equivalent to:
+;;
+;;   (module
+;;     (import "__host" "__set_value" (func (param i32 i32 i32 i32 i32)))
+;;     (import "__host" "__get_record" (func (param i32) (result memref)))
+;;     (memory (export "mem0") 1)
+;;     (memory (export "mem1") 1)
+;;     (func (export "store_ptr0") (param memref memref)
+;;       (memref.msstore 0 (local.get 0) (local.get 1)))
+;;     (func (export "store_ptr1") (param memref memref)
+;;       (memref.msstore 1 (local.get 0) (local.get 1)))
+;;     (func (export "load_ptr0") (param memref) (result memref)
+;;       (memref.msload 0 (local.get 0)))
+;;     (func (export "load_ptr1") (param memref) (result memref)
+;;       (memref.msload 1 (local.get 0))))
+;;
+;; Memref values are written as `v128.const i32x4 addr base end attr`.
+(module binary
+  "\00\61\73\6d\01\00\00\00"
+  "\01\18\04\60\05\7f\7f\7f\7f\7f\00\60\01\7f\01\6e\60\02\6e\6e\00\60\01\6e\01\6e"
+  "\02\2c\02\06\5f\5f\68\6f\73\74\0b\5f\5f\73\65\74\5f\76\61\6c\75\65\00\00\06\5f\5f\68\6f\73\74\0c\5f\5f\67\65\74\5f\72\65\63\6f\72\64\00\01"
+  "\03\05\04\02\02\03\03"
+  "\05\05\02\00\01\00\01"
+  "\07\41\06\04\6d\65\6d\30\02\00\04\6d\65\6d\31\02\01\0a\73\74\6f\72\65\5f\70\74\72\30\00\02\0a\73\74\6f\72\65\5f\70\74\72\31\00\03\09\6c\6f\61\64\5f\70\74\72\30\00\04\09\6c\6f\61\64\5f\70\74\72\31\00\05"
+  "\0a\27\04\09\00\20\00\20\01\f4\02\00\0b\0a\00\20\00\20\01\f4\42\01\00\0b\07\00\20\00\e4\02\00\0b\08\00\20\00\e4\42\01\00\0b"
+)
+
+;; the same pointer stored to both memories with different bounds
+(assert_return (invoke "store_ptr0" (v128.const i32x4 0x10 0 0 0) (v128.const i32x4 0x100 0x100 0x110 0x20)))
+(assert_return (invoke "store_ptr1" (v128.const i32x4 0x10 0 0 0) (v128.const i32x4 0x100 0x100 0x180 0x120)))
+(assert_return (invoke "load_ptr0" (v128.const i32x4 0x10 0 0 0)) (v128.const i32x4 0x100 0x100 0x110 0x20))
+(assert_return (invoke "load_ptr1" (v128.const i32x4 0x10 0 0 0)) (v128.const i32x4 0x100 0x100 0x180 0x120))
@@ -0,0 +1,22 @@
+;; Memrefs only carry the index of the first 16 memories of a module, so
+;; accesses through memrefs to the others are rejected.
+;;
+;; The text format doesn't have memref instructions, so the module is given in
+;; binary form. It is equivalent to:
+;;
+;;   (module
+;;     (memory 1) ;; 17 times
+;;     (global memref (memref.const 0x100 0x10 0x20))
+;;     (func (export "load") (result i32)
+;;       (i32.msload 16 (global.get 0))))
+(assert_invalid
+  (module binary
+    "\00\61\73\6d\01\00\00\00"
+    "\01\05\01\60\00\01\7f"
+    "\03\02\01\00"
+    "\05\23\11\00\01\00\01\00\01\00\01\00\01\00\01\00\01\00\01\00\01\00\01\00\01\00\01\00\01\00\01\00\01\00\01\00\01"
+    "\06\09\01\6e\00\da\80\02\10\20\0b"
+    "\07\08\01\04\6c\6f\61\64\00\00"
+    "\0a\0a\01\08\00\23\00\e0\42\10\00\0b"
+  )
+  "memory 16")
@@ -0,0 +1,44 @@
+;; Accesses through memrefs into a 64-bit memory, here the second memory of
+;; the module.
+;;
+;; The text format doesn't have memref instructions, so the module is given in
+;; binary form. It is equivalent to:
+;;
+;;   (module
+;;     (memory 1)
+;;     (memory i64 1)
+;;     (global memref (memref.const 0xfff0 0x20 0x120))
+;;     (func (export "store") (param i32 i32)
+;;       (i32.msstore 1 (memref.add (global.get 0) (local.get 0)) (local.get 1)))
+;;     (func (export "load") (param i32) (result i32)
+;;       (i32.msload 1 (memref.add (global.get 0) (local.get 0))))
+;;     (func (export "peek") (param i64) (result i32)
+;;       (i32.load8_u 1 (local.get 0)))
+;;     (func (export "alloc") (param i32 i32) (result memref)
+;;       (memref.alloc 0x120 (local.get 0) (local.get 1))))
+;;
+;; The object of the global extends past the end of the memory. Memref values
+;; are written as `v128.const i32x4 addr base end attr`.
+(module binary
+  "\00\61\73\6d\01\00\00\00"
+  "\01\16\04\60\02\7f\7f\00\60\01\7f\01\7f\60\01\7e\01\7f\60\02\7f\7f\01\6e"
+  "\03\05\04\00\01\02\03"
+  "\05\05\02\00\01\04\01"
+  "\06\0b\01\6e\00\da\f0\ff\03\20\a0\02\0b"
+  "\07\1f\04\05\73\74\6f\72\65\00\00\04\6c\6f\61\64\00\01\04\70\65\65\6b\00\02\05\61\6c\6c\6f\63\00\03"
+  "\0a\2e\04\0d\00\23\00\20\00\db\20\01\f0\42\01\00\0b\0b\00\23\00\20\00\db\e0\42\01\00\0b\08\00\20\00\2d\40\01\00\0b\09\00\20\00\20\01\dc\a0\02\0b"
+)
+
+;; accesses go to the memory of the memref
+(assert_return (invoke "store" (i32.const 4) (i32.const 42)))
+(assert_return (invoke "load" (i32.const 4)) (i32.const 42))
+(assert_return (invoke "peek" (i64.const 0xfff4)) (i32.const 42))
+
+;; accesses are checked against both the object and the memory
+(assert_trap (invoke "load" (i32.const 0x20)) "out of bounds memref access")
+(assert_trap (invoke "load" (i32.const 0x10)) "out of bounds memory access")
+
+;; allocations are made within the memory
+(assert_return (invoke "alloc" (i32.const 0x100) (i32.const 0x10))
+  (v128.const i32x4 0x100 0x100 0x110 0x120))
+(assert_trap (invoke "alloc" (i32.const 0xfff0) (i32.const 0x20)) "memref allocation out of bounds of linear memory")
@@ -0,0 +1,47 @@
+;; Narrowing memrefs to a sub-object with `memref.narrow`.
+;;
+;; The text format doesn't have memref instructions, so the module is given in
+;; binary form. It is equivalent to:
+;;
+;;   (module
+;;     (memory (export "mem") 1)
+;;     (global memref (memref.const 0x100 0x20 0x20))
+;;     (global memref (memref.const 0x200 0x20 0))
+;;     (func (export "narrow") (param i32) (result memref)
+;;       (memref.narrow 8 (local.get 0) (global.get 0)))
+;;     (func (export "narrow_load") (param i32 i32) (result i32)
+;;       (i32.msload (memref.add (memref.narrow 8 (local.get 0) (global.get 0)) (local.get 1))))
+;;     (func (export "narrow_unchecked") (param i32) (result memref)
+;;       (memref.narrow 0x08000008 (local.get 0) (global.get 0)))
+;;     (func (export "narrow_raw") (param i32) (result i32)
+;;       (memref.field 0 (memref.narrow 0x100 (local.get 0) (global.get 1)))))
+;;
+;; The immediate is the size of the sub-object, with bit 27 set if the
+;; narrowing isn't checked. Memref values are written as
+;; `v128.const i32x4 addr base end attr`.
+(module binary
+  "\00\61\73\6d\01\00\00\00"
+  "\01\11\03\60\01\7f\01\6e\60\02\7f\7f\01\7f\60\01\7f\01\7f"
+  "\03\05\04\00\01\00\02"
+  "\05\03\01\00\01"
+  "\06\11\02\6e\00\da\80\02\20\20\0b\6e\00\da\80\04\20\00\0b"
+  "\07\3e\05\03\6d\65\6d\02\00\06\6e\61\72\72\6f\77\00\00\0b\6e\61\72\72\6f\77\5f\6c\6f\61\64\00\01\10\6e\61\72\72\6f\77\5f\75\6e\63\68\65\63\6b\65\64\00\02\0a\6e\61\72\72\6f\77\5f\72\61\77\00\03"
+  "\0a\31\04\08\00\20\00\23\00\de\08\0b\0e\00\20\00\23\00\de\08\20\01\db\e0\02\00\0b\0b\00\20\00\23\00\de\88\80\80\40\0b\0b\00\20\00\23\01\de\80\02\df\00\0b"
+)
+
+;; the sub-object becomes the bounds of the memref, which keeps its address
+(assert_return (invoke "narrow" (i32.const 0x108)) (v128.const i32x4 0x100 0x108 0x110 0x24))
+(assert_return (invoke "narrow" (i32.const 0x118)) (v128.const i32x4 0x100 0x118 0x120 0x24))
+
+;; the sub-object must end within the object
+(assert_trap (invoke "narrow" (i32.const 0x119)) "out of bounds memref access")
+
+;; accesses are checked against the sub-object rather than the object
+(assert_return (invoke "narrow_load" (i32.const 0x100) (i32.const 4)) (i32.const 0))
+(assert_trap (invoke "narrow_load" (i32.const 0x100) (i32.const 6)) "out of bounds memref access")
+
+;; unchecked narrowing leaves the memref as-is
+(assert_return (invoke "narrow_unchecked" (i32.const 0x119)) (v128.const i32x4 0x100 0x100 0x120 0x20))
+
+;; memrefs without metadata aren't checked
+(assert_return (invoke "narrow_raw" (i32.const 0x300)) (i32.const 0x200))
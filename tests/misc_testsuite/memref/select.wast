@@ -0,0 +1,44 @@
+;; Choosing between memrefs with `memref.select`.
+;;
+;; The text format doesn't have memref instructions, so the module is given in
+;; binary form. It is equivalent to:
+;;
+;;   (module
+;;     (memory (export "mem") 1)
+;;     (global memref (memref.const 0x100 0x10 0x20))
+;;     (global memref (memref.const 0x200 0x10 0))
+;;     (func (export "select") (param memref memref i32) (result memref)
+;;       (memref.select (local.get 0) (local.get 1) (local.get 2)))
+;;     (func (export "select_load") (param i32) (result i32)
+;;       (i32.msload
+;;         (memref.add
+;;           (memref.select (global.get 0) (global.get 1) (local.get 0))
+;;           (i32.const 0x10)))))
+;;
+;; Memref values are written as `v128.const i32x4 addr base end attr`.
+(module binary
+  "\00\61\73\6d\01\00\00\00"
+  "\01\0d\02\60\03\6e\6e\7f\01\6e\60\01\7f\01\7f"
+  "\03\03\02\00\01"
+  "\05\03\01\00\01"
+  "\06\11\02\6e\00\da\80\02\10\20\0b\6e\00\da\80\04\10\00\0b"
+  "\07\1e\03\03\6d\65\6d\02\00\06\73\65\6c\65\63\74\00\00\0b\73\65\6c\65\63\74\5f\6c\6f\61\64\00\01"
+  "\0a\1b\02\09\00\20\00\20\01\20\02\d9\0b\0f\00\23\00\23\01\20\00\d9\41\10\db\e0\02\00\0b"
+)
+
+(assert_return
+  (invoke "select"
+    (v128.const i32x4 0x104 0x100 0x110 0x20)
+    (v128.const i32x4 0x208 0x200 0x210 0)
+    (i32.const 1))
+  (v128.const i32x4 0x104 0x100 0x110 0x20))
+(assert_return
+  (invoke "select"
+    (v128.const i32x4 0x104 0x100 0x110 0x20)
+    (v128.const i32x4 0x208 0x200 0x210 0)
+    (i32.const 0))
+  (v128.const i32x4 0x208 0x200 0x210 0))
+
+;; accesses are checked against the bounds of the selected memref
+(assert_trap (invoke "select_load" (i32.const 1)) "out of bounds memref access")
+(assert_return (invoke "select_load" (i32.const 0)) (i32.const 0))
@@ -0,0 +1,49 @@
+;; The metadata of memrefs stored to a linear memory survives the memory
+;; growing. When memref shadow metadata is enabled, this moves the shadow region
+;; to a larger one, and the memrefs stored to the new pages get records too.
+;;
+;; The text format doesn't have memref instructions, so the module is given in
+;; binary form. It is equivalent to:
+;;
+;;   (module
+;;     (import "__host" "__set_value" (func (param i32 i32 i32 i32 i32)))
+;;     (import "__host" "__get_record" (func (param i32) (result memref)))
+;;     (memory (export "mem") 1)
+;;     (func (export "store_ptr") (param memref memref)
+;;       (memref.msstore (local.get 0) (local.get 1)))
+;;     (func (export "load_ptr") (param memref) (result memref)
+;;       (memref.msload (local.get 0)))
+;;     (func (export "grow") (param i32) (result i32)
+;;       (memory.grow (local.get 0))))
+;;
+;; Memref values are written as `v128.const i32x4 addr base end attr`.
+(module binary
+  "\00\61\73\6d\01\00\00\00"
+  "\01\1d\05\60\05\7f\7f\7f\7f\7f\00\60\01\7f\01\6e\60\02\6e\6e\00\60\01\6e\01\6e\60\01\7f\01\7f"
+  "\02\2c\02\06\5f\5f\68\6f\73\74\0b\5f\5f\73\65\74\5f\76\61\6c\75\65\00\00\06\5f\5f\68\6f\73\74\0c\5f\5f\67\65\74\5f\72\65\63\6f\72\64\00\01"
+  "\03\04\03\02\03\04"
+  "\05\03\01\00\01"
+  "\07\25\04\03\6d\65\6d\02\00\09\73\74\6f\72\65\5f\70\74\72\00\02\08\6c\6f\61\64\5f\70\74\72\00\03\04\67\72\6f\77\00\04"
+  "\0a\1a\03\09\00\20\00\20\01\f4\02\00\0b\07\00\20\00\e4\02\00\0b\06\00\20\00\40\00\0b"
+)
+
+(assert_return (invoke "store_ptr" (v128.const i32x4 0x1000 0 0 0) (v128.const i32x4 0x104 0x100 0x110 0x20)))
+
+;; the next page can only be written to once the memory grew
+(assert_trap
+  (invoke "store_ptr" (v128.const i32x4 0x10000 0 0 0) (v128.const i32x4 0x108 0x100 0x110 0x20))
+  "out of bounds memory access")
+(assert_return (invoke "grow" (i32.const 1)) (i32.const 1))
+(assert_return (invoke "store_ptr" (v128.const i32x4 0x10000 0 0 0) (v128.const i32x4 0x108 0x100 0x110 0x20)))
+
+;; growing a page at a time and by many pages at once
+(assert_return (invoke "grow" (i32.const 1)) (i32.const 2))
+(assert_return (invoke "grow" (i32.const 1)) (i32.const 3))
+(assert_return (invoke "grow" (i32.const 16)) (i32.const 4))
+(assert_return (invoke "store_ptr" (v128.const i32x4 0x13fff0 0 0 0) (v128.const i32x4 0x10c 0x100 0x110 0x20)))
+
+;; the memrefs stored before each growth keep their bounds
+(assert_return (invoke "load_ptr" (v128.const i32x4 0x1000 0 0 0)) (v128.const i32x4 0x104 0x100 0x110 0x20))
+(assert_return (invoke "load_ptr" (v128.const i32x4 0x10000 0 0 0)) (v128.const i32x4 0x108 0x100 0x110 0x20))
+(assert_return (invoke "load_ptr" (v128.const i32x4 0x13fff0 0 0 0)) (v128.const i32x4 0x10c 0x100 0x110 0x20))
+(assert_return (invoke "load_ptr" (v128.const i32x4 0x20000 0 0 0)) (v128.const i32x4 0 0 0 0))